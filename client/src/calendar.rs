@@ -4,38 +4,117 @@ use uuid::Uuid;
 use crate::error::CaserError;
 use crate::event::CaserEvent;
 use crate::helpers::UrlBuilder;
+use crate::conditional::{Fetched, Written, etag_of, with_etag};
 use caser_common::event::EventPlain;
+use reqwest::StatusCode;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::Value;
 use std::ops::Deref;
 use std::convert::TryInto;
 
 pub struct CaserCalendar<'client>
 {
-    client: &'client CaserClient,
-    inner: Calendar,
+    pub(crate) client: &'client CaserClient,
+    pub(crate) inner: Calendar,
+
+    /// The calendar's ETag when it was fetched.
+    pub etag: Option<String>,
 }
 
 impl<'client> CaserCalendar<'client>
 {
-    pub async fn get_event_by_id(&self, id: Uuid) -> Result<CaserEvent<'client>, CaserError>
+    fn event_url(&self, id: Uuid) -> Result<reqwest::Url, CaserError>
     {
-        let url = UrlBuilder::new(self.client.host.clone())
-            .add_part("calendar")
+        UrlBuilder::new(self.client.host.clone())
+            .add_part("calendars")
             .add_part(&self.get_id().to_string())
             .add_part("events")
             .add_part(&id.to_string())
-            .build()?;
+            .build()
+    }
+
+    /// Gets an event, unless `if_none_match` is the ETag it still has.
+    pub async fn get_event_by_id(&self, id: Uuid, if_none_match: Option<&str>) -> Result<Fetched<CaserEvent<'client>>, CaserError>
+    {
+        let req = self.client.reqwest_client.get(self.event_url(id)?);
+        let response = with_etag(req, "If-None-Match", if_none_match).send().await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED
+        {
+            return Ok(Fetched::NotModified);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = etag_of(&response);
+        let event_plain: EventPlain = response.json().await?;
+
+        Ok(
+            Fetched::Modified(CaserEvent {
+                client: self.client,
+                inner: event_plain.try_into()?,
+                etag,
+            })
+        )
+    }
+
+    /// Updates an event, unless it no longer has the ETag `if_match`.
+    /// Returns the event's new ETag.
+    pub async fn update_event(&self, id: Uuid, event: &EventPlain, if_match: Option<&str>) -> Result<Written<Option<String>>, CaserError>
+    {
+        let req = self.client.reqwest_client.put(self.event_url(id)?).json(event);
+        let response = with_etag(req, "If-Match", if_match).send().await?;
 
-        let req = self.client.reqwest_client.get(url).build()?;
-        let response = self.client.reqwest_client.execute(req).await?;
+        if response.status() == StatusCode::PRECONDITION_FAILED
+        {
+            return Ok(Written::PreconditionFailed);
+        }
+
+        Ok(Written::Done(etag_of(&response.error_for_status()?)))
+    }
+
+    /// Changes an event with a JSON Merge Patch (RFC 7396), where `null`
+    /// clears a field, unless it no longer has the ETag `if_match`.
+    pub async fn patch_event(&self, id: Uuid, patch: &Value, if_match: Option<&str>) -> Result<Written<CaserEvent<'client>>, CaserError>
+    {
+        let req = self.client.reqwest_client.patch(self.event_url(id)?)
+            .header(CONTENT_TYPE, "application/merge-patch+json")
+            .body(patch.to_string());
+
+        let response = with_etag(req, "If-Match", if_match).send().await?;
+
+        if response.status() == StatusCode::PRECONDITION_FAILED
+        {
+            return Ok(Written::PreconditionFailed);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = etag_of(&response);
         let event_plain: EventPlain = response.json().await?;
 
         Ok(
-            CaserEvent {
+            Written::Done(CaserEvent {
                 client: self.client,
-                inner: event_plain.try_into()?
-            }
+                inner: event_plain.try_into()?,
+                etag,
+            })
         )
     }
+
+    /// Moves an event to the trash, unless it no longer has the ETag `if_match`.
+    pub async fn delete_event(&self, id: Uuid, if_match: Option<&str>) -> Result<Written<()>, CaserError>
+    {
+        let req = self.client.reqwest_client.delete(self.event_url(id)?);
+        let response = with_etag(req, "If-Match", if_match).send().await?;
+
+        if response.status() == StatusCode::PRECONDITION_FAILED
+        {
+            return Ok(Written::PreconditionFailed);
+        }
+
+        response.error_for_status()?;
+
+        Ok(Written::Done(()))
+    }
 }
 
 impl<'client> Deref for CaserCalendar<'client>
@@ -45,4 +124,4 @@ impl<'client> Deref for CaserCalendar<'client>
     fn deref(&self) -> &Self::Target {
         &self.inner
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,11 @@
-use reqwest::Url;
+use reqwest::{StatusCode, Url};
 use crate::error::CaserError;
+use crate::calendar::CaserCalendar;
+use crate::conditional::{Fetched, Written, etag_of, with_etag};
+use crate::helpers::UrlBuilder;
+use caser_common::calendar::Calendar;
 use reqwest::header::{HeaderMap, HeaderValue};
+use uuid::Uuid;
 
 pub struct CaserClient
 {
@@ -30,4 +35,80 @@ impl CaserClient
             }
         )
     }
-}
\ No newline at end of file
+
+    fn calendar_url(&self, id: Uuid) -> Result<Url, CaserError>
+    {
+        UrlBuilder::new(self.host.clone())
+            .add_part("calendars")
+            .add_part(&id.to_string())
+            .build()
+    }
+
+    /// Gets a calendar, unless `if_none_match` is the ETag it still has.
+    pub async fn get_calendar(&self, id: Uuid, if_none_match: Option<&str>) -> Result<Fetched<CaserCalendar<'_>>, CaserError>
+    {
+        let req = self.reqwest_client.get(self.calendar_url(id)?);
+        let response = with_etag(req, "If-None-Match", if_none_match).send().await?;
+
+        if response.status() == StatusCode::NOT_MODIFIED
+        {
+            return Ok(Fetched::NotModified);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = etag_of(&response);
+
+        Ok(
+            Fetched::Modified(CaserCalendar {
+                client: self,
+                inner: response.json().await?,
+                etag,
+            })
+        )
+    }
+
+    /// Updates a calendar, unless it no longer has the ETag `if_match`.
+    pub async fn update_calendar(&self, calendar: &Calendar, if_match: Option<&str>) -> Result<Written<CaserCalendar<'_>>, CaserError>
+    {
+        let req = self.reqwest_client.put(self.calendar_url(calendar.get_id())?).json(calendar);
+        let response = with_etag(req, "If-Match", if_match).send().await?;
+
+        if response.status() == StatusCode::PRECONDITION_FAILED
+        {
+            return Ok(Written::PreconditionFailed);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = etag_of(&response);
+
+        Ok(
+            Written::Done(CaserCalendar {
+                client: self,
+                inner: response.json().await?,
+                etag,
+            })
+        )
+    }
+
+    /// Moves a calendar to the trash (with its events if `cascade`
+    /// is `true`), unless it no longer has the ETag `if_match`.
+    pub async fn delete_calendar(&self, id: Uuid, cascade: bool, if_match: Option<&str>) -> Result<Written<()>, CaserError>
+    {
+        let url = UrlBuilder::new(self.host.clone())
+            .add_part("calendars")
+            .add_part(&id.to_string())
+            .add_query("cascade", &cascade.to_string())
+            .build()?;
+
+        let response = with_etag(self.reqwest_client.delete(url), "If-Match", if_match).send().await?;
+
+        if response.status() == StatusCode::PRECONDITION_FAILED
+        {
+            return Ok(Written::PreconditionFailed);
+        }
+
+        response.error_for_status()?;
+
+        Ok(Written::Done(()))
+    }
+}
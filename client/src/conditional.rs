@@ -0,0 +1,46 @@
+//! Results of conditional requests. The server gives calendars and events
+//! an ETag, which can be sent back in `If-None-Match` to only download them
+//! if they changed, and in `If-Match` to only change them if nobody else has.
+
+use reqwest::{RequestBuilder, Response};
+
+/// The result of a GET with `If-None-Match`.
+#[derive(Debug)]
+pub enum Fetched<T>
+{
+    /// The resource doesn't have the ETag that was sent (or none was sent).
+    Modified(T),
+
+    /// The resource still has the ETag that was sent.
+    NotModified,
+}
+
+/// The result of a PUT or DELETE with `If-Match`.
+#[derive(Debug)]
+pub enum Written<T>
+{
+    Done(T),
+
+    /// The resource changed since the ETag that was sent,
+    /// or it doesn't exist anymore.
+    PreconditionFailed,
+}
+
+/// Adds `header` to `request` if there's an ETag to send.
+pub(crate) fn with_etag(request: RequestBuilder, header: &str, etag: Option<&str>) -> RequestBuilder
+{
+    match etag
+    {
+        Some(etag) => request.header(header, etag),
+        None => request,
+    }
+}
+
+/// The ETag of the resource in `response`, if it has one.
+pub(crate) fn etag_of(response: &Response) -> Option<String>
+{
+    response.headers()
+        .get("ETag")
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.to_owned())
+}
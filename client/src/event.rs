@@ -6,6 +6,9 @@ pub struct CaserEvent<'client>
 {
     pub client: &'client CaserClient,
     pub inner: Event,
+
+    /// The event's ETag when it was fetched.
+    pub etag: Option<String>,
 }
 
 impl<'client> Deref for CaserEvent<'client>
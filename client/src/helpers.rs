@@ -44,6 +44,7 @@ impl UrlBuilder
                 .collect::<Vec<String>>()
                 .join("&");
 
+            result += "?";
             result += &query;
         }
 
@@ -17,4 +17,5 @@ pub mod error;
 pub mod client;
 pub mod calendar;
 pub mod event;
+pub mod conditional;
 mod helpers;
\ No newline at end of file
@@ -0,0 +1,309 @@
+//! Access control lists of calendars. A grant gives a principal (a user
+//! or group of the JWT issuer, or an API key) a role on a calendar.
+//! See docs/server/acl.md.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use uuid::Uuid;
+use chrono::NaiveDateTime;
+use crate::event::EventClass;
+
+/// What a principal can do with a calendar. Each role can do
+/// everything the roles before it can.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+pub enum Role
+{
+    /// Seeing the calendar and when its events happen, but not what
+    /// they are. Grants with this role always have the `FREE_BUSY`
+    /// visibility.
+    #[serde(rename = "FREE_BUSY")]
+    FreeBusy,
+
+    /// Reading the calendar and everything in it.
+    #[serde(rename = "READER")]
+    Reader,
+
+    /// Creating, changing and deleting events (and their attendees
+    /// and alarms), webhooks and feeds of the calendar.
+    #[serde(rename = "WRITER")]
+    Writer,
+
+    /// Changing and deleting the calendar itself and managing its grants.
+    #[serde(rename = "OWNER")]
+    Owner,
+}
+
+impl Display for Role
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            Role::FreeBusy => "FREE_BUSY",
+            Role::Reader => "READER",
+            Role::Writer => "WRITER",
+            Role::Owner => "OWNER",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for Role
+{
+    type Err = InvalidRole;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "FREE_BUSY" => Ok(Role::FreeBusy),
+            "READER" => Ok(Role::Reader),
+            "WRITER" => Ok(Role::Writer),
+            "OWNER" => Ok(Role::Owner),
+            _ => Err(InvalidRole),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid role.")]
+pub struct InvalidRole;
+
+/// Which events of a calendar a principal can see the details of (title,
+/// description, metadata, organizer and attendees), by their class. Events
+/// it can't see the details of are redacted to when they happen (see
+/// `caser_common::event::Redact`). Each visibility shows everything the
+/// visibilities before it show.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+pub enum Visibility
+{
+    /// No event.
+    #[serde(rename = "FREE_BUSY")]
+    FreeBusy,
+
+    /// `PUBLIC` events.
+    #[serde(rename = "PUBLIC")]
+    Public,
+
+    /// `PUBLIC` and `PRIVATE` events.
+    #[serde(rename = "PRIVATE")]
+    Private,
+
+    /// Every event, `CONFIDENTIAL` ones included.
+    #[serde(rename = "ALL")]
+    All,
+}
+
+impl Visibility
+{
+    pub fn shows(&self, class: EventClass) -> bool
+    {
+        match self
+        {
+            Visibility::FreeBusy => false,
+            Visibility::Public => class == EventClass::Public,
+            Visibility::Private => class != EventClass::Confidential,
+            Visibility::All => true,
+        }
+    }
+}
+
+impl Default for Visibility
+{
+    fn default() -> Self { Visibility::All }
+}
+
+impl Display for Visibility
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            Visibility::FreeBusy => "FREE_BUSY",
+            Visibility::Public => "PUBLIC",
+            Visibility::Private => "PRIVATE",
+            Visibility::All => "ALL",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for Visibility
+{
+    type Err = InvalidVisibility;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "FREE_BUSY" => Ok(Visibility::FreeBusy),
+            "PUBLIC" => Ok(Visibility::Public),
+            "PRIVATE" => Ok(Visibility::Private),
+            "ALL" => Ok(Visibility::All),
+            _ => Err(InvalidVisibility),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid visibility.")]
+pub struct InvalidVisibility;
+
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum PrincipalType
+{
+    /// A user of the JWT issuer, by `sub` claim.
+    #[serde(rename = "USER")]
+    User,
+
+    /// A group of the JWT issuer, by name (as in the groups claim).
+    #[serde(rename = "GROUP")]
+    Group,
+
+    /// An API key, by id.
+    #[serde(rename = "API_KEY")]
+    ApiKey,
+}
+
+impl Display for PrincipalType
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            PrincipalType::User => "USER",
+            PrincipalType::Group => "GROUP",
+            PrincipalType::ApiKey => "API_KEY",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for PrincipalType
+{
+    type Err = InvalidPrincipalType;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "USER" => Ok(PrincipalType::User),
+            "GROUP" => Ok(PrincipalType::Group),
+            "API_KEY" => Ok(PrincipalType::ApiKey),
+            _ => Err(InvalidPrincipalType),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid principal type.")]
+pub struct InvalidPrincipalType;
+
+/// A role given to a principal on a calendar. A calendar has
+/// at most one grant per principal.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct CalendarGrant
+{
+    /// Ignored in requests.
+    #[serde(default)]
+    pub id: Option<Uuid>,
+
+    /// Ignored in requests, grants are created in
+    /// the calendar of the route.
+    #[serde(default)]
+    pub calendar_id: Option<Uuid>,
+
+    pub principal_type: PrincipalType,
+
+    /// The user's `sub` claim, the group's name or the API key's id.
+    pub principal_id: String,
+
+    pub role: Role,
+
+    /// Defaults to `ALL`. Ignored (and always `FREE_BUSY`)
+    /// in grants with the `FREE_BUSY` role.
+    #[serde(default)]
+    pub visibility: Visibility,
+
+    /// Ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl CalendarGrant
+{
+    /// Whether the grant names a principal: a non-blank user or
+    /// group id, or the UUID of an API key.
+    pub fn validate(&self) -> bool
+    {
+        match self.principal_type
+        {
+            PrincipalType::ApiKey => Uuid::from_str(&self.principal_id).is_ok(),
+            PrincipalType::User | PrincipalType::Group => !self.principal_id.trim().is_empty(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn grant(principal_type: PrincipalType, principal_id: &str) -> CalendarGrant
+    {
+        CalendarGrant {
+            id: None,
+            calendar_id: None,
+            principal_type,
+            principal_id: principal_id.to_owned(),
+            role: Role::Reader,
+            visibility: Visibility::All,
+            created_at: None,
+        }
+    }
+
+    #[test]
+    fn roles_are_ordered_by_access()
+    {
+        assert!(Role::FreeBusy < Role::Reader);
+        assert!(Role::Reader < Role::Writer);
+        assert!(Role::Writer < Role::Owner);
+        assert_eq!(vec![Role::Reader, Role::Owner, Role::FreeBusy].into_iter().max(), Some(Role::Owner));
+    }
+
+    #[test]
+    fn roles_round_trip()
+    {
+        for role in &[Role::FreeBusy, Role::Reader, Role::Writer, Role::Owner]
+        {
+            assert_eq!(Role::from_str(&role.to_string()).unwrap(), *role);
+        }
+
+        assert!(Role::from_str("reader").is_err());
+    }
+
+    #[test]
+    fn visibilities_show_classes()
+    {
+        assert!(!Visibility::FreeBusy.shows(EventClass::Public));
+        assert!(Visibility::Public.shows(EventClass::Public));
+        assert!(!Visibility::Public.shows(EventClass::Private));
+        assert!(Visibility::Private.shows(EventClass::Private));
+        assert!(!Visibility::Private.shows(EventClass::Confidential));
+        assert!(Visibility::All.shows(EventClass::Confidential));
+    }
+
+    #[test]
+    fn validate_principal_id()
+    {
+        assert!(grant(PrincipalType::User, "user-1").validate());
+        assert!(grant(PrincipalType::Group, "staff").validate());
+        assert!(!grant(PrincipalType::Group, " ").validate());
+        assert!(grant(PrincipalType::ApiKey, "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60").validate());
+        assert!(!grant(PrincipalType::ApiKey, "user-1").validate());
+    }
+}
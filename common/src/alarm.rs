@@ -0,0 +1,280 @@
+//! Event reminders, modeled after RFC 5545's VALARM component.
+
+use chrono::{NaiveDateTime, Duration, NaiveTime};
+use uuid::Uuid;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::span::EventSpan;
+
+/// What should happen when an alarm is triggered (RFC 5545's ACTION property).
+///
+/// The server doesn't display anything or send emails by itself, this is
+/// just forwarded to whoever receives the alarm notification.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum AlarmAction
+{
+    #[serde(rename = "DISPLAY")]
+    Display,
+
+    #[serde(rename = "EMAIL")]
+    Email,
+
+    #[serde(rename = "AUDIO")]
+    Audio,
+}
+
+impl Default for AlarmAction
+{
+    fn default() -> Self { AlarmAction::Display }
+}
+
+impl Display for AlarmAction
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            AlarmAction::Display => "DISPLAY",
+            AlarmAction::Email => "EMAIL",
+            AlarmAction::Audio => "AUDIO",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for AlarmAction
+{
+    type Err = InvalidAlarmValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "DISPLAY" => Ok(AlarmAction::Display),
+            "EMAIL" => Ok(AlarmAction::Email),
+            "AUDIO" => Ok(AlarmAction::Audio),
+            _ => Err(InvalidAlarmValue("ACTION")),
+        }
+    }
+}
+
+/// Whether a relative trigger is relative to the start or
+/// to the end of the event (RFC 5545's RELATED parameter).
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum AlarmRelated
+{
+    #[serde(rename = "START")]
+    Start,
+
+    #[serde(rename = "END")]
+    End,
+}
+
+impl Default for AlarmRelated
+{
+    fn default() -> Self { AlarmRelated::Start }
+}
+
+impl Display for AlarmRelated
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        match self
+        {
+            AlarmRelated::Start => f.write_str("START"),
+            AlarmRelated::End => f.write_str("END"),
+        }
+    }
+}
+
+impl FromStr for AlarmRelated
+{
+    type Err = InvalidAlarmValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "START" => Ok(AlarmRelated::Start),
+            "END" => Ok(AlarmRelated::End),
+            _ => Err(InvalidAlarmValue("RELATED")),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid {0} value.")]
+pub struct InvalidAlarmValue(pub &'static str);
+
+/// A reminder of an event.
+///
+/// An alarm has either a relative trigger (`trigger_offset`) or an absolute
+/// trigger (`trigger_absolute`), never both. Alarms of recurring events with
+/// a relative trigger go off once for every instance of the event, alarms with
+/// an absolute trigger go off only once.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Alarm
+{
+    /// Id of the alarm in the database, `None` when
+    /// adding an alarm to an event.
+    pub id: Option<Uuid>,
+
+    #[serde(default)]
+    pub action: AlarmAction,
+
+    /// Minutes relative to the start (or end, see `related`) of the event.
+    /// Negative values mean before the event, e.g. -15 is 15 minutes before.
+    pub trigger_offset: Option<i32>,
+
+    #[serde(default)]
+    pub related: AlarmRelated,
+
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub trigger_absolute: Option<NaiveDateTime>,
+
+    /// How many more times the alarm goes off after it is first triggered.
+    #[serde(default)]
+    pub repeat: i32,
+
+    /// Minutes between repetitions, required if `repeat` is greater than 0.
+    pub repeat_interval: Option<i32>,
+
+    pub description: Option<String>,
+}
+
+impl Alarm
+{
+    /// An alarm is valid with exactly one trigger (relative or absolute)
+    /// and a repeat count that isn't negative. Alarms that repeat also
+    /// need a positive repeat interval.
+    pub fn validate(&self) -> bool
+    {
+        if self.trigger_offset.is_some() == self.trigger_absolute.is_some()
+        {
+            return false;
+        }
+
+        if self.repeat < 0
+        {
+            return false;
+        }
+
+        if self.repeat > 0 && self.repeat_interval.map_or(true, |x| x <= 0)
+        {
+            return false;
+        }
+
+        true
+    }
+
+    /// Maximum amount of time between the first and the last
+    /// time this alarm goes off.
+    pub fn get_repeat_duration(&self) -> Duration
+    {
+        Duration::minutes(self.repeat as i64 * self.repeat_interval.unwrap_or(0) as i64)
+    }
+
+    /// Calculates all times this alarm goes off for the event (or event
+    /// instance) with the span `span`, in chronological order.
+    ///
+    /// All-day events start and end at midnight.
+    pub fn due_times(&self, span: &EventSpan) -> Vec<NaiveDateTime>
+    {
+        let first = match (self.trigger_absolute, self.trigger_offset)
+        {
+            (Some(absolute), _) => absolute,
+            (None, Some(offset)) =>
+            {
+                let anchor = match self.related
+                {
+                    AlarmRelated::Start => span.get_start_date().and_time(span.get_start_time().unwrap_or(NaiveTime::from_hms(0, 0, 0))),
+                    AlarmRelated::End => span.get_end_date().and_time(span.get_end_time().unwrap_or(NaiveTime::from_hms(0, 0, 0))),
+                };
+
+                anchor + Duration::minutes(offset as i64)
+            },
+            (None, None) => return vec![],
+        };
+
+        let interval = Duration::minutes(self.repeat_interval.unwrap_or(0) as i64);
+
+        (0..=self.repeat.max(0))
+            .map(|i| first + interval * i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn alarm() -> Alarm
+    {
+        Alarm {
+            id: None,
+            action: AlarmAction::Display,
+            trigger_offset: None,
+            related: AlarmRelated::Start,
+            trigger_absolute: None,
+            repeat: 0,
+            repeat_interval: None,
+            description: None,
+        }
+    }
+
+    fn span() -> EventSpan
+    {
+        EventSpan::from_date_time_and_duration(NaiveDate::from_ymd(2021, 2, 1).and_hms(12, 0, 0), Duration::hours(1))
+    }
+
+    #[test]
+    fn relative_to_start()
+    {
+        let alarm = Alarm { trigger_offset: Some(-15), ..alarm() };
+
+        assert!(alarm.validate());
+        assert_eq!(alarm.due_times(&span()), [NaiveDate::from_ymd(2021, 2, 1).and_hms(11, 45, 0)]);
+    }
+
+    #[test]
+    fn relative_to_end_with_repeat()
+    {
+        let alarm = Alarm {
+            trigger_offset: Some(0),
+            related: AlarmRelated::End,
+            repeat: 2,
+            repeat_interval: Some(5),
+            ..alarm()
+        };
+
+        assert!(alarm.validate());
+        assert_eq!(alarm.due_times(&span()), [
+            NaiveDate::from_ymd(2021, 2, 1).and_hms(13, 0, 0),
+            NaiveDate::from_ymd(2021, 2, 1).and_hms(13, 5, 0),
+            NaiveDate::from_ymd(2021, 2, 1).and_hms(13, 10, 0),
+        ]);
+    }
+
+    #[test]
+    fn invalid_triggers()
+    {
+        assert!(!alarm().validate());
+
+        let both = Alarm {
+            trigger_offset: Some(-15),
+            trigger_absolute: Some(NaiveDate::from_ymd(2021, 2, 1).and_hms(8, 0, 0)),
+            ..alarm()
+        };
+        assert!(!both.validate());
+
+        let repeat_without_interval = Alarm { trigger_offset: Some(-15), repeat: 1, ..alarm() };
+        assert!(!repeat_without_interval.validate());
+
+        let negative_repeat = Alarm { trigger_offset: Some(-15), repeat: -1, ..alarm() };
+        assert!(!negative_repeat.validate());
+    }
+}
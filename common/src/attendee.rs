@@ -0,0 +1,241 @@
+//! Attendees and organizers of events, modeled after RFC 5545's
+//! ATTENDEE and ORGANIZER properties.
+
+use uuid::Uuid;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Participation role of an attendee (RFC 5545's ROLE parameter).
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum AttendeeRole
+{
+    #[serde(rename = "CHAIR")]
+    Chair,
+
+    #[serde(rename = "REQ-PARTICIPANT")]
+    ReqParticipant,
+
+    #[serde(rename = "OPT-PARTICIPANT")]
+    OptParticipant,
+
+    #[serde(rename = "NON-PARTICIPANT")]
+    NonParticipant,
+}
+
+impl Default for AttendeeRole
+{
+    fn default() -> Self { AttendeeRole::ReqParticipant }
+}
+
+impl Display for AttendeeRole
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            AttendeeRole::Chair => "CHAIR",
+            AttendeeRole::ReqParticipant => "REQ-PARTICIPANT",
+            AttendeeRole::OptParticipant => "OPT-PARTICIPANT",
+            AttendeeRole::NonParticipant => "NON-PARTICIPANT",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for AttendeeRole
+{
+    type Err = InvalidAttendeeValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "CHAIR" => Ok(AttendeeRole::Chair),
+            "REQ-PARTICIPANT" => Ok(AttendeeRole::ReqParticipant),
+            "OPT-PARTICIPANT" => Ok(AttendeeRole::OptParticipant),
+            "NON-PARTICIPANT" => Ok(AttendeeRole::NonParticipant),
+            _ => Err(InvalidAttendeeValue("ROLE")),
+        }
+    }
+}
+
+/// Participation status of an attendee (RFC 5545's PARTSTAT parameter
+/// for VEVENTs).
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ParticipationStatus
+{
+    #[serde(rename = "NEEDS-ACTION")]
+    NeedsAction,
+
+    #[serde(rename = "ACCEPTED")]
+    Accepted,
+
+    #[serde(rename = "DECLINED")]
+    Declined,
+
+    #[serde(rename = "TENTATIVE")]
+    Tentative,
+
+    #[serde(rename = "DELEGATED")]
+    Delegated,
+}
+
+impl Default for ParticipationStatus
+{
+    fn default() -> Self { ParticipationStatus::NeedsAction }
+}
+
+impl Display for ParticipationStatus
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            ParticipationStatus::NeedsAction => "NEEDS-ACTION",
+            ParticipationStatus::Accepted => "ACCEPTED",
+            ParticipationStatus::Declined => "DECLINED",
+            ParticipationStatus::Tentative => "TENTATIVE",
+            ParticipationStatus::Delegated => "DELEGATED",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for ParticipationStatus
+{
+    type Err = InvalidAttendeeValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "NEEDS-ACTION" => Ok(ParticipationStatus::NeedsAction),
+            "ACCEPTED" => Ok(ParticipationStatus::Accepted),
+            "DECLINED" => Ok(ParticipationStatus::Declined),
+            "TENTATIVE" => Ok(ParticipationStatus::Tentative),
+            "DELEGATED" => Ok(ParticipationStatus::Delegated),
+            _ => Err(InvalidAttendeeValue("PARTSTAT")),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid {0} value.")]
+pub struct InvalidAttendeeValue(pub &'static str);
+
+/// Someone who was invited to an event.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Attendee
+{
+    /// Id of the attendee in the database, `None` when
+    /// adding an attendee to an event.
+    pub id: Option<Uuid>,
+
+    pub email: String,
+    pub display_name: Option<String>,
+
+    #[serde(default)]
+    pub role: AttendeeRole,
+
+    #[serde(default)]
+    pub partstat: ParticipationStatus,
+
+    /// Whether the organizer expects a reply from this attendee.
+    #[serde(default)]
+    pub rsvp: bool,
+}
+
+/// The person who organizes an event.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Eq, PartialEq)]
+pub struct Organizer
+{
+    pub email: String,
+    pub display_name: Option<String>,
+}
+
+/// Body of a request to respond to an invitation.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct AttendeeResponse
+{
+    pub partstat: ParticipationStatus,
+}
+
+/// Merges the attendees of an override onto the attendees of its parent
+/// event. Attendees are matched by email (case insensitive), so an attendee
+/// that responded only to one instance of a recurring event shows up with
+/// that response in that instance and with the parent's response in the others.
+///
+/// Attendees that only exist in the override are appended to the result.
+pub fn merge_attendees(parent: &[Attendee], overridden: &[Attendee]) -> Vec<Attendee>
+{
+    let same_email = |a: &Attendee, b: &Attendee| a.email.eq_ignore_ascii_case(&b.email);
+
+    let mut merged: Vec<Attendee> = parent
+        .iter()
+        .map(|attendee|
+            overridden
+                .iter()
+                .find(|o| same_email(o, attendee))
+                .unwrap_or(attendee)
+                .clone()
+        )
+        .collect();
+
+    for attendee in overridden
+    {
+        if !parent.iter().any(|p| same_email(p, attendee))
+        {
+            merged.push(attendee.clone());
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn attendee(email: &str, partstat: ParticipationStatus) -> Attendee
+    {
+        Attendee {
+            id: None,
+            email: email.to_owned(),
+            display_name: None,
+            role: AttendeeRole::ReqParticipant,
+            partstat,
+            rsvp: true,
+        }
+    }
+
+    #[test]
+    fn merge_overridden_response()
+    {
+        let parent = vec![
+            attendee("alice@example.com", ParticipationStatus::Accepted),
+            attendee("bob@example.com", ParticipationStatus::Accepted),
+        ];
+
+        let overridden = vec![
+            attendee("Bob@example.com", ParticipationStatus::Declined),
+            attendee("carol@example.com", ParticipationStatus::NeedsAction),
+        ];
+
+        let merged = merge_attendees(&parent, &overridden);
+
+        let statuses = merged.iter().map(|a| a.partstat).collect::<Vec<_>>();
+        assert_eq!(statuses, [ParticipationStatus::Accepted, ParticipationStatus::Declined, ParticipationStatus::NeedsAction]);
+    }
+
+    #[test]
+    fn roles_round_trip()
+    {
+        for role in &[AttendeeRole::Chair, AttendeeRole::ReqParticipant, AttendeeRole::OptParticipant, AttendeeRole::NonParticipant]
+        {
+            assert_eq!(AttendeeRole::from_str(&role.to_string()).unwrap(), *role);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! The audit log, which records every insert, update and delete
+//! of calendars, events and API keys. See db_schema/19.sql.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use uuid::Uuid;
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use crate::acl::PrincipalType;
+
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ResourceType
+{
+    #[serde(rename = "CALENDAR")]
+    Calendar,
+
+    /// Events, overrides included.
+    #[serde(rename = "EVENT")]
+    Event,
+
+    #[serde(rename = "API_KEY")]
+    ApiKey,
+}
+
+impl Display for ResourceType
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            ResourceType::Calendar => "CALENDAR",
+            ResourceType::Event => "EVENT",
+            ResourceType::ApiKey => "API_KEY",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for ResourceType
+{
+    type Err = InvalidResourceType;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "CALENDAR" => Ok(ResourceType::Calendar),
+            "EVENT" => Ok(ResourceType::Event),
+            "API_KEY" => Ok(ResourceType::ApiKey),
+            _ => Err(InvalidResourceType),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid resource type.")]
+pub struct InvalidResourceType;
+
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum AuditAction
+{
+    #[serde(rename = "INSERT")]
+    Insert,
+
+    #[serde(rename = "UPDATE")]
+    Update,
+
+    #[serde(rename = "DELETE")]
+    Delete,
+}
+
+impl Display for AuditAction
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            AuditAction::Insert => "INSERT",
+            AuditAction::Update => "UPDATE",
+            AuditAction::Delete => "DELETE",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for AuditAction
+{
+    type Err = InvalidAuditAction;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "INSERT" => Ok(AuditAction::Insert),
+            "UPDATE" => Ok(AuditAction::Update),
+            "DELETE" => Ok(AuditAction::Delete),
+            _ => Err(InvalidAuditAction),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid audit action.")]
+pub struct InvalidAuditAction;
+
+/// A change to a calendar, an event or an API key.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct AuditEntry
+{
+    /// Entries are numbered in the order they were recorded.
+    pub id: i64,
+
+    pub tenant_id: Option<Uuid>,
+
+    /// Who made the change, `None` for changes the server made on its
+    /// own (e.g. syncing subscribed calendars).
+    pub principal_type: Option<PrincipalType>,
+
+    /// The user's `sub` claim or the API key's id.
+    pub principal_id: Option<String>,
+
+    /// Method and route (or CalDAV path) of the request
+    /// that made the change, e.g. `PUT /api/calendars/<calendar_id>`.
+    pub route: Option<String>,
+
+    pub resource_type: ResourceType,
+    pub resource_id: Uuid,
+    pub action: AuditAction,
+
+    /// The resource before the change, `None` for inserts. For
+    /// updates only the properties that changed.
+    pub before: Option<Value>,
+
+    /// The resource after the change, `None` for deletes. For
+    /// updates only the properties that changed.
+    pub after: Option<Value>,
+
+    #[serde(with = "crate::event::event_plain_serde::date_time")]
+    #[schemars(with = "NaiveDateTime")]
+    pub created_at: NaiveDateTime,
+}
@@ -1,4 +1,9 @@
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use serde_json::Value;
+use std::str::FromStr;
 use uuid::Uuid;
+use crate::subscription::Subscription;
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Calendar
@@ -8,7 +13,36 @@ pub struct Calendar
     /// This is useful when deserializing Calendar
     /// for create requests.
     #[serde(default = "Uuid::nil")]
-    id: Uuid,
+    pub id: Uuid,
+
+    pub name: Option<String>,
+    pub description: Option<String>,
+
+    /// A hex color, like `#1e90ff`.
+    pub color: Option<String>,
+
+    /// IANA name of the time zone apps should show the calendar's
+    /// events in, e.g. `Europe/Paris`. Events are still stored in UTC.
+    pub time_zone: Option<String>,
+
+    /// Arbitrary JSON the application wants to store along with the calendar.
+    pub metadata: Option<Value>,
+
+    /// Set if the calendar is a read-only mirror of an
+    /// external iCalendar file.
+    #[serde(default)]
+    pub subscription: Option<Subscription>,
+
+    /// Ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub last_modified: Option<NaiveDateTime>,
+
+    /// When the calendar was moved to the trash. Only set on calendars
+    /// listed in the trash, ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 
@@ -17,9 +51,78 @@ impl Calendar
     pub fn new(id: Uuid) -> Calendar
     {
         Calendar {
-            id
+            id,
+            name: None,
+            description: None,
+            color: None,
+            time_zone: None,
+            metadata: None,
+            subscription: None,
+            last_modified: None,
+            deleted_at: None,
         }
     }
 
     pub fn get_id(&self) -> Uuid { self.id }
-}
\ No newline at end of file
+
+    pub fn get_subscription(&self) -> Option<&Subscription> { self.subscription.as_ref() }
+
+    /// Whether the calendar can be stored: its color, if set, is a
+    /// `#RRGGBB` hex color and its time zone, if set, is a known IANA
+    /// time zone. The subscription is validated separately.
+    pub fn validate(&self) -> bool
+    {
+        self.invalid_field().is_none()
+    }
+
+    /// Like `validate`, but returns the name of the invalid field.
+    pub fn invalid_field(&self) -> Option<&'static str>
+    {
+        if !self.color.as_ref().map_or(true, |color| is_hex_color(color))
+        {
+            return Some("color");
+        }
+
+        if !self.time_zone.as_ref().map_or(true, |time_zone| Tz::from_str(time_zone).is_ok())
+        {
+            return Some("time_zone");
+        }
+
+        None
+    }
+}
+
+/// Checks if `color` looks like `#RRGGBB`.
+fn is_hex_color(color: &str) -> bool
+{
+    color.len() == 7
+        && color.starts_with('#')
+        && color[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn validate_color()
+    {
+        let calendar = |color: &str| Calendar { color: Some(color.to_owned()), ..Calendar::new(Uuid::nil()) };
+
+        assert!(calendar("#1e90FF").validate());
+        assert!(!calendar("1e90ff").validate());
+        assert!(!calendar("#1e90f").validate());
+        assert!(!calendar("#1e90fg").validate());
+    }
+
+    #[test]
+    fn validate_time_zone()
+    {
+        let calendar = |time_zone: &str| Calendar { time_zone: Some(time_zone.to_owned()), ..Calendar::new(Uuid::nil()) };
+
+        assert!(calendar("Europe/Paris").validate());
+        assert!(calendar("UTC").validate());
+        assert!(!calendar("Mars/Olympus_Mons").validate());
+    }
+}
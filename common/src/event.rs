@@ -10,6 +10,12 @@ use uuid::Uuid;
 use crate::span::{EventSpan, EventDateTimeSpan, EventDateSpan};
 use crate::recurrence::parser::RRuleParseError;
 use std::convert::{TryFrom, TryInto};
+use serde_json::Value;
+use crate::attendee::{Attendee, Organizer};
+use crate::acl::Visibility;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::merge_patch::merge_patch;
 
 
 #[derive(Clone, Debug)]
@@ -20,6 +26,15 @@ pub struct EventRecurrence
     rdates: Vec<NaiveDate>,
 }
 
+impl EventRecurrence
+{
+    pub fn get_rule(&self) -> &RecurrenceRule { &self.rule }
+
+    pub fn get_exdates(&self) -> &Vec<NaiveDate> { &self.exdates }
+
+    pub fn get_rdates(&self) -> &Vec<NaiveDate> { &self.rdates }
+}
+
 impl TryFrom<RecurrencePlain> for EventRecurrence
 {
     type Error = FromPlainError;
@@ -28,7 +43,7 @@ impl TryFrom<RecurrencePlain> for EventRecurrence
     {
         if value.rrule.is_none()
         {
-            return Err(FromPlainError::MissingField)
+            return Err(FromPlainError::MissingField("recurrence.rrule"))
         }
 
         Ok(
@@ -48,6 +63,7 @@ pub enum Event
 {
     Recurring(EventRecurring),
     Single(EventSingle),
+    Override(EventOverride),
 }
 
 impl ToPlain<EventPlain> for Event
@@ -58,6 +74,7 @@ impl ToPlain<EventPlain> for Event
         {
             Event::Recurring(e) => e.into_plain(),
             Event::Single(e) => e.into_plain(),
+            Event::Override(e) => e.into_plain(),
         }
     }
 }
@@ -79,24 +96,60 @@ impl TryFrom<EventPlain> for Event
 
     fn try_from(value: EventPlain) -> Result<Self, Self::Error>
     {
-        if value.start_date.is_none() || value.end_date.is_none()
+        if value.id.is_none()
         {
-            return Err(FromPlainError::MissingField);
+            return Err(FromPlainError::MissingField("id"));
         }
 
-        if value.start_time.is_some() != value.end_time.is_some()
+        if value.last_modified.is_none()
         {
-            return Err(FromPlainError::InvalidSpan);
+            return Err(FromPlainError::MissingField("last_modified"));
         }
 
-        if value.id.is_none()
+        // Overrides only store the fields they change, so they
+        // don't need a full span like the other event types.
+        if let Some(parent_id) = value.parent_id
+        {
+            if value.recurrence.is_some()
+            {
+                return Err(FromPlainError::InvalidOverride);
+            }
+
+            return Ok(
+                Event::Override(
+                    EventOverride {
+                        id: value.id.unwrap(),
+                        parent_id,
+                        recurrence_id: value.recurrence_id.ok_or(FromPlainError::MissingField("recurrence_id"))?,
+                        start_date: value.start_date,
+                        start_time: value.start_time,
+                        end_date: value.end_date,
+                        end_time: value.end_time,
+                        title: value.title,
+                        description: value.description,
+                        metadata: value.metadata,
+                        organizer: value.organizer,
+                        class: value.class.unwrap_or_default(),
+                        sequence: value.sequence.unwrap_or(0),
+                        last_modified: value.last_modified.unwrap(),
+                    }
+                )
+            );
+        }
+
+        if value.start_date.is_none()
         {
-            return Err(FromPlainError::MissingField);
+            return Err(FromPlainError::MissingField("start_date"));
         }
 
-        if value.last_modified.is_none()
+        if value.end_date.is_none()
         {
-            return Err(FromPlainError::MissingField);
+            return Err(FromPlainError::MissingField("end_date"));
+        }
+
+        if value.start_time.is_some() != value.end_time.is_some()
+        {
+            return Err(FromPlainError::InvalidSpan);
         }
 
         let span;
@@ -127,6 +180,12 @@ impl TryFrom<EventPlain> for Event
                         id: value.id.unwrap(),
                         span,
                         recurrence: value.recurrence.unwrap().try_into()?,
+                        title: value.title,
+                        description: value.description,
+                        metadata: value.metadata,
+                        organizer: value.organizer,
+                        class: value.class.unwrap_or_default(),
+                        sequence: value.sequence.unwrap_or(0),
                         last_modified: value.last_modified.unwrap()
                     }
                 )
@@ -138,7 +197,12 @@ impl TryFrom<EventPlain> for Event
                 Event::Single(
                     EventSingle {
                         id: value.id.unwrap(),
-                        parent_id: value.parent_id,
+                        title: value.title,
+                        description: value.description,
+                        metadata: value.metadata,
+                        organizer: value.organizer,
+                        class: value.class.unwrap_or_default(),
+                        sequence: value.sequence.unwrap_or(0),
                         last_modified: value.last_modified.unwrap(),
                         span,
                     }
@@ -158,6 +222,12 @@ pub struct EventRecurring
     id: Uuid,
     span: EventSpan,
     recurrence: EventRecurrence,
+    title: Option<String>,
+    description: Option<String>,
+    metadata: Option<Value>,
+    organizer: Option<Organizer>,
+    class: EventClass,
+    sequence: i32,
     last_modified: NaiveDateTime,
 }
 
@@ -171,7 +241,47 @@ impl EventRecurring
 
     pub fn get_recurrence(&self) -> EventRecurrence { self.recurrence.clone() }
 
+    pub fn get_title(&self) -> Option<&String> { self.title.as_ref() }
+
+    pub fn get_description(&self) -> Option<&String> { self.description.as_ref() }
+
+    pub fn get_metadata(&self) -> Option<&Value> { self.metadata.as_ref() }
+
+    pub fn get_class(&self) -> EventClass { self.class }
+
+    pub fn get_sequence(&self) -> i32 { self.sequence }
+
     pub fn get_last_modified(&self) -> NaiveDateTime { self.last_modified.clone() }
+
+    /// Builds the (non-overridden) instance of this event that happens
+    /// on `date`. The instance has the same duration and start time
+    /// as this event.
+    ///
+    /// This does not check if the event actually happens on `date`,
+    /// that's the recurrence algorithm's job.
+    pub fn instance_at(&self, date: NaiveDate) -> EventInstance
+    {
+        let span = match self.span
+        {
+            EventSpan::Date(date_span) => EventSpan::from_date_and_duration(date, date_span.end - date_span.start),
+            EventSpan::DateTime(datetime_span) => EventSpan::from_date_time_and_duration(
+                date.and_time(datetime_span.start.time()),
+                datetime_span.end - datetime_span.start
+            ),
+        };
+
+        EventInstance {
+            parent_id: self.id,
+            override_id: None,
+            recurrence_id: date,
+            span,
+            title: self.title.clone(),
+            description: self.description.clone(),
+            metadata: self.metadata.clone(),
+            organizer: self.organizer.clone(),
+            class: self.class,
+        }
+    }
 }
 
 impl ToPlain<EventPlain> for EventRecurring
@@ -181,6 +291,7 @@ impl ToPlain<EventPlain> for EventRecurring
         EventPlain {
             id: Some(self.id),
             parent_id: None,
+            recurrence_id: None,
 
             start_date: Some(self.span.get_start_date()),
             end_date: Some(self.span.get_end_date()),
@@ -195,7 +306,16 @@ impl ToPlain<EventPlain> for EventRecurring
                 }
             ),
 
+            title: self.title,
+            description: self.description,
+            metadata: self.metadata,
+            organizer: self.organizer,
+            class: Some(self.class),
+            attendees: None,
+
+            sequence: Some(self.sequence),
             last_modified: Some(self.last_modified),
+            deleted_at: None,
         }
     }
 }
@@ -212,29 +332,15 @@ impl ToPlain<EventPlain> for EventRecurring
 pub struct EventSingle
 {
     id: Uuid,
-
-    /// If this is Some, it means this event is a single
-    /// event that was originated from modifying the date/time
-    /// of an event instance. That event instance does not exist
-    /// anymore (i.e. won't be generated by `EventRecurring::generate_instances`)
-    /// and this one "took its place".
-    ///
-    /// If this is None it just means this is a non-recurring event,
-    /// without any relationship with any other event in the calendarold.
-    ///
-    /// For example:
-    ///
-    /// Imagine there's a recurrent event that starts at 2020-09-01 (Tue),
-    /// happens weekly (every Tuesday), and has an ID of `abc`.
-    /// Now imagine the user decided to move the instance of 2020-09-08
-    /// one day ahead, making it happen on 2020-09-09.
-    /// What happened "behind the scenes" is:
-    /// 1. The date 2020-09-08 was added to the recurrent event's EXDATES property.
-    /// 2. A (non-recurring) event was created at 2020-09-09, with the ID `cde`.
-    /// 3. The parent_id of the `cde` event was set to `abc`.
-    parent_id: Option<Uuid>,
     span: EventSpan,
 
+    title: Option<String>,
+    description: Option<String>,
+    metadata: Option<Value>,
+    organizer: Option<Organizer>,
+    class: EventClass,
+    sequence: i32,
+
     last_modified: NaiveDateTime,
 }
 
@@ -244,7 +350,17 @@ impl EventSingle
 
     pub fn get_id(&self) -> Uuid { self.id }
 
-    pub fn get_parent_id(&self) -> Option<Uuid> { self.parent_id }
+    pub fn get_title(&self) -> Option<&String> { self.title.as_ref() }
+
+    pub fn get_description(&self) -> Option<&String> { self.description.as_ref() }
+
+    pub fn get_metadata(&self) -> Option<&Value> { self.metadata.as_ref() }
+
+    pub fn get_class(&self) -> EventClass { self.class }
+
+    pub fn get_sequence(&self) -> i32 { self.sequence }
+
+    pub fn get_last_modified(&self) -> NaiveDateTime { self.last_modified }
 }
 
 impl ToPlain<EventPlain> for EventSingle
@@ -253,7 +369,8 @@ impl ToPlain<EventPlain> for EventSingle
     {
         EventPlain {
             id: Some(self.id),
-            parent_id: self.parent_id,
+            parent_id: None,
+            recurrence_id: None,
 
             start_date: Some(self.span.get_start_date()),
             end_date: Some(self.span.get_end_date()),
@@ -262,11 +379,164 @@ impl ToPlain<EventPlain> for EventSingle
 
             recurrence: None,
 
+            title: self.title,
+            description: self.description,
+            metadata: self.metadata,
+            organizer: self.organizer,
+            class: Some(self.class),
+            attendees: None,
+
+            sequence: Some(self.sequence),
             last_modified: Some(self.last_modified),
+            deleted_at: None,
+        }
+    }
+}
+
+
+
+
+
+
+/// An override of a single instance of a recurring event, the same thing
+/// as an event with a RECURRENCE-ID in RFC 5545.
+///
+/// Overrides only store the fields they change, every field that is `None`
+/// is inherited from the parent event. This way changes made to the parent
+/// event (e.g. changing its title) still show up in overridden instances,
+/// unless the override changed that same field.
+///
+/// For example:
+///
+/// Imagine there's a recurrent event that starts at 2020-09-01 (Tue),
+/// happens weekly (every Tuesday), and has an ID of `abc`.
+/// Now imagine the user decided to move the instance of 2020-09-08
+/// one day ahead, making it happen on 2020-09-09.
+/// What happened "behind the scenes" is that an override with a
+/// `parent_id` of `abc`, a `recurrence_id` of 2020-09-08 and a `start_date`
+/// of 2020-09-09 was created. All other fields of the override are `None`,
+/// so the instance keeps the parent's title, start time and duration.
+#[derive(Clone, Debug)]
+pub struct EventOverride
+{
+    id: Uuid,
+    parent_id: Uuid,
+
+    /// The date of the instance this override replaces, i.e. the date
+    /// the instance would happen on if it wasn't overridden.
+    recurrence_id: NaiveDate,
+
+    start_date: Option<NaiveDate>,
+    start_time: Option<NaiveTime>,
+    end_date: Option<NaiveDate>,
+    end_time: Option<NaiveTime>,
+
+    title: Option<String>,
+    description: Option<String>,
+    metadata: Option<Value>,
+    organizer: Option<Organizer>,
+    class: EventClass,
+    sequence: i32,
+
+    last_modified: NaiveDateTime,
+}
+
+impl EventOverride
+{
+    pub fn get_id(&self) -> Uuid { self.id }
+
+    pub fn get_parent_id(&self) -> Uuid { self.parent_id }
+
+    pub fn get_recurrence_id(&self) -> NaiveDate { self.recurrence_id }
+
+    pub fn get_sequence(&self) -> i32 { self.sequence }
+
+    pub fn get_last_modified(&self) -> NaiveDateTime { self.last_modified }
+
+    /// Merges this override's fields onto `instance`, which should be the
+    /// instance generated for this override's `recurrence_id`.
+    ///
+    /// Fields that aren't set in the override are kept from the instance,
+    /// except for the class, which is always the override's. If the
+    /// override moves the start of the instance but doesn't say anything
+    /// about its end, the instance keeps its duration.
+    pub fn apply(&self, instance: EventInstance) -> EventInstance
+    {
+        let duration = instance.span.get_duration();
+
+        let start_date = self.start_date.unwrap_or(instance.span.get_start_date());
+        let start_time = self.start_time.or(instance.span.get_start_time());
+
+        let changes_end = self.end_date.is_some() || self.end_time.is_some();
+
+        let span = match start_time
+        {
+            Some(start_time) =>
+            {
+                let start = start_date.and_time(start_time);
+
+                match self.end_time.or(instance.span.get_end_time())
+                {
+                    Some(end_time) if changes_end =>
+                    {
+                        let end_date = self.end_date
+                            .unwrap_or(start_date + (instance.span.get_end_date() - instance.span.get_start_date()));
+
+                        EventSpan::DateTime(EventDateTimeSpan { start, end: end_date.and_time(end_time) })
+                    },
+                    _ => EventSpan::from_date_time_and_duration(start, duration),
+                }
+            },
+            None => match self.end_date
+            {
+                Some(end_date) => EventSpan::Date(EventDateSpan { start: start_date, end: end_date }),
+                None => EventSpan::from_date_and_duration(start_date, duration),
+            },
+        };
+
+        EventInstance {
+            parent_id: instance.parent_id,
+            override_id: Some(self.id),
+            recurrence_id: instance.recurrence_id,
+            span,
+            title: self.title.clone().or(instance.title),
+            description: self.description.clone().or(instance.description),
+            metadata: self.metadata.clone().or(instance.metadata),
+            organizer: self.organizer.clone().or(instance.organizer),
+            class: self.class,
         }
     }
 }
 
+impl ToPlain<EventPlain> for EventOverride
+{
+    fn into_plain(self) -> EventPlain
+    {
+        EventPlain {
+            id: Some(self.id),
+            parent_id: Some(self.parent_id),
+            recurrence_id: Some(self.recurrence_id),
+
+            start_date: self.start_date,
+            end_date: self.end_date,
+            start_time: self.start_time,
+            end_time: self.end_time,
+
+            recurrence: None,
+
+            title: self.title,
+            description: self.description,
+            metadata: self.metadata,
+            organizer: self.organizer,
+            class: Some(self.class),
+            attendees: None,
+
+            sequence: Some(self.sequence),
+            last_modified: Some(self.last_modified),
+            deleted_at: None,
+        }
+    }
+}
 
 
 
@@ -277,7 +547,20 @@ impl ToPlain<EventPlain> for EventSingle
 pub struct EventInstance
 {
     parent_id: Uuid,
+
+    /// Id of the override that was applied to this instance, if any.
+    override_id: Option<Uuid>,
+
+    /// The date this instance would happen on if it wasn't overridden.
+    recurrence_id: NaiveDate,
+
     span: EventSpan,
+
+    title: Option<String>,
+    description: Option<String>,
+    metadata: Option<Value>,
+    organizer: Option<Organizer>,
+    class: EventClass,
 }
 
 impl EventInstance
@@ -285,6 +568,16 @@ impl EventInstance
     pub fn get_span(&self) -> EventSpan { self.span }
 
     pub fn get_parent_id(&self) -> Uuid { self.parent_id }
+
+    pub fn get_override_id(&self) -> Option<Uuid> { self.override_id }
+
+    pub fn get_recurrence_id(&self) -> NaiveDate { self.recurrence_id }
+
+    pub fn get_title(&self) -> Option<&String> { self.title.as_ref() }
+
+    pub fn get_description(&self) -> Option<&String> { self.description.as_ref() }
+
+    pub fn get_class(&self) -> EventClass { self.class }
 }
 
 impl ToPlain<EventPlain> for EventInstance
@@ -292,8 +585,9 @@ impl ToPlain<EventPlain> for EventInstance
     fn into_plain(self) -> EventPlain
     {
         EventPlain {
-            id: None,
+            id: self.override_id,
             parent_id: Some(self.parent_id),
+            recurrence_id: Some(self.recurrence_id),
 
             start_date: Some(self.span.get_start_date()),
             end_date: Some(self.span.get_end_date()),
@@ -302,12 +596,20 @@ impl ToPlain<EventPlain> for EventInstance
 
             recurrence: None,
 
+            title: self.title,
+            description: self.description,
+            metadata: self.metadata,
+            organizer: self.organizer,
+            class: Some(self.class),
+            attendees: None,
+
+            sequence: None,
             last_modified: None,
+            deleted_at: None,
         }
     }
 }
 
-
 /// This is a serializable representation of an event
 /// (single, recurrent or instance), it has two purposes:
 ///
@@ -321,11 +623,13 @@ impl ToPlain<EventPlain> for EventInstance
 /// - Single events don't have an rrule.
 /// - Recurring events have an rrule value.
 /// - Instance events don't have an id and have a parent id.
-/// - Edited instance events (instance events with overridden
-/// dates) have an id and a parent id.
+/// - Overrides have an id, a parent id and a recurrence id.
+/// Instances that were overridden also have all three, but their
+/// fields are the result of merging the override onto the parent event.
 ///
 /// If you want to create an EventPlain, call `to_plain`
-/// on an `EventSingle`, `EventInstance` or `EventRecurring`.
+/// on an `EventSingle`, `EventInstance`, `EventRecurring`
+/// or `EventOverride`.
 ///
 /// All fields are optional to allow for PATCH methods.
 #[derive(Serialize, Deserialize, Debug, JsonSchema)]
@@ -334,6 +638,12 @@ pub struct EventPlain
     pub id: Option<Uuid>,
     pub parent_id: Option<Uuid>,
 
+    /// Date of the instance an override replaces (RFC 5545's RECURRENCE-ID).
+    /// Only set on overrides and instances.
+    #[serde(default, with = "event_plain_serde::date_option")]
+    #[schemars(with = "Option<NaiveDate>")]
+    pub recurrence_id: Option<NaiveDate>,
+
     #[serde(default, with = "event_plain_serde::date_option")]
     #[schemars(with = "Option<NaiveDate>")]
     pub start_date: Option<NaiveDate>,
@@ -352,9 +662,37 @@ pub struct EventPlain
 
     pub recurrence: Option<RecurrencePlain>,
 
+    pub title: Option<String>,
+    pub description: Option<String>,
+
+    /// Arbitrary JSON the application wants to store along with the event.
+    pub metadata: Option<Value>,
+
+    pub organizer: Option<Organizer>,
+
+    /// Who can see what the event is, see `Visibility`. Overrides and
+    /// instances always have the class of their parent event, so this
+    /// is ignored when inserting or updating overrides.
+    pub class: Option<EventClass>,
+
+    /// Attendees are managed through the attendee routes, so this
+    /// is ignored in insert and update requests.
+    pub attendees: Option<Vec<Attendee>>,
+
+    /// How many times the event was changed (RFC 5545's SEQUENCE), starting
+    /// at 0. Set by the server, so this is ignored in insert and update
+    /// requests. Not set on instances.
+    pub sequence: Option<i32>,
+
     #[serde(default, with = "event_plain_serde::date_time_option")]
     #[schemars(with = "Option<NaiveDateTime>")]
     pub last_modified: Option<NaiveDateTime>,
+
+    /// When the event was moved to the trash. Only set on events
+    /// listed in the trash, ignored in requests.
+    #[serde(default, with = "event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
 
@@ -381,6 +719,17 @@ impl EventPlain
     /// has at least a `start_date` and an `end_date` and
     /// also check some other integrity constraints.
     ///
+    /// See `check_non_patch` for the list of checks and
+    /// to know why an event isn't valid.
+    ///
+    /// Returns `true` if the event is valid, `false` it it's not.
+    pub fn validate_non_patch(&self) -> bool
+    {
+        self.check_non_patch().is_ok()
+    }
+
+    /// Like `validate_non_patch`, but tells what's wrong with the event.
+    ///
     /// List of validation checks:
     ///
     /// - Checks if `start_date` and `end_date` are both set.
@@ -389,54 +738,314 @@ impl EventPlain
     /// - Checks if `rrule`, `exdates` and `rdates` are all set
     /// if `recurrence` is set.
     ///
-    /// Returns `true` if the event is valid, `false` it it's not.
-    pub fn validate_non_patch(&self) -> bool
+    /// Overrides (events with a `parent_id`) are checked with
+    /// `check_override` instead, since they only store the fields
+    /// they change.
+    pub fn check_non_patch(&self) -> Result<(), FromPlainError>
     {
-        if self.start_date.is_none() || self.end_date.is_none()
+        if self.parent_id.is_some()
         {
-            return false;
+            return self.check_override();
+        }
+
+        if self.recurrence_id.is_some()
+        {
+            return Err(FromPlainError::InvalidOverride);
+        }
+
+        if self.start_date.is_none()
+        {
+            return Err(FromPlainError::MissingField("start_date"));
+        }
+
+        if self.end_date.is_none()
+        {
+            return Err(FromPlainError::MissingField("end_date"));
         }
 
         if self.start_time.is_some() != self.end_time.is_some()
         {
-            return false;
+            return Err(FromPlainError::InvalidSpan);
         }
 
         if let Some(recurrence) = &self.recurrence
         {
             if recurrence.rrule.is_none()
-                || recurrence.exdates.is_none()
-                || recurrence.rdates.is_none()
             {
-                return false;
+                return Err(FromPlainError::MissingField("recurrence.rrule"));
+            }
+
+            if recurrence.exdates.is_none()
+            {
+                return Err(FromPlainError::MissingField("recurrence.exdates"));
+            }
+
+            if recurrence.rdates.is_none()
+            {
+                return Err(FromPlainError::MissingField("recurrence.rdates"));
             }
         }
 
-        true
+        Ok(())
+    }
+
+    /// Whether the event's data can be stored as an override of an
+    /// instance, `check_override` says what's wrong when it can't.
+    pub fn validate_override(&self) -> bool
+    {
+        self.check_override().is_ok()
+    }
+
+    /// Like `validate_override`, but tells what's wrong with the override.
+    ///
+    /// List of validation checks:
+    ///
+    /// - Checks if `parent_id` and `recurrence_id` are both set.
+    /// - Checks that `recurrence` is not set, overrides can't be recurrent.
+    /// - Checks that `end_time` isn't set without `start_time`, otherwise
+    /// we wouldn't know when an all-day instance starts.
+    pub fn check_override(&self) -> Result<(), FromPlainError>
+    {
+        if self.parent_id.is_none()
+        {
+            return Err(FromPlainError::MissingField("parent_id"));
+        }
+
+        if self.recurrence_id.is_none()
+        {
+            return Err(FromPlainError::MissingField("recurrence_id"));
+        }
+
+        if self.recurrence.is_some()
+        {
+            return Err(FromPlainError::InvalidOverride);
+        }
+
+        if self.end_time.is_some() && self.start_time.is_none()
+        {
+            return Err(FromPlainError::InvalidSpan);
+        }
+
+        Ok(())
+    }
+
+    /// Validate an event that's written as a whole, i.e. inserted or
+    /// replacing a stored one (like the result of `merge_patch`). On top
+    /// of `check_non_patch`'s checks:
+    ///
+    /// - Checks that the event ends after it starts, if it has times
+    /// (the database's `end_later_than_start`).
+    /// - Checks that the `rrule` can be parsed.
+    ///
+    /// `check_complete` tells which of these failed.
+    pub fn validate_complete(&self) -> bool
+    {
+        self.check_complete().is_ok()
+    }
+
+    /// Like `validate_complete`, but tells what's wrong with the event.
+    pub fn check_complete(&self) -> Result<(), FromPlainError>
+    {
+        self.check_non_patch()?;
+
+        if let (Some(start_date), Some(start_time), Some(end_date), Some(end_time)) = (self.start_date, self.start_time, self.end_date, self.end_time)
+        {
+            if start_date.and_time(start_time) >= end_date.and_time(end_time)
+            {
+                return Err(FromPlainError::InvalidSpan);
+            }
+        }
+
+        if let Some(rrule) = self.recurrence.as_ref().and_then(|r| r.rrule.as_ref())
+        {
+            RecurrenceRule::new(rrule).map_err(FromPlainError::RRuleParseError)?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies a JSON Merge Patch (see `crate::merge_patch`) to the event.
+    ///
+    /// The ids (`id`, `parent_id` and `recurrence_id`) and the fields
+    /// the server sets can't be patched, they're kept as they are.
+    /// The result isn't validated, see `validate_complete`.
+    pub fn merge_patch(self, patch: &Value) -> Result<EventPlain, serde_json::Error>
+    {
+        let mut value = serde_json::to_value(&self)?;
+        merge_patch(&mut value, patch);
+
+        let patched: EventPlain = serde_json::from_value(value)?;
+
+        Ok(
+            EventPlain {
+                id: self.id,
+                parent_id: self.parent_id,
+                recurrence_id: self.recurrence_id,
+                attendees: self.attendees,
+                sequence: self.sequence,
+                last_modified: self.last_modified,
+                deleted_at: self.deleted_at,
+                ..patched
+            }
+        )
     }
 }
 
-pub trait ToPlain<T: Serialize + Deserialize<'static>>
+pub trait ToPlain<T: Serialize + Deserialize<'static> + Redact>
 {
     fn into_plain(self) -> T;
+
+    /// Like `into_plain`, but strips what a principal seeing the
+    /// calendar with `visibility` isn't allowed to see.
+    ///
+    /// Attendees are added to plain events after they're built, so
+    /// routes that add them redact the plain events themselves.
+    fn into_visible_plain(self, visibility: Visibility) -> T
+        where Self: Sized
+    {
+        self.into_plain().redact(visibility)
+    }
+}
+
+/// Strips everything but when an event happens (and its ids, recurrence
+/// and class) if `visibility` doesn't show its class. This is the only
+/// place events get redacted, everything that serializes events for
+/// principals with a limited visibility goes through it.
+pub trait Redact
+{
+    fn redact(self, visibility: Visibility) -> Self;
+}
+
+impl Redact for EventPlain
+{
+    fn redact(self, visibility: Visibility) -> EventPlain
+    {
+        if visibility.shows(self.class.unwrap_or_default())
+        {
+            return self;
+        }
+
+        EventPlain {
+            title: None,
+            description: None,
+            metadata: None,
+            organizer: None,
+            attendees: None,
+            ..self
+        }
+    }
+}
+
+impl Redact for Event
+{
+    fn redact(self, visibility: Visibility) -> Event
+    {
+        match self
+        {
+            Event::Recurring(e) if !visibility.shows(e.class) => Event::Recurring(
+                EventRecurring { title: None, description: None, metadata: None, organizer: None, ..e }
+            ),
+            Event::Single(e) if !visibility.shows(e.class) => Event::Single(
+                EventSingle { title: None, description: None, metadata: None, organizer: None, ..e }
+            ),
+            Event::Override(e) if !visibility.shows(e.class) => Event::Override(
+                EventOverride { title: None, description: None, metadata: None, organizer: None, ..e }
+            ),
+            event => event,
+        }
+    }
+}
+
+impl<T: Redact> Redact for Vec<T>
+{
+    fn redact(self, visibility: Visibility) -> Vec<T>
+    {
+        self.into_iter().map(|x| x.redact(visibility)).collect()
+    }
+}
+
+/// The iCalendar CLASS of an event (RFC 5545, section 3.8.1.3), telling
+/// which principals can see what the event is (see `Visibility`). Everyone
+/// that can see the calendar can still see when the event happens.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum EventClass
+{
+    #[serde(rename = "PUBLIC")]
+    Public,
+
+    #[serde(rename = "PRIVATE")]
+    Private,
+
+    #[serde(rename = "CONFIDENTIAL")]
+    Confidential,
+}
+
+impl Default for EventClass
+{
+    fn default() -> Self { EventClass::Public }
+}
+
+impl Display for EventClass
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            EventClass::Public => "PUBLIC",
+            EventClass::Private => "PRIVATE",
+            EventClass::Confidential => "CONFIDENTIAL",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for EventClass
+{
+    type Err = InvalidEventClass;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "PUBLIC" => Ok(EventClass::Public),
+            "PRIVATE" => Ok(EventClass::Private),
+            "CONFIDENTIAL" => Ok(EventClass::Confidential),
+            _ => Err(InvalidEventClass),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
-#[error("Could not transform the plain event into a recurrent or single event.")]
+#[error("Invalid event class.")]
+pub struct InvalidEventClass;
+
+/// Why a plain event isn't a valid event. The messages say what's wrong
+/// with the event, not with the request, since the event could come from
+/// anywhere (e.g. an iCalendar file).
+#[derive(Error, Debug)]
 pub enum FromPlainError
 {
-    MissingField,
+    #[error("The event's {0} is required but missing.")]
+    MissingField(&'static str),
+
+    #[error("The event's start, end and times don't make a valid span.")]
     InvalidSpan,
-    RRuleParseError(RRuleParseError),
+
+    #[error("The event isn't a valid override.")]
+    InvalidOverride,
+
+    #[error("The event's rrule is invalid.")]
+    RRuleParseError(#[source] RRuleParseError),
 }
 
 
-/// Provides serde functions for `Option<NaiveDate>`, `Option<NaiveTime>`
-/// and `Option<Vec<NaiveDate>>`.
+/// Provides serde functions for `Option<NaiveDate>`, `Option<NaiveTime>`,
+/// `Option<Vec<NaiveDate>>`, `NaiveDateTime` and `Option<NaiveDateTime>`.
 ///
 /// Dates are formatted like `YYYY-MM-DD`.
 /// Times are formatted like `HH:MM:SS`.
-mod event_plain_serde
+pub(crate) mod event_plain_serde
 {
     const DATE_FORMAT: &'static str = "%Y-%m-%d";
     const TIME_FORMAT: &'static str = "%H:%M";
@@ -558,6 +1167,31 @@ mod event_plain_serde
         }
     }
 
+    pub mod date_time
+    {
+        use chrono::{NaiveDateTime};
+        use serde::{self, Deserialize, Serializer, Deserializer};
+
+        use super::DATE_TIME_FORMAT;
+
+        pub fn serialize<S>(date: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+        {
+            serializer.serialize_str(&format!("{}", date.format(DATE_TIME_FORMAT)))
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<NaiveDateTime, D::Error>
+            where
+                D: Deserializer<'de>,
+        {
+            let string = String::deserialize(deserializer)?;
+
+            NaiveDateTime::parse_from_str(&string, DATE_TIME_FORMAT)
+                .map_err(serde::de::Error::custom)
+        }
+    }
+
     pub mod date_time_option
     {
         use chrono::{NaiveDateTime};
@@ -590,4 +1224,222 @@ mod event_plain_serde
                 .transpose()
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::recurrence::RecurrenceRule;
+    use chrono::Duration;
+
+    fn weekly_event() -> EventRecurring
+    {
+        EventRecurring {
+            id: Uuid::new_v4(),
+            span: EventSpan::from_date_time_and_duration(
+                NaiveDate::from_ymd(2020, 9, 1).and_hms(12, 0, 0),
+                Duration::hours(1)
+            ),
+            recurrence: EventRecurrence {
+                rule: RecurrenceRule::new("FREQ=WEEKLY").unwrap(),
+                exdates: vec![],
+                rdates: vec![],
+            },
+            title: Some("Weekly meeting".to_owned()),
+            description: None,
+            metadata: None,
+            organizer: None,
+            class: EventClass::Private,
+            sequence: 0,
+            last_modified: NaiveDate::from_ymd(2020, 9, 1).and_hms(0, 0, 0),
+        }
+    }
+
+    fn empty_override(parent: &EventRecurring, recurrence_id: NaiveDate) -> EventOverride
+    {
+        EventOverride {
+            id: Uuid::new_v4(),
+            parent_id: parent.id,
+            recurrence_id,
+            start_date: None,
+            start_time: None,
+            end_date: None,
+            end_time: None,
+            title: None,
+            description: None,
+            metadata: None,
+            organizer: None,
+            class: EventClass::Private,
+            sequence: 0,
+            last_modified: NaiveDate::from_ymd(2020, 9, 1).and_hms(0, 0, 0),
+        }
+    }
+
+    #[test]
+    fn override_inherits_unchanged_fields()
+    {
+        let event = weekly_event();
+        let date = NaiveDate::from_ymd(2020, 9, 8);
+
+        let ovr = EventOverride {
+            description: Some("Bring snacks".to_owned()),
+            ..empty_override(&event, date)
+        };
+
+        let instance = ovr.apply(event.instance_at(date));
+
+        assert_eq!(instance.get_title(), Some(&"Weekly meeting".to_owned()));
+        assert_eq!(instance.description, Some("Bring snacks".to_owned()));
+        assert_eq!(instance.get_span().get_start_time(), Some(NaiveTime::from_hms(12, 0, 0)));
+        assert_eq!(instance.get_override_id(), Some(ovr.get_id()));
+    }
+
+    #[test]
+    fn override_moving_start_keeps_duration()
+    {
+        let event = weekly_event();
+        let date = NaiveDate::from_ymd(2020, 9, 8);
+
+        let ovr = EventOverride {
+            start_date: Some(NaiveDate::from_ymd(2020, 9, 9)),
+            start_time: Some(NaiveTime::from_hms(15, 0, 0)),
+            ..empty_override(&event, date)
+        };
+
+        let span = ovr.apply(event.instance_at(date)).get_span();
+
+        assert_eq!(span.get_start_date(), NaiveDate::from_ymd(2020, 9, 9));
+        assert_eq!(span.get_end_time(), Some(NaiveTime::from_hms(16, 0, 0)));
+        assert_eq!(span.get_duration(), Duration::hours(1));
+    }
+
+    #[test]
+    fn override_changing_end()
+    {
+        let event = weekly_event();
+        let date = NaiveDate::from_ymd(2020, 9, 8);
+
+        let ovr = EventOverride {
+            end_time: Some(NaiveTime::from_hms(14, 30, 0)),
+            ..empty_override(&event, date)
+        };
+
+        let span = ovr.apply(event.instance_at(date)).get_span();
+
+        assert_eq!(span.get_start_date(), date);
+        assert_eq!(span.get_start_time(), Some(NaiveTime::from_hms(12, 0, 0)));
+        assert_eq!(span.get_end_time(), Some(NaiveTime::from_hms(14, 30, 0)));
+    }
+
+    #[test]
+    fn free_busy_keeps_only_times()
+    {
+        let plain = weekly_event().into_visible_plain(Visibility::FreeBusy);
+
+        assert_eq!(plain.title, None);
+        assert_eq!(plain.start_date, Some(NaiveDate::from_ymd(2020, 9, 1)));
+        assert_eq!(plain.start_time, Some(NaiveTime::from_hms(12, 0, 0)));
+        assert!(plain.recurrence.is_some());
+    }
+
+    #[test]
+    fn redact_depends_on_class()
+    {
+        let plain = weekly_event().into_visible_plain(Visibility::Public);
+        assert_eq!(plain.title, None);
+        assert_eq!(plain.class, Some(EventClass::Private));
+
+        let plain = weekly_event().into_visible_plain(Visibility::Private);
+        assert_eq!(plain.title, Some("Weekly meeting".to_owned()));
+    }
+
+    #[test]
+    fn instances_keep_the_parent_class()
+    {
+        let event = weekly_event();
+        let date = NaiveDate::from_ymd(2020, 9, 8);
+
+        let instance = empty_override(&event, date).apply(event.instance_at(date));
+
+        assert_eq!(instance.get_class(), EventClass::Private);
+        assert_eq!(Event::Recurring(event).redact(Visibility::Public).into_plain().title, None);
+    }
+
+    #[test]
+    fn overrides_with_a_stricter_class_are_redacted()
+    {
+        let event = EventRecurring { class: EventClass::Public, ..weekly_event() };
+        let date = NaiveDate::from_ymd(2020, 9, 8);
+
+        let ovr = EventOverride {
+            title: Some("Performance review".to_owned()),
+            class: EventClass::Confidential,
+            ..empty_override(&event, date)
+        };
+
+        let instance = ovr.apply(event.instance_at(date));
+        assert_eq!(instance.get_class(), EventClass::Confidential);
+
+        let plain = instance.into_plain().redact(Visibility::Private);
+        assert_eq!(plain.title, None);
+    }
+
+    #[test]
+    fn merge_patch_clears_null_fields()
+    {
+        let event = weekly_event();
+        let id = event.id;
+
+        let patch = serde_json::json!({
+            "recurrence": null,
+            "start_time": null,
+            "end_time": null,
+            "title": "One-off meeting",
+            "id": Uuid::new_v4(),
+        });
+
+        let plain = event.into_plain().merge_patch(&patch).unwrap();
+
+        assert!(plain.recurrence.is_none());
+        assert_eq!(plain.start_time, None);
+        assert_eq!(plain.end_time, None);
+        assert_eq!(plain.start_date, Some(NaiveDate::from_ymd(2020, 9, 1)));
+        assert_eq!(plain.title, Some("One-off meeting".to_owned()));
+        assert_eq!(plain.id, Some(id));
+        assert!(plain.validate_complete());
+    }
+
+    #[test]
+    fn merge_patch_merges_recurrence()
+    {
+        let patch = serde_json::json!({ "recurrence": { "exdates": ["2020-09-08"] } });
+
+        let event = weekly_event();
+        let rrule = event.recurrence.rule.to_string();
+
+        let plain = event.into_plain().merge_patch(&patch).unwrap();
+        let recurrence = plain.recurrence.as_ref().unwrap();
+
+        assert_eq!(recurrence.rrule, Some(rrule));
+        assert_eq!(recurrence.exdates, Some(vec![NaiveDate::from_ymd(2020, 9, 8)]));
+        assert!(plain.validate_complete());
+    }
+
+    #[test]
+    fn validate_complete_checks_what_the_database_would()
+    {
+        let patch = serde_json::json!({ "end_time": "11:00" });
+        assert!(!weekly_event().into_plain().merge_patch(&patch).unwrap().validate_complete());
+
+        let patch = serde_json::json!({ "recurrence": { "rrule": "FREQ=SOMETIMES" } });
+        assert!(!weekly_event().into_plain().merge_patch(&patch).unwrap().validate_complete());
+
+        // A single event can't become recurring without its exdates and rdates.
+        let patch = serde_json::json!({ "recurrence": null, "start_time": null, "end_time": null });
+        let single = weekly_event().into_plain().merge_patch(&patch).unwrap();
+
+        let patch = serde_json::json!({ "recurrence": { "rrule": "FREQ=DAILY" } });
+        assert!(!single.merge_patch(&patch).unwrap().validate_complete());
+    }
+}
@@ -0,0 +1,80 @@
+//! Webcal feeds, which let calendar apps subscribe to a calendar
+//! through a secret URL, without an API key.
+
+use uuid::Uuid;
+use chrono::NaiveDateTime;
+use crate::acl::Visibility;
+
+/// Maximum expansion horizon of a feed, in days.
+pub const MAX_EXPANSION_HORIZON: i32 = 730;
+
+/// A secret feed of a calendar's events, served at `/feeds/<token>.ics`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Feed
+{
+    /// Id of the feed in the database, `None` when
+    /// creating a feed.
+    pub id: Option<Uuid>,
+
+    /// Secret part of the feed's URL, generated when the feed
+    /// is created. Ignored in requests.
+    pub token: Option<String>,
+
+    /// If set, recurring events are not exported with an RRULE, their
+    /// instances that happen up to this many days before or after the
+    /// current day are exported as separate events instead. For apps
+    /// that don't understand recurrence rules.
+    pub expansion_horizon: Option<i32>,
+
+    /// Which events the feed shows the details of, the others only
+    /// say when they happen. Anyone with the token can read the feed,
+    /// so it defaults to `PUBLIC`.
+    #[serde(default = "Feed::default_visibility")]
+    pub visibility: Visibility,
+
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl Feed
+{
+    fn default_visibility() -> Visibility { Visibility::Public }
+
+    /// Whether the feed can be created, i.e. its expansion horizon
+    /// is unset or between 1 and `MAX_EXPANSION_HORIZON` days.
+    pub fn validate(&self) -> bool
+    {
+        self.expansion_horizon.map_or(true, |days| days > 0 && days <= MAX_EXPANSION_HORIZON)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn feed(expansion_horizon: Option<i32>) -> Feed
+    {
+        Feed { id: None, token: None, expansion_horizon, visibility: Visibility::Public, created_at: None }
+    }
+
+    #[test]
+    fn validate_expansion_horizon()
+    {
+        assert!(feed(None).validate());
+        assert!(feed(Some(90)).validate());
+        assert!(!feed(Some(0)).validate());
+        assert!(!feed(Some(MAX_EXPANSION_HORIZON + 1)).validate());
+    }
+
+    #[test]
+    fn feeds_only_show_public_events_by_default()
+    {
+        let feed: Feed = serde_json::from_str("{}").unwrap();
+        assert_eq!(feed.visibility, Visibility::Public);
+
+        let feed: Feed = serde_json::from_str(r#"{"visibility": "PRIVATE"}"#).unwrap();
+        assert_eq!(feed.visibility, Visibility::Private);
+    }
+}
@@ -0,0 +1,72 @@
+//! Report of an iCalendar import.
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+use crate::event::event_plain_serde;
+
+/// What happened to a component of an imported file.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum ImportStatus
+{
+    /// The event was created or updated (or was already up to date).
+    #[serde(rename = "IMPORTED")]
+    Imported,
+
+    /// The component was ignored, e.g. it's not a VEVENT.
+    #[serde(rename = "SKIPPED")]
+    Skipped,
+
+    /// The component is invalid or couldn't be stored.
+    #[serde(rename = "FAILED")]
+    Failed,
+}
+
+/// Result of importing one component.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct ImportResult
+{
+    /// Name of the component, e.g. VEVENT.
+    pub component: String,
+
+    pub uid: Option<String>,
+
+    #[serde(default, with = "event_plain_serde::date_option")]
+    #[schemars(with = "Option<NaiveDate>")]
+    pub recurrence_id: Option<NaiveDate>,
+
+    pub status: ImportStatus,
+
+    /// Id of the event the component was imported as. For cancelled
+    /// instances, id of the recurring event they were removed from.
+    pub event_id: Option<Uuid>,
+
+    /// Why the component was skipped or failed.
+    pub message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default)]
+pub struct ImportReport
+{
+    pub imported: u32,
+    pub skipped: u32,
+    pub failed: u32,
+
+    /// One result per component, in the order they appear in the file
+    /// (except that overrides come after all recurring events).
+    pub components: Vec<ImportResult>,
+}
+
+impl ImportReport
+{
+    pub fn push(&mut self, result: ImportResult)
+    {
+        match result.status
+        {
+            ImportStatus::Imported => self.imported += 1,
+            ImportStatus::Skipped => self.skipped += 1,
+            ImportStatus::Failed => self.failed += 1,
+        }
+
+        self.components.push(result);
+    }
+}
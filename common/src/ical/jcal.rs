@@ -0,0 +1,214 @@
+//! jCal (RFC 7265), the JSON format of iCalendar.
+//!
+//! Events are written as iCalendar first and the resulting components
+//! are converted to jCal, so both formats always carry the same data.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_json::{Value, Map, json};
+use crate::event::Event;
+use super::{DATE_FORMAT, DATE_TIME_FORMAT};
+use super::parser::{self, Component, Property, ICalParseError, unescape_text};
+use super::writer::ICalWriter;
+
+/// Exports `events` as a jCal vcalendar. Like `writer::write_calendar`,
+/// overrides are written along with their parent event and overrides
+/// whose parent is not in `events` are skipped.
+///
+/// Fails if the iCalendar writer wrote something the parser can't
+/// read back, which would be a bug in one of them.
+pub fn write_calendar(events: &[Event], dtstamp: NaiveDateTime) -> Result<Value, ICalParseError>
+{
+    let mut writer = ICalWriter::new(dtstamp);
+    writer.write_events(events);
+
+    from_ics(&writer.finish())
+}
+
+/// Converts an iCalendar file we wrote into jCal. Our files only
+/// have one VCALENDAR.
+fn from_ics(ics: &str) -> Result<Value, ICalParseError>
+{
+    parser::parse(ics)?
+        .first()
+        .map(component)
+        .ok_or(ICalParseError::MissingComponent("VCALENDAR"))
+}
+
+/// Converts a component to `[name, properties, components]`.
+pub fn component(component: &Component) -> Value
+{
+    let properties: Vec<Value> = component.properties.iter().map(property).collect();
+    let components: Vec<Value> = component.components.iter().map(self::component).collect();
+
+    json!([component.name.to_lowercase(), properties, components])
+}
+
+/// Converts a property to `[name, parameters, type, values...]`.
+///
+/// The VALUE parameter only picks the type of the property, so it's
+/// not one of its jCal parameters.
+pub fn property(property: &Property) -> Value
+{
+    let mut params = Map::new();
+
+    for (name, value) in property.params.iter().filter(|(name, _)| name != "VALUE")
+    {
+        params.insert(name.to_lowercase(), Value::String(value.clone()));
+    }
+
+    let value_type = property.get_param("VALUE").map(|t| t.to_lowercase());
+
+    let (value_type, values) = match (property.name.as_str(), value_type.as_deref())
+    {
+        ("DTSTART", _) | ("DTEND", _) | ("RECURRENCE-ID", _) | ("EXDATE", _) | ("RDATE", _)
+            | ("DTSTAMP", _) | ("LAST-MODIFIED", _) | ("CREATED", _) =>
+        {
+            let value_type = value_type.unwrap_or_else(|| "date-time".to_owned());
+
+            let values = property.value
+                .split(',')
+                .map(|value| date_value(&value_type, value))
+                .collect();
+
+            (value_type, values)
+        },
+        ("RRULE", _) => ("recur".to_owned(), vec![recur(&property.value)]),
+        (_, Some(value_type)) if value_type != "text" => (value_type.to_owned(), vec![Value::String(property.value.clone())]),
+        _ => ("text".to_owned(), vec![Value::String(unescape_text(&property.value))]),
+    };
+
+    let mut array = vec![
+        Value::String(property.name.to_lowercase()),
+        Value::Object(params),
+        Value::String(value_type),
+    ];
+    array.extend(values);
+
+    Value::Array(array)
+}
+
+/// Converts a DATE (`20210201`) or DATE-TIME (`20210201T120000Z`)
+/// to its jCal form (`2021-02-01`, `2021-02-01T12:00:00Z`). Values
+/// that can't be parsed are kept as they are.
+fn date_value(value_type: &str, value: &str) -> Value
+{
+    let converted = match value_type
+    {
+        "date" => NaiveDate::parse_from_str(value, DATE_FORMAT)
+            .ok()
+            .map(|date| date.format("%Y-%m-%d").to_string()),
+        _ => NaiveDateTime::parse_from_str(value, DATE_TIME_FORMAT)
+            .ok()
+            .map(|date_time| date_time.format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+    };
+
+    Value::String(converted.unwrap_or_else(|| value.to_owned()))
+}
+
+/// Converts an RRULE to a jCal recur object (RFC 7265, section 3.6.10).
+/// Parts with more than one value become arrays, numeric parts numbers.
+fn recur(rrule: &str) -> Value
+{
+    let mut recur = Map::new();
+
+    for part in rrule.split(';')
+    {
+        let mut split = part.splitn(2, '=');
+
+        let (name, value) = match (split.next(), split.next())
+        {
+            (Some(name), Some(value)) => (name.to_lowercase(), value),
+            _ => continue,
+        };
+
+        let values: Vec<Value> = value
+            .split(',')
+            .map(|value| match name.as_str()
+            {
+                "until" if value.contains('T') => date_value("date-time", value),
+                "until" => date_value("date", value),
+                "freq" | "wkst" | "byday" => Value::String(value.to_owned()),
+                _ => value.parse::<i64>().map(Value::from).unwrap_or_else(|_| Value::String(value.to_owned())),
+            })
+            .collect();
+
+        let value = match name.as_str()
+        {
+            "freq" | "until" | "count" | "interval" | "wkst" => values.into_iter().next().unwrap_or(Value::Null),
+            _ => Value::Array(values),
+        };
+
+        recur.insert(name, value);
+    }
+
+    Value::Object(recur)
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::event::EventPlain;
+    use std::convert::TryFrom;
+
+    fn event(value: Value) -> Event
+    {
+        let plain: EventPlain = serde_json::from_value(value).unwrap();
+        Event::try_from(plain).unwrap()
+    }
+
+    #[test]
+    fn writes_recurring_event()
+    {
+        let events = vec![
+            event(json!({
+                "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+                "start_date": "2021-02-01",
+                "start_time": "12:00",
+                "end_date": "2021-02-01",
+                "end_time": "13:00",
+                "recurrence": { "rrule": "FREQ=WEEKLY;BYDAY=MO,WE;UNTIL=20210301", "exdates": ["2021-02-15", "2021-02-17"], "rdates": [] },
+                "title": "Standup, daily",
+                "last_modified": "2021-01-20T08:30",
+            })),
+        ];
+
+        let jcal = write_calendar(&events, NaiveDate::from_ymd(2021, 3, 1).and_hms(10, 0, 0)).unwrap();
+
+        assert_eq!(jcal[0], "vcalendar");
+        assert!(jcal[1].as_array().unwrap().contains(&json!(["version", {}, "text", "2.0"])));
+
+        let vevent = &jcal[2][0];
+        let properties = vevent[1].as_array().unwrap();
+
+        assert_eq!(vevent[0], "vevent");
+        assert!(properties.contains(&json!(["uid", {}, "text", "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60"])));
+        assert!(properties.contains(&json!(["dtstart", {}, "date-time", "2021-02-01T12:00:00Z"])));
+        assert!(properties.contains(&json!(["summary", {}, "text", "Standup, daily"])));
+        assert!(properties.contains(&json!(["exdate", {}, "date-time", "2021-02-15T12:00:00Z", "2021-02-17T12:00:00Z"])));
+        assert!(properties.contains(&json!(
+            ["rrule", {}, "recur", { "freq": "WEEKLY", "until": "2021-03-01T23:59:59Z", "byday": ["MO", "WE"] }]
+        )));
+    }
+
+    #[test]
+    fn writes_all_day_event()
+    {
+        let events = vec![
+            event(json!({
+                "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+                "start_date": "2021-02-01",
+                "end_date": "2021-02-03",
+                "recurrence": { "rrule": "FREQ=DAILY;COUNT=3;INTERVAL=2", "exdates": [], "rdates": [] },
+                "last_modified": "2021-01-20T08:30",
+            })),
+        ];
+
+        let jcal = write_calendar(&events, NaiveDate::from_ymd(2021, 3, 1).and_hms(10, 0, 0)).unwrap();
+        let properties = jcal[2][0][1].as_array().unwrap();
+
+        assert!(properties.contains(&json!(["dtstart", {}, "date", "2021-02-01"])));
+        assert!(properties.contains(&json!(["dtend", {}, "date", "2021-02-03"])));
+        assert!(properties.contains(&json!(["rrule", {}, "recur", { "freq": "DAILY", "count": 3, "interval": 2 }])));
+    }
+}
@@ -0,0 +1,19 @@
+//! iCalendar (RFC 5545) support.
+//!
+//! Only VEVENTs are supported, everything is in UTC (see `event`), so
+//! exported dates and date-times never have a TZID, and imported ones
+//! are converted to UTC.
+
+pub mod writer;
+pub mod parser;
+pub mod import;
+pub mod jcal;
+
+/// PRODID of the calendars we export.
+pub const PRODID: &str = "-//caser//caser calendar server//EN";
+
+/// Format of DATE values.
+pub(crate) const DATE_FORMAT: &str = "%Y%m%d";
+
+/// Format of DATE-TIME values in UTC.
+pub(crate) const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
@@ -0,0 +1,783 @@
+//! Parses iCalendar files and maps their VEVENTs to events.
+
+use chrono::{NaiveDate, NaiveDateTime, Duration, TimeZone};
+use chrono_tz::Tz;
+use std::str::FromStr;
+use crate::event::{EventPlain, RecurrencePlain, EventClass};
+use crate::attendee::Organizer;
+use crate::recurrence::RecurrenceRule;
+use crate::recurrence::parser::RRuleParseError;
+use super::{DATE_FORMAT, DATE_TIME_FORMAT};
+
+/// Format of local (floating or TZID) DATE-TIME values.
+const LOCAL_DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+#[derive(Error, Debug)]
+pub enum ICalParseError
+{
+    #[error("Line {0} is not a valid content line.")]
+    InvalidLine(usize),
+
+    #[error("Component {0} is never closed.")]
+    UnclosedComponent(String),
+
+    #[error("END:{0} doesn't close any component.")]
+    UnexpectedEnd(String),
+
+    #[error("Property {0} is required but missing.")]
+    MissingProperty(&'static str),
+
+    #[error("Component {0} is required but missing.")]
+    MissingComponent(&'static str),
+
+    #[error("Property {0} is duplicated.")]
+    DuplicateProperty(&'static str),
+
+    #[error("Property {0} has an invalid value.")]
+    InvalidValue(&'static str),
+
+    #[error("Property {0} is not supported.")]
+    UnsupportedProperty(&'static str),
+
+    #[error("Time zone {0} is not supported.")]
+    UnknownTimeZone(String),
+
+    #[error("Invalid RRULE: {0}")]
+    RRuleParseError(RRuleParseError),
+}
+
+/// A content line, e.g. `DTSTART;TZID=Europe/Paris:20210201T120000`.
+/// Names are uppercased, values are kept as they are (still escaped).
+#[derive(Debug, Clone)]
+pub struct Property
+{
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Property
+{
+    pub fn get_param(&self, name: &str) -> Option<&str>
+    {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A component (VCALENDAR, VEVENT, VTIMEZONE, ...) along with
+/// the components nested in it.
+#[derive(Debug, Clone)]
+pub struct Component
+{
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub components: Vec<Component>,
+}
+
+impl Component
+{
+    pub fn get_property(&self, name: &str) -> Option<&Property>
+    {
+        self.properties.iter().find(|p| p.name == name)
+    }
+
+    pub fn get_properties<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Property>
+    {
+        self.properties.iter().filter(move |p| p.name == name)
+    }
+
+    /// Gets a property that can be set at most once.
+    fn get_unique_property(&self, name: &'static str) -> Result<Option<&Property>, ICalParseError>
+    {
+        let mut properties = self.get_properties(name);
+        let property = properties.next();
+
+        if properties.next().is_some()
+        {
+            return Err(ICalParseError::DuplicateProperty(name));
+        }
+
+        Ok(property)
+    }
+
+    fn get_text(&self, name: &'static str) -> Result<Option<String>, ICalParseError>
+    {
+        Ok(self.get_unique_property(name)?.map(|p| unescape_text(&p.value)))
+    }
+}
+
+/// Parses an iCalendar file into its top level components,
+/// which are usually VCALENDARs.
+///
+/// Lines can end with CRLF or just LF, folded lines are unfolded.
+pub fn parse(input: &str) -> Result<Vec<Component>, ICalParseError>
+{
+    let mut components = vec![];
+    let mut stack: Vec<Component> = vec![];
+
+    for (number, line) in unfold(input)
+    {
+        let property = parse_line(&line).ok_or(ICalParseError::InvalidLine(number))?;
+
+        match property.name.as_str()
+        {
+            "BEGIN" =>
+            {
+                stack.push(Component {
+                    name: property.value.to_uppercase(),
+                    properties: vec![],
+                    components: vec![],
+                });
+            },
+            "END" =>
+            {
+                let name = property.value.to_uppercase();
+
+                let component = match stack.pop()
+                {
+                    Some(component) if component.name == name => component,
+                    _ => return Err(ICalParseError::UnexpectedEnd(name)),
+                };
+
+                match stack.last_mut()
+                {
+                    Some(parent) => parent.components.push(component),
+                    None => components.push(component),
+                }
+            },
+            _ =>
+            {
+                // Properties outside of any component are ignored.
+                if let Some(component) = stack.last_mut()
+                {
+                    component.properties.push(property);
+                }
+            },
+        }
+    }
+
+    if let Some(component) = stack.pop()
+    {
+        return Err(ICalParseError::UnclosedComponent(component.name));
+    }
+
+    Ok(components)
+}
+
+/// Joins folded lines and removes empty ones. Returns each
+/// line along with the (1-based) number of its first line.
+fn unfold(input: &str) -> Vec<(usize, String)>
+{
+    let mut lines: Vec<(usize, String)> = vec![];
+
+    for (i, line) in input.split('\n').enumerate()
+    {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if line.starts_with(' ') || line.starts_with('\t')
+        {
+            if let Some((_, last)) = lines.last_mut()
+            {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+
+        if !line.is_empty()
+        {
+            lines.push((i + 1, line.to_owned()));
+        }
+    }
+
+    lines
+}
+
+/// Parses a content line (RFC 5545, section 3.1).
+fn parse_line(line: &str) -> Option<Property>
+{
+    // Parameter values can contain colons and semicolons
+    // when they are quoted.
+    let mut quoted = false;
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut value_start = None;
+
+    for (i, c) in line.char_indices()
+    {
+        match c
+        {
+            '"' => quoted = !quoted,
+            ';' if !quoted =>
+            {
+                parts.push(&line[start..i]);
+                start = i + 1;
+            },
+            ':' if !quoted =>
+            {
+                parts.push(&line[start..i]);
+                value_start = Some(i + 1);
+                break;
+            },
+            _ => (),
+        }
+    }
+
+    let value = &line[value_start?..];
+    let mut parts = parts.into_iter();
+
+    let name = parts.next()?.to_uppercase();
+    if name.is_empty()
+    {
+        return None;
+    }
+
+    let mut params = vec![];
+    for param in parts
+    {
+        let mut sides = param.splitn(2, '=');
+        let param_name = sides.next()?.to_uppercase();
+        let param_value = sides.next()?.trim_matches('"');
+
+        params.push((param_name, param_value.to_owned()));
+    }
+
+    Some(Property { name, params, value: value.to_owned() })
+}
+
+/// Unescapes a TEXT value (RFC 5545, section 3.3.11).
+pub fn unescape_text(text: &str) -> String
+{
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next()
+    {
+        if c != '\\'
+        {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next()
+        {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// A DATE or DATE-TIME value, in UTC.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum DateValue
+{
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+impl DateValue
+{
+    fn date(&self) -> NaiveDate
+    {
+        match self
+        {
+            DateValue::Date(date) => *date,
+            DateValue::DateTime(date_time) => date_time.date(),
+        }
+    }
+}
+
+/// Parses the DATE or DATE-TIME values of `property` (more than one
+/// if it's a list, like EXDATE) and converts them to UTC.
+///
+/// Floating date-times (without TZID or a trailing Z) are taken as UTC,
+/// TZIDs must be IANA time zone names (VTIMEZONEs are not read).
+fn parse_dates(property: &Property, name: &'static str) -> Result<Vec<DateValue>, ICalParseError>
+{
+    let tz = match property.get_param("TZID")
+    {
+        Some(tzid) => Some(
+            Tz::from_str(tzid.trim_start_matches('/'))
+                .map_err(|_| ICalParseError::UnknownTimeZone(tzid.to_owned()))?
+        ),
+        None => None,
+    };
+
+    match property.get_param("VALUE")
+    {
+        None | Some("DATE") | Some("DATE-TIME") => (),
+        Some(_) => return Err(ICalParseError::UnsupportedProperty(name)),
+    }
+
+    property.value
+        .split(',')
+        .map(|value|
+        {
+            if value.len() == 8
+            {
+                return NaiveDate::parse_from_str(value, DATE_FORMAT)
+                    .map(DateValue::Date)
+                    .map_err(|_| ICalParseError::InvalidValue(name));
+            }
+
+            if value.ends_with('Z')
+            {
+                return NaiveDateTime::parse_from_str(value, DATE_TIME_FORMAT)
+                    .map(DateValue::DateTime)
+                    .map_err(|_| ICalParseError::InvalidValue(name));
+            }
+
+            let local = NaiveDateTime::parse_from_str(value, LOCAL_DATE_TIME_FORMAT)
+                .map_err(|_| ICalParseError::InvalidValue(name))?;
+
+            match tz
+            {
+                Some(tz) =>
+                {
+                    // Times skipped by a DST change are moved forward,
+                    // ambiguous ones take the first occurrence.
+                    tz.from_local_datetime(&local)
+                        .earliest()
+                        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+                        .map(|date_time| DateValue::DateTime(date_time.naive_utc()))
+                        .ok_or(ICalParseError::InvalidValue(name))
+                },
+                None => Ok(DateValue::DateTime(local)),
+            }
+        })
+        .collect()
+}
+
+fn parse_date(property: &Property, name: &'static str) -> Result<DateValue, ICalParseError>
+{
+    let mut dates = parse_dates(property, name)?;
+
+    if dates.len() != 1
+    {
+        return Err(ICalParseError::InvalidValue(name));
+    }
+
+    Ok(dates.remove(0))
+}
+
+/// Parses a DURATION value (RFC 5545, section 3.3.6), e.g. `P1DT2H30M`.
+fn parse_duration(value: &str) -> Option<Duration>
+{
+    let (sign, value) = match value.chars().next()?
+    {
+        '-' => (-1, &value[1..]),
+        '+' => (1, &value[1..]),
+        _ => (1, value),
+    };
+
+    let mut chars = value.strip_prefix('P')?.chars();
+    let mut duration = Duration::zero();
+    let mut number = String::new();
+    let mut in_time = false;
+
+    while let Some(c) = chars.next()
+    {
+        if c.is_ascii_digit()
+        {
+            number.push(c);
+            continue;
+        }
+
+        if c == 'T'
+        {
+            in_time = true;
+            continue;
+        }
+
+        let n: i64 = number.parse().ok()?;
+        number.clear();
+
+        duration = duration + match (c, in_time)
+        {
+            ('W', false) => Duration::weeks(n),
+            ('D', false) => Duration::days(n),
+            ('H', true) => Duration::hours(n),
+            ('M', true) => Duration::minutes(n),
+            ('S', true) => Duration::seconds(n),
+            _ => return None,
+        };
+    }
+
+    if !number.is_empty()
+    {
+        return None;
+    }
+
+    Some(duration * sign)
+}
+
+/// Makes an RRULE readable by `RecurrenceRule`, which only takes
+/// dates as UNTIL: date-time UNTILs (always in UTC) become the date
+/// they're on.
+fn normalize_rrule(rrule: &str) -> Result<String, ICalParseError>
+{
+    let mut parts = vec![];
+
+    for part in rrule.split(';').filter(|part| !part.is_empty())
+    {
+        let mut sides = part.splitn(2, '=');
+        let name = sides.next().unwrap_or("").to_uppercase();
+        let value = sides.next().ok_or(ICalParseError::InvalidValue("RRULE"))?;
+
+        if name == "UNTIL" && value.len() > 8
+        {
+            let until = NaiveDateTime::parse_from_str(value, DATE_TIME_FORMAT)
+                .or_else(|_| NaiveDateTime::parse_from_str(value, LOCAL_DATE_TIME_FORMAT))
+                .map_err(|_| ICalParseError::InvalidValue("RRULE"))?;
+
+            parts.push(format!("UNTIL={}", until.format(DATE_FORMAT)));
+        }
+        else
+        {
+            parts.push(format!("{}={}", name, value));
+        }
+    }
+
+    let rrule = parts.join(";");
+
+    RecurrenceRule::new(&rrule).map_err(ICalParseError::RRuleParseError)?;
+
+    Ok(rrule)
+}
+
+/// A VEVENT mapped to an event.
+#[derive(Debug)]
+pub struct ICalEvent
+{
+    pub uid: String,
+
+    /// Set if the VEVENT overrides an instance of the recurring
+    /// event with the same UID, `event.recurrence_id` is also set.
+    pub recurrence_id: Option<NaiveDate>,
+
+    /// Whether STATUS is CANCELLED. A cancelled override
+    /// means that the instance it overrides was removed.
+    pub cancelled: bool,
+
+    /// The event, without an id, a parent id or attendees.
+    pub event: EventPlain,
+}
+
+/// Gets the UID of a component, if it has one.
+pub fn get_uid(component: &Component) -> Option<String>
+{
+    component.get_property("UID").map(|p| p.value.clone())
+}
+
+/// Maps a VEVENT to an event.
+///
+/// Everything the server doesn't store (ATTENDEEs, VALARMs, CATEGORIES, ...)
+/// is ignored. EXDATEs and RDATEs only keep their date.
+pub fn parse_event(component: &Component) -> Result<ICalEvent, ICalParseError>
+{
+    let uid = component.get_unique_property("UID")?
+        .map(|p| p.value.clone())
+        .filter(|uid| !uid.is_empty())
+        .ok_or(ICalParseError::MissingProperty("UID"))?;
+
+    let recurrence_id = match component.get_unique_property("RECURRENCE-ID")?
+    {
+        Some(property) =>
+        {
+            if property.get_param("RANGE").is_some()
+            {
+                return Err(ICalParseError::UnsupportedProperty("RECURRENCE-ID;RANGE"));
+            }
+
+            Some(parse_date(property, "RECURRENCE-ID")?.date())
+        },
+        None => None,
+    };
+
+    let start = component.get_unique_property("DTSTART")?
+        .map(|p| parse_date(p, "DTSTART"))
+        .transpose()?
+        .ok_or(ICalParseError::MissingProperty("DTSTART"))?;
+
+    let end = match (component.get_unique_property("DTEND")?, component.get_unique_property("DURATION")?)
+    {
+        (Some(_), Some(_)) => return Err(ICalParseError::DuplicateProperty("DTEND and DURATION")),
+        (Some(property), None) => Some(parse_date(property, "DTEND")?),
+        (None, Some(property)) =>
+        {
+            let duration = parse_duration(&property.value).ok_or(ICalParseError::InvalidValue("DURATION"))?;
+
+            Some(match start
+            {
+                DateValue::Date(date) => DateValue::Date(date + Duration::days(duration.num_days())),
+                DateValue::DateTime(date_time) => DateValue::DateTime(date_time + duration),
+            })
+        },
+        // All-day events without an end last one day.
+        (None, None) => match start
+        {
+            DateValue::Date(date) => Some(DateValue::Date(date + Duration::days(1))),
+            DateValue::DateTime(_) => None,
+        },
+    };
+
+    let mut event = EventPlain {
+        id: None,
+        parent_id: None,
+        recurrence_id,
+        start_date: Some(start.date()),
+        start_time: None,
+        end_date: None,
+        end_time: None,
+        recurrence: None,
+        title: component.get_text("SUMMARY")?,
+        description: component.get_text("DESCRIPTION")?,
+        metadata: None,
+        organizer: None,
+        // Classes we don't know have to be treated as PRIVATE (RFC 5545, section 3.8.1.3).
+        class: component.get_unique_property("CLASS")?
+            .map(|p| EventClass::from_str(&p.value.to_uppercase()).unwrap_or(EventClass::Private)),
+        attendees: None,
+        sequence: None,
+        last_modified: None,
+        deleted_at: None,
+    };
+
+    match (start, end)
+    {
+        (DateValue::Date(start), Some(DateValue::Date(end))) =>
+        {
+            if end < start
+            {
+                return Err(ICalParseError::InvalidValue("DTEND"));
+            }
+
+            event.end_date = Some(end);
+        },
+        (DateValue::DateTime(start), Some(DateValue::DateTime(end))) =>
+        {
+            if end <= start
+            {
+                return Err(ICalParseError::InvalidValue("DTEND"));
+            }
+
+            event.start_time = Some(start.time());
+            event.end_date = Some(end.date());
+            event.end_time = Some(end.time());
+        },
+        // Overrides without an end keep the duration of the instance.
+        (DateValue::DateTime(start), None) if recurrence_id.is_some() =>
+        {
+            event.start_time = Some(start.time());
+        },
+        (DateValue::DateTime(_), None) => return Err(ICalParseError::MissingProperty("DTEND")),
+        (_, Some(_)) => return Err(ICalParseError::InvalidValue("DTEND")),
+        (DateValue::Date(_), None) => unreachable!(),
+    }
+
+    let mut rrules = component.get_properties("RRULE");
+    if let Some(rrule) = rrules.next()
+    {
+        if rrules.next().is_some()
+        {
+            return Err(ICalParseError::DuplicateProperty("RRULE"));
+        }
+
+        if recurrence_id.is_some()
+        {
+            return Err(ICalParseError::UnsupportedProperty("RRULE"));
+        }
+
+        let mut exdates = vec![];
+        for property in component.get_properties("EXDATE")
+        {
+            exdates.extend(parse_dates(property, "EXDATE")?.iter().map(DateValue::date));
+        }
+
+        let mut rdates = vec![];
+        for property in component.get_properties("RDATE")
+        {
+            rdates.extend(parse_dates(property, "RDATE")?.iter().map(DateValue::date));
+        }
+
+        event.recurrence = Some(RecurrencePlain {
+            rrule: Some(normalize_rrule(&rrule.value)?),
+            exdates: Some(exdates),
+            rdates: Some(rdates),
+        });
+    }
+
+    if let Some(organizer) = component.get_unique_property("ORGANIZER")?
+    {
+        let email = organizer.value
+            .get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+            .map(|_| organizer.value[7..].to_owned())
+            .unwrap_or(organizer.value.clone());
+
+        event.organizer = Some(Organizer {
+            email,
+            display_name: organizer.get_param("CN").map(|cn| cn.to_owned()),
+        });
+    }
+
+    let cancelled = component.get_unique_property("STATUS")?
+        .map(|p| p.value.eq_ignore_ascii_case("CANCELLED"))
+        .unwrap_or(false);
+
+    Ok(ICalEvent { uid, recurrence_id, cancelled, event })
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use chrono::NaiveTime;
+
+    fn parse_vevent(lines: &[&str]) -> Result<ICalEvent, ICalParseError>
+    {
+        let input = format!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n", lines.join("\r\n"));
+        let calendars = parse(&input).unwrap();
+
+        parse_event(&calendars[0].components[0])
+    }
+
+    #[test]
+    fn parses_components_and_folded_lines()
+    {
+        let input = "BEGIN:VCALENDAR\nVERSION:2.0\nBEGIN:VEVENT\nSUMMARY:Long\n  title\nDESCRIPTION;ALTREP=\"cid:a;b:c\":x\nEND:VEVENT\nEND:VCALENDAR\n";
+        let calendars = parse(input).unwrap();
+
+        assert_eq!(calendars.len(), 1);
+        assert_eq!(calendars[0].components[0].name, "VEVENT");
+
+        let event = &calendars[0].components[0];
+        assert_eq!(event.get_property("SUMMARY").unwrap().value, "Long title");
+
+        let description = event.get_property("DESCRIPTION").unwrap();
+        assert_eq!(description.get_param("ALTREP"), Some("cid:a;b:c"));
+        assert_eq!(description.value, "x");
+    }
+
+    #[test]
+    fn rejects_unbalanced_components()
+    {
+        assert!(matches!(parse("BEGIN:VCALENDAR\r\n"), Err(ICalParseError::UnclosedComponent(_))));
+        assert!(matches!(parse("BEGIN:VCALENDAR\r\nEND:VEVENT\r\n"), Err(ICalParseError::UnexpectedEnd(_))));
+        assert!(matches!(parse("not a line\r\n"), Err(ICalParseError::InvalidLine(1))));
+    }
+
+    #[test]
+    fn unescapes_text()
+    {
+        assert_eq!(unescape_text("a\\, b\\; c\\\\d\\nnew line"), "a, b; c\\d\nnew line");
+    }
+
+    #[test]
+    fn parses_durations()
+    {
+        assert_eq!(parse_duration("PT1H30M"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("P1W"), Some(Duration::weeks(1)));
+        assert_eq!(parse_duration("-P1DT1S"), Some(-(Duration::days(1) + Duration::seconds(1))));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("PT1"), None);
+    }
+
+    #[test]
+    fn converts_time_zones_to_utc()
+    {
+        let event = parse_vevent(&[
+            "UID:1",
+            "DTSTART;TZID=Europe/Paris:20210201T120000",
+            "DURATION:PT1H",
+        ]).unwrap().event;
+
+        assert_eq!(event.start_date, Some(NaiveDate::from_ymd(2021, 2, 1)));
+        assert_eq!(event.start_time, Some(NaiveTime::from_hms(11, 0, 0)));
+        assert_eq!(event.end_time, Some(NaiveTime::from_hms(12, 0, 0)));
+
+        assert!(matches!(
+            parse_vevent(&["UID:1", "DTSTART;TZID=Mars/Olympus:20210201T120000", "DURATION:PT1H"]),
+            Err(ICalParseError::UnknownTimeZone(_))
+        ));
+    }
+
+    #[test]
+    fn parses_recurring_event()
+    {
+        let parsed = parse_vevent(&[
+            "UID:standup@example.com",
+            "DTSTART:20210201T120000Z",
+            "DTEND:20210201T130000Z",
+            "RRULE:FREQ=WEEKLY;UNTIL=20210301T235959Z;WKST=MO",
+            "EXDATE:20210208T120000Z,20210215T120000Z",
+            "SUMMARY:Standup\\, daily",
+            "ORGANIZER;CN=Jane:mailto:jane@example.com",
+        ]).unwrap();
+
+        assert_eq!(parsed.uid, "standup@example.com");
+        assert_eq!(parsed.recurrence_id, None);
+
+        let recurrence = parsed.event.recurrence.unwrap();
+        assert_eq!(recurrence.rrule.unwrap(), "FREQ=WEEKLY;UNTIL=20210301;WKST=MO");
+        assert_eq!(recurrence.exdates.unwrap(), vec![NaiveDate::from_ymd(2021, 2, 8), NaiveDate::from_ymd(2021, 2, 15)]);
+
+        assert_eq!(parsed.event.title.unwrap(), "Standup, daily");
+
+        let organizer = parsed.event.organizer.unwrap();
+        assert_eq!(organizer.email, "jane@example.com");
+        assert_eq!(organizer.display_name.unwrap(), "Jane");
+    }
+
+    #[test]
+    fn parses_all_day_events()
+    {
+        let event = parse_vevent(&["UID:1", "DTSTART;VALUE=DATE:20210201"]).unwrap().event;
+
+        assert_eq!(event.start_time, None);
+        assert_eq!(event.end_date, Some(NaiveDate::from_ymd(2021, 2, 2)));
+
+        assert!(matches!(
+            parse_vevent(&["UID:1", "DTSTART;VALUE=DATE:20210201", "DTEND:20210202T100000Z"]),
+            Err(ICalParseError::InvalidValue("DTEND"))
+        ));
+    }
+
+    #[test]
+    fn parses_overrides()
+    {
+        let parsed = parse_vevent(&[
+            "UID:standup@example.com",
+            "RECURRENCE-ID:20210208T120000Z",
+            "DTSTART:20210208T140000Z",
+            "STATUS:CANCELLED",
+        ]).unwrap();
+
+        assert_eq!(parsed.recurrence_id, Some(NaiveDate::from_ymd(2021, 2, 8)));
+        assert_eq!(parsed.event.recurrence_id, parsed.recurrence_id);
+        assert_eq!(parsed.event.end_time, None);
+        assert!(parsed.cancelled);
+    }
+
+    #[test]
+    fn parses_classes()
+    {
+        let class = |line| parse_vevent(&["UID:1", "DTSTART;VALUE=DATE:20210201", line]).unwrap().event.class;
+
+        assert_eq!(class("CLASS:confidential"), Some(EventClass::Confidential));
+        assert_eq!(class("CLASS:X-SECRET"), Some(EventClass::Private));
+        assert_eq!(class("SUMMARY:No class"), None);
+    }
+
+    #[test]
+    fn requires_uid_and_end()
+    {
+        assert!(matches!(parse_vevent(&["DTSTART:20210201T120000Z"]), Err(ICalParseError::MissingProperty("UID"))));
+        assert!(matches!(parse_vevent(&["UID:1", "DTSTART:20210201T120000Z"]), Err(ICalParseError::MissingProperty("DTEND"))));
+    }
+}
@@ -0,0 +1,498 @@
+//! Serializes events as an iCalendar VCALENDAR.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Duration};
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::event::{Event, EventSingle, EventRecurring, EventOverride, EventInstance, EventClass};
+use crate::span::EventSpan;
+use super::{PRODID, DATE_FORMAT, DATE_TIME_FORMAT};
+
+/// Maximum length of a content line, in octets (not counting the CRLF).
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Builds a VCALENDAR one VEVENT at a time.
+///
+/// `dtstamp` is the DTSTAMP of every VEVENT, generally the time
+/// the calendar is exported.
+pub struct ICalWriter
+{
+    output: String,
+    dtstamp: NaiveDateTime,
+    uids: HashMap<Uuid, String>,
+}
+
+impl ICalWriter
+{
+    pub fn new(dtstamp: NaiveDateTime) -> ICalWriter
+    {
+        let mut writer = ICalWriter { output: String::new(), dtstamp, uids: HashMap::new() };
+
+        writer.property("BEGIN", "VCALENDAR");
+        writer.property("VERSION", "2.0");
+        writer.property("PRODID", PRODID);
+        writer.property("CALSCALE", "GREGORIAN");
+
+        writer
+    }
+
+    /// Writes `uid` as the UID of the event with id `id` (and of its
+    /// overrides) instead of its id, e.g. to keep the UID an event was
+    /// imported with.
+    pub fn set_uid(&mut self, id: Uuid, uid: String)
+    {
+        self.uids.insert(id, uid);
+    }
+
+    pub fn write_single(&mut self, event: &EventSingle)
+    {
+        self.begin_event(&self.uid(event.get_id()));
+        self.span(&event.get_span());
+        self.text("SUMMARY", event.get_title());
+        self.text("DESCRIPTION", event.get_description());
+        self.class(event.get_class());
+        self.end_event(event.get_last_modified(), event.get_sequence());
+    }
+
+    pub fn write_recurring(&mut self, event: &EventRecurring)
+    {
+        let span = event.get_span();
+        let recurrence = event.get_recurrence();
+
+        self.begin_event(&self.uid(event.get_id()));
+        self.span(&span);
+
+        // UNTIL must have the same value type as DTSTART, we only store
+        // its date so the event recurs until the end of that day.
+        let rrule = recurrence.get_rule().format_with_until(|until| match span.get_start_time()
+        {
+            Some(_) => until.and_hms(23, 59, 59).format(DATE_TIME_FORMAT).to_string(),
+            None => until.format(DATE_FORMAT).to_string(),
+        });
+
+        self.property("RRULE", &rrule);
+        self.date_list("EXDATE", recurrence.get_exdates(), span.get_start_time());
+        self.date_list("RDATE", recurrence.get_rdates(), span.get_start_time());
+
+        self.text("SUMMARY", event.get_title());
+        self.text("DESCRIPTION", event.get_description());
+        self.class(event.get_class());
+        self.end_event(event.get_last_modified(), event.get_sequence());
+    }
+
+    /// Writes the instance `event_override` overrides, with its
+    /// fields merged onto the `parent`'s.
+    pub fn write_override(&mut self, parent: &EventRecurring, event_override: &EventOverride)
+    {
+        let recurrence_id = event_override.get_recurrence_id();
+        let instance = event_override.apply(parent.instance_at(recurrence_id));
+
+        // Overridden instances share the UID of the recurring event.
+        self.begin_event(&self.uid(parent.get_id()));
+
+        match parent.get_span().get_start_time()
+        {
+            Some(time) => self.property("RECURRENCE-ID", &recurrence_id.and_time(time).format(DATE_TIME_FORMAT).to_string()),
+            None => self.property("RECURRENCE-ID;VALUE=DATE", &recurrence_id.format(DATE_FORMAT).to_string()),
+        }
+
+        self.span(&instance.get_span());
+        self.text("SUMMARY", instance.get_title());
+        self.text("DESCRIPTION", instance.get_description());
+        self.class(instance.get_class());
+        self.end_event(event_override.get_last_modified(), event_override.get_sequence());
+    }
+
+    /// Writes an instance of a recurring event as an event of its own,
+    /// for apps that don't understand RRULEs. Its UID is the recurring
+    /// event's id followed by the date of the instance.
+    pub fn write_instance(&mut self, instance: &EventInstance, last_modified: NaiveDateTime, sequence: i32)
+    {
+        let uid = format!("{}-{}", instance.get_parent_id(), instance.get_recurrence_id().format(DATE_FORMAT));
+
+        self.begin_event(&uid);
+        self.span(&instance.get_span());
+        self.text("SUMMARY", instance.get_title());
+        self.text("DESCRIPTION", instance.get_description());
+        self.class(instance.get_class());
+        self.end_event(last_modified, sequence);
+    }
+
+    /// Writes `events`. Overrides are written along with their parent
+    /// event, overrides whose parent is not in `events` are skipped.
+    pub fn write_events(&mut self, events: &[Event])
+    {
+        let parents: HashMap<Uuid, &EventRecurring> = events
+            .iter()
+            .filter_map(|event| match event
+            {
+                Event::Recurring(event) => Some((event.get_id(), event)),
+                _ => None,
+            })
+            .collect();
+
+        for event in events
+        {
+            match event
+            {
+                Event::Single(event) => self.write_single(event),
+                Event::Recurring(event) => self.write_recurring(event),
+                Event::Override(event_override) =>
+                {
+                    if let Some(parent) = parents.get(&event_override.get_parent_id())
+                    {
+                        self.write_override(parent, event_override);
+                    }
+                },
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> String
+    {
+        self.property("END", "VCALENDAR");
+        self.output
+    }
+
+    fn uid(&self, id: Uuid) -> String
+    {
+        self.uids.get(&id).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    fn begin_event(&mut self, uid: &str)
+    {
+        self.property("BEGIN", "VEVENT");
+        self.property("UID", uid);
+        self.property("DTSTAMP", &self.dtstamp.format(DATE_TIME_FORMAT).to_string());
+    }
+
+    fn end_event(&mut self, last_modified: NaiveDateTime, sequence: i32)
+    {
+        self.property("LAST-MODIFIED", &last_modified.format(DATE_TIME_FORMAT).to_string());
+
+        // SEQUENCE defaults to 0 (RFC 5545, section 3.8.7.4).
+        if sequence > 0
+        {
+            self.property("SEQUENCE", &sequence.to_string());
+        }
+
+        self.property("END", "VEVENT");
+    }
+
+    fn span(&mut self, span: &EventSpan)
+    {
+        match span
+        {
+            EventSpan::Date(span) =>
+            {
+                // DTEND is exclusive, an all-day event lasts at least a day.
+                let end = span.end.max(span.start + Duration::days(1));
+
+                self.property("DTSTART;VALUE=DATE", &span.start.format(DATE_FORMAT).to_string());
+                self.property("DTEND;VALUE=DATE", &end.format(DATE_FORMAT).to_string());
+            },
+            EventSpan::DateTime(span) =>
+            {
+                self.property("DTSTART", &span.start.format(DATE_TIME_FORMAT).to_string());
+                self.property("DTEND", &span.end.format(DATE_TIME_FORMAT).to_string());
+            },
+        }
+    }
+
+    /// Writes EXDATE or RDATE. If the event has a start time the dates
+    /// are written as date-times at that time, like DTSTART.
+    fn date_list(&mut self, name: &str, dates: &[NaiveDate], start_time: Option<NaiveTime>)
+    {
+        if dates.is_empty()
+        {
+            return;
+        }
+
+        match start_time
+        {
+            Some(time) =>
+            {
+                let value = dates.iter()
+                    .map(|date| date.and_time(time).format(DATE_TIME_FORMAT).to_string())
+                    .collect::<Vec<String>>()
+                    .join(",");
+
+                self.property(name, &value);
+            },
+            None =>
+            {
+                let value = dates.iter()
+                    .map(|date| date.format(DATE_FORMAT).to_string())
+                    .collect::<Vec<String>>()
+                    .join(",");
+
+                self.property(&format!("{};VALUE=DATE", name), &value);
+            },
+        }
+    }
+
+    /// PUBLIC is the default CLASS, so it's left out.
+    fn class(&mut self, class: EventClass)
+    {
+        if class != EventClass::Public
+        {
+            self.property("CLASS", &class.to_string());
+        }
+    }
+
+    fn text(&mut self, name: &str, value: Option<&String>)
+    {
+        if let Some(value) = value
+        {
+            self.property(name, &escape_text(value));
+        }
+    }
+
+    fn property(&mut self, name: &str, value: &str)
+    {
+        self.output.push_str(&fold_line(&format!("{}:{}", name, value)));
+    }
+}
+
+/// Exports `events` as a VCALENDAR. Overrides are written along with
+/// their parent event, overrides whose parent is not in `events` are
+/// skipped.
+pub fn write_calendar(events: &[Event], dtstamp: NaiveDateTime) -> String
+{
+    let mut writer = ICalWriter::new(dtstamp);
+    writer.write_events(events);
+    writer.finish()
+}
+
+/// Escapes a TEXT value (RFC 5545, section 3.3.11).
+pub fn escape_text(text: &str) -> String
+{
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars()
+    {
+        match c
+        {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Folds a content line so that no line is longer than 75 octets
+/// (RFC 5545, section 3.1), and terminates it with a CRLF.
+///
+/// Lines are never split in the middle of a UTF-8 character.
+pub fn fold_line(line: &str) -> String
+{
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut length = 0;
+
+    for c in line.chars()
+    {
+        if length + c.len_utf8() > MAX_LINE_LENGTH
+        {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+
+        folded.push(c);
+        length += c.len_utf8();
+    }
+
+    folded.push_str("\r\n");
+    folded
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::event::EventPlain;
+    use std::convert::TryFrom;
+    use serde_json::json;
+
+    fn event(value: serde_json::Value) -> Event
+    {
+        let plain: EventPlain = serde_json::from_value(value).unwrap();
+        Event::try_from(plain).unwrap()
+    }
+
+    fn dtstamp() -> NaiveDateTime
+    {
+        NaiveDate::from_ymd(2021, 3, 1).and_hms(10, 0, 0)
+    }
+
+    #[test]
+    fn escapes_text()
+    {
+        assert_eq!(escape_text("a, b; c\\d\nnew line"), "a\\, b\\; c\\\\d\\nnew line");
+    }
+
+    #[test]
+    fn folds_long_lines()
+    {
+        let line = format!("DESCRIPTION:{}", "é".repeat(50));
+        let folded = fold_line(&line);
+
+        for part in folded.split("\r\n").filter(|l| !l.is_empty())
+        {
+            assert!(part.len() <= MAX_LINE_LENGTH);
+        }
+
+        assert_eq!(folded.replace("\r\n ", ""), format!("{}\r\n", line));
+    }
+
+    #[test]
+    fn writes_recurring_event_with_override()
+    {
+        let id = "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60";
+
+        let events = vec![
+            event(json!({
+                "id": id,
+                "start_date": "2021-02-01",
+                "start_time": "12:00",
+                "end_date": "2021-02-01",
+                "end_time": "13:00",
+                "recurrence": { "rrule": "FREQ=WEEKLY;UNTIL=20210301", "exdates": ["2021-02-15"], "rdates": [] },
+                "title": "Standup, daily",
+                "last_modified": "2021-01-20T08:30",
+            })),
+            event(json!({
+                "id": "0f1e2d3c-4b5a-4968-8776-655443322110",
+                "parent_id": id,
+                "recurrence_id": "2021-02-08",
+                "start_time": "14:00",
+                "last_modified": "2021-01-21T09:00",
+            })),
+        ];
+
+        let ics = write_calendar(&events, dtstamp());
+        let lines: Vec<&str> = ics.split("\r\n").collect();
+
+        assert_eq!(lines[0], "BEGIN:VCALENDAR");
+        assert!(lines.contains(&"RRULE:FREQ=WEEKLY;UNTIL=20210301T235959Z"));
+        assert!(lines.contains(&"EXDATE:20210215T120000Z"));
+        assert!(lines.contains(&"SUMMARY:Standup\\, daily"));
+        assert!(lines.contains(&"RECURRENCE-ID:20210208T120000Z"));
+        assert!(lines.contains(&"DTSTART:20210208T140000Z"));
+        assert!(lines.contains(&"DTEND:20210208T150000Z"));
+        assert!(lines.contains(&"LAST-MODIFIED:20210121T090000Z"));
+        assert_eq!(lines.iter().filter(|l| **l == format!("UID:{}", id)).count(), 2);
+        assert_eq!(lines[lines.len() - 2], "END:VCALENDAR");
+    }
+
+    #[test]
+    fn writes_all_day_event()
+    {
+        let events = vec![
+            event(json!({
+                "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+                "start_date": "2021-02-01",
+                "end_date": "2021-02-03",
+                "last_modified": "2021-01-20T08:30",
+            })),
+        ];
+
+        let ics = write_calendar(&events, dtstamp());
+
+        assert!(ics.contains("DTSTART;VALUE=DATE:20210201\r\n"));
+        assert!(ics.contains("DTEND;VALUE=DATE:20210203\r\n"));
+        assert!(ics.contains("DTSTAMP:20210301T100000Z\r\n"));
+    }
+
+    #[test]
+    fn writes_until_as_date_for_all_day_events()
+    {
+        let events = vec![
+            event(json!({
+                "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+                "start_date": "2021-02-01",
+                "end_date": "2021-02-02",
+                "recurrence": { "rrule": "FREQ=DAILY;INTERVAL=2;UNTIL=20210301", "exdates": [], "rdates": [] },
+                "last_modified": "2021-01-20T08:30",
+            })),
+        ];
+
+        assert!(write_calendar(&events, dtstamp()).contains("RRULE:FREQ=DAILY;INTERVAL=2;UNTIL=20210301\r\n"));
+    }
+
+    #[test]
+    fn writes_sequence_once_changed()
+    {
+        let event_json = |sequence: i32| json!({
+            "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+            "start_date": "2021-02-01",
+            "end_date": "2021-02-01",
+            "sequence": sequence,
+            "last_modified": "2021-01-20T08:30",
+        });
+
+        assert!(!write_calendar(&[event(event_json(0))], dtstamp()).contains("SEQUENCE"));
+        assert!(write_calendar(&[event(event_json(3))], dtstamp()).contains("SEQUENCE:3\r\n"));
+    }
+
+    #[test]
+    fn writes_instance_as_event()
+    {
+        let recurring = match event(json!({
+            "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+            "start_date": "2021-02-01",
+            "start_time": "12:00",
+            "end_date": "2021-02-01",
+            "end_time": "13:00",
+            "recurrence": { "rrule": "FREQ=WEEKLY", "exdates": [], "rdates": [] },
+            "last_modified": "2021-01-20T08:30",
+        }))
+        {
+            Event::Recurring(event) => event,
+            _ => unreachable!(),
+        };
+
+        let mut writer = ICalWriter::new(dtstamp());
+        writer.write_instance(&recurring.instance_at(NaiveDate::from_ymd(2021, 2, 8)), recurring.get_last_modified(), recurring.get_sequence());
+        let ics = writer.finish();
+
+        assert!(ics.contains("UID:a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60-20210208\r\n"));
+        assert!(ics.contains("DTSTART:20210208T120000Z\r\n"));
+        assert!(!ics.contains("RRULE"));
+        assert!(!ics.contains("RECURRENCE-ID"));
+    }
+
+    #[test]
+    fn writes_uid_set_for_event()
+    {
+        let id = "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60";
+
+        let events = vec![
+            event(json!({
+                "id": id,
+                "start_date": "2021-02-01",
+                "end_date": "2021-02-01",
+                "recurrence": { "rrule": "FREQ=WEEKLY", "exdates": [], "rdates": [] },
+                "last_modified": "2021-01-20T08:30",
+            })),
+            event(json!({
+                "id": "0f1e2d3c-4b5a-4968-8776-655443322110",
+                "parent_id": id,
+                "recurrence_id": "2021-02-08",
+                "title": "Moved",
+                "last_modified": "2021-01-21T09:00",
+            })),
+        ];
+
+        let mut writer = ICalWriter::new(dtstamp());
+        writer.set_uid(Uuid::parse_str(id).unwrap(), "imported@example.com".to_owned());
+        writer.write_events(&events);
+        let ics = writer.finish();
+
+        assert_eq!(ics.matches("UID:imported@example.com\r\n").count(), 2);
+        assert!(!ics.contains(id));
+    }
+}
@@ -0,0 +1,471 @@
+//! JSCalendar (RFC 8984) representation of events.
+//!
+//! Everything is in UTC (see `event`), so events with a time have a
+//! `timeZone` of `Etc/UTC` and all-day events are floating, with
+//! `showWithoutTime` set.
+
+use chrono::{NaiveDateTime, NaiveTime, Duration, Weekday};
+use serde_json::{Value, Map, json};
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::calendar::Calendar;
+use crate::event::{Event, EventSingle, EventRecurring, EventOverride, EventInstance, EventClass};
+use crate::recurrence::{RecurrenceRule, RecurrenceLimit};
+use crate::span::EventSpan;
+
+/// Time zone of events with a time.
+pub const TIME_ZONE: &str = "Etc/UTC";
+
+/// Format of LocalDateTime values.
+const LOCAL_DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// Format of UTCDateTime values.
+const UTC_DATE_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// Converts `events` to JSCalendar Event objects. Overrides become
+/// `recurrenceOverrides` of their parent event, overrides whose parent
+/// is not in `events` are skipped.
+pub fn write_events(events: &[Event]) -> Vec<Value>
+{
+    let mut overrides: HashMap<Uuid, Vec<&EventOverride>> = HashMap::new();
+
+    for event in events
+    {
+        if let Event::Override(event_override) = event
+        {
+            overrides.entry(event_override.get_parent_id()).or_default().push(event_override);
+        }
+    }
+
+    events
+        .iter()
+        .filter_map(|event| match event
+        {
+            Event::Single(event) => Some(write_single(event)),
+            Event::Recurring(event) => Some(write_recurring(event, overrides.get(&event.get_id()).map(|o| o.as_slice()).unwrap_or(&[]))),
+            Event::Override(_) => None,
+        })
+        .collect()
+}
+
+/// Converts a calendar and its events to a JSCalendar Group whose
+/// `uid` is the id of the calendar.
+pub fn write_group(calendar: &Calendar, events: &[Event]) -> Value
+{
+    let mut object = Map::new();
+
+    object.insert("@type".to_owned(), json!("Group"));
+    object.insert("uid".to_owned(), json!(calendar.id.to_string()));
+    text(&mut object, "title", calendar.name.as_ref());
+    text(&mut object, "description", calendar.description.as_ref());
+
+    if let Some(color) = &calendar.color
+    {
+        object.insert("color".to_owned(), json!(color));
+    }
+
+    if let Some(last_modified) = calendar.last_modified
+    {
+        object.insert("updated".to_owned(), json!(last_modified.format(UTC_DATE_TIME_FORMAT).to_string()));
+    }
+
+    object.insert("entries".to_owned(), json!(write_events(events)));
+
+    Value::Object(object)
+}
+
+pub fn write_single(event: &EventSingle) -> Value
+{
+    let mut object = Map::new();
+
+    object.insert("@type".to_owned(), json!("Event"));
+    object.insert("uid".to_owned(), json!(event.get_id().to_string()));
+    object.insert("updated".to_owned(), json!(event.get_last_modified().format(UTC_DATE_TIME_FORMAT).to_string()));
+    text(&mut object, "title", event.get_title());
+    text(&mut object, "description", event.get_description());
+    privacy(&mut object, event.get_class());
+    sequence(&mut object, event.get_sequence());
+    span(&mut object, &event.get_span());
+
+    Value::Object(object)
+}
+
+/// Converts a recurring event along with its `overrides`. Exdates are
+/// excluded overrides, rdates empty ones (RFC 8984, section 4.3.5).
+pub fn write_recurring(event: &EventRecurring, overrides: &[&EventOverride]) -> Value
+{
+    let span = event.get_span();
+    let recurrence = event.get_recurrence();
+    let start_time = span.get_start_time().unwrap_or_else(|| NaiveTime::from_hms(0, 0, 0));
+
+    let mut object = Map::new();
+
+    object.insert("@type".to_owned(), json!("Event"));
+    object.insert("uid".to_owned(), json!(event.get_id().to_string()));
+    object.insert("updated".to_owned(), json!(event.get_last_modified().format(UTC_DATE_TIME_FORMAT).to_string()));
+    text(&mut object, "title", event.get_title());
+    text(&mut object, "description", event.get_description());
+    privacy(&mut object, event.get_class());
+    sequence(&mut object, event.get_sequence());
+    self::span(&mut object, &span);
+    object.insert("recurrenceRules".to_owned(), json!([recurrence_rule(recurrence.get_rule())]));
+
+    let mut recurrence_overrides = Map::new();
+
+    for date in recurrence.get_rdates()
+    {
+        recurrence_overrides.insert(local_date_time(date.and_time(start_time)), json!({}));
+    }
+
+    for event_override in overrides
+    {
+        let recurrence_id = event_override.get_recurrence_id();
+        let instance = event.instance_at(recurrence_id);
+        let patch = patch(&instance, &event_override.apply(instance.clone()));
+
+        recurrence_overrides.insert(local_date_time(recurrence_id.and_time(start_time)), patch);
+    }
+
+    for date in recurrence.get_exdates()
+    {
+        recurrence_overrides.insert(local_date_time(date.and_time(start_time)), json!({ "excluded": true }));
+    }
+
+    if !recurrence_overrides.is_empty()
+    {
+        object.insert("recurrenceOverrides".to_owned(), Value::Object(recurrence_overrides));
+    }
+
+    Value::Object(object)
+}
+
+/// Builds the PatchObject that turns `instance` into `overridden`.
+fn patch(instance: &EventInstance, overridden: &EventInstance) -> Value
+{
+    let mut before = Map::new();
+    let mut after = Map::new();
+
+    span(&mut before, &instance.get_span());
+    span(&mut after, &overridden.get_span());
+
+    before.insert("title".to_owned(), json!(instance.get_title()));
+    after.insert("title".to_owned(), json!(overridden.get_title()));
+    before.insert("description".to_owned(), json!(instance.get_description()));
+    after.insert("description".to_owned(), json!(overridden.get_description()));
+
+    let mut patch = Map::new();
+
+    // Properties that are no longer there (e.g. `showWithoutTime` when
+    // an override gives an all-day instance a time) are patched to null.
+    for key in before.keys()
+    {
+        if !after.contains_key(key)
+        {
+            patch.insert(key.clone(), Value::Null);
+        }
+    }
+
+    for (key, value) in after
+    {
+        if before.get(&key) != Some(&value)
+        {
+            patch.insert(key, value);
+        }
+    }
+
+    Value::Object(patch)
+}
+
+fn text(object: &mut Map<String, Value>, key: &str, value: Option<&String>)
+{
+    if let Some(value) = value
+    {
+        object.insert(key.to_owned(), json!(value));
+    }
+}
+
+/// Writes `privacy` (RFC 8984, section 4.4.3), left out for public events
+/// since it's the default. CONFIDENTIAL events are "secret" ones.
+fn privacy(object: &mut Map<String, Value>, class: EventClass)
+{
+    let privacy = match class
+    {
+        EventClass::Public => return,
+        EventClass::Private => "private",
+        EventClass::Confidential => "secret",
+    };
+
+    object.insert("privacy".to_owned(), json!(privacy));
+}
+
+/// Writes `sequence` (RFC 8984, section 4.1.7), left out
+/// until the event changes since it defaults to 0.
+fn sequence(object: &mut Map<String, Value>, sequence: i32)
+{
+    if sequence > 0
+    {
+        object.insert("sequence".to_owned(), json!(sequence));
+    }
+}
+
+/// Writes `start`, `duration` and either `timeZone` or `showWithoutTime`.
+fn span(object: &mut Map<String, Value>, span: &EventSpan)
+{
+    match span
+    {
+        EventSpan::Date(span) =>
+        {
+            // Like DTEND, an all-day event lasts at least a day.
+            let duration = (span.end - span.start).max(Duration::days(1));
+
+            object.insert("start".to_owned(), json!(local_date_time(span.start.and_hms(0, 0, 0))));
+            object.insert("duration".to_owned(), json!(format_duration(duration)));
+            object.insert("showWithoutTime".to_owned(), json!(true));
+        },
+        EventSpan::DateTime(span) =>
+        {
+            object.insert("start".to_owned(), json!(local_date_time(span.start)));
+            object.insert("duration".to_owned(), json!(format_duration(span.end - span.start)));
+            object.insert("timeZone".to_owned(), json!(TIME_ZONE));
+        },
+    }
+}
+
+/// Converts a recurrence rule to a RecurrenceRule object (RFC 8984,
+/// section 4.3.3). UNTIL is a date, the rule recurs until the end of it.
+pub fn recurrence_rule(rule: &RecurrenceRule) -> Value
+{
+    let mut object = Map::new();
+
+    object.insert("@type".to_owned(), json!("RecurrenceRule"));
+    object.insert("frequency".to_owned(), json!(rule.frequency.to_string().to_lowercase()));
+
+    if rule.interval > 1
+    {
+        object.insert("interval".to_owned(), json!(rule.interval));
+    }
+
+    match rule.limit
+    {
+        RecurrenceLimit::Indefinite => (),
+        RecurrenceLimit::Date(until) => { object.insert("until".to_owned(), json!(local_date_time(until.and_hms(23, 59, 59)))); },
+        RecurrenceLimit::Count(count) => { object.insert("count".to_owned(), json!(count)); },
+    }
+
+    if let Some(by_day) = &rule.by_day
+    {
+        let days: Vec<Value> = by_day.iter().map(|day| json!({ "@type": "NDay", "day": weekday(*day) })).collect();
+        object.insert("byDay".to_owned(), json!(days));
+    }
+
+    if let Some(by_month) = &rule.by_month
+    {
+        let months: Vec<String> = by_month.iter().map(|month| month.number_from_month().to_string()).collect();
+        object.insert("byMonth".to_owned(), json!(months));
+    }
+
+    for (key, values) in vec![
+        ("byMonthDay", &rule.by_month_day),
+        ("byYearDay", &rule.by_year_day),
+        ("byWeekNo", &rule.by_week_no),
+        ("bySetPosition", &rule.by_set_pos),
+    ]
+    {
+        if let Some(values) = values
+        {
+            object.insert(key.to_owned(), json!(values));
+        }
+    }
+
+    Value::Object(object)
+}
+
+fn weekday(day: Weekday) -> &'static str
+{
+    match day
+    {
+        Weekday::Mon => "mo",
+        Weekday::Tue => "tu",
+        Weekday::Wed => "we",
+        Weekday::Thu => "th",
+        Weekday::Fri => "fr",
+        Weekday::Sat => "sa",
+        Weekday::Sun => "su",
+    }
+}
+
+fn local_date_time(date_time: NaiveDateTime) -> String
+{
+    date_time.format(LOCAL_DATE_TIME_FORMAT).to_string()
+}
+
+/// Formats a (positive) duration as an ISO 8601 duration,
+/// e.g. `P1DT2H30M`.
+pub fn format_duration(duration: Duration) -> String
+{
+    let days = duration.num_days();
+    let seconds = (duration - Duration::days(days)).num_seconds();
+
+    let (hours, minutes, seconds) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+
+    let mut formatted = "P".to_owned();
+
+    if days > 0
+    {
+        formatted.push_str(&format!("{}D", days));
+    }
+
+    if hours > 0 || minutes > 0 || seconds > 0
+    {
+        formatted.push('T');
+
+        if hours > 0 { formatted.push_str(&format!("{}H", hours)); }
+        if minutes > 0 { formatted.push_str(&format!("{}M", minutes)); }
+        if seconds > 0 { formatted.push_str(&format!("{}S", seconds)); }
+    }
+
+    if formatted == "P"
+    {
+        formatted.push_str("T0S");
+    }
+
+    formatted
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::event::EventPlain;
+    use std::convert::TryFrom;
+
+    fn event(value: Value) -> Event
+    {
+        let plain: EventPlain = serde_json::from_value(value).unwrap();
+        Event::try_from(plain).unwrap()
+    }
+
+    #[test]
+    fn formats_durations()
+    {
+        assert_eq!(format_duration(Duration::hours(1)), "PT1H");
+        assert_eq!(format_duration(Duration::days(1) + Duration::minutes(90)), "P1DT1H30M");
+        assert_eq!(format_duration(Duration::days(2)), "P2D");
+        assert_eq!(format_duration(Duration::zero()), "PT0S");
+    }
+
+    #[test]
+    fn writes_single_all_day_event()
+    {
+        let events = vec![
+            event(json!({
+                "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+                "start_date": "2021-02-01",
+                "end_date": "2021-02-01",
+                "title": "Holiday",
+                "last_modified": "2021-01-20T08:30",
+            })),
+        ];
+
+        assert_eq!(write_events(&events), vec![json!({
+            "@type": "Event",
+            "uid": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+            "updated": "2021-01-20T08:30:00Z",
+            "title": "Holiday",
+            "start": "2021-02-01T00:00:00",
+            "duration": "P1D",
+            "showWithoutTime": true,
+        })]);
+    }
+
+    #[test]
+    fn writes_recurring_event_with_overrides()
+    {
+        let id = "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60";
+
+        let events = vec![
+            event(json!({
+                "id": id,
+                "start_date": "2021-02-01",
+                "start_time": "12:00",
+                "end_date": "2021-02-01",
+                "end_time": "13:00",
+                "recurrence": { "rrule": "FREQ=WEEKLY;BYDAY=MO;UNTIL=20210301", "exdates": ["2021-02-15"], "rdates": ["2021-02-03"] },
+                "title": "Standup",
+                "last_modified": "2021-01-20T08:30",
+            })),
+            event(json!({
+                "id": "0f1e2d3c-4b5a-4968-8776-655443322110",
+                "parent_id": id,
+                "recurrence_id": "2021-02-08",
+                "start_time": "14:00",
+                "title": "Late standup",
+                "last_modified": "2021-01-21T09:00",
+            })),
+        ];
+
+        let written = write_events(&events);
+
+        assert_eq!(written.len(), 1);
+        assert_eq!(written[0]["timeZone"], "Etc/UTC");
+        assert_eq!(written[0]["start"], "2021-02-01T12:00:00");
+        assert_eq!(written[0]["duration"], "PT1H");
+        assert_eq!(written[0]["recurrenceRules"], json!([{
+            "@type": "RecurrenceRule",
+            "frequency": "weekly",
+            "until": "2021-03-01T23:59:59",
+            "byDay": [{ "@type": "NDay", "day": "mo" }],
+        }]));
+        assert_eq!(written[0]["recurrenceOverrides"], json!({
+            "2021-02-03T12:00:00": {},
+            "2021-02-08T12:00:00": { "start": "2021-02-08T14:00:00", "title": "Late standup" },
+            "2021-02-15T12:00:00": { "excluded": true },
+        }));
+    }
+
+    #[test]
+    fn writes_privacy()
+    {
+        let events = vec![
+            event(json!({
+                "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+                "start_date": "2021-02-01",
+                "end_date": "2021-02-01",
+                "class": "CONFIDENTIAL",
+                "last_modified": "2021-01-20T08:30",
+            })),
+        ];
+
+        assert_eq!(write_events(&events)[0]["privacy"], "secret");
+    }
+
+    #[test]
+    fn writes_sequence()
+    {
+        let events = vec![
+            event(json!({
+                "id": "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60",
+                "start_date": "2021-02-01",
+                "end_date": "2021-02-01",
+                "sequence": 2,
+                "last_modified": "2021-01-20T08:30",
+            })),
+        ];
+
+        assert_eq!(write_events(&events)[0]["sequence"], 2);
+    }
+
+    #[test]
+    fn writes_group()
+    {
+        let calendar_id = Uuid::parse_str("0f1e2d3c-4b5a-4968-8776-655443322110").unwrap();
+        let calendar = Calendar { name: Some("Work".to_owned()), ..Calendar::new(calendar_id) };
+
+        assert_eq!(write_group(&calendar, &[]), json!({
+            "@type": "Group",
+            "uid": calendar_id.to_string(),
+            "title": "Work",
+            "entries": [],
+        }));
+    }
+}
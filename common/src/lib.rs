@@ -2,6 +2,19 @@ pub mod recurrence;
 pub mod event;
 pub mod calendar;
 pub mod span;
+pub mod attendee;
+pub mod alarm;
+pub mod webhook;
+pub mod sync;
+pub mod ical;
+pub mod feed;
+pub mod subscription;
+pub mod jscalendar;
+pub mod tenant;
+pub mod scope;
+pub mod acl;
+pub mod audit;
+pub mod merge_patch;
 
 #[macro_use] extern crate schemars;
 #[macro_use] extern crate serde;
@@ -0,0 +1,92 @@
+//! JSON Merge Patch (RFC 7396).
+//!
+//! Every member of a patch is in one of three states: absent members leave
+//! the target's member as it is, `null` members remove it, and any other
+//! value replaces it. Objects are merged recursively, everything else
+//! (arrays included) is replaced as a whole.
+
+use serde_json::{Map, Value};
+
+/// Applies `patch` to `target`.
+pub fn merge_patch(target: &mut Value, patch: &Value)
+{
+    let patch = match patch
+    {
+        Value::Object(patch) => patch,
+        patch =>
+        {
+            *target = patch.clone();
+            return;
+        },
+    };
+
+    if !target.is_object()
+    {
+        *target = Value::Object(Map::new());
+    }
+
+    if let Value::Object(target) = target
+    {
+        for (key, value) in patch
+        {
+            if value.is_null()
+            {
+                target.remove(key);
+            }
+            else
+            {
+                merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use serde_json::json;
+
+    fn patched(mut target: Value, patch: Value) -> Value
+    {
+        merge_patch(&mut target, &patch);
+        target
+    }
+
+    #[test]
+    fn members_are_left_removed_or_replaced()
+    {
+        assert_eq!(
+            patched(json!({"a": "b", "c": "d", "e": "f"}), json!({"a": "z", "c": null})),
+            json!({"a": "z", "e": "f"})
+        );
+    }
+
+    #[test]
+    fn objects_merge_recursively_and_arrays_are_replaced()
+    {
+        assert_eq!(
+            patched(
+                json!({"recurrence": {"rrule": "FREQ=DAILY", "exdates": ["2021-03-01"]}}),
+                json!({"recurrence": {"exdates": []}})
+            ),
+            json!({"recurrence": {"rrule": "FREQ=DAILY", "exdates": []}})
+        );
+
+        assert_eq!(patched(json!({"a": [1, 2]}), json!({"a": [3]})), json!({"a": [3]}));
+    }
+
+    #[test]
+    fn nulls_are_never_added()
+    {
+        assert_eq!(patched(json!({"a": null}), json!({"a": {"b": null, "c": 1}})), json!({"a": {"c": 1}}));
+        assert_eq!(patched(json!({}), json!({"a": null})), json!({}));
+    }
+
+    #[test]
+    fn non_object_patches_replace_the_target()
+    {
+        assert_eq!(patched(json!({"a": "b"}), json!(["c"])), json!(["c"]));
+        assert_eq!(patched(json!(["a"]), json!({"b": 1})), json!({"b": 1}));
+    }
+}
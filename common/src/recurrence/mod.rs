@@ -66,11 +66,12 @@ impl RecurrenceRule
         let rule = parser::parse(rrule)?;
         Ok(rule)
     }
-}
 
-impl Display for RecurrenceRule
-{
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    /// Formats the rule as an RRULE, like `Display` does, with `format_until`
+    /// writing the value of UNTIL. RFC 5545 wants UNTIL to have the same value
+    /// type as DTSTART, which the rule doesn't know about.
+    pub fn format_with_until<F>(&self, format_until: F) -> String
+        where F: Fn(NaiveDate) -> String
     {
         let freq = format!("FREQ={}", self.frequency);
 
@@ -124,17 +125,23 @@ impl Display for RecurrenceRule
         let limit = match self.limit
         {
             RecurrenceLimit::Indefinite => None,
-            RecurrenceLimit::Date(date) => Some(format!("UNTIL={}", date.format("%Y%m%d"))),
+            RecurrenceLimit::Date(date) => Some(format!("UNTIL={}", format_until(date))),
             RecurrenceLimit::Count(count) => Some(format!("COUNT={}", count)),
         };
 
-        let string = vec![Some(freq), interval, by_year_day, by_day, by_week_no, by_month_day, by_set_pos, by_month, limit]
+        vec![Some(freq), interval, by_year_day, by_day, by_week_no, by_month_day, by_set_pos, by_month, limit]
             .into_iter()
             .filter_map(|x| x)
             .collect::<Vec<String>>()
-            .join(";");
+            .join(";")
+    }
+}
 
-        f.write_str(&string)
+impl Display for RecurrenceRule
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        f.write_str(&self.format_with_until(|until| until.format("%Y%m%d").to_string()))
     }
 }
 
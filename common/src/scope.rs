@@ -0,0 +1,135 @@
+//! Scopes of API keys, which decide what routes a key can use.
+//! See docs/server/scopes.md.
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum Scope
+{
+    /// Reading calendars and everything in them.
+    #[serde(rename = "READ")]
+    Read,
+
+    /// Creating, changing and deleting calendars and everything
+    /// in them. Keys with this scope can also read.
+    #[serde(rename = "WRITE")]
+    Write,
+
+    /// Everything, including the admin routes.
+    #[serde(rename = "SUPER")]
+    Super,
+}
+
+impl Scope
+{
+    fn bit(self) -> u8
+    {
+        match self
+        {
+            Scope::Read => 0b001,
+            Scope::Write => 0b010,
+            Scope::Super => 0b100,
+        }
+    }
+}
+
+impl Display for Scope
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            Scope::Read => "READ",
+            Scope::Write => "WRITE",
+            Scope::Super => "SUPER",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for Scope
+{
+    type Err = InvalidScope;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "READ" => Ok(Scope::Read),
+            "WRITE" => Ok(Scope::Write),
+            "SUPER" => Ok(Scope::Super),
+            _ => Err(InvalidScope),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid scope.")]
+pub struct InvalidScope;
+
+/// The scopes of an API key, as a bitmask.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Default)]
+pub struct Scopes(u8);
+
+impl Scopes
+{
+    /// Parses the scopes of an API key as stored in the database.
+    /// Scopes we don't know about are ignored.
+    pub fn parse<S: AsRef<str>>(scopes: &[S]) -> Scopes
+    {
+        Scopes(
+            scopes.iter()
+                .filter_map(|scope| Scope::from_str(scope.as_ref()).ok())
+                .fold(0, |bits, scope| bits | scope.bit())
+        )
+    }
+
+    pub fn contains(self, scope: Scope) -> bool
+    {
+        self.0 & scope.bit() != 0
+    }
+
+    /// Checks if the scopes allow what `scope` does: `SUPER`
+    /// allows everything and `WRITE` also allows reading.
+    pub fn allows(self, scope: Scope) -> bool
+    {
+        match scope
+        {
+            Scope::Read => self.contains(Scope::Read) || self.allows(Scope::Write),
+            Scope::Write => self.contains(Scope::Write) || self.allows(Scope::Super),
+            Scope::Super => self.contains(Scope::Super),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn parses_scopes()
+    {
+        let scopes = Scopes::parse(&["READ", "read", "ADMIN"]);
+
+        assert!(scopes.contains(Scope::Read));
+        assert!(!scopes.contains(Scope::Write));
+        assert!(!scopes.contains(Scope::Super));
+        assert_eq!(Scopes::parse::<&str>(&[]), Scopes::default());
+    }
+
+    #[test]
+    fn higher_scopes_allow_lower_ones()
+    {
+        let read = Scopes::parse(&["READ"]);
+        let write = Scopes::parse(&["WRITE"]);
+        let super_ = Scopes::parse(&["SUPER"]);
+
+        assert!(read.allows(Scope::Read) && !read.allows(Scope::Write) && !read.allows(Scope::Super));
+        assert!(write.allows(Scope::Read) && write.allows(Scope::Write) && !write.allows(Scope::Super));
+        assert!(super_.allows(Scope::Read) && super_.allows(Scope::Write) && super_.allows(Scope::Super));
+        assert!(!Scopes::default().allows(Scope::Read));
+    }
+}
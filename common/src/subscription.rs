@@ -0,0 +1,165 @@
+//! Subscribed calendars, read-only calendars that mirror
+//! an external iCalendar file.
+
+use chrono::NaiveDateTime;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Default amount of minutes between two syncs of a subscription.
+pub const DEFAULT_REFRESH_INTERVAL: i32 = 60;
+
+/// Result of the last sync of a subscription.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum SyncStatus
+{
+    /// Never synced yet.
+    #[serde(rename = "PENDING")]
+    Pending,
+
+    #[serde(rename = "OK")]
+    Ok,
+
+    /// The file couldn't be fetched or parsed, the calendar
+    /// keeps the events of the last successful sync.
+    #[serde(rename = "FAILED")]
+    Failed,
+}
+
+impl Default for SyncStatus
+{
+    fn default() -> Self { SyncStatus::Pending }
+}
+
+impl Display for SyncStatus
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            SyncStatus::Pending => "PENDING",
+            SyncStatus::Ok => "OK",
+            SyncStatus::Failed => "FAILED",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for SyncStatus
+{
+    type Err = InvalidSyncStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "PENDING" => Ok(SyncStatus::Pending),
+            "OK" => Ok(SyncStatus::Ok),
+            "FAILED" => Ok(SyncStatus::Failed),
+            _ => Err(InvalidSyncStatus),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid sync status.")]
+pub struct InvalidSyncStatus;
+
+fn default_refresh_interval() -> i32 { DEFAULT_REFRESH_INTERVAL }
+
+/// The external file a calendar mirrors, and how syncing it went.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Subscription
+{
+    /// URL of the iCalendar file. `http`, `https` and `webcal`
+    /// URLs are supported, `file` URLs only if the server allows them.
+    pub url: String,
+
+    /// Minutes between two syncs.
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: i32,
+
+    /// Ignored in requests.
+    #[serde(default)]
+    pub status: SyncStatus,
+
+    /// When the file was last synced successfully. Ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub last_synced_at: Option<NaiveDateTime>,
+
+    /// Why the last sync failed, or which events of the file couldn't
+    /// be imported. Ignored in requests.
+    pub last_error: Option<String>,
+}
+
+impl Subscription
+{
+    /// Whether the subscription's URL has a scheme we can fetch and its
+    /// refresh interval is positive. Whether `file` URLs are allowed is
+    /// up to the server.
+    pub fn validate(&self) -> bool
+    {
+        self.invalid_field().is_none()
+    }
+
+    /// Like `validate`, but returns the name of the invalid field.
+    pub fn invalid_field(&self) -> Option<&'static str>
+    {
+        let supported = ["http://", "https://", "webcal://", "file://"]
+            .iter()
+            .any(|scheme| self.url.starts_with(scheme));
+
+        if !supported
+        {
+            return Some("url");
+        }
+
+        if self.refresh_interval <= 0
+        {
+            return Some("refresh_interval");
+        }
+
+        None
+    }
+
+    pub fn is_file(&self) -> bool
+    {
+        self.url.starts_with("file://")
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn subscription(url: &str) -> Subscription
+    {
+        Subscription {
+            url: url.to_owned(),
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            status: SyncStatus::Pending,
+            last_synced_at: None,
+            last_error: None,
+        }
+    }
+
+    #[test]
+    fn validate_url()
+    {
+        assert!(subscription("https://example.com/holidays.ics").validate());
+        assert!(subscription("webcal://example.com/holidays.ics").validate());
+        assert!(subscription("file:///tmp/holidays.ics").validate());
+        assert!(!subscription("ftp://example.com/holidays.ics").validate());
+    }
+
+    #[test]
+    fn validate_refresh_interval()
+    {
+        let subscription: Subscription = serde_json::from_str(r#"{"url": "https://example.com"}"#).unwrap();
+        assert_eq!(subscription.refresh_interval, DEFAULT_REFRESH_INTERVAL);
+
+        assert!(!Subscription { refresh_interval: 0, ..subscription("https://example.com") }.validate());
+    }
+}
@@ -0,0 +1,115 @@
+//! Incremental synchronization of a calendar's events.
+//!
+//! A sync token marks a point in a calendar's change log. Clients send
+//! the token they got from their last sync and receive the events that
+//! changed since then, along with a new token.
+
+use uuid::Uuid;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::event::EventPlain;
+
+/// Version prefix of sync tokens, bump it if the format changes.
+const TOKEN_VERSION: &str = "1";
+
+/// Position in the change log. Clients should treat
+/// the string form of this as opaque.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+pub struct SyncToken(i64);
+
+impl SyncToken
+{
+    pub fn new(seq: i64) -> SyncToken
+    {
+        SyncToken(seq)
+    }
+
+    /// Sequence number of the last change this token includes.
+    pub fn get_seq(&self) -> i64 { self.0 }
+}
+
+impl Display for SyncToken
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        write!(f, "{}-{:x}", TOKEN_VERSION, self.0)
+    }
+}
+
+impl FromStr for SyncToken
+{
+    type Err = InvalidSyncToken;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut parts = s.splitn(2, '-');
+
+        if parts.next() != Some(TOKEN_VERSION)
+        {
+            return Err(InvalidSyncToken);
+        }
+
+        parts.next()
+            .and_then(|seq| i64::from_str_radix(seq, 16).ok())
+            .filter(|seq| *seq >= 0)
+            .map(SyncToken)
+            .ok_or(InvalidSyncToken)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid sync token.")]
+pub struct InvalidSyncToken;
+
+/// The latest state of an event that changed since a sync token.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct EventChange
+{
+    pub id: Uuid,
+
+    /// The recurring event this event overrides, if it's an override.
+    pub parent_id: Option<Uuid>,
+
+    /// Whether the event was deleted, if it was this is a tombstone
+    /// and `event` is `None`.
+    pub deleted: bool,
+
+    pub event: Option<EventPlain>,
+}
+
+/// A page of changes.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct SyncResponse
+{
+    pub changes: Vec<EventChange>,
+
+    /// Token to use on the next sync. If `more` is true it
+    /// points to the next page of changes.
+    pub sync_token: String,
+
+    /// Whether there are more changes after this page.
+    pub more: bool,
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn token_round_trip()
+    {
+        let token = SyncToken::new(1234567);
+
+        assert_eq!(SyncToken::from_str(&token.to_string()).unwrap(), token);
+    }
+
+    #[test]
+    fn invalid_tokens()
+    {
+        assert!(SyncToken::from_str("").is_err());
+        assert!(SyncToken::from_str("2-ff").is_err());
+        assert!(SyncToken::from_str("1-xyz").is_err());
+        assert!(SyncToken::from_str("1--5").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Tenants, which own API keys and calendars. An API key
+//! only sees the calendars of its own tenant.
+
+use uuid::Uuid;
+use chrono::NaiveDateTime;
+use std::str::FromStr;
+use crate::scope::Scope;
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Tenant
+{
+    /// Nil when creating a tenant.
+    #[serde(default = "Uuid::nil")]
+    pub id: Uuid,
+
+    pub name: String,
+
+    /// Ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub created_at: Option<NaiveDateTime>,
+}
+
+impl Tenant
+{
+    /// A tenant only needs a name that isn't blank.
+    pub fn validate(&self) -> bool
+    {
+        !self.name.trim().is_empty()
+    }
+}
+
+/// An API key of a tenant. The key itself is only in the responses
+/// to creating or rotating it, it can't be read back afterwards.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct TenantApiKey
+{
+    /// Ignored in requests.
+    #[serde(default)]
+    pub id: Option<Uuid>,
+
+    /// The key clients put in the `Authorization` header, only set
+    /// when the key is created or rotated. Ignored in requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+
+    /// Ignored in requests, keys are created in the
+    /// tenant of the route.
+    #[serde(default)]
+    pub tenant_id: Option<Uuid>,
+
+    /// Lets admins tell keys apart, e.g. the app that uses the key.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    pub scopes: Vec<String>,
+
+    /// If set the key can only see these calendars of its tenant.
+    #[serde(default)]
+    pub calendar_ids: Option<Vec<Uuid>>,
+
+    /// The key stops working after this, if set.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub expires_at: Option<NaiveDateTime>,
+
+    /// Ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub created_at: Option<NaiveDateTime>,
+
+    /// Updated at most once a minute. Ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub last_used_at: Option<NaiveDateTime>,
+
+    /// Ignored in requests.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl TenantApiKey
+{
+    /// Whether the key can be created at `now`. It needs at least one
+    /// scope, only known `Scope`s, at least one calendar if it's
+    /// restricted to some and an expiry (if any) after `now`.
+    pub fn validate(&self, now: NaiveDateTime) -> bool
+    {
+        !self.scopes.is_empty()
+            && self.scopes.iter().all(|scope| Scope::from_str(scope).is_ok())
+            && self.calendar_ids.as_ref().map_or(true, |ids| !ids.is_empty())
+            && self.expires_at.map_or(true, |expires_at| expires_at > now)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn api_key(scopes: &[&str]) -> TenantApiKey
+    {
+        TenantApiKey {
+            id: None,
+            key: None,
+            tenant_id: None,
+            name: None,
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            calendar_ids: None,
+            expires_at: None,
+            created_at: None,
+            last_used_at: None,
+            revoked_at: None,
+        }
+    }
+
+    fn now() -> NaiveDateTime
+    {
+        NaiveDate::from_ymd(2021, 3, 1).and_hms(12, 0, 0)
+    }
+
+    #[test]
+    fn validate_name()
+    {
+        let tenant = |name: &str| Tenant { id: Uuid::nil(), name: name.to_owned(), created_at: None };
+
+        assert!(tenant("Acme").validate());
+        assert!(!tenant("").validate());
+        assert!(!tenant("  ").validate());
+    }
+
+    #[test]
+    fn validate_scopes()
+    {
+        assert!(api_key(&["READ"]).validate(now()));
+        assert!(api_key(&["READ", "WRITE", "SUPER"]).validate(now()));
+        assert!(!api_key(&[]).validate(now()));
+        assert!(!api_key(&["read"]).validate(now()));
+        assert!(!api_key(&["WRITE", "ADMIN"]).validate(now()));
+    }
+
+    #[test]
+    fn validate_calendars_and_expiry()
+    {
+        let restricted = |ids: Vec<Uuid>| TenantApiKey { calendar_ids: Some(ids), ..api_key(&["READ"]) };
+        let expiring = |expires_at: NaiveDateTime| TenantApiKey { expires_at: Some(expires_at), ..api_key(&["READ"]) };
+
+        assert!(restricted(vec![Uuid::nil()]).validate(now()));
+        assert!(!restricted(vec![]).validate(now()));
+
+        assert!(expiring(NaiveDate::from_ymd(2021, 3, 2).and_hms(0, 0, 0)).validate(now()));
+        assert!(!expiring(now()).validate(now()));
+    }
+}
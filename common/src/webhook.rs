@@ -0,0 +1,254 @@
+//! Webhook subscriptions, which notify other services of
+//! changes to the events of a calendar.
+
+use uuid::Uuid;
+use chrono::NaiveDateTime;
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// What a webhook is subscribed to.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum WebhookScope
+{
+    /// Changes to any event of the calendar.
+    #[serde(rename = "CALENDAR")]
+    Calendar,
+
+    /// Changes to a single event (`event_id`), not including its overrides.
+    #[serde(rename = "EVENT")]
+    Event,
+
+    /// Changes to a recurring event (`event_id`) or to any of its
+    /// overrides, i.e. anything that changes the event's instances.
+    #[serde(rename = "INSTANCES")]
+    Instances,
+}
+
+impl Default for WebhookScope
+{
+    fn default() -> Self { WebhookScope::Calendar }
+}
+
+impl Display for WebhookScope
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            WebhookScope::Calendar => "CALENDAR",
+            WebhookScope::Event => "EVENT",
+            WebhookScope::Instances => "INSTANCES",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for WebhookScope
+{
+    type Err = InvalidWebhookValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "CALENDAR" => Ok(WebhookScope::Calendar),
+            "EVENT" => Ok(WebhookScope::Event),
+            "INSTANCES" => Ok(WebhookScope::Instances),
+            _ => Err(InvalidWebhookValue("scope")),
+        }
+    }
+}
+
+/// State of a single delivery of a webhook.
+#[derive(Serialize, Deserialize, JsonSchema, Eq, PartialEq, Debug, Copy, Clone)]
+pub enum DeliveryStatus
+{
+    /// Not delivered yet, will be (re)tried at `next_attempt_at`.
+    #[serde(rename = "PENDING")]
+    Pending,
+
+    #[serde(rename = "DELIVERED")]
+    Delivered,
+
+    /// All attempts failed, the delivery won't be retried
+    /// unless it's explicitly requested.
+    #[serde(rename = "DEAD")]
+    Dead,
+
+    /// The alarm this delivery is for won't go off anymore
+    /// because its event was deleted.
+    #[serde(rename = "CANCELLED")]
+    Cancelled,
+}
+
+impl Display for DeliveryStatus
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result
+    {
+        let string = match self
+        {
+            DeliveryStatus::Pending => "PENDING",
+            DeliveryStatus::Delivered => "DELIVERED",
+            DeliveryStatus::Dead => "DEAD",
+            DeliveryStatus::Cancelled => "CANCELLED",
+        };
+
+        f.write_str(string)
+    }
+}
+
+impl FromStr for DeliveryStatus
+{
+    type Err = InvalidWebhookValue;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        match s
+        {
+            "PENDING" => Ok(DeliveryStatus::Pending),
+            "DELIVERED" => Ok(DeliveryStatus::Delivered),
+            "DEAD" => Ok(DeliveryStatus::Dead),
+            "CANCELLED" => Ok(DeliveryStatus::Cancelled),
+            _ => Err(InvalidWebhookValue("status")),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid {0} value.")]
+pub struct InvalidWebhookValue(pub &'static str);
+
+/// A subscription to changes of a calendar's events.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Webhook
+{
+    /// Id of the webhook in the database, `None` when
+    /// creating a webhook.
+    pub id: Option<Uuid>,
+
+    /// URL notifications are POSTed to.
+    pub url: String,
+
+    #[serde(default)]
+    pub scope: WebhookScope,
+
+    /// The event this webhook is subscribed to. Required if `scope`
+    /// is `EVENT` or `INSTANCES`, must be `None` if it is `CALENDAR`.
+    pub event_id: Option<Uuid>,
+
+    /// Key used to sign notifications. A random one is generated
+    /// if this is `None` when creating a webhook. Write-only: it's
+    /// only in the response to creating the webhook, readers of the
+    /// calendar could forge notifications with it otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl Webhook
+{
+    /// Whether the webhook can be stored: an http(s) URL, a secret
+    /// that isn't empty if one is given, and an `event_id` exactly
+    /// when the scope is about a single event.
+    pub fn validate(&self) -> bool
+    {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://")
+        {
+            return false;
+        }
+
+        if self.secret.as_ref().map_or(false, |s| s.is_empty())
+        {
+            return false;
+        }
+
+        (self.scope == WebhookScope::Calendar) == self.event_id.is_none()
+    }
+}
+
+/// A notification sent (or to be sent) to a webhook.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct WebhookDelivery
+{
+    pub id: Uuid,
+
+    /// `event.created`, `event.updated` or `event.deleted`.
+    pub event_type: String,
+
+    /// The body POSTed to the webhook.
+    pub payload: Value,
+
+    pub status: DeliveryStatus,
+
+    /// How many times the delivery was attempted.
+    pub attempts: i32,
+
+    #[serde(with = "crate::event::event_plain_serde::date_time")]
+    #[schemars(with = "NaiveDateTime")]
+    pub created_at: NaiveDateTime,
+
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub last_attempt_at: Option<NaiveDateTime>,
+
+    /// When the delivery will be attempted again, `None` unless
+    /// `status` is `PENDING`.
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub next_attempt_at: Option<NaiveDateTime>,
+
+    #[serde(default, with = "crate::event::event_plain_serde::date_time_option")]
+    #[schemars(with = "Option<NaiveDateTime>")]
+    pub delivered_at: Option<NaiveDateTime>,
+
+    /// HTTP status of the last response, `None` if no
+    /// response was received.
+    pub response_status: Option<i32>,
+
+    pub last_error: Option<String>,
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    fn webhook() -> Webhook
+    {
+        Webhook {
+            id: None,
+            url: "https://example.com/hooks/caser".to_owned(),
+            scope: WebhookScope::Calendar,
+            event_id: None,
+            secret: None,
+        }
+    }
+
+    #[test]
+    fn scope_requires_event_id()
+    {
+        assert!(webhook().validate());
+        assert!(!Webhook { event_id: Some(Uuid::nil()), ..webhook() }.validate());
+
+        assert!(!Webhook { scope: WebhookScope::Event, ..webhook() }.validate());
+        assert!(Webhook { scope: WebhookScope::Instances, event_id: Some(Uuid::nil()), ..webhook() }.validate());
+    }
+
+    #[test]
+    fn invalid_url_or_secret()
+    {
+        assert!(!Webhook { url: "ftp://example.com".to_owned(), ..webhook() }.validate());
+        assert!(!Webhook { secret: Some(String::new()), ..webhook() }.validate());
+    }
+
+    #[test]
+    fn secret_is_only_serialized_when_set()
+    {
+        let json = serde_json::to_value(webhook()).unwrap();
+        assert!(json.get("secret").is_none());
+
+        let json = serde_json::to_value(Webhook { secret: Some("s3cr3t".to_owned()), ..webhook() }).unwrap();
+        assert_eq!(json["secret"], "s3cr3t");
+    }
+}
@@ -0,0 +1,37 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds the ical_uid column to events, which stores the UID of events imported from
+-- iCalendar files, so that importing a file again updates the events it created
+-- instead of duplicating them. Overrides are identified by their parent and
+-- recurrence_id, so only recurring and single events have a UID.
+--
+-- Exports use the UID of other events too, which is their id. Those events get
+-- their id as ical_uid (existing ones here, new ones when they're inserted), so
+-- importing an export back into its calendar updates them as well.
+
+ALTER TABLE events ADD COLUMN ical_uid TEXT;
+
+ALTER TABLE events ADD CONSTRAINT ical_uid_only_when_not_override CHECK ((ical_uid IS NULL) OR (parent_event_id IS NULL));
+
+UPDATE events SET ical_uid = id::TEXT WHERE parent_event_id IS NULL;
+
+CREATE UNIQUE INDEX unique_ical_uid ON events (calendar_id, ical_uid) WHERE ical_uid IS NOT NULL;
+
+CREATE FUNCTION default_ical_uid() RETURNS TRIGGER AS $$
+BEGIN
+    IF (NEW.ical_uid IS NULL AND NEW.parent_event_id IS NULL) THEN
+        NEW.ical_uid := NEW.id::TEXT;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER default_ical_uid
+    BEFORE INSERT ON events
+    FOR EACH ROW EXECUTE PROCEDURE default_ical_uid();
+
+INSERT INTO schema_changelog (version) VALUES (10);
+
+COMMIT TRANSACTION;
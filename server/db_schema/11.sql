@@ -0,0 +1,29 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Creates the feeds table, which stores the secret tokens of a calendar's webcal
+-- feeds. Anyone with a token can read the calendar's events (without an API key)
+-- until the feed is deleted.
+
+CREATE TABLE feeds (
+    id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    calendar_id uuid NOT NULL,
+    token TEXT NOT NULL DEFAULT encode(gen_random_bytes(32), 'hex'),
+
+    -- in days, NULL if recurring events are exported with their RRULE
+    expansion_horizon INTEGER,
+
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT positive_expansion_horizon CHECK ((expansion_horizon IS NULL) OR (expansion_horizon > 0)),
+
+    CONSTRAINT pk_feeds PRIMARY KEY (id),
+    CONSTRAINT unique_feed_token UNIQUE (token),
+    CONSTRAINT fk_calendar_id FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE
+);
+
+CREATE INDEX feeds_calendar_id ON feeds (calendar_id);
+
+INSERT INTO schema_changelog (version) VALUES (11);
+
+COMMIT TRANSACTION;
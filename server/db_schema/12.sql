@@ -0,0 +1,34 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds subscriptions to calendars. A subscribed calendar mirrors an external iCalendar
+-- file (its subscription_url), which the server fetches every refresh_interval minutes
+-- and imports, removing events that are not in the file anymore. The other columns
+-- store how the last sync went.
+
+ALTER TABLE calendars ADD COLUMN subscription_url TEXT;
+
+-- in minutes
+ALTER TABLE calendars ADD COLUMN refresh_interval INTEGER;
+
+ALTER TABLE calendars ADD COLUMN sync_status TEXT;
+ALTER TABLE calendars ADD COLUMN last_synced_at TIMESTAMP WITHOUT TIME ZONE;
+ALTER TABLE calendars ADD COLUMN last_sync_error TEXT;
+ALTER TABLE calendars ADD COLUMN next_sync_at TIMESTAMP WITHOUT TIME ZONE;
+
+-- ETag of the last file fetched, to skip syncs when it didn't change
+ALTER TABLE calendars ADD COLUMN subscription_etag TEXT;
+
+ALTER TABLE calendars ADD CONSTRAINT valid_sync_status CHECK (sync_status IN ('PENDING', 'OK', 'FAILED'));
+ALTER TABLE calendars ADD CONSTRAINT positive_refresh_interval CHECK (refresh_interval > 0);
+ALTER TABLE calendars ADD CONSTRAINT subscription_fields CHECK (
+    (subscription_url IS NULL) = (refresh_interval IS NULL)
+    AND (subscription_url IS NULL) = (sync_status IS NULL)
+    AND (subscription_url IS NULL) = (next_sync_at IS NULL)
+);
+
+CREATE INDEX calendars_next_sync_at ON calendars (next_sync_at) WHERE subscription_url IS NOT NULL;
+
+INSERT INTO schema_changelog (version) VALUES (12);
+
+COMMIT TRANSACTION;
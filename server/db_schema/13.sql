@@ -0,0 +1,54 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds the dav_name column to events, the name CalDAV clients gave to the resource of
+-- a recurring or single event (its overrides are part of the same resource). Events
+-- without one are named after their id. The name is also logged in event_changes, so
+-- that sync-collection reports can tell clients which resources were deleted.
+
+ALTER TABLE events ADD COLUMN dav_name TEXT;
+
+ALTER TABLE events ADD CONSTRAINT dav_name_only_when_not_override CHECK ((dav_name IS NULL) OR (parent_event_id IS NULL));
+
+CREATE UNIQUE INDEX unique_dav_name ON events (calendar_id, dav_name) WHERE dav_name IS NOT NULL;
+
+ALTER TABLE event_changes ADD COLUMN dav_name TEXT;
+
+
+
+CREATE OR REPLACE FUNCTION log_event_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed events;
+    change_type TEXT;
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        changed := NEW;
+        change_type := 'CREATED';
+    ELSIF (TG_OP = 'UPDATE') THEN
+        IF (NEW IS NOT DISTINCT FROM OLD) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := NEW;
+        change_type := 'UPDATED';
+    ELSE
+        changed := OLD;
+        change_type := 'DELETED';
+    END IF;
+
+    -- Sequence values are handed out in the order changes are made, not in the
+    -- order they're committed. Changes to the same calendar are serialized so that
+    -- a change can't become visible after a change with a greater seq, otherwise
+    -- a client could sync past it and never see it.
+    PERFORM pg_advisory_xact_lock(hashtext(changed.calendar_id::TEXT));
+
+    INSERT INTO event_changes (calendar_id, event_id, parent_event_id, change_type, dav_name)
+    VALUES (changed.calendar_id, changed.id, changed.parent_event_id, change_type, changed.dav_name);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+INSERT INTO schema_changelog (version) VALUES (13);
+
+COMMIT TRANSACTION;
@@ -0,0 +1,20 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds the name, description, color, time_zone and metadata columns to calendars.
+-- time_zone is only a hint for apps, events are still stored in UTC. Time zone
+-- names are validated by the server, colors by the database.
+
+ALTER TABLE calendars ADD COLUMN name TEXT;
+ALTER TABLE calendars ADD COLUMN description TEXT;
+ALTER TABLE calendars ADD COLUMN color TEXT;
+ALTER TABLE calendars ADD COLUMN time_zone TEXT;
+ALTER TABLE calendars ADD COLUMN metadata JSONB;
+
+ALTER TABLE calendars ADD CONSTRAINT valid_color CHECK (color ~ '^#[0-9A-Fa-f]{6}$');
+
+CREATE INDEX calendars_last_modified ON calendars (last_modified);
+
+INSERT INTO schema_changelog (version) VALUES (14);
+
+COMMIT TRANSACTION;
@@ -0,0 +1,116 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds tenants. API keys and calendars belong to a tenant, and a key can only see
+-- the calendars (and everything in them) of its own tenant. Existing keys and
+-- calendars are moved to a tenant named 'default'.
+--
+-- The server filters every query by tenant itself. Row-level security is defense
+-- in depth: the server sets caser.tenant_id on the connections of requests made
+-- with an API key, and rows of other tenants are invisible to them. Connections
+-- without a tenant see no rows at all, unless they explicitly set
+-- caser.unrestricted to 'on' (background jobs and lookups that come before the
+-- tenant is known, like feed tokens). Superusers and roles with BYPASSRLS are
+-- never restricted, so the server should connect as a regular role for
+-- row-level security to have any effect.
+
+CREATE TABLE tenants (
+    id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    name TEXT NOT NULL,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT pk_tenants PRIMARY KEY (id)
+);
+
+INSERT INTO tenants (name) VALUES ('default');
+
+ALTER TABLE api_keys ADD COLUMN tenant_id uuid;
+UPDATE api_keys SET tenant_id = (SELECT id FROM tenants);
+ALTER TABLE api_keys ALTER COLUMN tenant_id SET NOT NULL;
+ALTER TABLE api_keys ADD CONSTRAINT fk_tenant_id FOREIGN KEY (tenant_id) REFERENCES tenants(id);
+
+ALTER TABLE calendars ADD COLUMN tenant_id uuid;
+UPDATE calendars SET tenant_id = (SELECT id FROM tenants);
+ALTER TABLE calendars ALTER COLUMN tenant_id SET NOT NULL;
+ALTER TABLE calendars ADD CONSTRAINT fk_tenant_id FOREIGN KEY (tenant_id) REFERENCES tenants(id);
+
+CREATE INDEX calendars_tenant_id ON calendars (tenant_id);
+
+
+
+CREATE OR REPLACE FUNCTION current_tenant() RETURNS uuid AS $$
+    SELECT NULLIF(current_setting('caser.tenant_id', true), '')::uuid;
+$$ LANGUAGE sql STABLE;
+
+COMMENT ON FUNCTION current_tenant() IS 'The tenant the connection is restricted to (see caser.tenant_id), NULL if it has none.';
+
+CREATE OR REPLACE FUNCTION is_unrestricted() RETURNS BOOLEAN AS $$
+    SELECT COALESCE(current_setting('caser.unrestricted', true), '') = 'on';
+$$ LANGUAGE sql STABLE;
+
+COMMENT ON FUNCTION is_unrestricted() IS 'Whether the connection sees the rows of every tenant (see caser.unrestricted).';
+
+ALTER TABLE calendars ENABLE ROW LEVEL SECURITY;
+ALTER TABLE calendars FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON calendars
+    USING (is_unrestricted() OR tenant_id = current_tenant());
+
+-- The tables below have no tenant_id, their rows belong to the tenant of their
+-- calendar. The subqueries only see the calendars of the current tenant (all
+-- of them if the connection is unrestricted).
+
+ALTER TABLE events ENABLE ROW LEVEL SECURITY;
+ALTER TABLE events FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON events
+    USING (calendar_id IN (SELECT id FROM calendars));
+
+ALTER TABLE event_changes ENABLE ROW LEVEL SECURITY;
+ALTER TABLE event_changes FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON event_changes
+    USING (calendar_id IN (SELECT id FROM calendars));
+
+ALTER TABLE webhooks ENABLE ROW LEVEL SECURITY;
+ALTER TABLE webhooks FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON webhooks
+    USING (calendar_id IN (SELECT id FROM calendars));
+
+ALTER TABLE feeds ENABLE ROW LEVEL SECURITY;
+ALTER TABLE feeds FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON feeds
+    USING (calendar_id IN (SELECT id FROM calendars));
+
+-- These belong to an event or a webhook instead of a calendar. Going through
+-- events and webhooks (whose own policies already apply) is enough.
+
+ALTER TABLE attendees ENABLE ROW LEVEL SECURITY;
+ALTER TABLE attendees FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON attendees
+    USING (event_id IN (SELECT id FROM events));
+
+ALTER TABLE alarms ENABLE ROW LEVEL SECURITY;
+ALTER TABLE alarms FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON alarms
+    USING (event_id IN (SELECT id FROM events));
+
+ALTER TABLE alarm_deliveries ENABLE ROW LEVEL SECURITY;
+ALTER TABLE alarm_deliveries FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON alarm_deliveries
+    USING (alarm_id IN (SELECT id FROM alarms));
+
+ALTER TABLE webhook_deliveries ENABLE ROW LEVEL SECURITY;
+ALTER TABLE webhook_deliveries FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON webhook_deliveries
+    USING (webhook_id IN (SELECT id FROM webhooks));
+
+INSERT INTO schema_changelog (version) VALUES (15);
+
+COMMIT TRANSACTION;
@@ -0,0 +1,59 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Stores a salted SHA-256 hash of API keys instead of the keys themselves. Keys
+-- get an id, new keys are sent as `<id>.<secret>` and only the secret is hashed.
+-- Existing keys are plain UUIDs, they keep working: they're marked as legacy and
+-- the UUID is hashed as their secret. Legacy keys also get an unsalted digest
+-- so they can be looked up by index, they're random UUIDs and don't need a salt
+-- to resist brute force.
+--
+-- Also adds names, expiry, revocation, last-used tracking and a restriction to
+-- specific calendars (NULL meaning all calendars of the key's tenant).
+
+ALTER TABLE api_keys ADD COLUMN id uuid NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE api_keys ADD COLUMN salt TEXT;
+ALTER TABLE api_keys ADD COLUMN secret_hash TEXT;
+ALTER TABLE api_keys ADD COLUMN legacy BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE api_keys ADD COLUMN legacy_digest TEXT;
+
+UPDATE api_keys SET salt = encode(gen_random_bytes(16), 'hex'), legacy = TRUE;
+UPDATE api_keys SET secret_hash = encode(digest(decode(salt, 'hex') || convert_to(api_key::TEXT, 'UTF8'), 'sha256'), 'hex');
+UPDATE api_keys SET legacy_digest = encode(digest(convert_to(api_key::TEXT, 'UTF8'), 'sha256'), 'hex');
+
+ALTER TABLE api_keys DROP COLUMN api_key;
+ALTER TABLE api_keys ADD CONSTRAINT pk_api_keys PRIMARY KEY (id);
+ALTER TABLE api_keys ALTER COLUMN salt SET NOT NULL;
+ALTER TABLE api_keys ALTER COLUMN secret_hash SET NOT NULL;
+ALTER TABLE api_keys ADD CONSTRAINT legacy_has_digest CHECK (legacy = (legacy_digest IS NOT NULL));
+
+ALTER TABLE api_keys ADD COLUMN name TEXT;
+ALTER TABLE api_keys ADD COLUMN calendar_ids uuid[];
+ALTER TABLE api_keys ADD COLUMN created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW();
+ALTER TABLE api_keys ADD COLUMN expires_at TIMESTAMP WITHOUT TIME ZONE;
+ALTER TABLE api_keys ADD COLUMN last_used_at TIMESTAMP WITHOUT TIME ZONE;
+ALTER TABLE api_keys ADD COLUMN revoked_at TIMESTAMP WITHOUT TIME ZONE;
+
+-- Legacy keys have no id in them, so they're found by their digest.
+CREATE UNIQUE INDEX api_keys_legacy ON api_keys (legacy_digest) WHERE legacy;
+
+-- Creates a key and returns it, to create the first key of a server (the
+-- server creates the others through its admin routes). Hashes the same way
+-- the server does.
+CREATE OR REPLACE FUNCTION create_api_key(key_tenant_id uuid, key_scopes TEXT[]) RETURNS TEXT AS $$
+DECLARE
+    new_secret TEXT := encode(gen_random_bytes(32), 'hex');
+    new_salt TEXT := encode(gen_random_bytes(16), 'hex');
+    new_id uuid;
+BEGIN
+    INSERT INTO api_keys (tenant_id, scopes, salt, secret_hash)
+    VALUES (key_tenant_id, key_scopes, new_salt, encode(digest(decode(new_salt, 'hex') || convert_to(new_secret, 'UTF8'), 'sha256'), 'hex'))
+    RETURNING id INTO new_id;
+
+    RETURN new_id || '.' || new_secret;
+END;
+$$ LANGUAGE plpgsql;
+
+INSERT INTO schema_changelog (version) VALUES (16);
+
+COMMIT TRANSACTION;
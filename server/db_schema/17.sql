@@ -0,0 +1,50 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds access control lists to calendars. A grant gives a principal a role on a
+-- calendar, principals are users (by `sub` claim) and groups of the JWT issuer
+-- and API keys (by id). Principals without a grant can't see a calendar, except
+-- principals with the SUPER scope, which own every calendar of their tenant.
+--
+-- Existing API keys saw every calendar of their tenant (or the ones they're
+-- restricted to), they're given OWNER grants on those calendars so they keep
+-- their access. Keys created afterwards only see what they're granted.
+
+CREATE TABLE calendar_grants (
+    id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    calendar_id uuid NOT NULL,
+    principal_type TEXT NOT NULL,
+    principal_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT pk_calendar_grants PRIMARY KEY (id),
+    CONSTRAINT fk_calendar_id FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE,
+    CONSTRAINT one_grant_per_principal UNIQUE (calendar_id, principal_type, principal_id),
+    CONSTRAINT valid_principal_type CHECK (principal_type IN ('USER', 'GROUP', 'API_KEY')),
+    CONSTRAINT valid_role CHECK (role IN ('FREE_BUSY', 'READER', 'WRITER', 'OWNER'))
+);
+
+CREATE INDEX calendar_grants_principal ON calendar_grants (principal_type, principal_id);
+
+-- Row-level security (see 15.sql) would hide every row from a migration run by
+-- a regular role.
+SET LOCAL caser.unrestricted = 'on';
+
+INSERT INTO calendar_grants (calendar_id, principal_type, principal_id, role)
+SELECT calendars.id, 'API_KEY', api_keys.id::TEXT, 'OWNER'
+FROM api_keys
+JOIN calendars ON calendars.tenant_id = api_keys.tenant_id
+WHERE
+    NOT ('SUPER' = ANY(api_keys.scopes))
+    AND (api_keys.calendar_ids IS NULL OR calendars.id = ANY(api_keys.calendar_ids));
+
+ALTER TABLE calendar_grants ENABLE ROW LEVEL SECURITY;
+ALTER TABLE calendar_grants FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON calendar_grants
+    USING (calendar_id IN (SELECT id FROM calendars));
+
+INSERT INTO schema_changelog (version) VALUES (17);
+
+COMMIT TRANSACTION;
@@ -0,0 +1,135 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds an append-only audit log of every insert, update and delete of calendars,
+-- events and API keys. Entries are written by triggers, so changes made any way
+-- (routes, CalDAV, imports, subscription syncs) are recorded.
+--
+-- The server sets caser.principal_type, caser.principal_id and caser.route on
+-- the connections of requests, like caser.tenant_id. Changes made on connections
+-- without them (background jobs) are recorded without a principal.
+--
+-- Updates only record the columns that changed. Bookkeeping columns (sync state
+-- of subscribed calendars, API key secrets and last use) are never recorded, and
+-- updates that only change them aren't either.
+--
+-- Entries can't be changed, and can only be deleted when caser.audit_pruning is
+-- 'on' (set by the server when it removes entries older than the retention window).
+
+CREATE TABLE audit_log (
+    id BIGSERIAL NOT NULL,
+    tenant_id uuid,
+    principal_type TEXT,
+    principal_id TEXT,
+    route TEXT,
+    resource_type TEXT NOT NULL,
+    resource_id uuid NOT NULL,
+    action TEXT NOT NULL,
+    before JSONB,
+    after JSONB,
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT pk_audit_log PRIMARY KEY (id),
+    CONSTRAINT valid_principal_type CHECK (principal_type IN ('USER', 'API_KEY')),
+    CONSTRAINT valid_resource_type CHECK (resource_type IN ('CALENDAR', 'EVENT', 'API_KEY')),
+    CONSTRAINT valid_action CHECK (action IN ('INSERT', 'UPDATE', 'DELETE'))
+);
+
+CREATE INDEX audit_log_tenant_created_at ON audit_log (tenant_id, created_at);
+CREATE INDEX audit_log_resource ON audit_log (resource_type, resource_id);
+
+-- Arguments: the resource type, followed by the columns that aren't recorded.
+CREATE FUNCTION record_audit() RETURNS TRIGGER AS $$
+DECLARE
+    ignored TEXT[] := TG_ARGV[1:];
+    changed JSONB;
+    before JSONB;
+    after JSONB;
+BEGIN
+    IF (TG_OP = 'DELETE') THEN
+        changed := to_jsonb(OLD);
+        before := changed - ignored;
+    ELSIF (TG_OP = 'INSERT') THEN
+        changed := to_jsonb(NEW);
+        after := changed - ignored;
+    ELSE
+        changed := to_jsonb(NEW);
+
+        SELECT jsonb_object_agg(o.key, o.value), jsonb_object_agg(o.key, n.value)
+        INTO before, after
+        FROM jsonb_each(to_jsonb(OLD) - ignored) AS o
+        JOIN jsonb_each(to_jsonb(NEW) - ignored) AS n ON n.key = o.key
+        WHERE o.value IS DISTINCT FROM n.value;
+
+        IF (before IS NULL) THEN
+            RETURN NULL;
+        END IF;
+    END IF;
+
+    INSERT INTO audit_log (tenant_id, principal_type, principal_id, route, resource_type, resource_id, action, before, after)
+    VALUES (
+        COALESCE(
+            (changed ->> 'tenant_id')::uuid,
+            (SELECT tenant_id FROM calendars WHERE id = (changed ->> 'calendar_id')::uuid),
+            current_tenant()
+        ),
+        NULLIF(current_setting('caser.principal_type', true), ''),
+        NULLIF(current_setting('caser.principal_id', true), ''),
+        NULLIF(current_setting('caser.route', true), ''),
+        TG_ARGV[0],
+        (changed ->> 'id')::uuid,
+        TG_OP,
+        before,
+        after
+    );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER record_audit
+    AFTER INSERT OR UPDATE OR DELETE ON calendars
+    FOR EACH ROW EXECUTE PROCEDURE record_audit(
+        'CALENDAR', 'last_modified', 'sync_status', 'last_synced_at', 'last_sync_error', 'next_sync_at', 'subscription_etag'
+    );
+
+CREATE TRIGGER record_audit
+    AFTER INSERT OR UPDATE OR DELETE ON events
+    FOR EACH ROW EXECUTE PROCEDURE record_audit('EVENT');
+
+CREATE TRIGGER record_audit
+    AFTER INSERT OR UPDATE OR DELETE ON api_keys
+    FOR EACH ROW EXECUTE PROCEDURE record_audit('API_KEY', 'salt', 'secret_hash', 'legacy_digest', 'last_used_at');
+
+CREATE FUNCTION protect_audit_log() RETURNS TRIGGER AS $$
+BEGIN
+    IF (TG_OP = 'DELETE' AND current_setting('caser.audit_pruning', true) = 'on') THEN
+        RETURN OLD;
+    END IF;
+
+    RAISE EXCEPTION 'The audit log is append-only.';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER protect_audit_log
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE PROCEDURE protect_audit_log();
+
+-- Entries are recorded for any tenant (e.g. a SUPER key creating an API key
+-- for another tenant), but connections only read their own tenant's.
+
+ALTER TABLE audit_log ENABLE ROW LEVEL SECURITY;
+ALTER TABLE audit_log FORCE ROW LEVEL SECURITY;
+
+CREATE POLICY tenant_isolation ON audit_log FOR SELECT
+    USING (is_unrestricted() OR tenant_id = current_tenant());
+
+CREATE POLICY append ON audit_log FOR INSERT
+    WITH CHECK (true);
+
+CREATE POLICY prune ON audit_log FOR DELETE
+    USING (true);
+
+INSERT INTO schema_changelog (version) VALUES (19);
+
+COMMIT TRANSACTION;
@@ -0,0 +1,204 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds the trash. Deleting a calendar or an event sets its deleted_at instead of
+-- removing the row, and the server leaves such rows out of everything but the
+-- trash routes. Deleting a calendar trashes its events with the same deleted_at,
+-- so restoring the calendar brings back the events that were deleted with it
+-- (but not those that were already in the trash). Overrides are trashed and
+-- restored with their recurring event.
+--
+-- Moving an event to the trash is logged (and sent to webhooks and listeners) as
+-- its deletion, and restoring it as its creation. Rows in the trash are removed
+-- for good by the server once they're older than the retention window, which
+-- isn't logged again.
+--
+-- ical_uid and dav_name only have to be unique among events that aren't in the
+-- trash, so an event can be created again after it was deleted. Same for the
+-- override of an instance, so the instance can be overridden again.
+
+ALTER TABLE calendars ADD COLUMN deleted_at TIMESTAMP WITHOUT TIME ZONE;
+ALTER TABLE events ADD COLUMN deleted_at TIMESTAMP WITHOUT TIME ZONE;
+
+CREATE INDEX calendars_deleted_at ON calendars (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX events_deleted_at ON events (calendar_id, deleted_at) WHERE deleted_at IS NOT NULL;
+
+DROP INDEX unique_ical_uid;
+CREATE UNIQUE INDEX unique_ical_uid ON events (calendar_id, ical_uid) WHERE ical_uid IS NOT NULL AND deleted_at IS NULL;
+
+DROP INDEX unique_dav_name;
+CREATE UNIQUE INDEX unique_dav_name ON events (calendar_id, dav_name) WHERE dav_name IS NOT NULL AND deleted_at IS NULL;
+
+ALTER TABLE events DROP CONSTRAINT unique_override;
+CREATE UNIQUE INDEX unique_override ON events (parent_event_id, recurrence_id) WHERE deleted_at IS NULL;
+
+
+
+CREATE OR REPLACE FUNCTION log_event_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed events;
+    change_type TEXT;
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        changed := NEW;
+        change_type := 'CREATED';
+    ELSIF (TG_OP = 'UPDATE') THEN
+        IF (NEW IS NOT DISTINCT FROM OLD) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := NEW;
+
+        IF (OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL) THEN
+            change_type := 'DELETED';
+        ELSIF (OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL) THEN
+            change_type := 'CREATED';
+        ELSIF (NEW.deleted_at IS NOT NULL) THEN
+            RETURN NULL;
+        ELSE
+            change_type := 'UPDATED';
+        END IF;
+    ELSE
+        IF (OLD.deleted_at IS NOT NULL) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := OLD;
+        change_type := 'DELETED';
+    END IF;
+
+    -- Sequence values are handed out in the order changes are made, not in the
+    -- order they're committed. Changes to the same calendar are serialized so that
+    -- a change can't become visible after a change with a greater seq, otherwise
+    -- a client could sync past it and never see it.
+    PERFORM pg_advisory_xact_lock(hashtext(changed.calendar_id::TEXT));
+
+    INSERT INTO event_changes (calendar_id, event_id, parent_event_id, change_type, dav_name)
+    VALUES (changed.calendar_id, changed.id, changed.parent_event_id, change_type, changed.dav_name);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+
+
+CREATE OR REPLACE FUNCTION queue_webhook_deliveries() RETURNS TRIGGER AS $$
+DECLARE
+    changed events;
+    event_type TEXT;
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        changed := NEW;
+        event_type := 'event.created';
+    ELSIF (TG_OP = 'UPDATE') THEN
+        IF (NEW IS NOT DISTINCT FROM OLD) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := NEW;
+
+        IF (OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL) THEN
+            event_type := 'event.deleted';
+        ELSIF (OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL) THEN
+            event_type := 'event.created';
+        ELSIF (NEW.deleted_at IS NOT NULL) THEN
+            RETURN NULL;
+        ELSE
+            event_type := 'event.updated';
+        END IF;
+    ELSE
+        IF (OLD.deleted_at IS NOT NULL) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := OLD;
+        event_type := 'event.deleted';
+    END IF;
+
+    INSERT INTO webhook_deliveries (webhook_id, event_type, payload)
+    SELECT
+        webhooks.id,
+        event_type,
+        jsonb_build_object(
+            'type', event_type,
+            'webhook_id', webhooks.id,
+            'calendar_id', changed.calendar_id,
+            'event_id', changed.id,
+            'parent_id', changed.parent_event_id,
+            'recurrence_id', to_char(changed.recurrence_id, 'YYYY-MM-DD'),
+            'occurred_at', to_char(NOW(), 'YYYY-MM-DD"T"HH24:MI:SS')
+        )
+    FROM webhooks
+    WHERE
+        webhooks.calendar_id = changed.calendar_id
+        AND (
+            webhooks.scope = 'CALENDAR'
+            OR (webhooks.scope = 'EVENT' AND webhooks.event_id = changed.id)
+            OR (webhooks.scope = 'INSTANCES' AND webhooks.event_id IN (changed.id, changed.parent_event_id))
+        );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+
+
+CREATE OR REPLACE FUNCTION notify_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed JSONB;
+    op TEXT := TG_OP;
+BEGIN
+    IF (TG_OP = 'DELETE') THEN
+        IF (OLD.deleted_at IS NOT NULL) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := to_jsonb(OLD);
+    ELSIF (TG_OP = 'UPDATE') THEN
+        IF (NEW IS NOT DISTINCT FROM OLD) THEN
+            RETURN NULL;
+        END IF;
+
+        IF (OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL) THEN
+            op := 'DELETE';
+        ELSIF (OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL) THEN
+            op := 'INSERT';
+        ELSIF (NEW.deleted_at IS NOT NULL) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := to_jsonb(NEW);
+    ELSE
+        changed := to_jsonb(NEW);
+    END IF;
+
+    PERFORM pg_notify('caser_changes', jsonb_build_object(
+        'table', TG_TABLE_NAME,
+        'op', op,
+        'calendar_id', CASE TG_TABLE_NAME WHEN 'calendars' THEN changed->'id' ELSE changed->'calendar_id' END
+    )::TEXT);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+
+
+-- Moving an event to the trash or out of it isn't a new revision.
+CREATE OR REPLACE FUNCTION set_event_sequence() RETURNS TRIGGER AS $$
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        NEW.sequence := 0;
+    ELSIF ((to_jsonb(NEW) - '{sequence,last_modified,deleted_at}'::TEXT[]) <> (to_jsonb(OLD) - '{sequence,last_modified,deleted_at}'::TEXT[])) THEN
+        NEW.sequence := OLD.sequence + 1;
+    ELSE
+        NEW.sequence := OLD.sequence;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+INSERT INTO schema_changelog (version) VALUES (21);
+
+COMMIT TRANSACTION;
@@ -0,0 +1,14 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Gives feeds a visibility, like grants have (see 18.sql). Anyone with a feed's
+-- token can read it, so feeds only show the details of PUBLIC events unless
+-- they're created with a wider visibility. Existing feeds get the default too.
+
+ALTER TABLE feeds ADD COLUMN visibility TEXT NOT NULL DEFAULT 'PUBLIC';
+
+ALTER TABLE feeds ADD CONSTRAINT valid_feed_visibility CHECK (visibility IN ('FREE_BUSY', 'PUBLIC', 'PRIVATE', 'ALL'));
+
+INSERT INTO schema_changelog (version) VALUES (22);
+
+COMMIT TRANSACTION;
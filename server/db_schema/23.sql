@@ -0,0 +1,36 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Sends alarms through the calendar's webhooks instead of a single alarm webhook.
+-- When an alarm goes off it's queued in webhook_deliveries as an `alarm`
+-- notification for every webhook of its event, so it's signed, retried with
+-- backoff and logged like the other notifications (see 7.sql).
+--
+-- alarm_deliveries only keeps track of the alarms that went off: PENDING until
+-- they're queued, QUEUED once they are, CANCELLED if their event was trashed in
+-- between. Alarm notifications still waiting for a retry when their event is
+-- trashed are CANCELLED too.
+
+ALTER TABLE alarm_deliveries ADD COLUMN status TEXT NOT NULL DEFAULT 'PENDING';
+
+-- Row-level security (see 15.sql) would hide every row from a migration run by
+-- a regular role.
+SET LOCAL caser.unrestricted = 'on';
+
+UPDATE alarm_deliveries SET status = 'QUEUED' WHERE delivered_at IS NOT NULL;
+
+ALTER TABLE alarm_deliveries RENAME COLUMN delivered_at TO queued_at;
+ALTER TABLE alarm_deliveries DROP COLUMN attempts;
+ALTER TABLE alarm_deliveries DROP COLUMN last_error;
+
+ALTER TABLE alarm_deliveries ADD CONSTRAINT valid_alarm_delivery_status CHECK (status IN ('PENDING', 'QUEUED', 'CANCELLED'));
+
+DROP INDEX alarm_deliveries_pending;
+CREATE INDEX alarm_deliveries_pending ON alarm_deliveries (due_at) WHERE status = 'PENDING';
+
+ALTER TABLE webhook_deliveries DROP CONSTRAINT valid_status;
+ALTER TABLE webhook_deliveries ADD CONSTRAINT valid_status CHECK (status IN ('PENDING', 'DELIVERED', 'DEAD', 'CANCELLED'));
+
+INSERT INTO schema_changelog (version) VALUES (23);
+
+COMMIT TRANSACTION;
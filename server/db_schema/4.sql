@@ -0,0 +1,80 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Turns child events (events with a parent_event_id) into RFC 5545 RECURRENCE-ID
+-- overrides. An override now only stores the fields it changes, every NULL column
+-- is inherited from the parent event when instances are generated. The
+-- recurrence_id column stores the date of the instance the override replaces.
+--
+-- Also adds title, description and metadata columns to events, so that there's
+-- something other than the dates to override.
+
+ALTER TABLE events ADD COLUMN title TEXT;
+ALTER TABLE events ADD COLUMN description TEXT;
+ALTER TABLE events ADD COLUMN metadata JSONB;
+ALTER TABLE events ADD COLUMN recurrence_id DATE;
+
+-- Before this migration moving an instance meant adding its date to the parent's
+-- exdates and creating a child event, but nothing recorded which exdate belongs to
+-- which child. Each child gets the exdate of its parent closest to its start date
+-- that no other child has claimed yet (earlier children first). Children left
+-- without one, e.g. children of events that aren't recurring anymore, become
+-- standalone single events instead of overriding an unrelated instance.
+DO $$
+DECLARE
+    child RECORD;
+    claimed DATE;
+BEGIN
+    FOR child IN
+        SELECT id, parent_event_id, start_date FROM events
+        WHERE parent_event_id IS NOT NULL
+        ORDER BY parent_event_id, start_date, id
+    LOOP
+        SELECT exdate INTO claimed
+        FROM events AS parents, unnest(parents.exdates) AS exdate
+        WHERE
+            parents.id = child.parent_event_id
+            AND parents.rrule IS NOT NULL
+            AND exdate NOT IN (
+                SELECT recurrence_id FROM events
+                WHERE parent_event_id = child.parent_event_id AND recurrence_id IS NOT NULL
+            )
+        ORDER BY abs(exdate - child.start_date), exdate
+        LIMIT 1;
+
+        IF (claimed IS NULL) THEN
+            UPDATE events SET parent_event_id = NULL WHERE id = child.id;
+        ELSE
+            UPDATE events SET recurrence_id = claimed WHERE id = child.id;
+        END IF;
+    END LOOP;
+END;
+$$;
+
+-- Overrides replace the instance they override, so the dates of existing
+-- overrides don't have to be in their parent's exdates anymore.
+UPDATE events
+SET exdates = ARRAY(
+    SELECT exdate FROM unnest(events.exdates) AS exdate
+    WHERE exdate NOT IN (SELECT recurrence_id FROM events AS children WHERE children.parent_event_id = events.id)
+)
+WHERE rrule IS NOT NULL;
+
+-- Overrides only store what they change, so they can have null dates and
+-- a start time without an end time (the instance keeps its duration).
+ALTER TABLE events ALTER COLUMN start_date DROP NOT NULL;
+ALTER TABLE events ALTER COLUMN end_date DROP NOT NULL;
+ALTER TABLE events DROP CONSTRAINT start_and_end_times;
+
+ALTER TABLE events ADD CONSTRAINT start_and_end_times CHECK ((parent_event_id IS NOT NULL) OR ((start_time IS NULL) = (end_time IS NULL)));
+ALTER TABLE events ADD CONSTRAINT dates_required_when_not_override CHECK ((parent_event_id IS NOT NULL) OR ((start_date IS NOT NULL) AND (end_date IS NOT NULL)));
+ALTER TABLE events ADD CONSTRAINT recurrence_id_only_when_override CHECK ((parent_event_id IS NULL) = (recurrence_id IS NULL));
+ALTER TABLE events ADD CONSTRAINT unique_override UNIQUE (parent_event_id, recurrence_id);
+
+-- Deleting a recurring event deletes its overrides.
+ALTER TABLE events DROP CONSTRAINT fk_parent_event_id;
+ALTER TABLE events ADD CONSTRAINT fk_parent_event_id FOREIGN KEY (parent_event_id) REFERENCES events(id) ON DELETE CASCADE;
+
+INSERT INTO schema_changelog (version) VALUES (4);
+
+COMMIT TRANSACTION;
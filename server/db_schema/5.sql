@@ -0,0 +1,34 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Adds an organizer to events and creates the attendees table, which stores the
+-- people invited to an event along with their participation status (PARTSTAT).
+-- Attendees of an override store responses to a single instance of a recurring event.
+
+ALTER TABLE events ADD COLUMN organizer_email TEXT;
+ALTER TABLE events ADD COLUMN organizer_name TEXT;
+
+ALTER TABLE events ADD CONSTRAINT organizer_name_requires_email CHECK ((organizer_name IS NULL) OR (organizer_email IS NOT NULL));
+
+CREATE TABLE attendees (
+    id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    event_id uuid NOT NULL,
+    email TEXT NOT NULL,
+    display_name TEXT,
+    role TEXT NOT NULL DEFAULT 'REQ-PARTICIPANT',
+    partstat TEXT NOT NULL DEFAULT 'NEEDS-ACTION',
+    rsvp BOOLEAN NOT NULL DEFAULT FALSE,
+
+    CONSTRAINT valid_role CHECK (role IN ('CHAIR', 'REQ-PARTICIPANT', 'OPT-PARTICIPANT', 'NON-PARTICIPANT')),
+    CONSTRAINT valid_partstat CHECK (partstat IN ('NEEDS-ACTION', 'ACCEPTED', 'DECLINED', 'TENTATIVE', 'DELEGATED')),
+
+    CONSTRAINT pk_attendees PRIMARY KEY (id),
+    CONSTRAINT fk_event_id FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
+
+-- Emails are case insensitive, so we can't use a simple UNIQUE constraint here.
+CREATE UNIQUE INDEX unique_attendee ON attendees (event_id, lower(email));
+
+INSERT INTO schema_changelog (version) VALUES (5);
+
+COMMIT TRANSACTION;
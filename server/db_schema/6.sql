@@ -0,0 +1,65 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Creates the alarms table, which stores reminders of events (like RFC 5545's VALARM),
+-- and the tables the alarm scheduler uses to keep track of its progress:
+-- alarm_deliveries stores every time an alarm went off and whether it was delivered,
+-- scheduler_state stores up to which point in time the scheduler already looked for
+-- due alarms.
+
+CREATE TABLE alarms (
+    id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    event_id uuid NOT NULL,
+    action TEXT NOT NULL DEFAULT 'DISPLAY',
+
+    -- relative trigger, in minutes
+    trigger_offset INTEGER,
+    trigger_related TEXT NOT NULL DEFAULT 'START',
+
+    trigger_absolute TIMESTAMP WITHOUT TIME ZONE,
+
+    repeat INTEGER NOT NULL DEFAULT 0,
+
+    -- in minutes
+    repeat_interval INTEGER,
+
+    description TEXT,
+
+    CONSTRAINT valid_action CHECK (action IN ('DISPLAY', 'EMAIL', 'AUDIO')),
+    CONSTRAINT valid_trigger_related CHECK (trigger_related IN ('START', 'END')),
+    CONSTRAINT one_trigger CHECK ((trigger_offset IS NULL) <> (trigger_absolute IS NULL)),
+    CONSTRAINT repeat_requires_interval CHECK ((repeat = 0) OR (repeat > 0 AND repeat_interval > 0)),
+
+    CONSTRAINT pk_alarms PRIMARY KEY (id),
+    CONSTRAINT fk_event_id FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
+
+CREATE TABLE alarm_deliveries (
+    alarm_id uuid NOT NULL,
+    due_at TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+
+    -- instance of the event the alarm went off for, NULL if the event isn't recurring
+    recurrence_id DATE,
+
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMP WITHOUT TIME ZONE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+
+    CONSTRAINT pk_alarm_deliveries PRIMARY KEY (alarm_id, due_at),
+    CONSTRAINT fk_alarm_id FOREIGN KEY (alarm_id) REFERENCES alarms(id) ON DELETE CASCADE
+);
+
+CREATE INDEX alarm_deliveries_pending ON alarm_deliveries (due_at) WHERE delivered_at IS NULL;
+
+CREATE TABLE scheduler_state (
+    name TEXT NOT NULL PRIMARY KEY,
+    checked_until TIMESTAMP WITHOUT TIME ZONE NOT NULL
+);
+
+-- Alarms that were due before the scheduler existed are not delivered.
+INSERT INTO scheduler_state (name, checked_until) VALUES ('alarms', NOW());
+
+INSERT INTO schema_changelog (version) VALUES (6);
+
+COMMIT TRANSACTION;
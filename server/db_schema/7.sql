@@ -0,0 +1,113 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Creates the webhooks table, which stores subscriptions to changes of a calendar's
+-- events, and the webhook_deliveries table, which stores every notification sent (or
+-- to be sent) to a webhook. Notifications are queued by a trigger on the events table,
+-- in the same transaction as the change, and are POSTed by the server in the background.
+
+CREATE TABLE webhooks (
+    id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    calendar_id uuid NOT NULL,
+    url TEXT NOT NULL,
+    scope TEXT NOT NULL DEFAULT 'CALENDAR',
+
+    -- Not a foreign key, the webhooks of an event must outlive it
+    -- so that they are notified when it is deleted.
+    event_id uuid,
+
+    secret TEXT NOT NULL DEFAULT encode(gen_random_bytes(32), 'hex'),
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT valid_scope CHECK (scope IN ('CALENDAR', 'EVENT', 'INSTANCES')),
+    CONSTRAINT scope_requires_event_id CHECK ((scope = 'CALENDAR') = (event_id IS NULL)),
+
+    CONSTRAINT pk_webhooks PRIMARY KEY (id),
+    CONSTRAINT fk_calendar_id FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhooks_calendar_id ON webhooks (calendar_id);
+
+CREATE TABLE webhook_deliveries (
+    id uuid NOT NULL DEFAULT uuid_generate_v4(),
+    webhook_id uuid NOT NULL,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+
+    status TEXT NOT NULL DEFAULT 'PENDING',
+    attempts INTEGER NOT NULL DEFAULT 0,
+
+    created_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    next_attempt_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+    last_attempt_at TIMESTAMP WITHOUT TIME ZONE,
+    delivered_at TIMESTAMP WITHOUT TIME ZONE,
+
+    response_status INTEGER,
+    last_error TEXT,
+
+    CONSTRAINT valid_status CHECK (status IN ('PENDING', 'DELIVERED', 'DEAD')),
+
+    CONSTRAINT pk_webhook_deliveries PRIMARY KEY (id),
+    CONSTRAINT fk_webhook_id FOREIGN KEY (webhook_id) REFERENCES webhooks(id) ON DELETE CASCADE
+);
+
+CREATE INDEX webhook_deliveries_log ON webhook_deliveries (webhook_id, created_at);
+CREATE INDEX webhook_deliveries_pending ON webhook_deliveries (next_attempt_at) WHERE status = 'PENDING';
+
+
+
+CREATE OR REPLACE FUNCTION queue_webhook_deliveries() RETURNS TRIGGER AS $$
+DECLARE
+    changed events;
+    event_type TEXT;
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        changed := NEW;
+        event_type := 'event.created';
+    ELSIF (TG_OP = 'UPDATE') THEN
+        IF (NEW IS NOT DISTINCT FROM OLD) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := NEW;
+        event_type := 'event.updated';
+    ELSE
+        changed := OLD;
+        event_type := 'event.deleted';
+    END IF;
+
+    INSERT INTO webhook_deliveries (webhook_id, event_type, payload)
+    SELECT
+        webhooks.id,
+        event_type,
+        jsonb_build_object(
+            'type', event_type,
+            'webhook_id', webhooks.id,
+            'calendar_id', changed.calendar_id,
+            'event_id', changed.id,
+            'parent_id', changed.parent_event_id,
+            'recurrence_id', to_char(changed.recurrence_id, 'YYYY-MM-DD'),
+            'occurred_at', to_char(NOW(), 'YYYY-MM-DD"T"HH24:MI:SS')
+        )
+    FROM webhooks
+    WHERE
+        webhooks.calendar_id = changed.calendar_id
+        AND (
+            webhooks.scope = 'CALENDAR'
+            OR (webhooks.scope = 'EVENT' AND webhooks.event_id = changed.id)
+            OR (webhooks.scope = 'INSTANCES' AND webhooks.event_id IN (changed.id, changed.parent_event_id))
+        );
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+COMMENT ON FUNCTION queue_webhook_deliveries() IS 'Queues a delivery for every webhook subscribed to the changed event. Should be used in AFTER INSERT OR UPDATE OR DELETE triggers on events.';
+
+CREATE TRIGGER queue_webhook_deliveries
+AFTER INSERT OR UPDATE OR DELETE ON events
+FOR EACH ROW EXECUTE PROCEDURE queue_webhook_deliveries();
+
+INSERT INTO schema_changelog (version) VALUES (7);
+
+COMMIT TRANSACTION;
@@ -0,0 +1,74 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Creates the event_changes table, a log of every time an event was created, updated
+-- or deleted, which backs sync tokens. Deleted events are kept in the log (as
+-- tombstones) so that clients can find out about deletions. Existing events are added
+-- to the log as created, so that syncing from scratch returns them.
+
+CREATE TABLE event_changes (
+    seq BIGINT GENERATED ALWAYS AS IDENTITY,
+    calendar_id uuid NOT NULL,
+
+    -- Not a foreign key, deleted events stay in the log.
+    event_id uuid NOT NULL,
+    parent_event_id uuid,
+
+    change_type TEXT NOT NULL,
+    changed_at TIMESTAMP WITHOUT TIME ZONE NOT NULL DEFAULT NOW(),
+
+    CONSTRAINT valid_change_type CHECK (change_type IN ('CREATED', 'UPDATED', 'DELETED')),
+
+    CONSTRAINT pk_event_changes PRIMARY KEY (seq),
+    CONSTRAINT fk_calendar_id FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE
+);
+
+CREATE INDEX event_changes_calendar_seq ON event_changes (calendar_id, seq);
+
+INSERT INTO event_changes (calendar_id, event_id, parent_event_id, change_type)
+SELECT calendar_id, id, parent_event_id, 'CREATED' FROM events ORDER BY last_modified;
+
+
+
+CREATE OR REPLACE FUNCTION log_event_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed events;
+    change_type TEXT;
+BEGIN
+    IF (TG_OP = 'INSERT') THEN
+        changed := NEW;
+        change_type := 'CREATED';
+    ELSIF (TG_OP = 'UPDATE') THEN
+        IF (NEW IS NOT DISTINCT FROM OLD) THEN
+            RETURN NULL;
+        END IF;
+
+        changed := NEW;
+        change_type := 'UPDATED';
+    ELSE
+        changed := OLD;
+        change_type := 'DELETED';
+    END IF;
+
+    -- Sequence values are handed out in the order changes are made, not in the
+    -- order they're committed. Changes to the same calendar are serialized so that
+    -- a change can't become visible after a change with a greater seq, otherwise
+    -- a client could sync past it and never see it.
+    PERFORM pg_advisory_xact_lock(hashtext(changed.calendar_id::TEXT));
+
+    INSERT INTO event_changes (calendar_id, event_id, parent_event_id, change_type)
+    VALUES (changed.calendar_id, changed.id, changed.parent_event_id, change_type);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+COMMENT ON FUNCTION log_event_change() IS 'Logs the change in event_changes. Should be used in AFTER INSERT OR UPDATE OR DELETE triggers on events.';
+
+CREATE TRIGGER log_event_change
+AFTER INSERT OR UPDATE OR DELETE ON events
+FOR EACH ROW EXECUTE PROCEDURE log_event_change();
+
+INSERT INTO schema_changelog (version) VALUES (8);
+
+COMMIT TRANSACTION;
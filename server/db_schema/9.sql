@@ -0,0 +1,52 @@
+BEGIN TRANSACTION;
+
+-- DESCRIPTION --
+-- Notifies changes to calendars and events on the caser_changes channel (through
+-- NOTIFY), which the server listens to in order to push changes to clients as soon
+-- as they happen. Notifications are only sent when the transaction commits.
+
+CREATE OR REPLACE FUNCTION notify_change() RETURNS TRIGGER AS $$
+DECLARE
+    changed JSONB;
+BEGIN
+    IF (TG_OP = 'DELETE') THEN
+        changed := to_jsonb(OLD);
+    ELSIF (TG_OP = 'UPDATE' AND NEW IS NOT DISTINCT FROM OLD) THEN
+        RETURN NULL;
+    ELSE
+        changed := to_jsonb(NEW);
+    END IF;
+
+    PERFORM pg_notify('caser_changes', jsonb_build_object(
+        'table', TG_TABLE_NAME,
+        'op', TG_OP,
+        'calendar_id', CASE TG_TABLE_NAME WHEN 'calendars' THEN changed->'id' ELSE changed->'calendar_id' END
+    )::TEXT);
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+COMMENT ON FUNCTION notify_change() IS 'Notifies the change on the caser_changes channel. Should be used in AFTER INSERT OR UPDATE OR DELETE triggers on calendars and events.';
+
+
+
+
+DROP TRIGGER IF EXISTS notify_change ON calendars;
+
+CREATE TRIGGER notify_change
+AFTER INSERT OR UPDATE OR DELETE ON calendars
+FOR EACH ROW EXECUTE PROCEDURE notify_change();
+
+
+
+
+DROP TRIGGER IF EXISTS notify_change ON events;
+
+CREATE TRIGGER notify_change
+AFTER INSERT OR UPDATE OR DELETE ON events
+FOR EACH ROW EXECUTE PROCEDURE notify_change();
+
+INSERT INTO schema_changelog (version) VALUES (9);
+
+COMMIT TRANSACTION;
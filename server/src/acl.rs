@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use postgres::Row;
+use uuid::Uuid;
+use crate::connection_pool::PgsqlConn;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use crate::calendar::belongs_to_tenant;
+use crate::authentication::auth_guard::{Principal, Subject, Scope};
+
+pub use caser_common::acl::*;
+
+impl FromRow for CalendarGrant
+{
+    type SelfType = CalendarGrant;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let principal_type: String = row.get_cell("principal_type")?;
+        let role: String = row.get_cell("role")?;
+        let visibility: String = row.get_cell("visibility")?;
+
+        Ok(
+            CalendarGrant {
+                id: row.get_cell("id")?,
+                calendar_id: row.get_cell("calendar_id")?,
+                principal_type: PrincipalType::from_str(&principal_type)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                principal_id: row.get_cell("principal_id")?,
+                role: Role::from_str(&role)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                visibility: Visibility::from_str(&visibility)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                created_at: row.get_cell("created_at")?,
+            }
+        )
+    }
+}
+
+/// What a principal can do with a calendar and which of its events
+/// it can see the details of.
+#[derive(Debug, Copy, Clone)]
+pub struct Permission
+{
+    pub role: Role,
+    pub visibility: Visibility,
+}
+
+impl Permission
+{
+    pub const OWNER: Permission = Permission { role: Role::Owner, visibility: Visibility::All };
+
+    /// The highest role and visibility of both permissions, principals
+    /// get the best of all the grants they have on a calendar.
+    fn max(self, other: Permission) -> Permission
+    {
+        Permission {
+            role: self.role.max(other.role),
+            visibility: self.visibility.max(other.visibility),
+        }
+    }
+}
+
+/// The calendars of its tenant a principal can see.
+pub enum Access
+{
+    /// All of them, as their owner. Only principals with
+    /// the `SUPER` scope (see db_schema/17.sql).
+    All,
+
+    /// The ones it has a grant on, with the highest role
+    /// and visibility it was given.
+    Granted(HashMap<Uuid, Permission>),
+}
+
+impl Access
+{
+    pub fn permission(&self, calendar_id: &Uuid) -> Option<Permission>
+    {
+        match self
+        {
+            Access::All => Some(Permission::OWNER),
+            Access::Granted(permissions) => permissions.get(calendar_id).copied(),
+        }
+    }
+
+    pub fn role(&self, calendar_id: &Uuid) -> Option<Role>
+    {
+        self.permission(calendar_id).map(|p| p.role)
+    }
+}
+
+/// The principal type and id grants of `subject` are stored with.
+pub fn grantee(subject: &Subject) -> (PrincipalType, String)
+{
+    match subject
+    {
+        Subject::ApiKey(id) => (PrincipalType::ApiKey, id.to_string()),
+        Subject::User(sub) => (PrincipalType::User, sub.clone()),
+    }
+}
+
+/// Finds the calendars `principal` can see. Grants are matched by
+/// the principal itself and by the groups it's in, principals
+/// without grants (API keys included) see no calendar.
+pub fn get_access(db: &mut PgsqlConn, principal: &Principal) -> Result<Access, DatabaseError>
+{
+    if principal.get_scopes().allows(Scope::Super)
+    {
+        return Ok(Access::All);
+    }
+
+    let (principal_type, principal_id) = grantee(principal.get_subject());
+
+    let query = "
+        SELECT calendar_id, role, visibility FROM calendar_grants
+        WHERE
+            (principal_type = $1 AND principal_id = $2)
+            OR (principal_type = 'GROUP' AND principal_id = ANY($3));
+    ";
+
+    let rows = db.query(query, &[&principal_type.to_string(), &principal_id, &principal.get_groups()])?;
+
+    let mut permissions: HashMap<Uuid, Permission> = HashMap::new();
+    for row in rows
+    {
+        let role: String = row.get_cell("role")?;
+        let visibility: String = row.get_cell("visibility")?;
+
+        let permission = Permission {
+            role: Role::from_str(&role).map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+            visibility: Visibility::from_str(&visibility).map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+        };
+
+        let highest = permissions.entry(row.get_cell("calendar_id")?).or_insert(permission);
+        *highest = permission.max(*highest);
+    }
+
+    Ok(Access::Granted(permissions))
+}
+
+/// The permission `principal` has on the calendar with id `calendar_id`,
+/// `None` if it can't see the calendar: if it's missing, of another tenant,
+/// if the principal is restricted from it or has no grant on it.
+pub fn get_calendar_permission(db: &mut PgsqlConn, principal: &Principal, calendar_id: &Uuid) -> Result<Option<Permission>, DatabaseError>
+{
+    if principal.is_restricted_from(calendar_id) || !belongs_to_tenant(db, calendar_id, &principal.get_tenant_id())?
+    {
+        return Ok(None);
+    }
+
+    Ok(get_access(db, principal)?.permission(calendar_id))
+}
+
+/// Ids of the calendars of its tenant `principal` can see,
+/// `None` if it can see all of them.
+pub fn get_visible_calendar_ids(db: &mut PgsqlConn, principal: &Principal) -> Result<Option<Vec<Uuid>>, DatabaseError>
+{
+    get_calendar_ids_with_role(db, principal, Role::FreeBusy)
+}
+
+/// Ids of the calendars of its tenant `principal` has at least `role`
+/// on, `None` if it has it on all of them.
+pub fn get_calendar_ids_with_role(db: &mut PgsqlConn, principal: &Principal, role: Role) -> Result<Option<Vec<Uuid>>, DatabaseError>
+{
+    let granted = match get_access(db, principal)?
+    {
+        Access::All => None,
+        Access::Granted(permissions) => Some(
+            permissions.into_iter()
+                .filter(|(_, permission)| permission.role >= role)
+                .map(|(id, _)| id)
+                .collect::<Vec<Uuid>>()
+        ),
+    };
+
+    Ok(
+        match (granted, principal.get_calendar_ids())
+        {
+            (None, restricted) => restricted.map(|ids| ids.to_vec()),
+            (Some(granted), None) => Some(granted),
+            (Some(granted), Some(restricted)) => Some(granted.into_iter().filter(|id| restricted.contains(id)).collect()),
+        }
+    )
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn principals_without_grants_see_no_calendar()
+    {
+        // What `get_access` finds for a new API key, `get_calendar_permission`
+        // then makes its calendar routes respond with 404.
+        let access = Access::Granted(HashMap::new());
+
+        assert!(access.permission(&Uuid::new_v4()).is_none());
+        assert!(Access::All.permission(&Uuid::new_v4()).is_some());
+    }
+}
@@ -0,0 +1,32 @@
+use postgres::Row;
+use std::str::FromStr;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+
+pub use caser_common::alarm::*;
+
+impl FromRow for Alarm
+{
+    type SelfType = Alarm;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let action: String = row.get_cell("action")?;
+        let related: String = row.get_cell("trigger_related")?;
+
+        Ok(
+            Alarm {
+                id: row.get_cell("id")?,
+                action: AlarmAction::from_str(&action)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                trigger_offset: row.get_cell("trigger_offset")?,
+                related: AlarmRelated::from_str(&related)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                trigger_absolute: row.get_cell("trigger_absolute")?,
+                repeat: row.get_cell("repeat")?,
+                repeat_interval: row.get_cell("repeat_interval")?,
+                description: row.get_cell("description")?,
+            }
+        )
+    }
+}
\ No newline at end of file
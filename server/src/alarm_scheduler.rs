@@ -0,0 +1,278 @@
+//! Background job that looks for alarms that are due and delivers them.
+//!
+//! The scheduler keeps track of the point in time up to which it already
+//! looked for due alarms (the `checked_until` column of the `scheduler_state`
+//! table). On every tick it looks for alarms that are due between `checked_until`
+//! and now, stores them in `alarm_deliveries` and moves `checked_until` forward,
+//! all in the same transaction. This way restarting the server never skips
+//! alarms (`checked_until` only moves if the due alarms were stored) and never
+//! schedules an alarm twice (every window of time is only checked once, and
+//! `alarm_deliveries` can't have the same alarm twice for the same time).
+//!
+//! Stored alarms are then queued in `webhook_deliveries` as `alarm`
+//! notifications for the webhooks of their event, the webhook dispatcher
+//! signs, sends and retries them like any other notification. Alarms of
+//! events that were trashed in between are cancelled instead.
+
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::error::Error;
+use std::collections::HashMap;
+use chrono::{NaiveDateTime, NaiveDate};
+use uuid::Uuid;
+use crate::connection_pool::{PgsqlPool, PgsqlConn};
+use crate::configs::Configs;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::DatabaseError;
+use crate::alarm::Alarm;
+use crate::event::{Event, EventOverride, GenerateInstances};
+use serde_json::{Value, json};
+
+/// Name of the scheduler's row in the `scheduler_state` table.
+const SCHEDULER_NAME: &str = "alarms";
+
+/// Maximum amount of alarms queued in a single tick.
+const QUEUE_BATCH_SIZE: i64 = 100;
+
+/// Starts the alarm scheduler in a new thread.
+pub fn spawn(pool: PgsqlPool, configs: Configs) -> JoinHandle<()>
+{
+    thread::spawn(move || loop
+    {
+        if let Err(e) = tick(&pool)
+        {
+            log::error!("Alarm scheduler failed: {}", e);
+        }
+
+        thread::sleep(Duration::from_secs(configs.get_alarm_poll_interval()));
+    })
+}
+
+fn tick(pool: &PgsqlPool) -> Result<(), Box<dyn Error>>
+{
+    let mut db = pool.get_unrestricted_conn()?;
+
+    schedule_due_alarms(&mut db)?;
+    queue_pending_alarms(&mut db)?;
+
+    Ok(())
+}
+
+/// A single time an alarm goes off.
+struct DueAlarm
+{
+    alarm_id: Uuid,
+    due_at: NaiveDateTime,
+
+    /// The instance of the event this alarm is for,
+    /// None if the event is not recurring.
+    recurrence_id: Option<NaiveDate>,
+}
+
+/// Looks for alarms that are due between the last time this was
+/// executed and now and stores them in `alarm_deliveries`.
+fn schedule_due_alarms(db: &mut PgsqlConn) -> Result<(), DatabaseError>
+{
+    let mut transaction = db.transaction()?;
+
+    // FOR UPDATE makes sure two servers using the same database
+    // don't check the same window of time.
+    let query = "SELECT checked_until, NOW()::TIMESTAMP AS now FROM scheduler_state WHERE name = $1 FOR UPDATE";
+    let rows = transaction.query(query, &[&SCHEDULER_NAME])?;
+
+    let (from, to): (NaiveDateTime, NaiveDateTime) = match rows.get(0)
+    {
+        Some(row) => (row.get_cell("checked_until")?, row.get_cell("now")?),
+        None => return Ok(()),
+    };
+
+    let query = "SELECT * FROM alarms WHERE trigger_offset IS NOT NULL OR trigger_absolute <= $1";
+    let alarms = transaction.query(query, &[&to])?
+        .into_iter()
+        .map(|row| Ok((row.get_cell::<Uuid>("event_id")?, Alarm::from_row(&row)?)))
+        .collect::<Result<Vec<(Uuid, Alarm)>, DatabaseError>>()?;
+
+    let event_ids: Vec<Uuid> = alarms.iter().map(|(event_id, _)| *event_id).collect();
+
+    let mut events = HashMap::new();
+    for row in transaction.query("SELECT * FROM events WHERE id = ANY($1) AND deleted_at IS NULL", &[&event_ids])?
+    {
+        events.insert(row.get_cell::<Uuid>("id")?, Event::from_row(&row)?);
+    }
+
+    let mut overrides: HashMap<Uuid, Vec<EventOverride>> = HashMap::new();
+    for row in transaction.query("SELECT * FROM events WHERE parent_event_id = ANY($1) AND deleted_at IS NULL", &[&event_ids])?
+    {
+        if let Event::Override(event_override) = Event::from_row(&row)?
+        {
+            overrides.entry(event_override.get_parent_id()).or_default().push(event_override);
+        }
+    }
+
+    let mut due_alarms = vec![];
+    for (event_id, alarm) in &alarms
+    {
+        if let Some(event) = events.get(event_id)
+        {
+            let no_overrides = vec![];
+            let event_overrides = overrides.get(event_id).unwrap_or(&no_overrides);
+
+            due_alarms.extend(
+                find_due_alarms(alarm, event, event_overrides, from, to)
+            );
+        }
+    }
+
+    let query = "
+        INSERT INTO alarm_deliveries (alarm_id, due_at, recurrence_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT ON CONSTRAINT pk_alarm_deliveries DO NOTHING;
+    ";
+
+    for due_alarm in due_alarms
+    {
+        transaction.execute(query, &[&due_alarm.alarm_id, &due_alarm.due_at, &due_alarm.recurrence_id])?;
+    }
+
+    transaction.execute("UPDATE scheduler_state SET checked_until = $2 WHERE name = $1", &[&SCHEDULER_NAME, &to])?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
+
+/// Finds the times `alarm` (of `event`) goes off between `from` (exclusive)
+/// and `to` (inclusive).
+fn find_due_alarms(alarm: &Alarm, event: &Event, overrides: &[EventOverride], from: NaiveDateTime, to: NaiveDateTime) -> Vec<DueAlarm>
+{
+    let alarm_id = match alarm.id
+    {
+        Some(id) => id,
+        None => return vec![],
+    };
+
+    let in_window = |due_at: &NaiveDateTime| *due_at > from && *due_at <= to;
+
+    match event
+    {
+        Event::Single(event) => alarm.due_times(&event.get_span())
+            .into_iter()
+            .filter(in_window)
+            .map(|due_at| DueAlarm { alarm_id, due_at, recurrence_id: None })
+            .collect(),
+
+        // Absolute triggers only go off once, no matter how many
+        // instances the event has.
+        Event::Recurring(event) if alarm.trigger_absolute.is_some() => alarm.due_times(&event.get_span())
+            .into_iter()
+            .filter(in_window)
+            .map(|due_at| DueAlarm { alarm_id, due_at, recurrence_id: None })
+            .collect(),
+
+        Event::Recurring(event) =>
+        {
+            // Only instances that start close enough to the window can
+            // have alarms that go off inside of it.
+            let offset = chrono::Duration::minutes(alarm.trigger_offset.unwrap_or(0) as i64);
+            let since = (from - offset - alarm.get_repeat_duration() - event.get_span().get_duration()).date();
+            let until = (to - offset).date();
+
+            event
+                .generate_instances(Some(since), Some(until), overrides, 0, usize::MAX)
+                .into_iter()
+                .flat_map(|instance|
+                    alarm.due_times(&instance.get_span())
+                        .into_iter()
+                        .filter(in_window)
+                        .map(move |due_at| DueAlarm { alarm_id, due_at, recurrence_id: Some(instance.get_recurrence_id()) })
+                )
+                .collect()
+        },
+
+        Event::Override(_) => vec![],
+    }
+}
+
+/// Queues the alarms that went off as `alarm` notifications of the
+/// webhooks of their event (see db_schema/23.sql).
+fn queue_pending_alarms(db: &mut PgsqlConn) -> Result<(), DatabaseError>
+{
+    let mut transaction = db.transaction()?;
+
+    // Events can be trashed between the time their alarm
+    // goes off and the time it's queued.
+    let cancel = "
+        UPDATE alarm_deliveries SET status = 'CANCELLED'
+        WHERE status = 'PENDING' AND alarm_id IN (
+            SELECT alarms.id FROM alarms
+            JOIN events ON events.id = alarms.event_id
+            WHERE events.deleted_at IS NOT NULL
+        );
+    ";
+
+    transaction.execute(cancel, &[])?;
+
+    // SKIP LOCKED lets other servers using the same database
+    // queue the alarms this one didn't lock.
+    let query = "
+        SELECT
+            alarm_deliveries.alarm_id, alarm_deliveries.due_at, alarm_deliveries.recurrence_id,
+            alarms.action, alarms.description, alarms.event_id, events.parent_event_id, events.calendar_id
+        FROM alarm_deliveries
+        JOIN alarms ON alarms.id = alarm_deliveries.alarm_id
+        JOIN events ON events.id = alarms.event_id AND events.deleted_at IS NULL
+        WHERE alarm_deliveries.status = 'PENDING'
+        ORDER BY alarm_deliveries.due_at
+        LIMIT $1
+        FOR UPDATE OF alarm_deliveries SKIP LOCKED;
+    ";
+
+    let rows = transaction.query(query, &[&QUEUE_BATCH_SIZE])?;
+
+    // Same webhooks as the changes of the event would notify,
+    // see queue_webhook_deliveries in db_schema/21.sql.
+    let queue = "
+        INSERT INTO webhook_deliveries (webhook_id, event_type, payload)
+        SELECT webhooks.id, 'alarm', $4::JSONB || jsonb_build_object('webhook_id', webhooks.id)
+        FROM webhooks
+        WHERE
+            webhooks.calendar_id = $1
+            AND (
+                webhooks.scope = 'CALENDAR'
+                OR (webhooks.scope = 'EVENT' AND webhooks.event_id = $2)
+                OR (webhooks.scope = 'INSTANCES' AND webhooks.event_id IN ($2, $3))
+            );
+    ";
+
+    for row in rows
+    {
+        let alarm_id: Uuid = row.get_cell("alarm_id")?;
+        let due_at: NaiveDateTime = row.get_cell("due_at")?;
+        let event_id: Uuid = row.get_cell("event_id")?;
+        let parent_id: Option<Uuid> = row.get_cell("parent_event_id")?;
+        let calendar_id: Uuid = row.get_cell("calendar_id")?;
+
+        let payload: Value = json!({
+            "type": "alarm",
+            "alarm_id": alarm_id,
+            "event_id": event_id,
+            "calendar_id": calendar_id,
+            "parent_id": parent_id,
+            "recurrence_id": row.get_cell::<Option<NaiveDate>>("recurrence_id")?.map(|d| d.format("%Y-%m-%d").to_string()),
+            "due_at": due_at.format("%Y-%m-%dT%H:%M").to_string(),
+            "action": row.get_cell::<String>("action")?,
+            "description": row.get_cell::<Option<String>>("description")?,
+        });
+
+        transaction.execute(queue, &[&calendar_id, &event_id, &parent_id, &payload])?;
+
+        transaction.execute(
+            "UPDATE alarm_deliveries SET status = 'QUEUED', queued_at = NOW() WHERE alarm_id = $1 AND due_at = $2",
+            &[&alarm_id, &due_at]
+        )?;
+    }
+
+    transaction.commit()?;
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use postgres::Row;
+use uuid::Uuid;
+use std::collections::HashMap;
+use std::str::FromStr;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use crate::connection_pool::PgsqlConn;
+
+pub use caser_common::attendee::*;
+
+impl FromRow for Attendee
+{
+    type SelfType = Attendee;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let role: String = row.get_cell("role")?;
+        let partstat: String = row.get_cell("partstat")?;
+
+        Ok(
+            Attendee {
+                id: row.get_cell("id")?,
+                email: row.get_cell("email")?,
+                display_name: row.get_cell("display_name")?,
+                role: AttendeeRole::from_str(&role)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                partstat: ParticipationStatus::from_str(&partstat)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                rsvp: row.get_cell("rsvp")?,
+            }
+        )
+    }
+}
+
+/// Gets the attendees of all events in `event_ids`, grouped by event id.
+///
+/// Events without attendees are not present in the returned map.
+pub fn get_attendees(db: &mut PgsqlConn, event_ids: &[Uuid]) -> Result<HashMap<Uuid, Vec<Attendee>>, DatabaseError>
+{
+    let query = "SELECT * FROM attendees WHERE event_id = ANY($1) ORDER BY email";
+
+    let rows = db.query(query, &[&event_ids])?;
+
+    let mut attendees: HashMap<Uuid, Vec<Attendee>> = HashMap::new();
+    for row in rows
+    {
+        attendees
+            .entry(row.get_cell("event_id")?)
+            .or_default()
+            .push(Attendee::from_row(&row)?);
+    }
+
+    Ok(attendees)
+}
@@ -0,0 +1,40 @@
+use std::str::FromStr;
+use postgres::Row;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use crate::acl::PrincipalType;
+
+pub use caser_common::audit::*;
+
+impl FromRow for AuditEntry
+{
+    type SelfType = AuditEntry;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let principal_type: Option<String> = row.get_cell("principal_type")?;
+        let resource_type: String = row.get_cell("resource_type")?;
+        let action: String = row.get_cell("action")?;
+
+        Ok(
+            AuditEntry {
+                id: row.get_cell("id")?,
+                tenant_id: row.get_cell("tenant_id")?,
+                principal_type: principal_type
+                    .map(|t| PrincipalType::from_str(&t))
+                    .transpose()
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                principal_id: row.get_cell("principal_id")?,
+                route: row.get_cell("route")?,
+                resource_type: ResourceType::from_str(&resource_type)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                resource_id: row.get_cell("resource_id")?,
+                action: AuditAction::from_str(&action)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                before: row.get_cell("before")?,
+                after: row.get_cell("after")?,
+                created_at: row.get_cell("created_at")?,
+            }
+        )
+    }
+}
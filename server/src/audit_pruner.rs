@@ -0,0 +1,42 @@
+//! Background job that removes audit log entries older than
+//! `AUDIT_RETENTION_DAYS`. Only started if it's set.
+
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::error::Error;
+use crate::connection_pool::PgsqlPool;
+
+/// How often (in seconds) old entries are removed.
+const PRUNE_INTERVAL: u64 = 60 * 60;
+
+/// Starts the audit pruner in a new thread.
+pub fn spawn(pool: PgsqlPool, retention_days: u32) -> JoinHandle<()>
+{
+    thread::spawn(move || loop
+    {
+        if let Err(e) = prune(&pool, retention_days)
+        {
+            log::error!("Audit pruner failed: {}", e);
+        }
+
+        thread::sleep(Duration::from_secs(PRUNE_INTERVAL));
+    })
+}
+
+fn prune(pool: &PgsqlPool, retention_days: u32) -> Result<(), Box<dyn Error>>
+{
+    let mut db = pool.get_unrestricted_conn()?;
+    let mut transaction = db.transaction()?;
+
+    // The audit log is append-only unless this is set (see db_schema/19.sql).
+    transaction.batch_execute("SET LOCAL caser.audit_pruning = 'on';")?;
+
+    transaction.execute(
+        "DELETE FROM audit_log WHERE created_at < NOW() - $1::INTEGER * INTERVAL '1 day';",
+        &[&(retention_days as i32)]
+    )?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! API keys as clients send them and as they're stored.
+//!
+//! A key is `<id>.<secret>`: the id finds the key in the database and the
+//! secret proves the client has it. Only a salted SHA-256 hash of the secret
+//! is stored, so the key can't be read back, it's shown once when it's
+//! created (or rotated). Secrets are 256 random bits, a slow hash would
+//! not make them any harder to guess.
+//!
+//! Keys created before this scheme are plain UUIDs, their hashes were
+//! computed by db_schema/16.sql with the UUID as the secret.
+
+use std::str::FromStr;
+use ring::digest;
+use ring::constant_time;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::error::Unspecified;
+use uuid::Uuid;
+
+const SECRET_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+
+/// What a client sent to authenticate.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Credentials
+{
+    Key { id: Uuid, secret: String },
+
+    /// A key created before keys had ids, see the module's docs.
+    Legacy(Uuid),
+}
+
+impl FromStr for Credentials
+{
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let s = s.trim();
+
+        match s.split_once('.')
+        {
+            Some((id, secret)) if is_hex(secret) && secret.len() == SECRET_LEN * 2 => Ok(
+                Credentials::Key { id: Uuid::from_str(id).map_err(|_| ())?, secret: secret.to_owned() }
+            ),
+            Some(_) => Err(()),
+            None => Uuid::from_str(s).map(Credentials::Legacy).map_err(|_| ()),
+        }
+    }
+}
+
+/// A secret and what's stored of it.
+pub struct NewSecret
+{
+    pub secret: String,
+    pub salt: String,
+    pub hash: String,
+}
+
+impl NewSecret
+{
+    pub fn generate() -> Result<NewSecret, Unspecified>
+    {
+        let random = SystemRandom::new();
+
+        let mut secret = [0u8; SECRET_LEN];
+        let mut salt = [0u8; SALT_LEN];
+        random.fill(&mut secret)?;
+        random.fill(&mut salt)?;
+
+        let secret = to_hex(&secret);
+        let hash = hash_secret(&salt, &secret);
+
+        Ok(NewSecret { secret, salt: to_hex(&salt), hash })
+    }
+}
+
+/// Formats the key clients send, from its id and secret.
+pub fn format_key(id: &Uuid, secret: &str) -> String
+{
+    format!("{}.{}", id, secret)
+}
+
+/// Hashes `secret` salted with `salt`, returns the hash as a lowercase hex string.
+pub fn hash_secret(salt: &[u8], secret: &str) -> String
+{
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(salt);
+    context.update(secret.as_bytes());
+
+    to_hex(context.finish().as_ref())
+}
+
+/// Unsalted hash of a legacy key, stored along with its salted hash so
+/// that the key can be found with an index. Legacy keys are random UUIDs,
+/// so they don't need a salt to resist brute force.
+pub fn legacy_digest(key: &Uuid) -> String
+{
+    hash_secret(&[], &key.to_string())
+}
+
+/// Checks if `secret` hashes to `hash`, `salt` and `hash` being
+/// the hex strings stored in the database.
+pub fn verify_secret(salt: &str, hash: &str, secret: &str) -> bool
+{
+    match from_hex(salt)
+    {
+        Some(salt) => constant_time::verify_slices_are_equal(hash_secret(&salt, secret).as_bytes(), hash.as_bytes()).is_ok(),
+        None => false,
+    }
+}
+
+fn is_hex(s: &str) -> bool
+{
+    s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn to_hex(bytes: &[u8]) -> String
+{
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>>
+{
+    if s.len() % 2 != 0 || !is_hex(s)
+    {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    const ID: &str = "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60";
+
+    #[test]
+    fn parses_credentials()
+    {
+        let id = Uuid::from_str(ID).unwrap();
+        let secret = "ab".repeat(SECRET_LEN);
+
+        assert_eq!(Credentials::from_str(&format_key(&id, &secret)), Ok(Credentials::Key { id, secret: secret.clone() }));
+        assert_eq!(Credentials::from_str(ID), Ok(Credentials::Legacy(id)));
+
+        assert_eq!(Credentials::from_str(&format!("{}.abc", ID)), Err(()));
+        assert_eq!(Credentials::from_str(&format!("garbage.{}", secret)), Err(()));
+        assert_eq!(Credentials::from_str("garbage"), Err(()));
+    }
+
+    #[test]
+    fn verifies_generated_secrets()
+    {
+        let new = NewSecret::generate().unwrap();
+
+        assert_eq!(new.secret.len(), SECRET_LEN * 2);
+        assert!(verify_secret(&new.salt, &new.hash, &new.secret));
+        assert!(!verify_secret(&new.salt, &new.hash, &"00".repeat(SECRET_LEN)));
+        assert!(!verify_secret("not hex", &new.hash, &new.secret));
+    }
+
+    #[test]
+    fn hashes_salt_then_secret()
+    {
+        // Same as encode(digest('salt' || 'secret', 'sha256'), 'hex') in PostgreSQL.
+        assert_eq!(
+            hash_secret(b"salt", "secret"),
+            "bede90386d450cea8b77b822f8887065e4e5abf132c2f9dccfcc7fbd4cba5e35"
+        );
+    }
+
+    #[test]
+    fn digests_legacy_keys_like_the_migration()
+    {
+        // Same as encode(digest(api_key::TEXT, 'sha256'), 'hex') in 16.sql.
+        assert_eq!(
+            legacy_digest(&Uuid::from_str(ID).unwrap()),
+            "90d55384a50aee9a7724bda87d309515ae3e36d7297e56f9ccffa2928a8551e8"
+        );
+    }
+}
@@ -1,53 +1,510 @@
 use rocket::request::{FromRequest, Outcome};
-use rocket::Request;
-use rocket::http::Status;
-use crate::connection_pool::PgsqlConn;
-use rocket::outcome::IntoOutcome;
+use rocket::{Request, State};
+use rocket::http::{RawStr, Status};
+use crate::connection_pool::{PgsqlConn, PgsqlPool};
 use uuid::Uuid;
 use std::str::FromStr;
+use std::ops::Deref;
+use crate::acl::{Role, Visibility, Permission, get_calendar_permission};
 use crate::database_helpers::RowHelpers;
+use crate::database_error::DatabaseError;
+use crate::authentication::api_key::{Credentials, legacy_digest, verify_secret};
+use crate::authentication::jwt::JwtVerifier;
+use okapi::Map;
+use okapi::openapi3::{RefOr, SecurityScheme, SecuritySchemeData};
 
-pub struct InvalidScopeError;
+pub use caser_common::scope::*;
 
-pub struct ApiKey
+/// Who made a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Subject
 {
-    key: Uuid,
+    /// An API key, by id.
+    ApiKey(Uuid),
 
-    // TODO: use a bitmask here to reduce allocations (?)
-    scopes: Vec<String>,
+    /// A user of the JWT issuer, by `sub` claim.
+    User(String),
 }
 
-impl<'a, 'r> FromRequest<'a, 'r> for ApiKey
+/// An authenticated API key or bearer token.
+#[derive(Clone)]
+pub struct Principal
 {
-    type Error = ();
+    subject: Subject,
+
+    /// The tenant the principal belongs to, it can
+    /// only see this tenant's calendars.
+    tenant_id: Uuid,
+
+    scopes: Scopes,
+
+    /// If set the principal can only see these calendars (of its
+    /// tenant). Only API keys can be restricted to calendars.
+    calendar_ids: Option<Vec<Uuid>>,
+
+    /// Groups of the JWT issuer the principal is in, its grants
+    /// include the grants of its groups.
+    groups: Vec<String>,
+
+    /// The principal's permission on the calendar of the route's `calendar_id`
+    /// parameter, set when the request is authenticated. `None` if the route
+    /// has no such parameter or if the principal can't see the calendar.
+    calendar_permission: Option<Permission>,
+}
+
+impl Principal
+{
+    pub fn get_subject(&self) -> &Subject { &self.subject }
+
+    pub fn get_tenant_id(&self) -> Uuid { self.tenant_id }
+
+    pub fn get_scopes(&self) -> Scopes { self.scopes }
 
-    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+    pub fn get_calendar_ids(&self) -> Option<&[Uuid]> { self.calendar_ids.as_deref() }
 
-        // TODO: improve error handling here, this is horrendous.
+    pub fn get_groups(&self) -> &[String] { &self.groups }
 
-        // get Authorization header
-        let api_key = request.headers()
-            .get_one("Authorization")
-            .and_then(|x| Uuid::from_str(x).ok())
-            .into_outcome((Status::Unauthorized, ()))?;
+    pub fn get_calendar_role(&self) -> Option<Role> { self.calendar_permission.map(|p| p.role) }
 
-        // get api key scopes from db
-        let mut db = request.guard::<PgsqlConn>().unwrap();
-        let query = "SELECT scopes FROM api_keys WHERE api_key = $1;";
-        let rows = db.query(query, &[&api_key]).unwrap();
+    /// Which events of the route's calendar the principal can see the
+    /// details of, everything if the route has no calendar.
+    pub fn get_visibility(&self) -> Visibility
+    {
+        self.calendar_permission.map_or(Visibility::All, |p| p.visibility)
+    }
+
+    /// Checks if the principal is restricted to specific calendars
+    /// and `calendar_id` is not one of them. Doesn't check the tenant.
+    pub fn is_restricted_from(&self, calendar_id: &Uuid) -> bool
+    {
+        self.calendar_ids.as_ref().map_or(false, |ids| !ids.contains(calendar_id))
+    }
+}
+
+/// The principal of a request, authenticated once and shared by the
+/// guards that need it (`Principal` and `PgsqlConn`). `Err` if it
+/// couldn't be authenticated, `None` if the request has no valid
+/// API key or bearer token.
+struct RequestPrincipal(Result<Option<Principal>, ()>);
+
+/// Gets the principal of the request's `Authorization` header, which
+/// has either an API key or a bearer token (a JWT, see `jwt`).
+pub fn request_principal<'r>(request: &'r Request) -> &'r Result<Option<Principal>, ()>
+{
+    &request.local_cache(|| RequestPrincipal(authenticate_request(request))).0
+}
+
+fn authenticate_request(request: &Request) -> Result<Option<Principal>, ()>
+{
+    let header = match request.headers().get_one("Authorization")
+    {
+        Some(header) => header,
+        None => return Ok(None),
+    };
 
-        if let Some(row) = rows.get(0)
+    // Not the request's connection, the `PgsqlConn` guard needs the
+    // principal before handing that one out. This one is returned to
+    // the pool before then, so a request never holds two connections.
+    let pool = request.guard::<State<PgsqlPool>>().succeeded().ok_or(())?;
+
+    let principal = match header.strip_prefix("Bearer ")
+    {
+        Some(token) =>
+        {
+            let verifier = request.guard::<State<Option<JwtVerifier>>>().succeeded().ok_or(())?;
+
+            match verifier.as_ref()
+            {
+                Some(verifier) =>
+                {
+                    let mut db = pool.get_conn().map_err(|_| ())?;
+                    authenticate_token(&mut db, verifier, token.trim()).map_err(|_| ())?.map(|principal| (principal, db))
+                },
+                None => None,
+            }
+        },
+        None => match Credentials::from_str(header)
+        {
+            Ok(credentials) =>
+            {
+                let mut db = pool.get_conn().map_err(|_| ())?;
+                authenticate(&mut db, &credentials).map_err(|_| ())?.map(|principal| (principal, db))
+            },
+            Err(_) => None,
+        },
+    };
+
+    match principal
+    {
+        Some((mut principal, mut db)) =>
+        {
+            if let Some(Some(calendar_id)) = calendar_id_param(request)
+            {
+                db.restrict_to_tenant(&principal.get_tenant_id()).map_err(|_| ())?;
+                principal.calendar_permission = get_calendar_permission(&mut db, &principal, &calendar_id).map_err(|_| ())?;
+            }
+
+            Ok(Some(principal))
+        },
+        None => Ok(None),
+    }
+}
+
+/// Gets the `calendar_id` parameter of the request's route, `None` if
+/// the route has no such parameter. `Some(None)` if the parameter is
+/// not a calendar id. The iCalendar export of a calendar has the id
+/// followed by `.ics` as its parameter.
+fn calendar_id_param(request: &Request) -> Option<Option<Uuid>>
+{
+    let index = request.route()?
+        .uri
+        .path()
+        .split('/')
+        .filter(|segment| segment.starts_with('<'))
+        .position(|segment| segment.starts_with("<calendar_id>"))?;
+
+    let param = request.get_param::<&RawStr>(index)?.ok()?;
+
+    let param = param.as_str();
+
+    Some(Uuid::from_str(param.strip_suffix(".ics").unwrap_or(param)).ok())
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Principal
+{
+    type Error = ();
+
+    /// Succeeds if the request has a valid API key or bearer token. If the
+    /// route has a `calendar_id` parameter the principal must also be able to
+    /// see the calendar, calendars it can't see (see `get_calendar_permission`) are
+    /// treated as missing (404).
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        let principal = match request_principal(request)
         {
-            Outcome::Success(
-                ApiKey {
-                    key: api_key,
-                    scopes: row.get_cell("scopes").ok().into_outcome((Status::InternalServerError, ()))?
-                }
-            )
+            Ok(Some(principal)) => principal.clone(),
+            Ok(None) => return Outcome::Failure((Status::Unauthorized, ())),
+            Err(_) => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+
+        match calendar_id_param(request)
+        {
+            None => Outcome::Success(principal),
+            // The permission was looked up when the request was authenticated.
+            Some(Some(_)) if principal.calendar_permission.is_some() => Outcome::Success(principal),
+            Some(Some(_)) => Outcome::Failure((Status::NotFound, ())),
+            // The route's own guards won't accept the parameter either.
+            Some(None) => Outcome::Forward(()),
         }
-        else
+    }
+}
+
+/// Succeeds if the request has a principal whose scopes allow `scope` and,
+/// if the route has a calendar, whose role on the calendar is at least `role`.
+/// Fails with 403 if the principal's scopes or role don't allow it.
+fn require(request: &Request, scope: Scope, role: Role) -> Outcome<Principal, ()>
+{
+    match request.guard::<Principal>()
+    {
+        Outcome::Success(principal) if principal.scopes.allows(scope) && principal.get_calendar_role().map_or(true, |r| r >= role) =>
+            Outcome::Success(principal),
+        Outcome::Success(_) => Outcome::Failure((Status::Forbidden, ())),
+        Outcome::Forward(f) => Outcome::Forward(f),
+        Outcome::Failure(e) => Outcome::Failure(e),
+    }
+}
+
+/// The name of the API key security scheme in the OpenAPI document.
+const API_KEY_SCHEME: &str = "api_key";
+
+/// The name of the bearer token security scheme in the OpenAPI document.
+const BEARER_SCHEME: &str = "bearer";
+
+/// The schemes a requirement names, with the scopes they need.
+pub type SecurityRequirement = Map<String, Vec<String>>;
+
+/// The security schemes of the OpenAPI document: API keys and bearer
+/// tokens, both in the `Authorization` header.
+pub fn security_schemes() -> Map<String, RefOr<SecurityScheme>>
+{
+    let mut schemes = Map::new();
+
+    schemes.insert(API_KEY_SCHEME.to_owned(), SecurityScheme {
+        schema_type: "apiKey".to_owned(),
+        description: Some("An API key, as `<id>.<secret>`.".to_owned()),
+        data: SecuritySchemeData::ApiKey { name: "Authorization".to_owned(), location: "header".to_owned() },
+        extensions: Default::default(),
+    }.into());
+
+    schemes.insert(BEARER_SCHEME.to_owned(), SecurityScheme {
+        schema_type: "http".to_owned(),
+        description: Some("A JWT of the configured issuer, its roles give it its scopes.".to_owned()),
+        data: SecuritySchemeData::Http { scheme: "bearer".to_owned(), bearer_format: Some("JWT".to_owned()) },
+        extensions: Default::default(),
+    }.into());
+
+    schemes
+}
+
+/// A request guard that authenticates requests, whose requirements can
+/// be documented. `rocket_okapi` doesn't document request guards, the
+/// routes list their guard for it (see `routes::get_routes`).
+pub trait OpenApiFromRequest
+{
+    /// The security requirements of the routes using the guard, any one
+    /// of them is enough.
+    fn security_requirements() -> Vec<SecurityRequirement>;
+}
+
+/// The requirements of principals whose scopes allow `scope`: one for
+/// every scope that allows it, with either scheme.
+fn scope_requirements(scope: Scope) -> Vec<SecurityRequirement>
+{
+    let scopes = [Scope::Read, Scope::Write, Scope::Super];
+
+    [API_KEY_SCHEME, BEARER_SCHEME].iter()
+        .flat_map(|scheme| scopes.iter()
+            .filter(|s| Scopes::parse(&[s.to_string()]).allows(scope))
+            .map(move |s| vec![(scheme.to_string(), vec![s.to_string()])].into_iter().collect())
+        )
+        .collect()
+}
+
+/// A principal that can see when the events of the route's calendar
+/// happen: with the `READ` scope and at least the `FREE_BUSY` role.
+pub struct RequireFreeBusy(Principal);
+
+impl Deref for RequireFreeBusy
+{
+    type Target = Principal;
+
+    fn deref(&self) -> &Self::Target
+    {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequireFreeBusy
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        require(request, Scope::Read, Role::FreeBusy).map(RequireFreeBusy)
+    }
+}
+
+impl OpenApiFromRequest for RequireFreeBusy
+{
+    fn security_requirements() -> Vec<SecurityRequirement>
+    {
+        scope_requirements(Scope::Read)
+    }
+}
+
+/// A principal that can read the route's calendar: with the `READ`
+/// scope (`WRITE` and `SUPER` also allow reading) and at least the
+/// `READER` role.
+pub struct RequireRead(Principal);
+
+impl Deref for RequireRead
+{
+    type Target = Principal;
+
+    fn deref(&self) -> &Self::Target
+    {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequireRead
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        require(request, Scope::Read, Role::Reader).map(RequireRead)
+    }
+}
+
+impl OpenApiFromRequest for RequireRead
+{
+    fn security_requirements() -> Vec<SecurityRequirement>
+    {
+        scope_requirements(Scope::Read)
+    }
+}
+
+/// A principal that can write to the route's calendar: with the `WRITE`
+/// (or `SUPER`) scope and at least the `WRITER` role.
+pub struct RequireWrite(Principal);
+
+impl Deref for RequireWrite
+{
+    type Target = Principal;
+
+    fn deref(&self) -> &Self::Target
+    {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequireWrite
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        require(request, Scope::Write, Role::Writer).map(RequireWrite)
+    }
+}
+
+impl OpenApiFromRequest for RequireWrite
+{
+    fn security_requirements() -> Vec<SecurityRequirement>
+    {
+        scope_requirements(Scope::Write)
+    }
+}
+
+/// A principal that owns the route's calendar: with the `WRITE`
+/// (or `SUPER`) scope and the `OWNER` role.
+pub struct RequireOwner(Principal);
+
+impl Deref for RequireOwner
+{
+    type Target = Principal;
+
+    fn deref(&self) -> &Self::Target
+    {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequireOwner
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        require(request, Scope::Write, Role::Owner).map(RequireOwner)
+    }
+}
+
+impl OpenApiFromRequest for RequireOwner
+{
+    fn security_requirements() -> Vec<SecurityRequirement>
+    {
+        scope_requirements(Scope::Write)
+    }
+}
+
+/// A principal with the `SUPER` scope, required by the admin routes.
+pub struct RequireSuper(Principal);
+
+impl Deref for RequireSuper
+{
+    type Target = Principal;
+
+    fn deref(&self) -> &Self::Target
+    {
+        &self.0
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for RequireSuper
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        require(request, Scope::Super, Role::Owner).map(RequireSuper)
+    }
+}
+
+impl OpenApiFromRequest for RequireSuper
+{
+    fn security_requirements() -> Vec<SecurityRequirement>
+    {
+        scope_requirements(Scope::Super)
+    }
+}
+
+const API_KEY_FIELDS: &str = "id, tenant_id, scopes, calendar_ids, salt, secret_hash";
+
+/// Finds the API key `credentials` are for, `None` if there's no such key,
+/// if the secret is wrong or if the key was revoked or expired. Records
+/// that the key was used.
+pub fn authenticate(db: &mut PgsqlConn, credentials: &Credentials) -> Result<Option<Principal>, DatabaseError>
+{
+    let valid = "revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())";
+
+    let (rows, secret) = match credentials
+    {
+        Credentials::Key { id, secret } => (
+            db.query(format!("SELECT {} FROM api_keys WHERE id = $1 AND NOT legacy AND {};", API_KEY_FIELDS, valid).as_str(), &[id])?,
+            secret.clone(),
+        ),
+        Credentials::Legacy(key) => (
+            db.query(format!("SELECT {} FROM api_keys WHERE legacy AND legacy_digest = $1 AND {};", API_KEY_FIELDS, valid).as_str(), &[&legacy_digest(key)])?,
+            key.to_string(),
+        ),
+    };
+
+    for row in rows
+    {
+        let salt: String = row.get_cell("salt")?;
+        let hash: String = row.get_cell("secret_hash")?;
+
+        if verify_secret(&salt, &hash, &secret)
         {
-            Outcome::Failure((Status::Unauthorized, ()))
+            let id = row.get_cell("id")?;
+
+            let principal = Principal {
+                subject: Subject::ApiKey(id),
+                tenant_id: row.get_cell("tenant_id")?,
+                scopes: Scopes::parse(&row.get_cell::<Vec<String>>("scopes")?),
+                calendar_ids: row.get_cell("calendar_ids")?,
+                groups: vec![],
+                calendar_permission: None,
+            };
+
+            // Not more than once a minute, to save writes on busy keys.
+            db.execute(
+                "UPDATE api_keys SET last_used_at = NOW() WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute');",
+                &[&id]
+            )?;
+
+            return Ok(Some(principal));
         }
     }
-}
\ No newline at end of file
+
+    Ok(None)
+}
+
+/// Verifies a bearer token, `None` if it's not valid or if its
+/// tenant doesn't exist.
+fn authenticate_token(db: &mut PgsqlConn, verifier: &JwtVerifier, token: &str) -> Result<Option<Principal>, DatabaseError>
+{
+    let claims = match verifier.verify(token)
+    {
+        Ok(claims) => claims,
+        Err(_) => return Ok(None),
+    };
+
+    if db.query("SELECT 1 FROM tenants WHERE id = $1", &[&claims.tenant_id])?.is_empty()
+    {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        Principal {
+            subject: Subject::User(claims.subject),
+            tenant_id: claims.tenant_id,
+            scopes: claims.scopes,
+            calendar_ids: None,
+            groups: claims.groups,
+            calendar_permission: None,
+        }
+    ))
+}
@@ -1 +1,3 @@
-pub mod auth_guard;
\ No newline at end of file
+pub mod auth_guard;
+pub mod api_key;
+pub mod jwt;
@@ -0,0 +1,796 @@
+//! CalDAV (RFC 4791) interface to the calendars and their events,
+//! including sync-collection reports (RFC 6578).
+//!
+//! Rocket only routes the standard HTTP methods, and CalDAV needs PROPFIND
+//! and REPORT, so the interface is served by an HTTP server of its own, on
+//! the address set in `CALDAV_ADDRESS`. Clients authenticate with HTTP
+//! Basic authentication, using an API key as the password.
+//!
+//! Layout:
+//! - `/dav/principal/`: the principal, its calendar home is `/dav/calendars/`.
+//! - `/dav/calendars/<calendar-id>/`: a calendar.
+//! - `/dav/calendars/<calendar-id>/<name>.ics`: a recurring or single
+//!   event, with its overrides (see `resource`).
+
+mod path;
+mod xml;
+mod resource;
+
+use std::io::{Cursor, Read};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Request, Response, Server};
+use uuid::Uuid;
+use crate::authentication::auth_guard::{Principal, Scope, authenticate};
+use crate::authentication::api_key::Credentials;
+use crate::acl::{Role, Visibility, get_access, get_calendar_permission};
+use crate::connection_pool::{PgsqlPool, PgsqlConn};
+use crate::database_error::DatabaseError;
+use crate::sync::SyncToken;
+use self::path::{DavPath, ROOT, PRINCIPAL, CALENDAR_HOME};
+use self::xml::{PropName, PropRequest, Report, MultiStatus, XmlError, DAV, CALDAV, CALENDARSERVER};
+use self::resource::{CalendarCollection, Resource, PutError};
+
+/// Amount of threads serving requests.
+const WORKERS: usize = 4;
+
+/// Maximum size of a request body, in bytes.
+const MAX_BODY_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Sync tokens must be URIs (RFC 6578, section 3.2).
+const SYNC_TOKEN_PREFIX: &str = "urn:caser:sync:";
+
+const DAV_COMPLIANCE: &str = "1, 3, calendar-access";
+const ALLOWED_METHODS: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+const ICS_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// Properties returned for `allprop` requests.
+const ALL_PROPS: [(&str, &str); 6] = [
+    (DAV, "resourcetype"),
+    (DAV, "displayname"),
+    (DAV, "getetag"),
+    (DAV, "getcontenttype"),
+    (DAV, "sync-token"),
+    (CALENDARSERVER, "getctag"),
+];
+
+/// Starts the CalDAV server on `address` (e.g. `0.0.0.0:8001`).
+pub fn spawn(pool: PgsqlPool, address: &str) -> Vec<JoinHandle<()>>
+{
+    let server = Arc::new(Server::http(address).expect("Could not start the CalDAV server."));
+
+    println!("Serving CalDAV at {}", address);
+
+    (0..WORKERS)
+        .map(|_| {
+            let server = server.clone();
+            let pool = pool.clone();
+
+            thread::spawn(move || for request in server.incoming_requests()
+            {
+                serve(&pool, request);
+            })
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Depth
+{
+    Zero,
+    One,
+
+    /// Treated like `One`, calendars are as deep as it gets.
+    Infinity,
+}
+
+struct DavRequest
+{
+    method: String,
+    path: String,
+    depth: Depth,
+    authorization: Option<String>,
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    body: String,
+}
+
+struct DavResponse
+{
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl DavResponse
+{
+    fn new(status: u16) -> DavResponse
+    {
+        DavResponse { status, headers: vec![], body: String::new() }
+    }
+
+    fn multistatus(multistatus: MultiStatus) -> DavResponse
+    {
+        DavResponse::new(207).body("application/xml; charset=utf-8", multistatus.finish())
+    }
+
+    /// A response with the precondition (or postcondition) that failed,
+    /// `content` being the content of its element.
+    fn error(status: u16, condition: &PropName, content: &str) -> DavResponse
+    {
+        DavResponse::new(status).body("application/xml; charset=utf-8", xml::error(condition, content))
+    }
+
+    fn header(mut self, name: &'static str, value: &str) -> DavResponse
+    {
+        self.headers.push((name, value.to_owned()));
+        self
+    }
+
+    fn body(self, content_type: &str, body: String) -> DavResponse
+    {
+        let mut response = self.header("Content-Type", content_type);
+        response.body = body;
+        response
+    }
+
+    fn into_response(self) -> Response<Cursor<Vec<u8>>>
+    {
+        let mut response = Response::from_data(self.body.into_bytes()).with_status_code(self.status);
+
+        for (name, value) in self.headers
+        {
+            if let Ok(header) = Header::from_bytes(name.as_bytes(), value.as_bytes())
+            {
+                response.add_header(header);
+            }
+        }
+
+        response
+    }
+}
+
+/// A calendar, resource or one of the collections above
+/// them, whose properties are being read.
+enum Target<'a>
+{
+    Root,
+    Principal,
+    CalendarHome,
+    Calendar(&'a CalendarCollection),
+    Resource(&'a CalendarCollection, &'a Resource),
+}
+
+impl Target<'_>
+{
+    fn href(&self) -> String
+    {
+        match self
+        {
+            Target::Root => DavPath::Root.href(),
+            Target::Principal => DavPath::Principal.href(),
+            Target::CalendarHome => DavPath::CalendarHome.href(),
+            Target::Calendar(calendar) => DavPath::Calendar(calendar.id).href(),
+            Target::Resource(calendar, resource) => resource.get_path(calendar.id).href(),
+        }
+    }
+
+    fn is_read_only(&self) -> bool
+    {
+        match self
+        {
+            Target::Calendar(calendar) | Target::Resource(calendar, _) => calendar.read_only,
+            _ => true,
+        }
+    }
+
+    /// Gets the value of a property (as XML), `None` if the target doesn't have it.
+    fn prop(&self, prop: &PropName) -> Option<String>
+    {
+        let value = match (prop.namespace.as_str(), prop.name.as_str(), self)
+        {
+            (DAV, "resourcetype", Target::Principal) => "<d:collection/><d:principal/>".to_owned(),
+            (DAV, "resourcetype", Target::Calendar(_)) => "<d:collection/><c:calendar/>".to_owned(),
+            (DAV, "resourcetype", Target::Resource(..)) => String::new(),
+            (DAV, "resourcetype", _) => "<d:collection/>".to_owned(),
+
+            (DAV, "displayname", Target::Principal) => "caser".to_owned(),
+            (DAV, "displayname", Target::CalendarHome) => "Calendars".to_owned(),
+            (DAV, "displayname", Target::Calendar(calendar)) => xml::escape(&calendar.name.clone().unwrap_or_else(|| calendar.id.to_string())),
+
+            (DAV, "current-user-principal", _) => xml::href(PRINCIPAL),
+            (DAV, "principal-URL", Target::Principal) => xml::href(PRINCIPAL),
+            (CALDAV, "calendar-home-set", Target::Root) | (CALDAV, "calendar-home-set", Target::Principal) => xml::href(CALENDAR_HOME),
+
+            (DAV, "current-user-privilege-set", target) =>
+            {
+                let privileges: &[&str] = if target.is_read_only() { &["read"] } else { &["read", "write", "write-content", "bind", "unbind"] };
+
+                privileges.iter()
+                    .map(|privilege| format!("<d:privilege><d:{}/></d:privilege>", privilege))
+                    .collect()
+            },
+
+            (DAV, "supported-report-set", Target::Calendar(_)) =>
+            {
+                ["c:calendar-query", "c:calendar-multiget", "d:sync-collection"]
+                    .iter()
+                    .map(|report| format!("<d:supported-report><d:report><{}/></d:report></d:supported-report>", report))
+                    .collect()
+            },
+
+            (CALDAV, "supported-calendar-component-set", Target::Calendar(_)) => "<c:comp name=\"VEVENT\"/>".to_owned(),
+            (DAV, "sync-token", Target::Calendar(calendar)) => xml::escape(&sync_token_uri(calendar.sync_token)),
+            (CALENDARSERVER, "getctag", Target::Calendar(calendar)) => xml::escape(&calendar.sync_token.to_string()),
+
+            (DAV, "getetag", Target::Resource(_, resource)) => xml::escape(resource.get_etag()),
+            (DAV, "getcontenttype", Target::Resource(..)) => ICS_CONTENT_TYPE.to_owned(),
+            (CALDAV, "calendar-data", Target::Resource(_, resource)) => xml::escape(resource.get_ics()),
+
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+fn serve(pool: &PgsqlPool, mut request: Request)
+{
+    let response = match read_request(&mut request)
+    {
+        Ok(dav_request) => match pool.get_conn()
+        {
+            Ok(mut db) => handle(&mut db, &dav_request).unwrap_or_else(|e| {
+                log::error!("CalDAV request failed: {}", e);
+                DavResponse::new(500)
+            }),
+            Err(e) =>
+            {
+                log::error!("CalDAV request failed: {}", e);
+                DavResponse::new(500)
+            },
+        },
+        Err(status) => DavResponse::new(status),
+    };
+
+    if let Err(e) = request.respond(response.into_response())
+    {
+        log::error!("Could not send CalDAV response: {}", e);
+    }
+}
+
+/// Reads the parts of a request the handlers need, returns the
+/// status to respond with if the request can't be read.
+fn read_request(request: &mut Request) -> Result<DavRequest, u16>
+{
+    let header = |name: &'static str| request.headers()
+        .iter()
+        .find(|header| header.field.equiv(name))
+        .map(|header| header.value.as_str().to_owned());
+
+    let depth = match header("Depth").as_deref()
+    {
+        Some("0") => Depth::Zero,
+        Some("1") => Depth::One,
+        _ => Depth::Infinity,
+    };
+
+    let authorization = header("Authorization");
+    let if_match = header("If-Match");
+    let if_none_match = header("If-None-Match");
+
+    let method = request.method().to_string();
+    let path = request.url().to_owned();
+
+    let mut body = String::new();
+    if request.as_reader().take(MAX_BODY_SIZE + 1).read_to_string(&mut body).is_err()
+    {
+        return Err(400);
+    }
+
+    if body.len() as u64 > MAX_BODY_SIZE
+    {
+        return Err(413);
+    }
+
+    Ok(DavRequest { method, path, depth, authorization, if_match, if_none_match, body })
+}
+
+fn handle(db: &mut PgsqlConn, request: &DavRequest) -> Result<DavResponse, DatabaseError>
+{
+    if request.method == "OPTIONS"
+    {
+        return Ok(DavResponse::new(200).header("DAV", DAV_COMPLIANCE).header("Allow", ALLOWED_METHODS));
+    }
+
+    // Service discovery (RFC 6764).
+    if request.path.starts_with("/.well-known/caldav")
+    {
+        return Ok(DavResponse::new(301).header("Location", ROOT));
+    }
+
+    let api_key = match authenticate_header(db, request.authorization.as_deref())?
+    {
+        Some(api_key) => api_key,
+        None => return Ok(DavResponse::new(401).header("WWW-Authenticate", "Basic realm=\"caser\"")),
+    };
+
+    // The key's scopes come before its roles, like the guards of the REST
+    // API check them: a READ key can't change anything, whatever its grants.
+    let writes = request.method == "PUT" || request.method == "DELETE";
+
+    if !api_key.get_scopes().allows(if writes { Scope::Write } else { Scope::Read })
+    {
+        return Ok(DavResponse::new(403));
+    }
+
+    // Like in the REST API, the connection only sees the rows of the key's
+    // tenant and the changes made through it are attributed to the key.
+    db.restrict_to_tenant(&api_key.get_tenant_id())?;
+    db.set_audit_context(&api_key, &format!("{} {}", request.method, request.path))?;
+
+    let path = match DavPath::parse(&request.path)
+    {
+        Some(path) => path,
+        None => return Ok(DavResponse::new(404)),
+    };
+
+    // Like in the REST API, calendars the key can't see are treated as
+    // missing. CalDAV has no free/busy-only view, so `FREE_BUSY` isn't
+    // enough to read a calendar, and changing it takes at least `WRITER`.
+    // Clients write back whole resources, so changing them also takes
+    // seeing them in full, or redacted details would be written back.
+    let visibility = match &path
+    {
+        DavPath::Calendar(calendar_id) | DavPath::Resource(calendar_id, _) =>
+        {
+            let required = if writes { Role::Writer } else { Role::Reader };
+
+            match get_calendar_permission(db, &api_key, calendar_id)?
+            {
+                Some(p) if p.role >= required && (!writes || p.visibility == Visibility::All) => p.visibility,
+                Some(_) => return Ok(DavResponse::new(403)),
+                None => return Ok(DavResponse::new(404)),
+            }
+        },
+        _ => Visibility::All,
+    };
+
+    match request.method.as_str()
+    {
+        "PROPFIND" => propfind(db, &api_key, request, &path, visibility),
+        "REPORT" => report(db, request, &path, visibility),
+        // The server leaves the body out of HEAD responses.
+        "GET" | "HEAD" => get(db, &path, visibility),
+        "PUT" => put(db, request, &path),
+        "DELETE" => delete(db, request, &path),
+        _ => Ok(DavResponse::new(405).header("Allow", ALLOWED_METHODS)),
+    }
+}
+
+/// Gets the API key in an Authorization header: the password of Basic
+/// authentication (or the user name, if there's no password) or, like
+/// in the REST API, the key itself.
+fn api_key_from_header(header: &str) -> Option<Credentials>
+{
+    let header = header.trim();
+
+    let credentials = match header.strip_prefix("Basic ")
+    {
+        Some(credentials) => String::from_utf8(base64::decode(credentials.trim()).ok()?).ok()?,
+        None => return Credentials::from_str(header).ok(),
+    };
+
+    let mut parts = credentials.splitn(2, ':');
+    let user = parts.next().unwrap_or("");
+    let password = parts.next().unwrap_or("");
+
+    Credentials::from_str(if password.is_empty() { user } else { password }).ok()
+}
+
+fn authenticate_header(db: &mut PgsqlConn, header: Option<&str>) -> Result<Option<Principal>, DatabaseError>
+{
+    match header.and_then(api_key_from_header)
+    {
+        Some(credentials) => authenticate(db, &credentials),
+        None => Ok(None),
+    }
+}
+
+fn sync_token_uri(token: SyncToken) -> String
+{
+    format!("{}{}", SYNC_TOKEN_PREFIX, token)
+}
+
+fn parse_sync_token_uri(uri: &str) -> Option<SyncToken>
+{
+    uri.strip_prefix(SYNC_TOKEN_PREFIX)
+        .and_then(|token| SyncToken::from_str(token).ok())
+}
+
+/// Checks if an If-Match or If-None-Match header matches
+/// `etag`, the ETag of the resource (if it exists).
+fn etag_matches(header: &str, etag: Option<&str>) -> bool
+{
+    match etag
+    {
+        Some(etag) => header.split(',').map(|tag| tag.trim()).any(|tag| tag == "*" || tag == etag),
+        None => false,
+    }
+}
+
+fn preconditions_hold(request: &DavRequest, resource: Option<&Resource>) -> bool
+{
+    let etag = resource.map(|resource| resource.get_etag());
+
+    request.if_match.as_deref().map_or(true, |header| etag_matches(header, etag))
+        && request.if_none_match.as_deref().map_or(true, |header| !etag_matches(header, etag))
+}
+
+fn find_calendar(db: &mut PgsqlConn, calendar_id: &Uuid) -> Result<Option<CalendarCollection>, DatabaseError>
+{
+    Ok(resource::get_calendars(db, None, Some(calendar_id))?.pop())
+}
+
+fn find_resource(db: &mut PgsqlConn, calendar_id: &Uuid, name: &str, visibility: Visibility) -> Result<Option<Resource>, DatabaseError>
+{
+    Ok(resource::get_resources(db, calendar_id, None, Some(&[name.to_owned()][..]), visibility)?.pop())
+}
+
+fn write_props(multistatus: &mut MultiStatus, target: &Target, request: &PropRequest)
+{
+    let all_props = || ALL_PROPS.iter().map(|(namespace, name)| PropName::new(namespace, name));
+
+    match request
+    {
+        PropRequest::Prop(props) =>
+        {
+            let mut found = vec![];
+            let mut not_found = vec![];
+
+            for prop in props
+            {
+                match target.prop(prop)
+                {
+                    Some(value) => found.push((prop.clone(), value)),
+                    None => not_found.push(prop.clone()),
+                }
+            }
+
+            multistatus.propstat(&target.href(), &found, &not_found);
+        },
+        PropRequest::AllProp =>
+        {
+            let found: Vec<(PropName, String)> = all_props()
+                .filter_map(|prop| target.prop(&prop).map(|value| (prop, value)))
+                .collect();
+
+            multistatus.propstat(&target.href(), &found, &[]);
+        },
+        PropRequest::PropName =>
+        {
+            let found: Vec<(PropName, String)> = all_props()
+                .filter(|prop| target.prop(prop).is_some())
+                .map(|prop| (prop, String::new()))
+                .collect();
+
+            multistatus.propstat(&target.href(), &found, &[]);
+        },
+    }
+}
+
+fn propfind(db: &mut PgsqlConn, api_key: &Principal, request: &DavRequest, path: &DavPath, visibility: Visibility) -> Result<DavResponse, DatabaseError>
+{
+    let props = match xml::parse_propfind(&request.body)
+    {
+        Ok(props) => props,
+        Err(_) => return Ok(DavResponse::new(400)),
+    };
+
+    let mut multistatus = MultiStatus::new();
+
+    match path
+    {
+        DavPath::Root => write_props(&mut multistatus, &Target::Root, &props),
+        DavPath::Principal => write_props(&mut multistatus, &Target::Principal, &props),
+        DavPath::CalendarHome =>
+        {
+            write_props(&mut multistatus, &Target::CalendarHome, &props);
+
+            if request.depth != Depth::Zero
+            {
+                let access = get_access(db, api_key)?;
+
+                for calendar in resource::get_calendars(db, Some(&api_key.get_tenant_id()), None)?
+                {
+                    let readable = access.role(&calendar.id).map_or(false, |role| role >= Role::Reader);
+
+                    if api_key.is_restricted_from(&calendar.id) || !readable
+                    {
+                        continue;
+                    }
+
+                    write_props(&mut multistatus, &Target::Calendar(&calendar), &props);
+                }
+            }
+        },
+        DavPath::Calendar(calendar_id) =>
+        {
+            let calendar = match find_calendar(db, calendar_id)?
+            {
+                Some(calendar) => calendar,
+                None => return Ok(DavResponse::new(404)),
+            };
+
+            write_props(&mut multistatus, &Target::Calendar(&calendar), &props);
+
+            if request.depth != Depth::Zero
+            {
+                for resource in resource::get_resources(db, calendar_id, None, None, visibility)?
+                {
+                    write_props(&mut multistatus, &Target::Resource(&calendar, &resource), &props);
+                }
+            }
+        },
+        DavPath::Resource(calendar_id, name) =>
+        {
+            let calendar = find_calendar(db, calendar_id)?;
+            let resource = find_resource(db, calendar_id, name, visibility)?;
+
+            match (calendar, resource)
+            {
+                (Some(calendar), Some(resource)) => write_props(&mut multistatus, &Target::Resource(&calendar, &resource), &props),
+                _ => return Ok(DavResponse::new(404)),
+            }
+        },
+    }
+
+    Ok(DavResponse::multistatus(multistatus))
+}
+
+fn report(db: &mut PgsqlConn, request: &DavRequest, path: &DavPath, visibility: Visibility) -> Result<DavResponse, DatabaseError>
+{
+    let unsupported = DavResponse::error(403, &PropName::new(DAV, "supported-report"), "");
+
+    let calendar_id = match path
+    {
+        DavPath::Calendar(calendar_id) => calendar_id,
+        _ => return Ok(unsupported),
+    };
+
+    let calendar = match find_calendar(db, calendar_id)?
+    {
+        Some(calendar) => calendar,
+        None => return Ok(DavResponse::new(404)),
+    };
+
+    let report = match xml::parse_report(&request.body)
+    {
+        Ok(report) => report,
+        Err(XmlError::UnsupportedReport(_)) => return Ok(unsupported),
+        Err(_) => return Ok(DavResponse::new(400)),
+    };
+
+    let mut multistatus = MultiStatus::new();
+
+    match report
+    {
+        Report::CalendarQuery { props, time_range } =>
+        {
+            let resources = resource::get_resources(db, calendar_id, None, None, visibility)?;
+
+            for resource in resources.iter().filter(|r| time_range.map_or(true, |range| r.overlaps(&range)))
+            {
+                write_props(&mut multistatus, &Target::Resource(&calendar, resource), &props);
+            }
+        },
+        Report::CalendarMultiget { props, hrefs } =>
+        {
+            let name = |href: &str| match DavPath::parse(href)
+            {
+                Some(DavPath::Resource(id, name)) if id == *calendar_id => Some(name),
+                _ => None,
+            };
+
+            let names: Vec<String> = hrefs.iter().filter_map(|href| name(href)).collect();
+            let resources = resource::get_resources(db, calendar_id, None, Some(&names), visibility)?;
+
+            for href in &hrefs
+            {
+                match name(href).and_then(|name| resources.iter().find(|r| r.get_name() == name))
+                {
+                    Some(resource) => write_props(&mut multistatus, &Target::Resource(&calendar, resource), &props),
+                    None => multistatus.status(href, "404 Not Found"),
+                }
+            }
+        },
+        Report::SyncCollection { props, sync_token } =>
+        {
+            // The calendar's token was read before its resources,
+            // so they're never older than the token says.
+            let since = match sync_token.map(|token| parse_sync_token_uri(&token))
+            {
+                None => None,
+                Some(Some(token)) if token <= calendar.sync_token => Some(token),
+                Some(_) => return Ok(DavResponse::error(403, &PropName::new(DAV, "valid-sync-token"), "")),
+            };
+
+            match since
+            {
+                Some(since) =>
+                {
+                    let (resources, deleted) = resource::get_changed_resources(db, calendar_id, since, calendar.sync_token, visibility)?;
+
+                    for resource in &resources
+                    {
+                        write_props(&mut multistatus, &Target::Resource(&calendar, resource), &props);
+                    }
+
+                    for name in deleted
+                    {
+                        multistatus.status(&DavPath::Resource(*calendar_id, name).href(), "404 Not Found");
+                    }
+                },
+                None =>
+                {
+                    for resource in resource::get_resources(db, calendar_id, None, None, visibility)?
+                    {
+                        write_props(&mut multistatus, &Target::Resource(&calendar, &resource), &props);
+                    }
+                },
+            }
+
+            multistatus.sync_token(&sync_token_uri(calendar.sync_token));
+        },
+    }
+
+    Ok(DavResponse::multistatus(multistatus))
+}
+
+fn get(db: &mut PgsqlConn, path: &DavPath, visibility: Visibility) -> Result<DavResponse, DatabaseError>
+{
+    let (calendar_id, name) = match path
+    {
+        DavPath::Resource(calendar_id, name) => (calendar_id, name),
+        _ => return Ok(DavResponse::new(405).header("Allow", "OPTIONS, PROPFIND, REPORT")),
+    };
+
+    match find_resource(db, calendar_id, name, visibility)?
+    {
+        Some(resource) => Ok(
+            DavResponse::new(200)
+                .header("ETag", resource.get_etag())
+                .body(ICS_CONTENT_TYPE, resource.get_ics().to_owned())
+        ),
+        None => Ok(DavResponse::new(404)),
+    }
+}
+
+fn put(db: &mut PgsqlConn, request: &DavRequest, path: &DavPath) -> Result<DavResponse, DatabaseError>
+{
+    let (calendar_id, name) = match path
+    {
+        DavPath::Resource(calendar_id, name) => (calendar_id, name),
+        _ => return Ok(DavResponse::new(405).header("Allow", "OPTIONS, PROPFIND, REPORT")),
+    };
+
+    let calendar = match find_calendar(db, calendar_id)?
+    {
+        Some(calendar) => calendar,
+        None => return Ok(DavResponse::new(409)),
+    };
+
+    if calendar.read_only
+    {
+        return Ok(DavResponse::error(403, &PropName::new(DAV, "need-privileges"), ""));
+    }
+
+    let existing = find_resource(db, calendar_id, name, Visibility::All)?;
+
+    if !preconditions_hold(request, existing.as_ref())
+    {
+        return Ok(DavResponse::new(412));
+    }
+
+    match resource::put_resource(db, calendar_id, name, existing.as_ref(), &request.body)
+    {
+        Ok(resource) =>
+        {
+            let status = if existing.is_some() { 204 } else { 201 };
+            Ok(DavResponse::new(status).header("ETag", resource.get_etag()))
+        },
+        Err(PutError::Database(e)) => Err(e),
+        Err(PutError::InvalidCalendarData(_)) => Ok(DavResponse::error(403, &PropName::new(CALDAV, "valid-calendar-data"), "")),
+        Err(PutError::UidConflict(owner)) =>
+        {
+            let href = owner
+                .map(|owner| xml::href(&DavPath::Resource(*calendar_id, owner).href()))
+                .unwrap_or_default();
+
+            Ok(DavResponse::error(403, &PropName::new(CALDAV, "no-uid-conflict"), &href))
+        },
+    }
+}
+
+fn delete(db: &mut PgsqlConn, request: &DavRequest, path: &DavPath) -> Result<DavResponse, DatabaseError>
+{
+    // Calendars can only be deleted through the REST API.
+    let (calendar_id, name) = match path
+    {
+        DavPath::Resource(calendar_id, name) => (calendar_id, name),
+        _ => return Ok(DavResponse::new(405).header("Allow", "OPTIONS, PROPFIND, REPORT")),
+    };
+
+    let calendar = match find_calendar(db, calendar_id)?
+    {
+        Some(calendar) => calendar,
+        None => return Ok(DavResponse::new(404)),
+    };
+
+    if calendar.read_only
+    {
+        return Ok(DavResponse::error(403, &PropName::new(DAV, "need-privileges"), ""));
+    }
+
+    let resource = match find_resource(db, calendar_id, name, Visibility::All)?
+    {
+        Some(resource) => resource,
+        None => return Ok(DavResponse::new(404)),
+    };
+
+    if !preconditions_hold(request, Some(&resource))
+    {
+        return Ok(DavResponse::new(412));
+    }
+
+    resource::delete_resource(db, &resource)?;
+
+    Ok(DavResponse::new(204))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    const KEY: &str = "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60";
+
+    #[test]
+    fn reads_api_key_from_authorization()
+    {
+        let key = Some(Credentials::Legacy(Uuid::from_str(KEY).unwrap()));
+
+        assert_eq!(api_key_from_header(&format!("Basic {}", base64::encode(format!("user:{}", KEY)))), key);
+        assert_eq!(api_key_from_header(&format!("Basic {}", base64::encode(KEY))), key);
+        assert_eq!(api_key_from_header(&format!("Basic {}", base64::encode(format!("{}:", KEY)))), key);
+        assert_eq!(api_key_from_header(KEY), key);
+
+        let secret = "0f".repeat(32);
+        let new_key = Some(Credentials::Key { id: Uuid::from_str(KEY).unwrap(), secret: secret.clone() });
+        assert_eq!(api_key_from_header(&format!("Basic {}", base64::encode(format!("user:{}.{}", KEY, secret)))), new_key);
+        assert_eq!(api_key_from_header(&format!("{}.{}", KEY, secret)), new_key);
+
+        assert_eq!(api_key_from_header("Basic not-base64"), None);
+        assert_eq!(api_key_from_header(&format!("Basic {}", base64::encode("user:password"))), None);
+    }
+
+    #[test]
+    fn sync_token_uris_round_trip()
+    {
+        let token = SyncToken::new(42);
+
+        assert_eq!(parse_sync_token_uri(&sync_token_uri(token)), Some(token));
+        assert_eq!(parse_sync_token_uri(&token.to_string()), None);
+        assert_eq!(parse_sync_token_uri("urn:caser:sync:garbage"), None);
+    }
+
+    #[test]
+    fn matches_etags()
+    {
+        assert!(etag_matches("\"a\", \"b\"", Some("\"b\"")));
+        assert!(etag_matches("*", Some("\"b\"")));
+        assert!(!etag_matches("*", None));
+        assert!(!etag_matches("W/\"b\"", Some("\"b\"")));
+    }
+}
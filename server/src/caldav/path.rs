@@ -0,0 +1,164 @@
+//! The paths of the CalDAV interface and the hrefs that point to them.
+
+use std::str::FromStr;
+use uuid::Uuid;
+
+pub const ROOT: &str = "/dav/";
+pub const PRINCIPAL: &str = "/dav/principal/";
+pub const CALENDAR_HOME: &str = "/dav/calendars/";
+
+/// Something the CalDAV interface serves.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum DavPath
+{
+    /// `/dav/`
+    Root,
+
+    /// `/dav/principal/`, the only principal. Every API key sees the same calendars.
+    Principal,
+
+    /// `/dav/calendars/`, the collection of every calendar.
+    CalendarHome,
+
+    /// `/dav/calendars/<calendar-id>/`
+    Calendar(Uuid),
+
+    /// `/dav/calendars/<calendar-id>/<name>.ics`, a recurring or single
+    /// event along with its overrides. `name` is stored without the extension.
+    Resource(Uuid, String),
+}
+
+impl DavPath
+{
+    /// Parses the path of a request (or an href), ignoring its query string
+    /// and whether collections end with a slash. Absolute URLs are accepted.
+    pub fn parse(path: &str) -> Option<DavPath>
+    {
+        let path = path.split(|c| c == '?' || c == '#').next().unwrap_or("");
+
+        // Hrefs in REPORT bodies may be absolute URLs.
+        let path = match path.find("://")
+        {
+            Some(i) => &path[path[i + 3..].find('/').map(|j| i + 3 + j)?..],
+            None => path,
+        };
+
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match segments.as_slice()
+        {
+            ["dav"] => Some(DavPath::Root),
+            ["dav", "principal"] => Some(DavPath::Principal),
+            ["dav", "calendars"] => Some(DavPath::CalendarHome),
+            ["dav", "calendars", calendar_id] => Uuid::from_str(calendar_id).ok().map(DavPath::Calendar),
+            ["dav", "calendars", calendar_id, resource] =>
+            {
+                let calendar_id = Uuid::from_str(calendar_id).ok()?;
+                let name = percent_decode(resource.strip_suffix(".ics")?)?;
+
+                if name.is_empty()
+                {
+                    return None;
+                }
+
+                Some(DavPath::Resource(calendar_id, name))
+            },
+            _ => None,
+        }
+    }
+
+    pub fn href(&self) -> String
+    {
+        match self
+        {
+            DavPath::Root => ROOT.to_owned(),
+            DavPath::Principal => PRINCIPAL.to_owned(),
+            DavPath::CalendarHome => CALENDAR_HOME.to_owned(),
+            DavPath::Calendar(calendar_id) => format!("{}{}/", CALENDAR_HOME, calendar_id),
+            DavPath::Resource(calendar_id, name) => format!("{}{}/{}.ics", CALENDAR_HOME, calendar_id, percent_encode(name)),
+        }
+    }
+}
+
+/// Percent-encodes everything but unreserved characters (RFC 3986, section 2.3).
+pub fn percent_encode(segment: &str) -> String
+{
+    segment.bytes()
+        .map(|byte| match byte
+        {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Decodes a percent-encoded path segment, `None` if it's
+/// not valid percent-encoded UTF-8.
+pub fn percent_decode(segment: &str) -> Option<String>
+{
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len()
+    {
+        if bytes[i] == b'%'
+        {
+            let hex = segment.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        }
+        else
+        {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    const CALENDAR_ID: &str = "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60";
+
+    #[test]
+    fn parses_paths()
+    {
+        let calendar_id = Uuid::from_str(CALENDAR_ID).unwrap();
+
+        assert_eq!(DavPath::parse("/dav"), Some(DavPath::Root));
+        assert_eq!(DavPath::parse("/dav/principal/"), Some(DavPath::Principal));
+        assert_eq!(DavPath::parse("/dav/calendars"), Some(DavPath::CalendarHome));
+        assert_eq!(DavPath::parse(&format!("/dav/calendars/{}/", CALENDAR_ID)), Some(DavPath::Calendar(calendar_id)));
+        assert_eq!(
+            DavPath::parse(&format!("https://example.com/dav/calendars/{}/event%40example.com.ics?x=1", CALENDAR_ID)),
+            Some(DavPath::Resource(calendar_id, "event@example.com".to_owned()))
+        );
+
+        assert_eq!(DavPath::parse("/dav/calendars/not-a-uuid/"), None);
+        assert_eq!(DavPath::parse(&format!("/dav/calendars/{}/event", CALENDAR_ID)), None);
+        assert_eq!(DavPath::parse(&format!("/dav/calendars/{}/.ics", CALENDAR_ID)), None);
+        assert_eq!(DavPath::parse("/api/calendars"), None);
+    }
+
+    #[test]
+    fn hrefs_round_trip()
+    {
+        let path = DavPath::Resource(Uuid::from_str(CALENDAR_ID).unwrap(), "a b/ç".to_owned());
+
+        assert_eq!(path.href(), format!("/dav/calendars/{}/a%20b%2F%C3%A7.ics", CALENDAR_ID));
+        assert_eq!(DavPath::parse(&path.href()), Some(path));
+    }
+
+    #[test]
+    fn rejects_invalid_percent_encoding()
+    {
+        assert_eq!(percent_decode("a%2"), None);
+        assert_eq!(percent_decode("a%zz"), None);
+        assert_eq!(percent_decode("%FF"), None);
+    }
+}
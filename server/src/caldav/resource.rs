@@ -0,0 +1,425 @@
+//! The calendars and resources the CalDAV interface serves, and how
+//! resources are written back to events.
+//!
+//! A resource is a recurring or single event along with its overrides,
+//! written as an iCalendar file. Its name is the one the client gave it
+//! when creating it (the `dav_name` column) or, for events created some
+//! other way, the event's id.
+
+use std::collections::HashSet;
+use chrono::{NaiveDateTime, Duration};
+use ring::digest;
+use uuid::Uuid;
+use caser_common::ical::writer::ICalWriter;
+use caser_common::ical::parser::{parse, get_uid};
+use caser_common::ical::import::ImportStatus;
+use crate::connection_pool::PgsqlConn;
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::event::{Event, EventOverride, GenerateInstances, Redact};
+use crate::acl::Visibility;
+use crate::ical::import_components;
+use caser_common::span::EventSpan;
+use crate::sync::{SyncToken, get_current_token};
+use super::path::DavPath;
+use super::xml::TimeRange;
+
+/// Maximum amount of instances of a recurring event checked against
+/// a time range that has no end.
+const MAX_OPEN_RANGE_INSTANCES: usize = 1000;
+
+/// A calendar, as a CalDAV collection.
+pub struct CalendarCollection
+{
+    pub id: Uuid,
+
+    pub name: Option<String>,
+
+    /// Subscribed calendars can't be changed.
+    pub read_only: bool,
+
+    pub sync_token: SyncToken,
+}
+
+pub struct Resource
+{
+    id: Uuid,
+    name: String,
+    ics: String,
+    etag: String,
+    events: Vec<Event>,
+}
+
+impl Resource
+{
+    /// `events` are the recurring or single event with id `id` followed
+    /// by its overrides, `uid` is the UID the resource is written with.
+    /// The events are redacted to what `visibility` shows.
+    fn new(id: Uuid, name: String, uid: String, events: Vec<Event>, visibility: Visibility) -> Resource
+    {
+        let events = events.redact(visibility);
+
+        // The DTSTAMP of a stored resource is when it last changed, so that
+        // the file (and therefore the ETag) only changes when the events do.
+        let last_modified = events.iter()
+            .map(|event| match event
+            {
+                Event::Single(event) => event.get_last_modified(),
+                Event::Recurring(event) => event.get_last_modified(),
+                Event::Override(event) => event.get_last_modified(),
+            })
+            .max()
+            .unwrap_or_else(|| NaiveDateTime::from_timestamp(0, 0));
+
+        let mut writer = ICalWriter::new(last_modified);
+        writer.set_uid(id, uid);
+        writer.write_events(&events);
+        let ics = writer.finish();
+
+        let digest = digest::digest(&digest::SHA256, ics.as_bytes());
+        let etag = format!("\"{}\"", digest.as_ref()[..16].iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+
+        Resource { id, name, ics, etag, events }
+    }
+
+    pub fn get_id(&self) -> Uuid { self.id }
+
+    pub fn get_name(&self) -> &str { &self.name }
+
+    pub fn get_ics(&self) -> &str { &self.ics }
+
+    pub fn get_etag(&self) -> &str { &self.etag }
+
+    pub fn get_path(&self, calendar_id: Uuid) -> DavPath
+    {
+        DavPath::Resource(calendar_id, self.name.clone())
+    }
+
+    /// Checks if the event, or any instance of it, overlaps `range`
+    /// (RFC 4791, section 9.9).
+    pub fn overlaps(&self, range: &TimeRange) -> bool
+    {
+        match self.events.first()
+        {
+            Some(Event::Single(event)) => span_overlaps(&event.get_span(), range),
+            Some(Event::Recurring(event)) =>
+            {
+                let overrides: Vec<EventOverride> = self.events
+                    .iter()
+                    .filter_map(|e| match e
+                    {
+                        Event::Override(o) => Some(o.clone()),
+                        _ => None,
+                    })
+                    .collect();
+
+                // Instances that start before the range can still overlap it.
+                let since = range.start.map(|start| (start - event.get_span().get_duration() - Duration::days(1)).date());
+                let until = range.end.map(|end| end.date());
+                let limit = if until.is_some() { usize::MAX } else { MAX_OPEN_RANGE_INSTANCES };
+
+                event.generate_instances(since, until, &overrides, 0, limit)
+                    .iter()
+                    .any(|instance| span_overlaps(&instance.get_span(), range))
+            },
+            _ => false,
+        }
+    }
+}
+
+fn span_overlaps(span: &EventSpan, range: &TimeRange) -> bool
+{
+    // All-day events last until the end of their last day.
+    let (start, end) = match span
+    {
+        EventSpan::Date(span) => (span.start.and_hms(0, 0, 0), (span.end.max(span.start) + Duration::days(1)).and_hms(0, 0, 0)),
+        EventSpan::DateTime(span) => (span.start, span.end.max(span.start)),
+    };
+
+    // Events without duration overlap the range if they start in it.
+    let after_start = range.start.map_or(true, |range_start| end > range_start || (start == end && start >= range_start));
+    let before_end = range.end.map_or(true, |range_end| start < range_end);
+
+    after_start && before_end
+}
+
+#[derive(Error, Debug)]
+pub enum PutError
+{
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+
+    /// The body is not an iCalendar file with the events of a single UID.
+    #[error("{0}")]
+    InvalidCalendarData(String),
+
+    /// Another resource of the calendar has the UID of the body (its name is
+    /// returned), or the body tries to change the UID of the resource.
+    #[error("UID conflict.")]
+    UidConflict(Option<String>),
+}
+
+impl From<postgres::Error> for PutError
+{
+    fn from(e: postgres::Error) -> Self
+    {
+        PutError::Database(e.into())
+    }
+}
+
+/// Gets the calendars (of the tenant with id `tenant_id`, if set),
+/// or only the calendar with id `calendar_id`.
+pub fn get_calendars(db: &mut PgsqlConn, tenant_id: Option<&Uuid>, calendar_id: Option<&Uuid>) -> Result<Vec<CalendarCollection>, DatabaseError>
+{
+    let query = "
+        SELECT id, name, subscription_url IS NOT NULL AS read_only FROM calendars
+        WHERE ($1::UUID IS NULL OR tenant_id = $1) AND ($2::UUID IS NULL OR id = $2) AND deleted_at IS NULL
+        ORDER BY id;
+    ";
+
+    let rows = db.query(query, &[&tenant_id, &calendar_id])?;
+
+    let mut calendars = vec![];
+    for row in rows
+    {
+        let id: Uuid = row.get_cell("id")?;
+
+        calendars.push(CalendarCollection {
+            id,
+            name: row.get_cell("name")?,
+            read_only: row.get_cell("read_only")?,
+            sync_token: get_current_token(db, &id)?,
+        });
+    }
+
+    Ok(calendars)
+}
+
+/// Gets the resources of the calendar with id `calendar_id`. If `ids` is set
+/// only the resources of those events are returned, if `names` is set only the
+/// resources with those names.
+pub fn get_resources(db: &mut PgsqlConn, calendar_id: &Uuid, ids: Option<&[Uuid]>, names: Option<&[String]>, visibility: Visibility) -> Result<Vec<Resource>, DatabaseError>
+{
+    let query = "
+        SELECT
+            events.*,
+            master.id AS resource_id,
+            COALESCE(master.dav_name, master.id::TEXT) AS resource_name,
+            COALESCE(master.ical_uid, master.id::TEXT) AS resource_uid
+        FROM events
+        JOIN events AS master ON master.id = COALESCE(events.parent_event_id, events.id)
+        WHERE
+            events.calendar_id = $1
+            AND events.deleted_at IS NULL
+            AND ($2::UUID[] IS NULL OR master.id = ANY($2))
+            AND ($3::TEXT[] IS NULL OR COALESCE(master.dav_name, master.id::TEXT) = ANY($3))
+        ORDER BY master.start_date, master.id, events.parent_event_id NULLS FIRST, events.recurrence_id;
+    ";
+
+    let rows = db.query(query, &[calendar_id, &ids, &names])?;
+
+    let mut resources = vec![];
+    let mut current: Option<(Uuid, String, String, Vec<Event>)> = None;
+
+    for row in rows
+    {
+        let id: Uuid = row.get_cell("resource_id")?;
+
+        match &mut current
+        {
+            Some((current_id, _, _, events)) if *current_id == id => events.push(Event::from_row(&row)?),
+            _ =>
+            {
+                if let Some((id, name, uid, events)) = current.take()
+                {
+                    resources.push(Resource::new(id, name, uid, events, visibility));
+                }
+
+                current = Some((id, row.get_cell("resource_name")?, row.get_cell("resource_uid")?, vec![Event::from_row(&row)?]));
+            },
+        }
+    }
+
+    if let Some((id, name, uid, events)) = current
+    {
+        resources.push(Resource::new(id, name, uid, events, visibility));
+    }
+
+    Ok(resources)
+}
+
+/// Gets the resources that changed after `since` (up to `until`) and the
+/// names of those that were deleted.
+pub fn get_changed_resources(db: &mut PgsqlConn, calendar_id: &Uuid, since: SyncToken, until: SyncToken, visibility: Visibility) -> Result<(Vec<Resource>, Vec<String>), DatabaseError>
+{
+    let query = "
+        SELECT DISTINCT COALESCE(parent_event_id, event_id) AS resource_id FROM event_changes
+        WHERE calendar_id = $1 AND seq > $2 AND seq <= $3;
+    ";
+
+    let ids = db.query(query, &[calendar_id, &since.get_seq(), &until.get_seq()])?
+        .iter()
+        .map(|row| row.get_cell("resource_id"))
+        .collect::<Result<Vec<Uuid>, _>>()?;
+
+    let resources = get_resources(db, calendar_id, Some(&ids), None, visibility)?;
+
+    let found: HashSet<Uuid> = resources.iter().map(|r| r.get_id()).collect();
+    let deleted: Vec<Uuid> = ids.into_iter().filter(|id| !found.contains(id)).collect();
+
+    // Deleted events are only in the change log.
+    let query = "
+        SELECT DISTINCT ON (event_id) COALESCE(dav_name, event_id::TEXT) AS name FROM event_changes
+        WHERE calendar_id = $1 AND event_id = ANY($2)
+        ORDER BY event_id, seq DESC;
+    ";
+
+    let names = db.query(query, &[calendar_id, &deleted])?
+        .iter()
+        .map(|row| row.get_cell("name"))
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok((resources, names))
+}
+
+/// Creates or replaces the resource named `name` (which is `existing`,
+/// if it exists) with the events of the iCalendar file `body`.
+/// Returns the resource as it was stored.
+pub fn put_resource(db: &mut PgsqlConn, calendar_id: &Uuid, name: &str, existing: Option<&Resource>, body: &str) -> Result<Resource, PutError>
+{
+    let calendars = parse(body).map_err(|e| PutError::InvalidCalendarData(e.to_string()))?;
+
+    let uids: HashSet<String> = calendars
+        .iter()
+        .filter(|c| c.name == "VCALENDAR")
+        .flat_map(|c| c.components.iter())
+        .filter(|c| c.name == "VEVENT")
+        .filter_map(get_uid)
+        .collect();
+
+    if uids.len() != 1
+    {
+        return Err(PutError::InvalidCalendarData("A resource must have the VEVENTs of exactly one UID.".to_owned()));
+    }
+
+    let uid = uids.into_iter().next().unwrap();
+
+    // The event the import would update, see `ical::upsert_event`.
+    let query = "
+        SELECT id, COALESCE(dav_name, id::TEXT) AS name, COALESCE(ical_uid, id::TEXT) AS uid FROM events
+        WHERE calendar_id = $1 AND parent_event_id IS NULL AND ical_uid = $2 AND deleted_at IS NULL;
+    ";
+
+    let rows = db.query(query, &[calendar_id, &uid])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        let owner: Uuid = row.get_cell("id")?;
+
+        if existing.map(|r| r.get_id()) != Some(owner)
+        {
+            return Err(PutError::UidConflict(Some(row.get_cell("name")?)));
+        }
+    }
+    else if existing.is_some()
+    {
+        return Err(PutError::UidConflict(None));
+    }
+
+    let mut transaction = db.transaction()?;
+
+    let report = import_components(&mut transaction, calendar_id, &calendars)?;
+
+    if let Some(failed) = report.components.iter().find(|c| c.status == ImportStatus::Failed)
+    {
+        return Err(PutError::InvalidCalendarData(failed.message.clone().unwrap_or_default()));
+    }
+
+    let id = report.components
+        .iter()
+        .find(|c| c.status == ImportStatus::Imported && c.recurrence_id.is_none())
+        .and_then(|c| c.event_id)
+        .ok_or_else(|| PutError::InvalidCalendarData("The resource has no (not cancelled) VEVENT without a RECURRENCE-ID.".to_owned()))?;
+
+    // The resource is replaced, overrides that are not in it anymore are removed.
+    let overrides: Vec<Uuid> = report.components
+        .iter()
+        .filter(|c| c.status == ImportStatus::Imported && c.recurrence_id.is_some())
+        .filter_map(|c| c.event_id)
+        .collect();
+
+    transaction.execute("DELETE FROM events WHERE parent_event_id = $1 AND id <> ALL($2)", &[&id, &overrides])?;
+
+    let dav_name = if name == id.to_string() { None } else { Some(name) };
+    transaction.execute("UPDATE events SET dav_name = $2 WHERE id = $1 AND dav_name IS DISTINCT FROM $2", &[&id, &dav_name])?;
+
+    transaction.commit()?;
+
+    get_resources(db, calendar_id, Some(&[id][..]), None, Visibility::All)?
+        .pop()
+        .ok_or_else(|| PutError::Database(DatabaseErrorKind::ExpectedRow(0).into()))
+}
+
+/// Moves a resource, i.e. its event and the event's overrides, to the trash.
+pub fn delete_resource(db: &mut PgsqlConn, resource: &Resource) -> Result<(), DatabaseError>
+{
+    db.execute(
+        "UPDATE events SET deleted_at = NOW() WHERE (id = $1 OR parent_event_id = $1) AND deleted_at IS NULL",
+        &[&resource.get_id()]
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use chrono::NaiveDate;
+    use caser_common::span::{EventDateSpan, EventDateTimeSpan};
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> TimeRange
+    {
+        TimeRange {
+            start: Some(NaiveDate::from_ymd(2021, 2, start.0).and_hms(start.1, 0, 0)),
+            end: Some(NaiveDate::from_ymd(2021, 2, end.0).and_hms(end.1, 0, 0)),
+        }
+    }
+
+    #[test]
+    fn date_time_spans_overlap_ranges()
+    {
+        let span = EventSpan::DateTime(EventDateTimeSpan {
+            start: NaiveDate::from_ymd(2021, 2, 1).and_hms(12, 0, 0),
+            end: NaiveDate::from_ymd(2021, 2, 1).and_hms(13, 0, 0),
+        });
+
+        assert!(span_overlaps(&span, &range((1, 0), (2, 0))));
+        assert!(span_overlaps(&span, &range((1, 12), (1, 13))));
+        assert!(!span_overlaps(&span, &range((1, 13), (1, 14))));
+        assert!(!span_overlaps(&span, &range((1, 10), (1, 12))));
+        assert!(span_overlaps(&span, &TimeRange { start: None, end: Some(NaiveDate::from_ymd(2021, 2, 1).and_hms(12, 30, 0)) }));
+    }
+
+    #[test]
+    fn date_spans_last_whole_days()
+    {
+        let span = EventSpan::Date(EventDateSpan {
+            start: NaiveDate::from_ymd(2021, 2, 1),
+            end: NaiveDate::from_ymd(2021, 2, 2),
+        });
+
+        assert!(span_overlaps(&span, &range((2, 23), (3, 0))));
+        assert!(!span_overlaps(&span, &range((3, 0), (4, 0))));
+    }
+
+    #[test]
+    fn instant_spans_overlap_if_they_start_in_range()
+    {
+        let start = NaiveDate::from_ymd(2021, 2, 1).and_hms(12, 0, 0);
+        let span = EventSpan::DateTime(EventDateTimeSpan { start, end: start });
+
+        assert!(span_overlaps(&span, &range((1, 12), (1, 13))));
+        assert!(!span_overlaps(&span, &range((1, 11), (1, 12))));
+    }
+}
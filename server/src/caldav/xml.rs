@@ -0,0 +1,420 @@
+//! Parsing of PROPFIND and REPORT bodies, and writing of multistatus responses.
+
+use chrono::NaiveDateTime;
+use roxmltree::{Document, Node};
+
+pub const DAV: &str = "DAV:";
+pub const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+pub const CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+
+/// Namespaces written with a prefix (the others are declared
+/// on the elements that use them).
+const PREFIXES: [(&str, &str); 3] = [("d", DAV), ("c", CALDAV), ("cs", CALENDARSERVER)];
+
+#[derive(Error, Debug)]
+pub enum XmlError
+{
+    #[error(transparent)]
+    Parse(#[from] roxmltree::Error),
+
+    #[error("Expected {0}.")]
+    UnexpectedElement(&'static str),
+
+    #[error("Unsupported report {0}.")]
+    UnsupportedReport(String),
+
+    #[error("Invalid time-range.")]
+    InvalidTimeRange,
+}
+
+/// Name of a property, e.g. `DAV:` `getetag`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PropName
+{
+    pub namespace: String,
+    pub name: String,
+}
+
+impl PropName
+{
+    pub fn new(namespace: &str, name: &str) -> PropName
+    {
+        PropName { namespace: namespace.to_owned(), name: name.to_owned() }
+    }
+}
+
+/// The properties a PROPFIND or REPORT asks for.
+#[derive(Debug, PartialEq)]
+pub enum PropRequest
+{
+    /// Every property that is not expensive to compute.
+    AllProp,
+
+    /// The names of the properties, without their values.
+    PropName,
+
+    Prop(Vec<PropName>),
+}
+
+/// A `time-range` filter, in UTC. Either end can be unbounded.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct TimeRange
+{
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Report
+{
+    /// `calendar-query`. Only time ranges on VEVENTs are supported
+    /// as filters, other filters match everything.
+    CalendarQuery
+    {
+        props: PropRequest,
+        time_range: Option<TimeRange>,
+    },
+
+    CalendarMultiget
+    {
+        props: PropRequest,
+        hrefs: Vec<String>,
+    },
+
+    /// `sync-collection` (RFC 6578), `sync_token` is `None` on the initial sync.
+    SyncCollection
+    {
+        props: PropRequest,
+        sync_token: Option<String>,
+    },
+}
+
+/// Parses the body of a PROPFIND, an empty body asks for all properties.
+pub fn parse_propfind(body: &str) -> Result<PropRequest, XmlError>
+{
+    if body.trim().is_empty()
+    {
+        return Ok(PropRequest::AllProp);
+    }
+
+    let document = Document::parse(body)?;
+    let root = document.root_element();
+
+    if !is(&root, DAV, "propfind")
+    {
+        return Err(XmlError::UnexpectedElement("DAV:propfind"));
+    }
+
+    parse_prop_request(&root)
+}
+
+pub fn parse_report(body: &str) -> Result<Report, XmlError>
+{
+    let document = Document::parse(body)?;
+    let root = document.root_element();
+
+    let props = parse_prop_request(&root)?;
+
+    if is(&root, CALDAV, "calendar-query")
+    {
+        let time_range = root.descendants()
+            .find(|node| is(node, CALDAV, "time-range"))
+            .map(|node| parse_time_range(&node))
+            .transpose()?;
+
+        Ok(Report::CalendarQuery { props, time_range })
+    }
+    else if is(&root, CALDAV, "calendar-multiget")
+    {
+        let hrefs = children(&root, DAV, "href")
+            .filter_map(|node| node.text())
+            .map(|href| href.trim().to_owned())
+            .collect();
+
+        Ok(Report::CalendarMultiget { props, hrefs })
+    }
+    else if is(&root, DAV, "sync-collection")
+    {
+        let sync_token = children(&root, DAV, "sync-token")
+            .next()
+            .and_then(|node| node.text())
+            .map(|token| token.trim().to_owned())
+            .filter(|token| !token.is_empty());
+
+        Ok(Report::SyncCollection { props, sync_token })
+    }
+    else
+    {
+        Err(XmlError::UnsupportedReport(root.tag_name().name().to_owned()))
+    }
+}
+
+fn parse_prop_request(parent: &Node) -> Result<PropRequest, XmlError>
+{
+    if children(parent, DAV, "propname").next().is_some()
+    {
+        return Ok(PropRequest::PropName);
+    }
+
+    match children(parent, DAV, "prop").next()
+    {
+        Some(prop) => Ok(PropRequest::Prop(
+            prop.children()
+                .filter(|node| node.is_element())
+                .map(|node| PropName::new(node.tag_name().namespace().unwrap_or(""), node.tag_name().name()))
+                .collect()
+        )),
+        None => Ok(PropRequest::AllProp),
+    }
+}
+
+fn parse_time_range(node: &Node) -> Result<TimeRange, XmlError>
+{
+    let parse = |name: &str| node.attribute(name)
+        .map(|value| NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ").map_err(|_| XmlError::InvalidTimeRange))
+        .transpose();
+
+    let time_range = TimeRange { start: parse("start")?, end: parse("end")? };
+
+    match time_range
+    {
+        TimeRange { start: None, end: None } => Err(XmlError::InvalidTimeRange),
+        TimeRange { start: Some(start), end: Some(end) } if start >= end => Err(XmlError::InvalidTimeRange),
+        time_range => Ok(time_range),
+    }
+}
+
+fn is(node: &Node, namespace: &str, name: &str) -> bool
+{
+    node.is_element() && node.tag_name().namespace() == Some(namespace) && node.tag_name().name() == name
+}
+
+fn children<'a, 'input: 'a>(node: &Node<'a, 'input>, namespace: &'a str, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>>
+{
+    node.children().filter(move |child| is(child, namespace, name))
+}
+
+/// Escapes text and attribute values.
+pub fn escape(text: &str) -> String
+{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes an element whose content is already XML.
+pub fn element(prop: &PropName, content: &str) -> String
+{
+    let (name, declaration) = match PREFIXES.iter().find(|(_, namespace)| *namespace == prop.namespace)
+    {
+        Some((prefix, _)) => (format!("{}:{}", prefix, prop.name), String::new()),
+        None => (format!("x:{}", prop.name), format!(" xmlns:x=\"{}\"", escape(&prop.namespace))),
+    };
+
+    if content.is_empty()
+    {
+        format!("<{}{}/>", name, declaration)
+    }
+    else
+    {
+        format!("<{}{}>{}</{}>", name, declaration, content, name)
+    }
+}
+
+/// Writes a `DAV:href`.
+pub fn href(href: &str) -> String
+{
+    format!("<d:href>{}</d:href>", escape(href))
+}
+
+/// Writes a `DAV:error` body with the condition that failed,
+/// `content` being the content of its element.
+pub fn error(condition: &PropName, content: &str) -> String
+{
+    format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:error {}>{}</d:error>", namespaces(), element(condition, content))
+}
+
+fn namespaces() -> String
+{
+    PREFIXES.iter()
+        .map(|(prefix, namespace)| format!("xmlns:{}=\"{}\"", prefix, namespace))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Builds a `DAV:multistatus` response.
+pub struct MultiStatus
+{
+    output: String,
+}
+
+impl MultiStatus
+{
+    pub fn new() -> MultiStatus
+    {
+        MultiStatus {
+            output: format!("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:multistatus {}>", namespaces()),
+        }
+    }
+
+    /// Writes the response of a resource, `found` being the properties
+    /// it has (with their values) and `not_found` those it doesn't.
+    pub fn propstat(&mut self, resource_href: &str, found: &[(PropName, String)], not_found: &[PropName])
+    {
+        self.output.push_str("<d:response>");
+        self.output.push_str(&href(resource_href));
+
+        if !found.is_empty() || not_found.is_empty()
+        {
+            self.output.push_str("<d:propstat><d:prop>");
+            for (prop, value) in found
+            {
+                self.output.push_str(&element(prop, value));
+            }
+            self.output.push_str("</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>");
+        }
+
+        if !not_found.is_empty()
+        {
+            self.output.push_str("<d:propstat><d:prop>");
+            for prop in not_found
+            {
+                self.output.push_str(&element(prop, ""));
+            }
+            self.output.push_str("</d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>");
+        }
+
+        self.output.push_str("</d:response>");
+    }
+
+    /// Writes the response of a resource that has no properties
+    /// to show, e.g. one that doesn't exist (anymore).
+    pub fn status(&mut self, resource_href: &str, status: &str)
+    {
+        self.output.push_str(&format!("<d:response>{}<d:status>HTTP/1.1 {}</d:status></d:response>", href(resource_href), status));
+    }
+
+    pub fn sync_token(&mut self, token: &str)
+    {
+        self.output.push_str(&format!("<d:sync-token>{}</d:sync-token>", escape(token)));
+    }
+
+    pub fn finish(mut self) -> String
+    {
+        self.output.push_str("</d:multistatus>");
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parses_propfind()
+    {
+        let body = r#"<?xml version="1.0"?>
+            <D:propfind xmlns:D="DAV:" xmlns:CS="http://calendarserver.org/ns/">
+                <D:prop><D:getetag/><CS:getctag/></D:prop>
+            </D:propfind>"#;
+
+        assert_eq!(parse_propfind(body).unwrap(), PropRequest::Prop(vec![
+            PropName::new(DAV, "getetag"),
+            PropName::new(CALENDARSERVER, "getctag"),
+        ]));
+
+        assert_eq!(parse_propfind("").unwrap(), PropRequest::AllProp);
+        assert_eq!(parse_propfind(r#"<propfind xmlns="DAV:"><allprop/></propfind>"#).unwrap(), PropRequest::AllProp);
+        assert_eq!(parse_propfind(r#"<propfind xmlns="DAV:"><propname/></propfind>"#).unwrap(), PropRequest::PropName);
+        assert!(parse_propfind(r#"<propfind xmlns="urn:other"/>"#).is_err());
+    }
+
+    #[test]
+    fn parses_calendar_query()
+    {
+        let body = r#"<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+                <D:prop><D:getetag/></D:prop>
+                <C:filter>
+                    <C:comp-filter name="VCALENDAR">
+                        <C:comp-filter name="VEVENT">
+                            <C:time-range start="20210201T000000Z" end="20210301T000000Z"/>
+                        </C:comp-filter>
+                    </C:comp-filter>
+                </C:filter>
+            </C:calendar-query>"#;
+
+        assert_eq!(parse_report(body).unwrap(), Report::CalendarQuery {
+            props: PropRequest::Prop(vec![PropName::new(DAV, "getetag")]),
+            time_range: Some(TimeRange {
+                start: Some(NaiveDate::from_ymd(2021, 2, 1).and_hms(0, 0, 0)),
+                end: Some(NaiveDate::from_ymd(2021, 3, 1).and_hms(0, 0, 0)),
+            }),
+        });
+    }
+
+    #[test]
+    fn rejects_invalid_time_range()
+    {
+        let query = |attributes: &str| format!(
+            r#"<C:calendar-query xmlns:C="urn:ietf:params:xml:ns:caldav"><C:filter><C:time-range {}/></C:filter></C:calendar-query>"#,
+            attributes
+        );
+
+        assert!(parse_report(&query("")).is_err());
+        assert!(parse_report(&query(r#"start="2021-02-01""#)).is_err());
+        assert!(parse_report(&query(r#"start="20210301T000000Z" end="20210201T000000Z""#)).is_err());
+        assert!(parse_report(&query(r#"start="20210301T000000Z""#)).is_ok());
+    }
+
+    #[test]
+    fn parses_multiget_and_sync_collection()
+    {
+        let body = r#"<C:calendar-multiget xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+                <D:prop><C:calendar-data/></D:prop>
+                <D:href>/dav/calendars/a/1.ics</D:href>
+                <D:href> /dav/calendars/a/2.ics </D:href>
+            </C:calendar-multiget>"#;
+
+        match parse_report(body).unwrap()
+        {
+            Report::CalendarMultiget { hrefs, .. } => assert_eq!(hrefs, vec!["/dav/calendars/a/1.ics", "/dav/calendars/a/2.ics"]),
+            report => panic!("Unexpected report {:?}", report),
+        }
+
+        let body = r#"<D:sync-collection xmlns:D="DAV:"><D:sync-token/><D:sync-level>1</D:sync-level><D:prop><D:getetag/></D:prop></D:sync-collection>"#;
+
+        match parse_report(body).unwrap()
+        {
+            Report::SyncCollection { sync_token, .. } => assert_eq!(sync_token, None),
+            report => panic!("Unexpected report {:?}", report),
+        }
+
+        assert!(matches!(parse_report(r#"<D:expand-property xmlns:D="DAV:"/>"#), Err(XmlError::UnsupportedReport(_))));
+    }
+
+    #[test]
+    fn writes_multistatus()
+    {
+        let mut multistatus = MultiStatus::new();
+        multistatus.propstat(
+            "/dav/calendars/",
+            &[(PropName::new(DAV, "getetag"), "\"a&b\"".replace('&', "&amp;"))],
+            &[PropName::new("urn:other", "color")],
+        );
+        multistatus.status("/dav/calendars/x.ics", "404 Not Found");
+        multistatus.sync_token("urn:caser:sync:1-a");
+
+        let xml = multistatus.finish();
+
+        assert!(xml.contains("<d:getetag>\"a&amp;b\"</d:getetag>"));
+        assert!(xml.contains("<x:color xmlns:x=\"urn:other\"/>"));
+        assert!(xml.contains("<d:status>HTTP/1.1 404 Not Found</d:status></d:response>"));
+        assert!(xml.ends_with("<d:sync-token>urn:caser:sync:1-a</d:sync-token></d:multistatus>"));
+
+        // Well formed.
+        Document::parse(&xml).unwrap();
+    }
+}
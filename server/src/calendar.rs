@@ -1,33 +1,15 @@
 use crate::database_helpers::{FromRow, RowHelpers};
 use postgres::Row;
-use crate::database_error::DatabaseError;
+use postgres::types::ToSql;
+use crate::connection_pool::PgsqlConn;
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use std::str::FromStr;
 use uuid::Uuid;
+use crate::subscription::{Subscription, SyncStatus};
 
-pub const CALENDAR_FIELDS: &str = "id, tenant_id";
-
-#[derive(Debug, Serialize, Deserialize, JsonSchema)]
-pub struct Calendar
-{
-    /// If this is a nil UUID (it's a thing, look it up)
-    /// it means the calendar does not exist in the database.
-    /// This is useful when deserializing Calendar
-    /// for create requests.
-    #[serde(default = "Uuid::nil")]
-    id: Uuid,
-}
-
-
-impl Calendar
-{
-    pub fn new(id: Uuid) -> Calendar
-    {
-        Calendar {
-            id
-        }
-    }
+pub use caser_common::calendar::*;
 
-    pub fn get_id(&self) -> Uuid { self.id }
-}
+pub const CALENDAR_FIELDS: &str = "id, tenant_id";
 
 impl FromRow for Calendar
 {
@@ -35,10 +17,57 @@ impl FromRow for Calendar
 
     fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
     {
+        let url: Option<String> = row.get_cell("subscription_url")?;
+
+        let subscription = match url
+        {
+            Some(url) =>
+            {
+                let status: String = row.get_cell("sync_status")?;
+
+                Some(Subscription {
+                    url,
+                    refresh_interval: row.get_cell("refresh_interval")?,
+                    status: SyncStatus::from_str(&status)
+                        .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                    last_synced_at: row.get_cell("last_synced_at")?,
+                    last_error: row.get_cell("last_sync_error")?,
+                })
+            },
+            None => None,
+        };
+
         Ok (
             Calendar {
-                id: row.get_cell("id")?
+                id: row.get_cell("id")?,
+                name: row.get_cell("name")?,
+                description: row.get_cell("description")?,
+                color: row.get_cell("color")?,
+                time_zone: row.get_cell("time_zone")?,
+                metadata: row.get_cell("metadata")?,
+                subscription,
+                last_modified: row.get_cell("last_modified")?,
+                deleted_at: row.get_cell("deleted_at")?,
             }
         )
     }
-}
\ No newline at end of file
+}
+
+/// Checks if the calendar with id `calendar_id` is subscribed to an external
+/// file. The events of subscribed calendars can only be changed by syncing
+/// the file, so routes that change events refuse to change them.
+pub fn is_subscribed(db: &mut PgsqlConn, calendar_id: &(dyn ToSql + Sync)) -> Result<bool, DatabaseError>
+{
+    let query = "SELECT 1 FROM calendars WHERE id = $1 AND subscription_url IS NOT NULL AND deleted_at IS NULL";
+
+    Ok(!db.query(query, &[calendar_id])?.is_empty())
+}
+
+/// Checks if the calendar with id `calendar_id` belongs to the tenant `tenant_id`.
+/// `false` if the calendar doesn't exist or is in the trash.
+pub fn belongs_to_tenant(db: &mut PgsqlConn, calendar_id: &Uuid, tenant_id: &Uuid) -> Result<bool, DatabaseError>
+{
+    let query = "SELECT 1 FROM calendars WHERE id = $1 AND tenant_id = $2 AND deleted_at IS NULL";
+
+    Ok(!db.query(query, &[calendar_id, tenant_id])?.is_empty())
+}
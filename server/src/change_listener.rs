@@ -0,0 +1,244 @@
+//! Listens to the change notifications PostgreSQL sends (through
+//! LISTEN/NOTIFY, see db_schema/9.sql) and hands them to whoever is
+//! waiting for changes, i.e. the live change stream routes.
+//!
+//! Notifications only tell which calendar changed. Event changes are
+//! read from the change log (see `sync`), so a notification that is
+//! missed (e.g. while reconnecting to the database) at worst delays a
+//! change, it never loses it.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Condvar};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use postgres::fallible_iterator::FallibleIterator;
+use crate::connection_pool::PgsqlPool;
+
+/// Name of the channel the database notifies changes on.
+pub const CHANNEL: &str = "caser_changes";
+
+/// How many notices are kept for waiters that fall behind.
+const MAX_NOTICES: usize = 1024;
+
+/// Something that changed in a calendar.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Notice
+{
+    /// One of the calendar's events changed.
+    Events,
+    CalendarUpdated,
+    CalendarDeleted,
+}
+
+/// Payload of a change notification, as built by the
+/// `notify_change` trigger.
+#[derive(Deserialize)]
+struct ChangePayload
+{
+    table: String,
+    op: String,
+    calendar_id: Uuid,
+}
+
+/// Notices that waiters get when they fell too far behind to know
+/// what changed (or when notifications might have been missed), so
+/// they should check for changes anyway.
+const LAGGED: &[Notice] = &[Notice::Events];
+
+struct HubState
+{
+    /// Id of the last notice published.
+    last_id: u64,
+
+    /// The most recent notices and their ids.
+    notices: VecDeque<(u64, Uuid, Notice)>,
+}
+
+/// Hands notices from the listener to the threads waiting for them.
+pub struct ChangeHub
+{
+    state: Mutex<HubState>,
+    condvar: Condvar,
+}
+
+impl ChangeHub
+{
+    pub fn new() -> ChangeHub
+    {
+        ChangeHub {
+            state: Mutex::new(HubState { last_id: 0, notices: VecDeque::new() }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Id of the last notice published. Waiting with this as the cursor
+    /// only returns notices published after this was called.
+    pub fn get_cursor(&self) -> u64
+    {
+        self.state.lock().unwrap().last_id
+    }
+
+    fn publish(&self, calendar_id: Uuid, notice: Notice)
+    {
+        let mut state = self.state.lock().unwrap();
+
+        state.last_id += 1;
+        let id = state.last_id;
+        state.notices.push_back((id, calendar_id, notice));
+
+        if state.notices.len() > MAX_NOTICES
+        {
+            state.notices.pop_front();
+        }
+
+        self.condvar.notify_all();
+    }
+
+    /// Tells all waiters to check for changes, used when
+    /// notifications might have been missed.
+    fn publish_lagged(&self)
+    {
+        let mut state = self.state.lock().unwrap();
+
+        state.last_id += 1;
+        state.notices.clear();
+
+        self.condvar.notify_all();
+    }
+
+    /// Waits up to `timeout` for notices of the calendar with id
+    /// `calendar_id` published after `cursor`.
+    ///
+    /// Returns the new cursor and the notices, which are empty if
+    /// the timeout was reached.
+    pub fn wait(&self, cursor: u64, calendar_id: Uuid, timeout: Duration) -> (u64, Vec<Notice>)
+    {
+        let deadline = Instant::now() + timeout;
+        let mut cursor = cursor;
+        let mut state = self.state.lock().unwrap();
+
+        loop
+        {
+            let oldest = state.notices.front().map(|(id, _, _)| *id).unwrap_or(state.last_id + 1);
+
+            // Notices after the cursor were dropped.
+            if state.last_id > cursor && oldest > cursor + 1
+            {
+                return (state.last_id, LAGGED.to_vec());
+            }
+
+            let notices: Vec<Notice> = state.notices
+                .iter()
+                .filter(|(id, calendar, _)| *id > cursor && *calendar == calendar_id)
+                .map(|(_, _, notice)| *notice)
+                .collect();
+
+            if !notices.is_empty()
+            {
+                return (state.last_id, notices);
+            }
+
+            // Nothing for this calendar up to here.
+            cursor = state.last_id;
+
+            let now = Instant::now();
+            if now >= deadline
+            {
+                return (cursor, vec![]);
+            }
+
+            state = self.condvar.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+/// Starts listening to change notifications in a new thread.
+///
+/// The listener holds one of the pool's connections for as long as
+/// the server runs.
+pub fn spawn(pool: PgsqlPool, hub: Arc<ChangeHub>) -> JoinHandle<()>
+{
+    thread::spawn(move || loop
+    {
+        if let Err(e) = listen(&pool, &hub)
+        {
+            log::error!("Change listener failed: {}", e);
+        }
+
+        // Notifications sent while reconnecting are lost.
+        hub.publish_lagged();
+        thread::sleep(Duration::from_secs(1));
+    })
+}
+
+fn listen(pool: &PgsqlPool, hub: &ChangeHub) -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut db = pool.get_conn()?;
+
+    db.batch_execute(&format!("LISTEN {};", CHANNEL))?;
+
+    let mut notifications = db.notifications();
+    let mut iter = notifications.blocking_iter();
+
+    while let Some(notification) = iter.next()?
+    {
+        let payload: ChangePayload = match serde_json::from_str(notification.payload())
+        {
+            Ok(payload) => payload,
+            Err(e) =>
+            {
+                log::error!("Invalid change notification: {}", e);
+                continue;
+            }
+        };
+
+        let notice = match (payload.table.as_str(), payload.op.as_str())
+        {
+            ("calendars", "DELETE") => Notice::CalendarDeleted,
+            ("calendars", _) => Notice::CalendarUpdated,
+            _ => Notice::Events,
+        };
+
+        hub.publish(payload.calendar_id, notice);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn wait_for_calendar_notices()
+    {
+        let hub = ChangeHub::new();
+        let calendar = Uuid::new_v4();
+        let other_calendar = Uuid::new_v4();
+
+        let cursor = hub.get_cursor();
+        hub.publish(other_calendar, Notice::Events);
+        hub.publish(calendar, Notice::CalendarUpdated);
+
+        let (cursor, notices) = hub.wait(cursor, calendar, Duration::from_millis(10));
+        assert_eq!(notices, [Notice::CalendarUpdated]);
+
+        let (_, notices) = hub.wait(cursor, calendar, Duration::from_millis(10));
+        assert!(notices.is_empty());
+    }
+
+    #[test]
+    fn lagged_waiters_check_for_changes()
+    {
+        let hub = ChangeHub::new();
+        let calendar = Uuid::new_v4();
+
+        let cursor = hub.get_cursor();
+        hub.publish_lagged();
+
+        let (_, notices) = hub.wait(cursor, calendar, Duration::from_millis(10));
+        assert_eq!(notices, [Notice::Events]);
+    }
+}
@@ -1,11 +1,12 @@
 
-use crate::env_helpers::get_env_default;
+use crate::env_helpers::{get_env_default, get_env_optional};
 
 /// Stores the server's configuration variables.
 ///
 /// Note: if you want to get the page size from inside a route
 /// use `CommonQueryParams` instead, which supports the `limit` parameter
 /// and falls back to the configured page size.
+#[derive(Clone)]
 pub struct Configs
 {
     /// Maximum amount of resources a single request can return.
@@ -14,6 +15,60 @@ pub struct Configs
     ///
     /// Generally used as a LIMIT clause in SQL queries.
     page_size: u32,
+
+    /// How often (in seconds) the alarm scheduler looks for alarms
+    /// that are due.
+    alarm_poll_interval: u64,
+
+    /// How often (in seconds) the webhook dispatcher looks for
+    /// notifications that are due.
+    webhook_poll_interval: u64,
+
+    /// How many times a notification is sent to a webhook
+    /// before giving up on it.
+    webhook_max_attempts: i32,
+
+    /// How often (in seconds) the subscription fetcher looks for
+    /// subscribed calendars that are due for a sync.
+    subscription_poll_interval: u64,
+
+    /// Whether calendars can subscribe to `file://` URLs, which read
+    /// files from the server's file system. Only meant for testing.
+    subscription_allow_files: bool,
+
+    /// Address the CalDAV server listens on. If this is None
+    /// the CalDAV interface is disabled.
+    caldav_address: Option<String>,
+
+    /// Issuer (`iss` claim) of the JWTs accepted as bearer tokens.
+    /// Bearer authentication is disabled if this or `jwt_jwks` is None.
+    jwt_issuer: Option<String>,
+
+    /// URL or file path of the issuer's JWKS.
+    jwt_jwks: Option<String>,
+
+    /// Audience (`aud` claim) tokens must be issued for. If this
+    /// is None the audience isn't checked.
+    jwt_audience: Option<String>,
+
+    /// Claim with the roles of the token's user, roles named
+    /// like a scope give the user that scope.
+    jwt_roles_claim: String,
+
+    /// Claim with the groups of the token's user, which
+    /// calendars can be shared with.
+    jwt_groups_claim: String,
+
+    /// Claim with the id of the token's tenant.
+    jwt_tenant_claim: String,
+
+    /// How many days audit log entries are kept. If this
+    /// is None entries are kept forever.
+    audit_retention_days: Option<u32>,
+
+    /// How many days deleted calendars and events stay in
+    /// the trash before they're removed for good.
+    trash_retention_days: u32,
 }
 
 impl Configs
@@ -23,10 +78,95 @@ impl Configs
         self.page_size
     }
 
+    pub fn get_alarm_poll_interval(&self) -> u64
+    {
+        self.alarm_poll_interval
+    }
+
+    pub fn get_webhook_poll_interval(&self) -> u64
+    {
+        self.webhook_poll_interval
+    }
+
+    pub fn get_webhook_max_attempts(&self) -> i32
+    {
+        self.webhook_max_attempts
+    }
+
+    pub fn get_subscription_poll_interval(&self) -> u64
+    {
+        self.subscription_poll_interval
+    }
+
+    pub fn get_subscription_allow_files(&self) -> bool
+    {
+        self.subscription_allow_files
+    }
+
+    pub fn get_caldav_address(&self) -> Option<&String>
+    {
+        self.caldav_address.as_ref()
+    }
+
+    pub fn get_jwt_issuer(&self) -> Option<&String>
+    {
+        self.jwt_issuer.as_ref()
+    }
+
+    pub fn get_jwt_jwks(&self) -> Option<&String>
+    {
+        self.jwt_jwks.as_ref()
+    }
+
+    pub fn get_jwt_audience(&self) -> Option<&String>
+    {
+        self.jwt_audience.as_ref()
+    }
+
+    pub fn get_jwt_roles_claim(&self) -> &str
+    {
+        &self.jwt_roles_claim
+    }
+
+    pub fn get_jwt_groups_claim(&self) -> &str
+    {
+        &self.jwt_groups_claim
+    }
+
+    pub fn get_jwt_tenant_claim(&self) -> &str
+    {
+        &self.jwt_tenant_claim
+    }
+
+    pub fn get_audit_retention_days(&self) -> Option<u32>
+    {
+        self.audit_retention_days
+    }
+
+    pub fn get_trash_retention_days(&self) -> u32
+    {
+        self.trash_retention_days
+    }
+
     pub fn get_configs() -> Configs
     {
         Configs {
             page_size: get_env_default("PAGE_SIZE", "1000").parse().expect("PAGE_SIZE is not a positive integer."),
+            alarm_poll_interval: get_env_default("ALARM_POLL_INTERVAL", "30").parse().expect("ALARM_POLL_INTERVAL is not a positive integer."),
+            webhook_poll_interval: get_env_default("WEBHOOK_POLL_INTERVAL", "5").parse().expect("WEBHOOK_POLL_INTERVAL is not a positive integer."),
+            webhook_max_attempts: get_env_default("WEBHOOK_MAX_ATTEMPTS", "8").parse().expect("WEBHOOK_MAX_ATTEMPTS is not a positive integer."),
+            subscription_poll_interval: get_env_default("SUBSCRIPTION_POLL_INTERVAL", "60").parse().expect("SUBSCRIPTION_POLL_INTERVAL is not a positive integer."),
+            subscription_allow_files: get_env_default("SUBSCRIPTION_ALLOW_FILES", "false").parse().expect("SUBSCRIPTION_ALLOW_FILES is not true or false."),
+            caldav_address: get_env_optional("CALDAV_ADDRESS"),
+            jwt_issuer: get_env_optional("JWT_ISSUER"),
+            jwt_jwks: get_env_optional("JWT_JWKS"),
+            jwt_audience: get_env_optional("JWT_AUDIENCE"),
+            jwt_roles_claim: get_env_default("JWT_ROLES_CLAIM", "roles"),
+            jwt_groups_claim: get_env_default("JWT_GROUPS_CLAIM", "groups"),
+            jwt_tenant_claim: get_env_default("JWT_TENANT_CLAIM", "tenant"),
+            audit_retention_days: get_env_optional("AUDIT_RETENTION_DAYS")
+                .map(|days| days.parse().expect("AUDIT_RETENTION_DAYS is not a positive integer.")),
+            trash_retention_days: get_env_default("TRASH_RETENTION_DAYS", "30").parse().expect("TRASH_RETENTION_DAYS is not a positive integer."),
         }
     }
 }
\ No newline at end of file
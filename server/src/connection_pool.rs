@@ -1,15 +1,54 @@
-use r2d2_postgres::r2d2::{Pool, PooledConnection};
+use r2d2_postgres::r2d2::{Pool, PooledConnection, ManageConnection};
 use r2d2_postgres::PostgresConnectionManager;
-use postgres::NoTls;
+use postgres::{Client, NoTls};
 use std::ops::{Deref, DerefMut};
 use rocket::request::{FromRequest, Outcome};
 use crate::database_error::{DatabaseError, DatabaseErrorKind};
 use rocket::http::Status;
 use rocket::{Request, State};
+use uuid::Uuid;
+use crate::authentication::auth_guard::{Principal, request_principal};
+use crate::acl::grantee;
 
+#[derive(Clone)]
 pub struct PgsqlPool
 {
-    pool: Pool<PostgresConnectionManager<NoTls>>,
+    pool: Pool<ConnectionManager>,
+}
+
+/// Hands out the pool's connections like `PostgresConnectionManager`,
+/// but also discards the connections that were marked as broken.
+#[derive(Debug)]
+struct ConnectionManager(PostgresConnectionManager<NoTls>);
+
+struct PooledClient
+{
+    client: Client,
+
+    /// Whether the connection still carries the state of whoever used
+    /// it last (see `Drop for PgsqlConn`) and must not be reused.
+    broken: bool,
+}
+
+impl ManageConnection for ConnectionManager
+{
+    type Connection = PooledClient;
+    type Error = postgres::Error;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error>
+    {
+        Ok( PooledClient { client: self.0.connect()?, broken: false } )
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error>
+    {
+        self.0.is_valid(&mut conn.client)
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool
+    {
+        conn.broken || self.0.has_broken(&mut conn.client)
+    }
 }
 
 impl PgsqlPool
@@ -21,29 +60,115 @@ impl PgsqlPool
             NoTls,
         );
 
-        let pool = Pool::new(manager).unwrap();
+        let pool = Pool::new(ConnectionManager(manager)).unwrap();
 
         PgsqlPool { pool }
     }
 
+    /// Gets a connection that sees no rows of tables with row-level security
+    /// until it's restricted to a tenant (see `PgsqlConn::restrict_to_tenant`).
     pub fn get_conn(&self) -> Result<PgsqlConn, r2d2_postgres::r2d2::Error>
     {
-        Ok( PgsqlConn { conn: self.pool.get()? } )
+        Ok( PgsqlConn { conn: self.pool.get()?, restricted: false, audited: false, unrestricted: false } )
+    }
+
+    /// Gets a connection that sees the rows of every tenant. Only meant for
+    /// background jobs and for lookups across tenants.
+    pub fn get_unrestricted_conn(&self) -> Result<PgsqlConn, DatabaseError>
+    {
+        let conn = self.pool.get().map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?;
+        let mut conn = PgsqlConn { conn, restricted: false, audited: false, unrestricted: true };
+
+        conn.batch_execute("SET caser.unrestricted = 'on';")?;
+
+        Ok(conn)
     }
 }
 
 pub struct PgsqlConn
 {
-    conn: PooledConnection<PostgresConnectionManager<NoTls>>,
+    conn: PooledConnection<ConnectionManager>,
+
+    /// Whether `restrict_to_tenant` was called, in which case the
+    /// restriction is lifted before the connection goes back to the pool.
+    restricted: bool,
+
+    /// Whether `set_audit_context` was called, in which case the
+    /// context is cleared before the connection goes back to the pool.
+    audited: bool,
+
+    /// Whether the connection sees the rows of every tenant, which
+    /// it stops doing before it goes back to the pool.
+    unrestricted: bool,
+}
+
+impl PgsqlConn
+{
+    /// Restricts this connection to the rows of the tenant with id `tenant_id`
+    /// through row-level security (see db_schema/15.sql). Queries should still
+    /// filter by tenant, this only guards against the ones that forget to.
+    /// Until this is called the connection sees no rows at all.
+    pub fn restrict_to_tenant(&mut self, tenant_id: &Uuid) -> Result<(), DatabaseError>
+    {
+        self.restricted = true;
+        self.conn.client.execute("SELECT set_config('caser.tenant_id', $1, false);", &[&tenant_id.to_string()])?;
+
+        Ok(())
+    }
+
+    /// Attributes the changes made through this connection to `principal`
+    /// and `route` in the audit log (see db_schema/19.sql).
+    pub fn set_audit_context(&mut self, principal: &Principal, route: &str) -> Result<(), DatabaseError>
+    {
+        let (principal_type, principal_id) = grantee(principal.get_subject());
+
+        self.audited = true;
+        self.conn.client.execute(
+            "SELECT set_config('caser.principal_type', $1, false), set_config('caser.principal_id', $2, false), set_config('caser.route', $3, false);",
+            &[&principal_type.to_string(), &principal_id, &route]
+        )?;
+
+        Ok(())
+    }
+}
+
+impl Drop for PgsqlConn
+{
+    fn drop(&mut self)
+    {
+        let mut reset = String::new();
+
+        if self.restricted
+        {
+            reset.push_str("RESET caser.tenant_id;");
+        }
+
+        if self.audited
+        {
+            reset.push_str("RESET caser.principal_type; RESET caser.principal_id; RESET caser.route;");
+        }
+
+        if self.unrestricted
+        {
+            reset.push_str("RESET caser.unrestricted;");
+        }
+
+        // A connection that would go back to the pool still restricted to
+        // (or attributed to) someone else is closed instead.
+        if !reset.is_empty() && self.conn.client.batch_execute(&reset).is_err()
+        {
+            self.conn.broken = true;
+        }
+    }
 }
 
 impl Deref for PgsqlConn
 {
-    type Target = PooledConnection<PostgresConnectionManager<NoTls>>;
+    type Target = Client;
 
     fn deref(&self) -> &Self::Target
     {
-        &self.conn
+        &self.conn.client
     }
 }
 
@@ -51,7 +176,7 @@ impl DerefMut for PgsqlConn
 {
     fn deref_mut(&mut self) -> &mut Self::Target
     {
-        &mut self.conn
+        &mut self.conn.client
     }
 }
 
@@ -77,6 +202,28 @@ impl<'a, 'r> FromRequest<'a, 'r> for PgsqlConn
             Err(_) => return Outcome::Failure((Status::InternalServerError, DatabaseErrorKind::Other(Box::new(PoolGetFail {})).into())),
         };
 
-        Outcome::Success(PgsqlConn { conn })
+        let mut conn = PgsqlConn { conn, restricted: false, audited: false, unrestricted: false };
+
+        // Connections of requests made with an API key or bearer token
+        // only see the rows of the principal's tenant, and the changes
+        // made through them are attributed to the principal.
+        if let Ok(Some(principal)) = request_principal(request)
+        {
+            let route = match request.route()
+            {
+                Some(route) => format!("{} {}", route.method, route.uri),
+                None => format!("{} {}", request.method(), request.uri().path()),
+            };
+
+            let result = conn.restrict_to_tenant(&principal.get_tenant_id())
+                .and_then(|_| conn.set_audit_context(principal, &route));
+
+            if let Err(e) = result
+            {
+                return Outcome::Failure((Status::InternalServerError, e));
+            }
+        }
+
+        Outcome::Success(conn)
     }
 }
\ No newline at end of file
@@ -25,6 +25,11 @@ pub enum DatabaseErrorKind
 }
 
 
+impl DatabaseError
+{
+    pub fn kind(&self) -> &DatabaseErrorKind { &self.kind }
+}
+
 impl From<DatabaseErrorKind> for DatabaseError
 {
     fn from(kind: DatabaseErrorKind) -> Self
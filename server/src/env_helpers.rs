@@ -14,4 +14,11 @@ pub fn get_env_default(name: &str, default: &str) -> String
         .find(|(key, _)| key == name)
         .map(|(_, value)| value)
         .unwrap_or(default.to_owned())
+}
+
+pub fn get_env_optional(name: &str) -> Option<String>
+{
+    env::vars()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
 }
\ No newline at end of file
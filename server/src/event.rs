@@ -0,0 +1,220 @@
+//! Server side of the events defined in `caser_common::event`:
+//! mapping database rows to events and calculating event instances.
+
+use postgres::Row;
+use chrono::NaiveDate;
+use itertools::Itertools;
+use std::convert::TryInto;
+use std::str::FromStr;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use crate::recurrence::RecurrenceRuleInstance;
+use crate::iter_helpers::MergeOrderedTrait;
+
+pub use caser_common::event::*;
+use caser_common::attendee::Organizer;
+
+impl FromRow for Event
+{
+    type SelfType = Event;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let rrule: Option<String> = row.get_cell("rrule")?;
+
+        let plain = EventPlain {
+            id: row.get_cell("id")?,
+            parent_id: row.get_cell("parent_event_id")?,
+            recurrence_id: row.get_cell("recurrence_id")?,
+
+            start_date: row.get_cell("start_date")?,
+            start_time: row.get_cell("start_time")?,
+            end_date: row.get_cell("end_date")?,
+            end_time: row.get_cell("end_time")?,
+
+            recurrence: match rrule
+            {
+                Some(rrule) => Some(
+                    RecurrencePlain {
+                        rrule: Some(rrule),
+                        exdates: Some(row.get_cell_with_default("exdates", vec![])?),
+                        rdates: Some(row.get_cell_with_default("rdates", vec![])?),
+                    }
+                ),
+                None => None,
+            },
+
+            title: row.get_cell("title")?,
+            description: row.get_cell("description")?,
+            metadata: row.get_cell("metadata")?,
+
+            organizer: row.get_cell::<Option<String>>("organizer_email")?
+                .map(|email| Ok::<_, DatabaseError>(
+                    Organizer {
+                        email,
+                        display_name: row.get_cell("organizer_name")?,
+                    }
+                ))
+                .transpose()?,
+
+            class: Some(
+                EventClass::from_str(&row.get_cell::<String>("class")?)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?
+            ),
+
+            attendees: None,
+
+            sequence: row.get_cell("sequence")?,
+            last_modified: row.get_cell("last_modified")?,
+            deleted_at: None,
+        };
+
+        plain
+            .try_into()
+            .map_err(|e: FromPlainError| DatabaseErrorKind::Other(Box::new(e)).into())
+    }
+}
+
+pub trait GenerateInstances
+{
+    /// Calculates the instances of this event that start between `since`
+    /// and `until` (both inclusive), skipping the first `offset` instances
+    /// and returning at most `limit` instances.
+    ///
+    /// Overrides in `overrides` are merged onto the instances they
+    /// replace (i.e. the instances whose date is the override's `recurrence_id`)
+    /// before filtering, so an override that moves its instance into the
+    /// window is returned and one that moves it out of the window isn't.
+    /// Instances are sorted by their start date before they're paged.
+    fn generate_instances(
+        &self,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+        overrides: &[EventOverride],
+        offset: usize,
+        limit: usize,
+    ) -> Vec<EventInstance>;
+
+    /// Checks if the event has an instance on `date`, i.e. if an
+    /// override with that `recurrence_id` would replace something.
+    fn has_instance(&self, date: NaiveDate) -> bool
+    {
+        self.generate_instances(Some(date), Some(date), &[], 0, usize::MAX)
+            .iter()
+            .any(|instance| instance.get_recurrence_id() == date)
+    }
+}
+
+impl GenerateInstances for EventRecurring
+{
+    fn generate_instances(
+        &self,
+        since: Option<NaiveDate>,
+        until: Option<NaiveDate>,
+        overrides: &[EventOverride],
+        offset: usize,
+        limit: usize,
+    ) -> Vec<EventInstance>
+    {
+        let recurrence = self.get_recurrence();
+        let rule_instance = RecurrenceRuleInstance::new(recurrence.get_rule(), self.get_span().get_start_date());
+
+        let rdates: Vec<NaiveDate> = recurrence.get_rdates()
+            .iter()
+            .cloned()
+            .sorted()
+            .collect();
+
+        let exdates = recurrence.get_exdates();
+
+        let dates = || rule_instance
+            .calculate_instances()
+            .merge_ordered(rdates.iter().cloned())
+            .dedup()
+            .filter(move |date| !exdates.contains(date));
+
+        let find_override = |date: NaiveDate| overrides.iter().find(|o| o.get_recurrence_id() == date);
+
+        let in_window = |start: NaiveDate| since.map_or(true, |since| start >= since) && until.map_or(true, |until| start <= until);
+
+        // Overrides can move their instance anywhere, so overridden instances
+        // are sorted by where they start now. There are only as many of them
+        // as there are overrides.
+        let last_override = overrides.iter().map(|o| o.get_recurrence_id()).max();
+
+        let overridden = dates()
+            .take_while(|date| last_override.map_or(false, |last| *date <= last))
+            .filter_map(|date| find_override(date).map(|o| o.apply(self.instance_at(date))))
+            .filter(|instance| in_window(instance.get_span().get_start_date()))
+            .sorted_by_key(|instance| instance.get_span().get_start_date());
+
+        // The other instances start on their date, so they're already sorted.
+        let others = dates()
+            .filter(|date| find_override(*date).is_none())
+            .skip_while(|date| since.map_or(false, |since| *date < since))
+            .take_while(|date| until.map_or(true, |until| *date <= until))
+            .map(|date| self.instance_at(date));
+
+        overridden
+            .merge_by(others, |a, b| a.get_span().get_start_date() <= b.get_span().get_start_date())
+            .skip(offset)
+            .take(limit)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::convert::TryFrom;
+    use serde_json::json;
+
+    fn event(value: serde_json::Value) -> Event
+    {
+        let plain: EventPlain = serde_json::from_value(value).unwrap();
+        Event::try_from(plain).unwrap()
+    }
+
+    #[test]
+    fn pages_instances_by_start_date()
+    {
+        let id = "a3d6d5c8-5d8b-4c3e-9f0e-1b2c3d4e5f60";
+
+        let recurring = match event(json!({
+            "id": id,
+            "start_date": "2021-02-01",
+            "end_date": "2021-02-01",
+            "recurrence": { "rrule": "FREQ=WEEKLY;UNTIL=20210301", "exdates": [], "rdates": [] },
+            "last_modified": "2021-01-20T08:30",
+        }))
+        {
+            Event::Recurring(recurring) => recurring,
+            _ => panic!("Expected a recurring event."),
+        };
+
+        // Moves the instance of Feb 8 past the one of Feb 15.
+        let overrides = match event(json!({
+            "id": "0f1e2d3c-4b5a-4968-8776-655443322110",
+            "parent_id": id,
+            "recurrence_id": "2021-02-08",
+            "start_date": "2021-02-17",
+            "end_date": "2021-02-17",
+            "last_modified": "2021-01-21T09:00",
+        }))
+        {
+            Event::Override(event_override) => vec![event_override],
+            _ => panic!("Expected an override."),
+        };
+
+        let starts = |offset: usize, limit: usize| recurring
+            .generate_instances(None, None, &overrides, offset, limit)
+            .iter()
+            .map(|instance| instance.get_span().get_start_date().format("%Y-%m-%d").to_string())
+            .collect::<Vec<_>>();
+
+        assert_eq!(starts(0, 2), ["2021-02-01", "2021-02-15"]);
+        assert_eq!(starts(2, 2), ["2021-02-17", "2021-02-22"]);
+        assert_eq!(starts(4, 2), ["2021-03-01"]);
+    }
+}
@@ -0,0 +1,100 @@
+use postgres::Row;
+use chrono::{NaiveDate, NaiveDateTime, Duration};
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use crate::acl::Visibility;
+use crate::event::{Event, EventOverride, GenerateInstances, Redact};
+use crate::sync::SyncToken;
+use caser_common::ical::writer::{ICalWriter, write_calendar};
+use std::str::FromStr;
+
+pub use caser_common::feed::*;
+
+impl FromRow for Feed
+{
+    type SelfType = Feed;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let visibility: String = row.get_cell("visibility")?;
+
+        Ok(
+            Feed {
+                id: row.get_cell("id")?,
+                token: row.get_cell("token")?,
+                expansion_horizon: row.get_cell("expansion_horizon")?,
+                visibility: Visibility::from_str(&visibility)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                created_at: row.get_cell("created_at")?,
+            }
+        )
+    }
+}
+
+/// Gets the ETag of a feed, `token` being the calendar's current sync token.
+///
+/// The feed changes when an event changes (i.e. when the token changes)
+/// and, if it expands recurring events, when the day changes. The ETag
+/// is weak because DTSTAMPs change on every request.
+pub fn get_etag(feed: &Feed, token: SyncToken, today: NaiveDate) -> String
+{
+    match feed.expansion_horizon
+    {
+        Some(days) => format!("W/\"{}-{}-{}\"", token, days, today.format("%Y%m%d")),
+        None => format!("W/\"{}\"", token),
+    }
+}
+
+/// Writes a feed's VCALENDAR, with the events redacted to the feed's
+/// visibility. If the feed has an expansion horizon, recurring events
+/// are replaced by their instances within it.
+pub fn write_feed(feed: &Feed, events: Vec<Event>, dtstamp: NaiveDateTime) -> String
+{
+    let events = &events.redact(feed.visibility)[..];
+
+    let days = match feed.expansion_horizon
+    {
+        Some(days) => Duration::days(days as i64),
+        None => return write_calendar(events, dtstamp),
+    };
+
+    let today = dtstamp.date();
+    let mut writer = ICalWriter::new(dtstamp);
+
+    for event in events
+    {
+        match event
+        {
+            Event::Single(event) => writer.write_single(event),
+            Event::Recurring(event) =>
+            {
+                let overrides: Vec<EventOverride> = events
+                    .iter()
+                    .filter_map(|e| match e
+                    {
+                        Event::Override(o) if o.get_parent_id() == event.get_id() => Some(o.clone()),
+                        _ => None,
+                    })
+                    .collect();
+
+                let instances = event.generate_instances(Some(today - days), Some(today + days), &overrides, 0, usize::MAX);
+
+                for instance in instances
+                {
+                    let event_override = overrides
+                        .iter()
+                        .find(|o| Some(o.get_id()) == instance.get_override_id());
+
+                    let last_modified = event_override.map_or(event.get_last_modified(), |o| o.get_last_modified());
+                    let sequence = event_override.map_or(event.get_sequence(), |o| o.get_sequence());
+
+                    writer.write_instance(&instance, last_modified, sequence);
+                }
+            },
+            // Already merged onto the instances of their recurring event.
+            Event::Override(_) => (),
+        }
+    }
+
+    writer.finish()
+}
@@ -0,0 +1,395 @@
+//! Server side of iCalendar support: reading the events to export
+//! and importing iCalendar files into calendars.
+//!
+//! Imported recurring and single events keep their UID (in the `ical_uid`
+//! column), overrides are identified by their parent and recurrence id, so
+//! importing the same file again updates the events it created.
+
+use std::collections::HashMap;
+use chrono::NaiveDate;
+use postgres::Transaction;
+use uuid::Uuid;
+use crate::connection_pool::PgsqlConn;
+use crate::database_error::DatabaseError;
+use crate::database_helpers::FromRow;
+use crate::event::Event;
+
+pub use caser_common::ical::*;
+use caser_common::ical::parser::{Component, ICalEvent, parse_event, get_uid};
+use caser_common::ical::import::{ImportReport, ImportResult, ImportStatus};
+
+/// Gets all events of the calendar with id `calendar_id`, in the order
+/// they're exported. The order doesn't matter to iCalendar apps, but a
+/// stable one makes exports easier to diff.
+pub fn get_calendar_events(db: &mut PgsqlConn, calendar_id: &Uuid) -> Result<Vec<Event>, DatabaseError>
+{
+    let query = "SELECT * FROM events WHERE calendar_id = $1 AND deleted_at IS NULL ORDER BY start_date, id";
+
+    db.query(query, &[calendar_id])?
+        .iter()
+        .map(|row| Event::from_row(row))
+        .collect()
+}
+
+/// Gets the event with id `event_id` along with the rest of its resource:
+/// a recurring event comes with its overrides, an override with its
+/// recurring event (and its siblings). `None` if there's no such event.
+pub fn get_event_family(db: &mut PgsqlConn, calendar_id: &Uuid, event_id: &Uuid) -> Result<Option<Vec<Event>>, DatabaseError>
+{
+    let query = "
+        SELECT * FROM events
+        WHERE
+            calendar_id = $1
+            AND deleted_at IS NULL
+            AND (
+                id = $2
+                OR parent_event_id = $2
+                OR id = (SELECT parent_event_id FROM events WHERE calendar_id = $1 AND id = $2)
+            )
+        ORDER BY parent_event_id NULLS FIRST, recurrence_id;
+    ";
+
+    let events = db.query(query, &[calendar_id, event_id])?
+        .iter()
+        .map(|row| Event::from_row(row))
+        .collect::<Result<Vec<Event>, _>>()?;
+
+    let found = events.iter().any(|event| match event
+    {
+        Event::Single(e) => e.get_id() == *event_id,
+        Event::Recurring(e) => e.get_id() == *event_id,
+        Event::Override(e) => e.get_id() == *event_id,
+    });
+
+    Ok(if found { Some(events) } else { None })
+}
+
+fn result(component: &Component, recurrence_id: Option<NaiveDate>, status: ImportStatus, event_id: Option<Uuid>, message: Option<&str>) -> ImportResult
+{
+    ImportResult {
+        component: component.name.clone(),
+        uid: get_uid(component),
+        recurrence_id,
+        status,
+        event_id,
+        message: message.map(|m| m.to_owned()),
+    }
+}
+
+/// Imports the VEVENTs of `calendars` (the top level components of
+/// an iCalendar file) into the calendar with id `calendar_id`.
+///
+/// Each component is imported on its own (in a savepoint of `transaction`),
+/// one that fails doesn't stop the others from being imported. Only errors
+/// that prevent the whole import (like losing the connection) are returned.
+pub fn import_components(transaction: &mut Transaction, calendar_id: &Uuid, calendars: &[Component]) -> Result<ImportReport, DatabaseError>
+{
+    let mut report = ImportReport::default();
+    let mut masters: Vec<(&Component, ICalEvent)> = vec![];
+    let mut overrides: Vec<(&Component, ICalEvent)> = vec![];
+
+    for calendar in calendars
+    {
+        if calendar.name != "VCALENDAR"
+        {
+            report.push(result(calendar, None, ImportStatus::Skipped, None, Some("Not a VCALENDAR")));
+            continue;
+        }
+
+        for component in &calendar.components
+        {
+            match component.name.as_str()
+            {
+                "VEVENT" => match parse_event(component)
+                {
+                    Ok(event) if event.recurrence_id.is_some() => overrides.push((component, event)),
+                    Ok(event) => masters.push((component, event)),
+                    Err(e) => report.push(result(component, None, ImportStatus::Failed, None, Some(&e.to_string()))),
+                },
+                // Time zones are looked up by their TZID, see `parse_event`.
+                "VTIMEZONE" => (),
+                _ => report.push(result(component, None, ImportStatus::Skipped, None, Some("Only VEVENTs are imported"))),
+            }
+        }
+    }
+
+    // Instances removed by cancelled overrides are excluded from
+    // their recurring event, so that updating it doesn't bring them back.
+    for (_, event_override) in overrides.iter().filter(|(_, o)| o.cancelled)
+    {
+        let master = masters.iter_mut().find(|(_, m)| m.uid == event_override.uid);
+
+        if let Some((_, master)) = master
+        {
+            if let Some(exdates) = master.event.recurrence.as_mut().and_then(|r| r.exdates.as_mut())
+            {
+                exdates.extend(event_override.recurrence_id);
+            }
+        }
+    }
+
+    // Recurring events overrides can be linked to, by UID.
+    let mut parents: HashMap<String, Result<Uuid, &str>> = HashMap::new();
+
+    for (component, master) in masters
+    {
+        if parents.contains_key(&master.uid)
+        {
+            report.push(result(component, None, ImportStatus::Skipped, None, Some("Duplicate UID")));
+            continue;
+        }
+
+        if master.cancelled
+        {
+            parents.insert(master.uid, Err("The event with this UID was not imported"));
+            report.push(result(component, None, ImportStatus::Skipped, None, Some("The event is cancelled")));
+            continue;
+        }
+
+        if !master.event.validate_non_patch()
+        {
+            parents.insert(master.uid, Err("The event with this UID was not imported"));
+            report.push(result(component, None, ImportStatus::Failed, None, Some("Invalid event")));
+            continue;
+        }
+
+        let mut savepoint = transaction.transaction()?;
+
+        match upsert_event(&mut savepoint, calendar_id, &master)
+        {
+            Ok(id) =>
+            {
+                savepoint.commit()?;
+
+                let parent = match master.event.recurrence
+                {
+                    Some(_) => Ok(id),
+                    None => Err("The event with this UID is not recurring"),
+                };
+
+                parents.insert(master.uid, parent);
+
+                report.push(result(component, None, ImportStatus::Imported, Some(id), None));
+            },
+            Err(e) =>
+            {
+                parents.insert(master.uid, Err("The event with this UID was not imported"));
+                report.push(result(component, None, ImportStatus::Failed, None, Some(&e.to_string())));
+            },
+        }
+    }
+
+    for (component, mut event_override) in overrides
+    {
+        let recurrence_id = event_override.recurrence_id;
+
+        let parent = match parents.get(&event_override.uid)
+        {
+            Some(parent) => *parent,
+            None => find_recurring_event(transaction, calendar_id, &event_override.uid)?,
+        };
+
+        let parent_id = match parent
+        {
+            Ok(id) => id,
+            Err(message) =>
+            {
+                report.push(result(component, recurrence_id, ImportStatus::Failed, None, Some(message)));
+                continue;
+            },
+        };
+
+        event_override.event.parent_id = Some(parent_id);
+
+        if !event_override.event.validate_override()
+        {
+            report.push(result(component, recurrence_id, ImportStatus::Failed, None, Some("Invalid override")));
+            continue;
+        }
+
+        let mut savepoint = transaction.transaction()?;
+
+        let imported = if event_override.cancelled
+        {
+            cancel_instance(&mut savepoint, &parent_id, &event_override).map(|_| parent_id)
+        }
+        else
+        {
+            upsert_override(&mut savepoint, calendar_id, &event_override)
+        };
+
+        match imported
+        {
+            Ok(id) =>
+            {
+                savepoint.commit()?;
+                report.push(result(component, recurrence_id, ImportStatus::Imported, Some(id), None));
+            },
+            Err(e) => report.push(result(component, recurrence_id, ImportStatus::Failed, None, Some(&e.to_string()))),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Finds an event with UID `uid` that was imported before,
+/// overrides can only be linked to recurring events.
+fn find_recurring_event(transaction: &mut Transaction, calendar_id: &Uuid, uid: &str) -> Result<Result<Uuid, &'static str>, DatabaseError>
+{
+    let query = "
+        SELECT id, rrule IS NOT NULL AS recurring FROM events
+        WHERE calendar_id = $1 AND parent_event_id IS NULL AND ical_uid = $2 AND deleted_at IS NULL;
+    ";
+
+    let rows = transaction.query(query, &[calendar_id, &uid])?;
+
+    Ok(match rows.get(0)
+    {
+        Some(row) if row.try_get("recurring")? => Ok(row.try_get("id")?),
+        Some(_) => Err("The event with this UID is not recurring"),
+        None => Err("There's no event with this UID"),
+    })
+}
+
+/// Creates or updates a recurring or single event, returns its id.
+fn upsert_event(transaction: &mut Transaction, calendar_id: &Uuid, imported: &ICalEvent) -> Result<Uuid, postgres::Error>
+{
+    let event = &imported.event;
+
+    // Only the UID is matched, events we exported have their id as UID
+    // and as ical_uid (see db_schema/10.sql), so importing an export
+    // back into its calendar updates its events.
+    let query = "
+        SELECT id FROM events
+        WHERE calendar_id = $1 AND parent_event_id IS NULL AND ical_uid = $2 AND deleted_at IS NULL;
+    ";
+
+    let rows = transaction.query(query, &[calendar_id, &imported.uid])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        let id: Uuid = row.try_get("id")?;
+
+        let query = "
+            UPDATE events SET
+                start_date = $2, start_time = $3, end_date = $4, end_time = $5,
+                rrule = $6, exdates = $7, rdates = $8, title = $9, description = $10,
+                organizer_email = $11, organizer_name = $12, class = $13
+            WHERE id = $1;
+        ";
+
+        transaction.execute(query, &[
+            &id,
+            &event.start_date,
+            &event.start_time,
+            &event.end_date,
+            &event.end_time,
+            &event.recurrence.as_ref().map(|r| &r.rrule),
+            &event.recurrence.as_ref().map(|r| &r.exdates),
+            &event.recurrence.as_ref().map(|r| &r.rdates),
+            &event.title,
+            &event.description,
+            &event.organizer.as_ref().map(|o| &o.email),
+            &event.organizer.as_ref().and_then(|o| o.display_name.as_ref()),
+            &event.class.unwrap_or_default().to_string(),
+        ])?;
+
+        // Events that are not recurring can't have overrides.
+        if event.recurrence.is_none()
+        {
+            transaction.execute("DELETE FROM events WHERE parent_event_id = $1", &[&id])?;
+        }
+
+        return Ok(id);
+    }
+
+    let query = "
+        INSERT INTO events
+        (
+            start_date, start_time, end_date, end_time, rrule, exdates,
+            rdates, title, description, organizer_email, organizer_name,
+            class, ical_uid, calendar_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        RETURNING id;
+    ";
+
+    let row = transaction.query_one(query, &[
+        &event.start_date,
+        &event.start_time,
+        &event.end_date,
+        &event.end_time,
+        &event.recurrence.as_ref().map(|r| &r.rrule),
+        &event.recurrence.as_ref().map(|r| &r.exdates),
+        &event.recurrence.as_ref().map(|r| &r.rdates),
+        &event.title,
+        &event.description,
+        &event.organizer.as_ref().map(|o| &o.email),
+        &event.organizer.as_ref().and_then(|o| o.display_name.as_ref()),
+        &event.class.unwrap_or_default().to_string(),
+        &imported.uid,
+        calendar_id,
+    ])?;
+
+    row.try_get("id")
+}
+
+/// Creates or updates an override, returns its id.
+fn upsert_override(transaction: &mut Transaction, calendar_id: &Uuid, imported: &ICalEvent) -> Result<Uuid, postgres::Error>
+{
+    let event = &imported.event;
+
+    let query = "
+        INSERT INTO events
+        (
+            parent_event_id, recurrence_id, start_date, start_time, end_date,
+            end_time, title, description, organizer_email, organizer_name,
+            calendar_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (parent_event_id, recurrence_id) WHERE deleted_at IS NULL DO UPDATE SET
+            start_date = EXCLUDED.start_date,
+            start_time = EXCLUDED.start_time,
+            end_date = EXCLUDED.end_date,
+            end_time = EXCLUDED.end_time,
+            title = EXCLUDED.title,
+            description = EXCLUDED.description,
+            organizer_email = EXCLUDED.organizer_email,
+            organizer_name = EXCLUDED.organizer_name
+        RETURNING id;
+    ";
+
+    let row = transaction.query_one(query, &[
+        &event.parent_id,
+        &event.recurrence_id,
+        &event.start_date,
+        &event.start_time,
+        &event.end_date,
+        &event.end_time,
+        &event.title,
+        &event.description,
+        &event.organizer.as_ref().map(|o| &o.email),
+        &event.organizer.as_ref().and_then(|o| o.display_name.as_ref()),
+        calendar_id,
+    ])?;
+
+    row.try_get("id")
+}
+
+/// Removes the instance a cancelled override overrides from its
+/// recurring event, along with the override, if there's one.
+fn cancel_instance(transaction: &mut Transaction, parent_id: &Uuid, imported: &ICalEvent) -> Result<(), postgres::Error>
+{
+    let query = "
+        UPDATE events SET exdates = array_append(COALESCE(exdates, '{}'::DATE[]), $2)
+        WHERE id = $1 AND NOT ($2 = ANY(COALESCE(exdates, '{}'::DATE[])));
+    ";
+
+    transaction.execute(query, &[parent_id, &imported.recurrence_id])?;
+
+    let query = "DELETE FROM events WHERE parent_event_id = $1 AND recurrence_id = $2";
+
+    transaction.execute(query, &[parent_id, &imported.recurrence_id])?;
+
+    Ok(())
+}
@@ -9,11 +9,29 @@ mod database_helpers;
 mod routes;
 mod calendar;
 mod event;
+mod attendee;
+mod alarm;
+mod alarm_scheduler;
+mod webhook;
+mod webhook_dispatcher;
+mod sync;
+mod change_listener;
+mod ical;
+mod feed;
+mod subscription;
+mod subscription_fetcher;
+mod caldav;
 mod recurrence;
 mod configs;
 mod env_helpers;
 mod iter_helpers;
 mod authentication;
+mod tenant;
+mod acl;
+mod audit;
+mod audit_pruner;
+mod trash_purger;
+mod problem;
 
 extern crate dotenv;
 #[macro_use] extern crate thiserror;
@@ -22,18 +40,46 @@ extern crate dotenv;
 #[macro_use] extern crate rocket_okapi;
 
 use crate::connection_pool::PgsqlPool;
-use rocket::Request;
 use env_helpers::{get_env, get_env_default};
 use crate::configs::Configs;
+use crate::change_listener::ChangeHub;
+use crate::authentication::jwt::JwtVerifier;
+use std::sync::Arc;
 use rocket_okapi::swagger_ui::{make_swagger_ui, SwaggerUIConfig};
 
 fn main()
 {
     dotenv::dotenv().ok();
 
+    let pool = get_pgsql_pool();
+    let configs = Configs::get_configs();
+
+    alarm_scheduler::spawn(pool.clone(), configs.clone());
+    webhook_dispatcher::spawn(pool.clone(), configs.clone());
+    subscription_fetcher::spawn(pool.clone(), configs.clone());
+
+    if let Some(retention_days) = configs.get_audit_retention_days()
+    {
+        audit_pruner::spawn(pool.clone(), retention_days);
+    }
+
+    trash_purger::spawn(pool.clone(), configs.get_trash_retention_days());
+
+    if let Some(address) = configs.get_caldav_address()
+    {
+        caldav::spawn(pool.clone(), address);
+    }
+
+    let jwt_verifier = JwtVerifier::from_configs(&configs).expect("Could not load the JWKS.");
+
+    let change_hub = Arc::new(ChangeHub::new());
+    change_listener::spawn(pool.clone(), change_hub.clone());
+
     rocket::ignite()
-        .manage(get_pgsql_pool())
-        .manage(Configs::get_configs())
+        .manage(pool)
+        .manage(configs)
+        .manage(change_hub)
+        .manage(jwt_verifier)
         .mount("/api", routes::get_routes())
         .mount(
             "/swagger-ui/",
@@ -42,7 +88,7 @@ fn main()
                 ..Default::default()
             }),
         )
-        .register(catchers![not_found])
+        .register(routes::get_catchers())
         .launch();
 }
 
@@ -64,7 +110,3 @@ fn get_pgsql_pool() -> PgsqlPool
     PgsqlPool::new(&format!("host={} port={} dbname={} user={} password={}", pg_host, pg_port, pg_user, pg_user, pg_password))
 }
 
-
-
-#[catch(404)]
-fn not_found(_req: &Request) -> () {}
\ No newline at end of file
@@ -0,0 +1,444 @@
+//! Error responses. Everything that fails (routes, guards, Rocket itself)
+//! responds with an RFC 7807 problem (`application/problem+json`), which
+//! has a stable `code` clients can match on, the request field at fault
+//! if there's one, and the causes of the error.
+
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use caser_common::event::FromPlainError;
+use caser_common::recurrence::parser::RRuleParseError;
+use rocket::{Request, Response};
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::response::OpenApiResponder;
+use rocket_okapi::util::add_schema_response;
+use okapi::openapi3::Responses;
+use postgres::error::SqlState;
+use r2d2_postgres::r2d2;
+use std::io::Cursor;
+
+/// Machine-readable error codes. These are part of the API,
+/// so codes can be added but never renamed or removed.
+#[derive(Serialize, JsonSchema, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode
+{
+    /// The request is invalid in a way no other code describes.
+    BadRequest,
+
+    /// The body isn't valid JSON, or doesn't have the expected shape.
+    InvalidBody,
+
+    /// A required field is missing.
+    MissingField,
+
+    /// A field has an invalid value.
+    InvalidField,
+
+    /// An event's start, end and times don't make up a valid span.
+    InvalidSpan,
+
+    /// An override is invalid, e.g. it has a recurrence.
+    InvalidOverride,
+
+    /// An RRULE couldn't be parsed.
+    InvalidRrule,
+
+    /// The request refers to something that doesn't exist (other than
+    /// the resource in the path, which is `NOT_FOUND`).
+    InvalidReference,
+
+    /// The calendar is a subscription, which can't be changed.
+    SubscribedCalendar,
+
+    /// The request would leave something the database doesn't allow.
+    ConstraintViolation,
+
+    /// The request clashes with something that already exists.
+    Conflict,
+
+    /// There's no API key or token, or it's invalid.
+    Unauthorized,
+
+    /// The API key or token isn't allowed to do this.
+    Forbidden,
+
+    NotFound,
+
+    /// The resource doesn't have the ETag in `If-Match`.
+    PreconditionFailed,
+
+    InternalError,
+}
+
+/// Something that led to an error, e.g. the RRULE property that
+/// couldn't be parsed or the database constraint that failed.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Cause
+{
+    pub code: ErrorCode,
+    pub detail: String,
+}
+
+/// An RFC 7807 problem.
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq)]
+pub struct Problem
+{
+    /// Always `about:blank`, `code` tells problems apart.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+
+    /// The reason phrase of the status.
+    pub title: String,
+
+    pub status: u16,
+
+    pub code: ErrorCode,
+
+    /// What went wrong, for humans.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+
+    /// The request field at fault, as a path like `recurrence.rrule`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub causes: Vec<Cause>,
+}
+
+impl Problem
+{
+    pub fn new(status: Status, code: ErrorCode) -> Problem
+    {
+        Problem {
+            problem_type: "about:blank".to_owned(),
+            title: status.reason.to_owned(),
+            status: status.code,
+            code,
+            detail: None,
+            field: None,
+            causes: vec![],
+        }
+    }
+
+    pub fn detail(mut self, detail: &str) -> Problem
+    {
+        self.detail = Some(detail.to_owned());
+        self
+    }
+
+    pub fn field(mut self, field: &str) -> Problem
+    {
+        self.field = Some(field.to_owned());
+        self
+    }
+
+    fn field_option(self, field: Option<&str>) -> Problem
+    {
+        match field
+        {
+            Some(field) => self.field(field),
+            None => self,
+        }
+    }
+
+    pub fn cause(mut self, code: ErrorCode, detail: &str) -> Problem
+    {
+        self.causes.push(Cause { code, detail: detail.to_owned() });
+        self
+    }
+
+    /// 400 with `code`, the most common problem.
+    pub fn bad_request(code: ErrorCode, detail: &str) -> Problem
+    {
+        Problem::new(Status::BadRequest, code).detail(detail)
+    }
+
+    /// 400 because `field` is invalid.
+    pub fn invalid_field(field: &str, detail: &str) -> Problem
+    {
+        Problem::bad_request(ErrorCode::InvalidField, detail).field(field)
+    }
+
+    pub fn subscribed_calendar() -> Problem
+    {
+        Problem::bad_request(ErrorCode::SubscribedCalendar, "The calendar is a subscription, its events can't be changed.")
+    }
+
+    /// 403 with a reason, for principals the guards let through
+    /// that still can't do this.
+    pub fn forbidden(detail: &str) -> Problem
+    {
+        Problem::new(Status::Forbidden, ErrorCode::Forbidden).detail(detail)
+    }
+
+    pub fn not_found() -> Problem
+    {
+        Problem::new(Status::NotFound, ErrorCode::NotFound)
+    }
+
+    /// 409, the resource clashes with one that already exists.
+    pub fn conflict(detail: &str) -> Problem
+    {
+        Problem::new(Status::Conflict, ErrorCode::Conflict).detail(detail)
+    }
+
+    pub fn precondition_failed() -> Problem
+    {
+        Problem::new(Status::PreconditionFailed, ErrorCode::PreconditionFailed)
+            .detail("The resource changed since it was fetched.")
+    }
+
+    /// 500, without details so that nothing internal leaks.
+    pub fn internal() -> Problem
+    {
+        Problem::new(Status::InternalServerError, ErrorCode::InternalError)
+    }
+
+    /// The problem of a response that failed with `status`
+    /// without saying why (guards, Rocket's own errors).
+    pub fn from_status(status: Status) -> Problem
+    {
+        let (code, detail) = match status.code
+        {
+            400 => (ErrorCode::BadRequest, None),
+            401 => (ErrorCode::Unauthorized, Some("The request needs a valid API key or token in the Authorization header.")),
+            403 => (ErrorCode::Forbidden, Some("The API key or token isn't allowed to do this.")),
+            404 => (ErrorCode::NotFound, None),
+            412 => (ErrorCode::PreconditionFailed, Some("The resource changed since it was fetched.")),
+            422 => (ErrorCode::InvalidBody, Some("The body doesn't have the expected shape.")),
+            code if code < 500 => (ErrorCode::BadRequest, None),
+            _ => (ErrorCode::InternalError, None),
+        };
+
+        Problem {
+            detail: detail.map(|d| d.to_owned()),
+            ..Problem::new(status, code)
+        }
+    }
+
+    /// Adds the problem's schema to `responses` for the 4XX and 5XX codes.
+    pub fn add_responses(gen: &mut OpenApiGenerator, responses: &mut Responses) -> rocket_okapi::Result<()>
+    {
+        for status in &[400, 401, 403, 404, 409, 500]
+        {
+            add_schema_response(responses, *status, "application/problem+json", gen.json_schema::<Problem>())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The field, code and message of a database constraint. Names that
+/// aren't listed here still respond with `CONSTRAINT_VIOLATION`.
+fn describe_constraint(name: &str) -> Option<(Option<&'static str>, ErrorCode, &'static str)>
+{
+    let description = match name
+    {
+        "end_later_than_start" => (Some("end_time"), ErrorCode::InvalidSpan, "The event must end after it starts."),
+        "start_and_end_times" => (Some("start_time"), ErrorCode::InvalidSpan, "start_time and end_time must both be set, or neither."),
+        "dates_required_when_not_override" => (Some("start_date"), ErrorCode::MissingField, "Events need a start_date and an end_date."),
+        "no_exdates_rdates_if_no_rrule" => (Some("recurrence.rrule"), ErrorCode::MissingField, "exdates and rdates need an rrule."),
+        "no_recurrent_child" | "no_rrule_exdates_rdates_when_child" => (Some("recurrence"), ErrorCode::InvalidOverride, "Overrides can't have a recurrence."),
+        "recurrence_id_only_when_override" => (Some("recurrence_id"), ErrorCode::InvalidOverride, "Only overrides have a recurrence_id, and they need one."),
+        "unique_override" => (Some("recurrence_id"), ErrorCode::Conflict, "The instance already has an override."),
+        "unique_ical_uid" => (None, ErrorCode::Conflict, "Another event of the calendar has the same UID."),
+        "unique_dav_name" => (None, ErrorCode::Conflict, "Another event of the calendar has the same CalDAV name."),
+        "organizer_name_requires_email" => (Some("organizer.email"), ErrorCode::MissingField, "The organizer's name needs an email."),
+        "valid_class" => (Some("class"), ErrorCode::InvalidField, "The class must be PUBLIC, PRIVATE or CONFIDENTIAL."),
+        "valid_color" => (Some("color"), ErrorCode::InvalidField, "The color must be a hex color like #1e90ff."),
+        "positive_refresh_interval" => (Some("subscription.refresh_interval"), ErrorCode::InvalidField, "The refresh interval must be positive."),
+        "one_trigger" => (Some("trigger_offset"), ErrorCode::InvalidField, "Alarms need either a trigger_offset or a trigger_absolute, not both."),
+        "repeat_requires_interval" => (Some("repeat_interval"), ErrorCode::InvalidField, "Repeating alarms need a positive repeat_interval."),
+        "scope_requires_event_id" => (Some("event_id"), ErrorCode::InvalidField, "Only EVENT and INSTANCES webhooks have an event_id, and they need one."),
+        "positive_expansion_horizon" => (Some("expansion_horizon"), ErrorCode::InvalidField, "The expansion horizon must be positive."),
+        "free_busy_sees_no_details" => (Some("visibility"), ErrorCode::InvalidField, "FREE_BUSY grants can only have the FREE_BUSY visibility."),
+        _ => return None,
+    };
+
+    Some(description)
+}
+
+impl From<DatabaseError> for Problem
+{
+    fn from(e: DatabaseError) -> Self
+    {
+        let db_error = match e.kind()
+        {
+            DatabaseErrorKind::PostgresError(pg_error) => pg_error.as_db_error(),
+            _ => None,
+        };
+
+        let constraint = match e.kind()
+        {
+            DatabaseErrorKind::FailedConstraint(name) => Some(name.as_str()),
+            _ => db_error.and_then(|db_error| db_error.constraint()),
+        };
+
+        match (db_error.map(|db_error| db_error.code()), constraint)
+        {
+            (Some(&SqlState::FOREIGN_KEY_VIOLATION), Some(constraint)) =>
+                Problem::bad_request(ErrorCode::InvalidReference, "The request refers to something that doesn't exist.")
+                    .cause(ErrorCode::InvalidReference, constraint),
+            (_, Some(constraint)) =>
+            {
+                let problem = match describe_constraint(constraint)
+                {
+                    Some((field, ErrorCode::Conflict, detail)) =>
+                        Problem::conflict(detail).field_option(field),
+                    Some((field, code, detail)) =>
+                        Problem::bad_request(code, detail).field_option(field),
+                    None =>
+                        Problem::bad_request(ErrorCode::ConstraintViolation, "The request would leave data the database doesn't allow."),
+                };
+
+                problem.cause(ErrorCode::ConstraintViolation, constraint)
+            },
+            _ =>
+            {
+                log::error!("Request failed: {:?}", e);
+                Problem::internal()
+            },
+        }
+    }
+}
+
+impl From<postgres::Error> for Problem
+{
+    fn from(e: postgres::Error) -> Self
+    {
+        Problem::from(DatabaseError::from(e))
+    }
+}
+
+impl From<r2d2::Error> for Problem
+{
+    fn from(e: r2d2::Error) -> Self
+    {
+        Problem::from(DatabaseError::from(DatabaseErrorKind::Other(Box::new(e))))
+    }
+}
+
+impl From<RRuleParseError> for Problem
+{
+    fn from(e: RRuleParseError) -> Self
+    {
+        Problem::bad_request(ErrorCode::InvalidRrule, "The rrule is invalid.")
+            .field("recurrence.rrule")
+            .cause(ErrorCode::InvalidRrule, &e.to_string())
+    }
+}
+
+impl From<FromPlainError> for Problem
+{
+    fn from(e: FromPlainError) -> Self
+    {
+        let detail = e.to_string();
+
+        match e
+        {
+            FromPlainError::MissingField(field) => Problem::bad_request(ErrorCode::MissingField, &detail).field(field),
+            FromPlainError::InvalidSpan => Problem::bad_request(ErrorCode::InvalidSpan, &detail),
+            FromPlainError::InvalidOverride => Problem::bad_request(ErrorCode::InvalidOverride, &detail),
+            FromPlainError::RRuleParseError(e) => Problem {
+                detail: Some(detail),
+                ..Problem::from(e)
+            },
+        }
+    }
+}
+
+impl<'r> Responder<'r> for Problem
+{
+    fn respond_to(self, _request: &Request) -> response::Result<'r>
+    {
+        let body = serde_json::to_string(&self).map_err(|_| Status::InternalServerError)?;
+
+        Response::build()
+            .status(Status::from_code(self.status).unwrap_or(Status::InternalServerError))
+            .header(ContentType::new("application", "problem+json"))
+            .sized_body(Cursor::new(body))
+            .ok()
+    }
+}
+
+impl<'r> OpenApiResponder<'r> for Problem
+{
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses>
+    {
+        let mut responses = Responses::default();
+        Problem::add_responses(gen, &mut responses)?;
+
+        Ok(responses)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn known_constraints_have_a_field_and_message()
+    {
+        let problem = Problem::from(DatabaseError::from(DatabaseErrorKind::FailedConstraint("end_later_than_start".to_owned())));
+
+        assert_eq!(problem.status, 400);
+        assert_eq!(problem.code, ErrorCode::InvalidSpan);
+        assert_eq!(problem.field, Some("end_time".to_owned()));
+        assert_eq!(problem.causes, vec![Cause { code: ErrorCode::ConstraintViolation, detail: "end_later_than_start".to_owned() }]);
+    }
+
+    #[test]
+    fn unique_constraints_are_conflicts()
+    {
+        let problem = Problem::from(DatabaseError::from(DatabaseErrorKind::FailedConstraint("unique_ical_uid".to_owned())));
+
+        assert_eq!(problem.status, 409);
+        assert_eq!(problem.code, ErrorCode::Conflict);
+    }
+
+    #[test]
+    fn other_database_errors_hide_their_details()
+    {
+        let problem = Problem::from(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty));
+
+        assert_eq!(problem.status, 500);
+        assert_eq!(problem.code, ErrorCode::InternalError);
+        assert_eq!(problem.detail, None);
+        assert!(problem.causes.is_empty());
+    }
+
+    #[test]
+    fn rrule_errors_are_nested_causes()
+    {
+        let problem = Problem::from(FromPlainError::RRuleParseError(RRuleParseError::InvalidValue("FREQ")));
+
+        assert_eq!(problem.code, ErrorCode::InvalidRrule);
+        assert_eq!(problem.detail, Some("The event's rrule is invalid.".to_owned()));
+        assert_eq!(problem.field, Some("recurrence.rrule".to_owned()));
+        assert_eq!(problem.causes[0].detail, "Property FREQ has an invalid value.");
+    }
+
+    #[test]
+    fn missing_fields_are_named()
+    {
+        let problem = Problem::from(FromPlainError::MissingField("end_date"));
+
+        assert_eq!(problem.code, ErrorCode::MissingField);
+        assert_eq!(problem.field, Some("end_date".to_owned()));
+    }
+
+    #[test]
+    fn serializes_as_problem_json()
+    {
+        let value = serde_json::to_value(Problem::subscribed_calendar()).unwrap();
+
+        assert_eq!(value["type"], "about:blank");
+        assert_eq!(value["title"], "Bad Request");
+        assert_eq!(value["status"], 400);
+        assert_eq!(value["code"], "SUBSCRIBED_CALENDAR");
+        assert!(value.get("field").is_none());
+    }
+}
@@ -0,0 +1,92 @@
+//! The result of the API routes: a JSON body, or a `Problem`.
+
+use crate::problem::Problem;
+use rocket::{Request, Response};
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket_contrib::json::Json;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::response::OpenApiResponder;
+use okapi::openapi3::Responses;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::ops::Try;
+
+pub enum ApiResult<T>
+{
+    /// 200 with `T` as JSON.
+    Ok(T),
+
+    /// 201 with `T` as JSON and its URL in `Location`.
+    Created(T, String),
+
+    Problem(Problem),
+}
+
+impl<T> ApiResult<T>
+{
+    /// 404, the most common problem.
+    pub fn not_found() -> ApiResult<T>
+    {
+        ApiResult::Problem(Problem::not_found())
+    }
+}
+
+impl<T> From<Problem> for ApiResult<T>
+{
+    fn from(problem: Problem) -> Self
+    {
+        ApiResult::Problem(problem)
+    }
+}
+
+/// Lets routes use `?` on anything that converts into a `Problem`,
+/// e.g. database errors, and on other `ApiResult`s.
+impl<T> Try for ApiResult<T>
+{
+    type Ok = ApiResult<T>;
+    type Error = Problem;
+
+    fn into_result(self) -> Result<Self::Ok, Self::Error>
+    {
+        match self
+        {
+            ApiResult::Problem(problem) => Err(problem),
+            result => Ok(result),
+        }
+    }
+
+    fn from_error(e: Self::Error) -> Self { ApiResult::Problem(e) }
+
+    fn from_ok(v: Self::Ok) -> Self { v }
+}
+
+impl<'r, T: Serialize> Responder<'r> for ApiResult<T>
+{
+    fn respond_to(self, request: &Request) -> response::Result<'r>
+    {
+        match self
+        {
+            ApiResult::Ok(value) => Json(value).respond_to(request),
+            ApiResult::Created(value, location) =>
+            {
+                Response::build_from(Json(value).respond_to(request)?)
+                    .status(Status::Created)
+                    .raw_header("Location", location)
+                    .ok()
+            },
+            ApiResult::Problem(problem) => problem.respond_to(request),
+        }
+    }
+}
+
+impl<'r, T: Serialize + JsonSchema> OpenApiResponder<'r> for ApiResult<T>
+{
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses>
+    {
+        let mut responses = Json::<T>::responses(gen)?;
+        Problem::add_responses(gen, &mut responses)?;
+
+        Ok(responses)
+    }
+}
@@ -0,0 +1,45 @@
+//! Catchers for the errors that don't come from a route, e.g. a guard
+//! that failed or a body that couldn't be parsed, so that they respond
+//! with a problem like the routes do.
+
+use crate::problem::Problem;
+use rocket::{Catcher, Request};
+use rocket::http::Status;
+
+#[catch(400)]
+fn bad_request(_req: &Request) -> Problem { Problem::from_status(Status::BadRequest) }
+
+#[catch(401)]
+fn unauthorized(_req: &Request) -> Problem { Problem::from_status(Status::Unauthorized) }
+
+#[catch(403)]
+fn forbidden(_req: &Request) -> Problem { Problem::from_status(Status::Forbidden) }
+
+#[catch(404)]
+fn not_found(_req: &Request) -> Problem { Problem::from_status(Status::NotFound) }
+
+#[catch(413)]
+fn payload_too_large(_req: &Request) -> Problem { Problem::from_status(Status::PayloadTooLarge) }
+
+#[catch(415)]
+fn unsupported_media_type(_req: &Request) -> Problem { Problem::from_status(Status::UnsupportedMediaType) }
+
+#[catch(422)]
+fn unprocessable_entity(_req: &Request) -> Problem { Problem::from_status(Status::UnprocessableEntity) }
+
+#[catch(500)]
+fn internal_error(_req: &Request) -> Problem { Problem::from_status(Status::InternalServerError) }
+
+pub fn get_catchers() -> Vec<Catcher>
+{
+    catchers![
+        bad_request,
+        unauthorized,
+        forbidden,
+        not_found,
+        payload_too_large,
+        unsupported_media_type,
+        unprocessable_entity,
+        internal_error,
+    ]
+}
@@ -0,0 +1,254 @@
+//! Conditional requests (RFC 7232). Calendars and events have an ETag
+//! derived from what's in their body, clients send it back in `If-None-Match`
+//! to skip downloading what they already have, and in `If-Match` so that
+//! they don't overwrite changes they haven't seen.
+
+use crate::problem::Problem;
+use crate::routes::api_result::ApiResult;
+use rocket::{Request, Response};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::http::Status;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::response::OpenApiResponder;
+use rocket_okapi::util::add_schema_response;
+use okapi::openapi3::Responses;
+use crate::acl::Visibility;
+use chrono::NaiveDateTime;
+use std::ops::Try;
+
+/// The ETag of a calendar or an event that was last modified at `last_modified`.
+pub fn etag(last_modified: NaiveDateTime) -> String
+{
+    format!("\"{}\"", last_modified.timestamp_nanos())
+}
+
+/// The ETag of an event seen with `visibility`. Besides the event itself
+/// (its `last_modified` and `sequence`), its body has its attendees, whose
+/// rows `attendees_digest` is a digest of, and is redacted according to the
+/// visibility, so principals that see different bodies get different ETags.
+pub fn event_etag(last_modified: NaiveDateTime, sequence: i32, attendees_digest: &str, visibility: Visibility) -> String
+{
+    format!("\"{}-{}-{}-{}\"", last_modified.timestamp_nanos(), sequence, attendees_digest, visibility)
+}
+
+/// The tags of an `If-Match` or `If-None-Match` header.
+fn tags(header: &str) -> impl Iterator<Item = &str>
+{
+    header.split(',').map(|tag| tag.trim())
+}
+
+/// The `If-None-Match` header.
+pub struct IfNoneMatch(Option<String>);
+
+impl IfNoneMatch
+{
+    /// Checks if any of the ETags in the header matches `etag`,
+    /// using the weak comparison (RFC 7232, section 2.3.2).
+    pub fn matches(&self, etag: &str) -> bool
+    {
+        let etag = etag.trim_start_matches("W/");
+
+        self.0.as_deref().map_or(false, |header| tags(header)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+        )
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfNoneMatch
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        Outcome::Success(IfNoneMatch(
+            request.headers().get_one("If-None-Match").map(|etag| etag.to_owned())
+        ))
+    }
+}
+
+/// The `If-Match` header.
+pub struct IfMatch(Option<String>);
+
+impl IfMatch
+{
+    /// Checks if the request can change a resource whose ETag is `etag`
+    /// (`None` if it doesn't exist), i.e. if there's no header or if any of
+    /// its ETags matches using the strong comparison (RFC 7232, section 3.1).
+    pub fn matches(&self, etag: Option<&str>) -> bool
+    {
+        match (self.0.as_deref(), etag)
+        {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(header), Some(etag)) => tags(header).any(|tag| tag == "*" || (!tag.starts_with("W/") && tag == etag)),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfMatch
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        Outcome::Success(IfMatch(
+            request.headers().get_one("If-Match").map(|etag| etag.to_owned())
+        ))
+    }
+}
+
+/// The response of a route that supports conditional requests.
+pub enum Conditional<T>
+{
+    /// The route's result, with the resource's ETag if it has one.
+    Result(ApiResult<T>, Option<String>),
+
+    /// 304, the resource still has the ETag in `If-None-Match`.
+    NotModified(String),
+
+    /// 412, the resource doesn't have any of the ETags in `If-Match`.
+    PreconditionFailed,
+}
+
+impl<T> Conditional<T>
+{
+    /// Answers a GET with `result`, or with 304 if it's a resource
+    /// whose ETag (according to `etag_of`) matches `if_none_match`.
+    pub fn get(result: ApiResult<T>, if_none_match: &IfNoneMatch, etag_of: impl Fn(&T) -> Option<String>) -> Conditional<T>
+    {
+        match result
+        {
+            ApiResult::Ok(value) => match etag_of(&value)
+            {
+                Some(etag) if if_none_match.matches(&etag) => Conditional::NotModified(etag),
+                etag => Conditional::Result(ApiResult::Ok(value), etag),
+            },
+            result => Conditional::Result(result, None),
+        }
+    }
+}
+
+impl<T> From<ApiResult<T>> for Conditional<T>
+{
+    fn from(result: ApiResult<T>) -> Self
+    {
+        Conditional::Result(result, None)
+    }
+}
+
+impl<T> From<Problem> for Conditional<T>
+{
+    fn from(problem: Problem) -> Self
+    {
+        Conditional::Result(ApiResult::Problem(problem), None)
+    }
+}
+
+/// Lets routes use `?` like with `ApiResult`.
+impl<T> Try for Conditional<T>
+{
+    type Ok = Conditional<T>;
+    type Error = Problem;
+
+    fn into_result(self) -> Result<Self::Ok, Self::Error> { Ok(self) }
+
+    fn from_error(e: Self::Error) -> Self { e.into() }
+
+    fn from_ok(v: Self::Ok) -> Self { v }
+}
+
+impl<'r, T> Responder<'r> for Conditional<T>
+    where ApiResult<T>: Responder<'r>
+{
+    fn respond_to(self, request: &Request) -> response::Result<'r>
+    {
+        match self
+        {
+            Conditional::Result(result, etag) =>
+            {
+                let mut response = result.respond_to(request)?;
+
+                if let Some(etag) = etag
+                {
+                    response.set_raw_header("ETag", etag);
+                }
+
+                Ok(response)
+            },
+            Conditional::NotModified(etag) =>
+            {
+                Response::build()
+                    .status(Status::NotModified)
+                    .raw_header("ETag", etag)
+                    .ok()
+            },
+            Conditional::PreconditionFailed => Problem::precondition_failed().respond_to(request),
+        }
+    }
+}
+
+impl<'r, T> OpenApiResponder<'r> for Conditional<T>
+    where ApiResult<T>: OpenApiResponder<'r>
+{
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses>
+    {
+        let mut responses = ApiResult::<T>::responses(gen)?;
+        add_schema_response(&mut responses, 412, "application/problem+json", gen.json_schema::<Problem>())?;
+
+        Ok(responses)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn if_none_match_compares_weakly()
+    {
+        let header = |value: &str| IfNoneMatch(Some(value.to_owned()));
+
+        assert!(header("\"a\", \"b\"").matches("\"b\""));
+        assert!(header("W/\"b\"").matches("\"b\""));
+        assert!(header("*").matches("\"b\""));
+        assert!(!header("\"a\"").matches("\"b\""));
+        assert!(!IfNoneMatch(None).matches("\"b\""));
+    }
+
+    #[test]
+    fn if_match_compares_strongly()
+    {
+        let header = |value: &str| IfMatch(Some(value.to_owned()));
+
+        assert!(header("\"a\", \"b\"").matches(Some("\"b\"")));
+        assert!(header("*").matches(Some("\"b\"")));
+        assert!(!header("W/\"b\"").matches(Some("\"b\"")));
+        assert!(!header("*").matches(None));
+        assert!(IfMatch(None).matches(Some("\"b\"")));
+        assert!(IfMatch(None).matches(None));
+    }
+
+    #[test]
+    fn etags_change_with_last_modified()
+    {
+        let last_modified = NaiveDate::from_ymd(2021, 3, 1).and_hms_micro(12, 0, 0, 1);
+
+        assert_eq!(etag(last_modified), etag(last_modified));
+        assert_ne!(etag(last_modified), etag(NaiveDate::from_ymd(2021, 3, 1).and_hms_micro(12, 0, 0, 2)));
+    }
+
+    #[test]
+    fn event_etags_change_with_attendees_and_visibility()
+    {
+        let last_modified = NaiveDate::from_ymd(2021, 3, 1).and_hms(12, 0, 0);
+        let base = event_etag(last_modified, 1, "a", Visibility::All);
+
+        assert_eq!(base, event_etag(last_modified, 1, "a", Visibility::All));
+        assert_ne!(base, event_etag(last_modified, 2, "a", Visibility::All));
+        assert_ne!(base, event_etag(last_modified, 1, "b", Visibility::All));
+        assert_ne!(base, event_etag(last_modified, 1, "a", Visibility::Public));
+    }
+}
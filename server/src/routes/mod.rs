@@ -1,26 +1,194 @@
-//! TODO: protect all routes with an ApiKey request guard automatically
+//! TODO: protect all routes with a Principal request guard automatically
 
 use rocket::Route;
-use rocket_okapi::routes_with_openapi;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::handlers::OpenApiHandler;
+use rocket_okapi::settings::OpenApiSettings;
+use okapi::openapi3::Info;
+use crate::authentication::auth_guard::{OpenApiFromRequest, SecurityRequirement, security_schemes};
 
 mod routes_calendar;
 mod routes_event;
+mod routes_attendee;
+mod routes_revision;
+mod routes_trash;
+mod routes_alarm;
+mod routes_webhook;
+mod routes_stream;
+mod routes_ical;
+mod routes_feed;
+mod routes_representation;
+mod routes_tenant;
+mod routes_api_key;
+mod routes_grant;
+mod routes_audit;
 mod common_query_params;
+mod conditional;
+mod api_result;
+mod catchers;
+
+pub use catchers::get_catchers;
+
+/// Like `routes_with_openapi!`, but routes can be followed by the guard
+/// that authenticates them (`route => Guard`), whose security requirements
+/// are added to the route's operation (see `OpenApiFromRequest`).
+macro_rules! routes_with_security
+{
+    ($($module:ident :: $route:ident $(=> $guard:ty)?),* $(,)?) => {
+        paste::paste! {
+            with_openapi(
+                rocket::routes![$($module::$route),*],
+                vec![$(
+                    OpenApiRoute {
+                        add_operation: $module::[<okapi_add_operation_for_ $route _>],
+                        operation_id: concat!(stringify!($module), "_", stringify!($route)),
+                        security: security_requirements!($($guard)?),
+                    }
+                ),*],
+            )
+        }
+    };
+}
+
+macro_rules! security_requirements
+{
+    () => { None };
+    ($guard:ty) => { Some(<$guard as OpenApiFromRequest>::security_requirements()) };
+}
+
+/// A route of the OpenAPI document.
+struct OpenApiRoute
+{
+    /// Adds the route's operation, generated by `#[openapi]`.
+    add_operation: fn(&mut OpenApiGenerator, String) -> rocket_okapi::Result<()>,
+
+    operation_id: &'static str,
+
+    /// The requirements of the route's guard, `None` if
+    /// the route doesn't need authentication.
+    security: Option<Vec<SecurityRequirement>>,
+}
+
+/// Adds the route of the OpenAPI document of `openapi_routes`
+/// (`/openapi.json`) to `routes`.
+fn with_openapi(mut routes: Vec<Route>, openapi_routes: Vec<OpenApiRoute>) -> Vec<Route>
+{
+    let settings = OpenApiSettings::new();
+    let mut gen = OpenApiGenerator::new(settings.clone());
+
+    for route in &openapi_routes
+    {
+        (route.add_operation)(&mut gen, route.operation_id.to_owned())
+            .unwrap_or_else(|_| panic!("Could not generate OpenAPI operation for `{}`.", route.operation_id));
+    }
+
+    let mut spec = gen.into_openapi();
+
+    spec.info = Info {
+        title: env!("CARGO_PKG_NAME").to_owned(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        ..Default::default()
+    };
+
+    spec.components.get_or_insert_with(Default::default).security_schemes = security_schemes();
+
+    for path in spec.paths.values_mut()
+    {
+        let operations = vec![
+            &mut path.get, &mut path.put, &mut path.post, &mut path.delete,
+            &mut path.options, &mut path.head, &mut path.patch, &mut path.trace,
+        ];
+
+        for operation in operations.into_iter().flatten()
+        {
+            operation.security = openapi_routes.iter()
+                .find(|route| operation.operation_id.as_deref() == Some(route.operation_id))
+                .and_then(|route| route.security.clone());
+        }
+    }
+
+    routes.push(OpenApiHandler::new(spec).into_route(&settings.json_path));
+    routes
+}
 
 /// All project routes go in here, main.rs
 /// uses this method to get all routes.
 pub fn get_routes() -> Vec<Route>
 {
-    routes_with_openapi![
-        routes_calendar::get_calendar,
-        routes_calendar::insert_calendar,
-        routes_calendar::list_calendars,
-
-        routes_event::get_event,
-        routes_event::insert_event,
-        routes_event::get_instances,
-        routes_event::update_event,
-        routes_event::list_events,
-        routes_event::check_for_changes,
+    routes_with_security![
+        routes_calendar::get_calendar => RequireFreeBusy,
+        routes_calendar::insert_calendar => RequireWrite,
+        routes_calendar::list_calendars => RequireRead,
+        routes_calendar::update_calendar => RequireOwner,
+        routes_calendar::delete_calendar => RequireOwner,
+        routes_calendar::check_for_changes => RequireRead,
+        routes_calendar::refresh_subscription => RequireWrite,
+
+        routes_trash::list_trashed_calendars => RequireRead,
+        routes_trash::restore_calendar => RequireWrite,
+        routes_trash::list_trash => RequireRead,
+        routes_trash::restore_event => RequireWrite,
+
+        routes_event::get_event => RequireFreeBusy,
+        routes_event::insert_event => RequireWrite,
+        routes_event::get_instances => RequireFreeBusy,
+        routes_event::update_event => RequireWrite,
+        routes_event::patch_event => RequireWrite,
+        routes_event::delete_event => RequireWrite,
+        routes_event::list_events => RequireFreeBusy,
+        routes_event::check_for_changes => RequireRead,
+        routes_event::sync_events => RequireRead,
+
+        routes_revision::list_revisions => RequireRead,
+        routes_revision::get_revision => RequireRead,
+        routes_revision::restore_revision => RequireWrite,
+
+        routes_attendee::list_attendees => RequireRead,
+        routes_attendee::insert_attendee => RequireWrite,
+        routes_attendee::delete_attendee => RequireWrite,
+        routes_attendee::respond => RequireWrite,
+
+        routes_alarm::list_alarms => RequireRead,
+        routes_alarm::insert_alarm => RequireWrite,
+        routes_alarm::delete_alarm => RequireWrite,
+
+        routes_webhook::list_webhooks => RequireRead,
+        routes_webhook::get_webhook => RequireRead,
+        routes_webhook::insert_webhook => RequireWrite,
+        routes_webhook::update_webhook => RequireWrite,
+        routes_webhook::delete_webhook => RequireWrite,
+        routes_webhook::list_deliveries => RequireRead,
+        routes_webhook::retry_delivery => RequireWrite,
+
+        routes_stream::stream => RequireRead,
+        routes_stream::poll => RequireRead,
+
+        routes_ical::export_calendar => RequireRead,
+        routes_ical::export_event => RequireRead,
+        routes_ical::import_calendar => RequireWrite,
+
+        routes_feed::list_feeds => RequireRead,
+        routes_feed::insert_feed => RequireWrite,
+        routes_feed::delete_feed => RequireWrite,
+        routes_feed::get_feed,
+
+        routes_representation::get_calendar => RequireRead,
+        routes_representation::get_event => RequireRead,
+        routes_representation::list_events => RequireRead,
+
+        routes_tenant::insert_tenant => RequireSuper,
+        routes_tenant::list_tenants => RequireSuper,
+        routes_tenant::get_tenant => RequireSuper,
+
+        routes_api_key::insert_api_key => RequireSuper,
+        routes_api_key::list_api_keys => RequireSuper,
+        routes_api_key::revoke_api_key => RequireSuper,
+        routes_api_key::rotate_api_key => RequireSuper,
+
+        routes_grant::list_grants => RequireOwner,
+        routes_grant::insert_grant => RequireOwner,
+        routes_grant::delete_grant => RequireOwner,
+
+        routes_audit::list_audit_entries => RequireSuper,
     ]
 }
\ No newline at end of file
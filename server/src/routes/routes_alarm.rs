@@ -0,0 +1,114 @@
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::{ErrorCode, Problem};
+use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
+use rocket_contrib::json::Json;
+use crate::authentication::auth_guard::{RequireRead, RequireWrite};
+use crate::alarm::Alarm;
+use uuid::Uuid;
+
+/// Lists the alarms of an event.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/events/<event_id>/alarms")]
+pub fn list_alarms(mut db: PgsqlConn, _principal: RequireRead, calendar_id: UuidParam, event_id: UuidParam) -> ApiResult<Vec<Alarm>>
+{
+    let query = "
+        SELECT alarms.* FROM alarms
+        JOIN events ON events.id = alarms.event_id
+        WHERE events.calendar_id = $1 AND alarms.event_id = $2 AND events.deleted_at IS NULL;
+    ";
+
+    let rows = db.query(query, &[&calendar_id, &event_id])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| Alarm::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Adds an alarm to an event. Overrides can't have alarms, the
+/// alarms of a recurring event go off for all of its instances.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 201, 400, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/events/<event_id>/alarms", data = "<alarm>")]
+pub fn insert_alarm(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, event_id: UuidParam, alarm: Json<Alarm>) -> ApiResult<Alarm>
+{
+    if alarm.id.is_some()
+    {
+        return Problem::invalid_field("id", "Alarms get their id from the server.").into();
+    }
+
+    if !alarm.validate()
+    {
+        return Problem::bad_request(ErrorCode::InvalidField, "The alarm needs either a relative or an absolute trigger, and a positive interval if it repeats.").into();
+    }
+
+    let query = "
+        INSERT INTO alarms (event_id, action, trigger_offset, trigger_related, trigger_absolute, repeat, repeat_interval, description)
+        SELECT id, $3, $4, $5, $6, $7, $8, $9 FROM events
+        WHERE calendar_id = $1 AND id = $2 AND parent_event_id IS NULL AND deleted_at IS NULL
+        RETURNING *;
+    ";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &event_id,
+        &alarm.action.to_string(),
+        &alarm.trigger_offset,
+        &alarm.related.to_string(),
+        &alarm.trigger_absolute,
+        &alarm.repeat,
+        &alarm.repeat_interval,
+        &alarm.description,
+    ])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        ApiResult::Created(
+            Alarm::from_row(row)?,
+            format!("/api/calendars/{}/events/{}/alarms/{}", calendar_id, event_id, row.get_cell::<Uuid>("id")?)
+        )
+    }
+    else
+    {
+        ApiResult::not_found()
+    }
+}
+
+/// Removes an alarm from an event.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[delete("/calendars/<calendar_id>/events/<event_id>/alarms/<alarm_id>")]
+pub fn delete_alarm(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, event_id: UuidParam, alarm_id: UuidParam) -> ApiResult<()>
+{
+    let query = "
+        DELETE FROM alarms
+        USING events
+        WHERE
+            events.id = alarms.event_id
+            AND events.calendar_id = $1
+            AND events.deleted_at IS NULL
+            AND alarms.event_id = $2
+            AND alarms.id = $3;
+    ";
+
+    if db.execute(query, &[&calendar_id, &event_id, &alarm_id])? == 0
+    {
+        ApiResult::not_found()
+    }
+    else
+    {
+        ApiResult::Ok(())
+    }
+}
\ No newline at end of file
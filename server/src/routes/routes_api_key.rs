@@ -0,0 +1,180 @@
+//! Admin routes to manage the API keys of tenants, only API
+//! keys with the `SUPER` scope can use them.
+
+use crate::connection_pool::{PgsqlConn, PgsqlPool};
+use crate::routes::api_result::ApiResult;
+use crate::problem::{ErrorCode, Problem};
+use crate::database_helpers::{FromRow, UuidParam};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use rocket_contrib::json::Json;
+use rocket::State;
+use crate::authentication::auth_guard::RequireSuper;
+use crate::authentication::api_key::{NewSecret, format_key};
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::tenant::TenantApiKey;
+use chrono::Utc;
+
+/// Creates an API key in a tenant. The key is in the `key` property
+/// of the response, it can't be read again afterwards.
+///
+/// Required scope: `SUPER`
+///
+/// Response codes: 201, 400, 403, 404, 500
+#[openapi]
+#[post("/admin/tenants/<tenant_id>/api_keys", data = "<api_key>")]
+pub fn insert_api_key(mut db: PgsqlConn, pool: State<PgsqlPool>, _super_key: RequireSuper, tenant_id: UuidParam, api_key: Json<TenantApiKey>) -> ApiResult<TenantApiKey>
+{
+    let tenant_id = tenant_id.into_inner();
+
+    if api_key.id.is_some()
+    {
+        return Problem::invalid_field("id", "API keys get their id from the server.").into();
+    }
+
+    if !api_key.validate(Utc::now().naive_utc())
+    {
+        return Problem::bad_request(ErrorCode::InvalidField, "The key needs known scopes, at least one calendar if it's limited, and an expiry in the future.").into();
+    }
+
+    if db.query("SELECT 1 FROM tenants WHERE id = $1", &[&tenant_id])?.is_empty()
+    {
+        return ApiResult::not_found();
+    }
+
+    // The request's connection only sees the calendars of the
+    // admin's own tenant, which may not be the key's tenant.
+    if let Some(calendar_ids) = &api_key.calendar_ids
+    {
+        let mut unrestricted = pool.get_unrestricted_conn()?;
+        let query = "SELECT COUNT(DISTINCT id) FROM calendars WHERE tenant_id = $1 AND id = ANY($2) AND deleted_at IS NULL";
+
+        let found: i64 = unrestricted.query_one(query, &[&tenant_id, calendar_ids])?.get(0);
+        let mut distinct = calendar_ids.clone();
+        distinct.sort();
+        distinct.dedup();
+
+        if found != distinct.len() as i64
+        {
+            return Problem::bad_request(ErrorCode::InvalidReference, "Some of the calendars aren't the tenant's.").field("calendar_ids").into();
+        }
+    }
+
+    let secret = match NewSecret::generate()
+    {
+        Ok(secret) => secret,
+        Err(_) => return Problem::internal().into(),
+    };
+
+    let query = "
+        INSERT INTO api_keys (tenant_id, name, scopes, calendar_ids, expires_at, salt, secret_hash)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING *;
+    ";
+
+    let rows = db.query(query, &[
+        &tenant_id,
+        &api_key.name,
+        &api_key.scopes,
+        &api_key.calendar_ids,
+        &api_key.expires_at,
+        &secret.salt,
+        &secret.hash,
+    ])?;
+
+    match rows.get(0)
+    {
+        Some(row) =>
+        {
+            let mut api_key = TenantApiKey::from_row(row)?;
+            api_key.key = api_key.id.map(|id| format_key(&id, &secret.secret));
+
+            ApiResult::Created(api_key, format!("/api/admin/tenants/{}/api_keys", tenant_id))
+        },
+        None => ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()),
+    }
+}
+
+/// Lists the API keys of a tenant, including revoked and expired ones.
+///
+/// Required scope: `SUPER`
+///
+/// Response codes: 200, 403, 500
+#[openapi]
+#[get("/admin/tenants/<tenant_id>/api_keys")]
+pub fn list_api_keys(mut db: PgsqlConn, _super_key: RequireSuper, tenant_id: UuidParam, common_params: CommonQueryParams) -> ApiResult<Vec<TenantApiKey>>
+{
+    let query = "SELECT * FROM api_keys WHERE tenant_id = $1 ORDER BY created_at OFFSET $2 LIMIT $3;";
+
+    let rows = db.query(query, &[
+        &tenant_id,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| TenantApiKey::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Revokes an API key, it stops working right away. Revoked
+/// keys are kept (and listed) but can't be used again.
+///
+/// Required scope: `SUPER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[delete("/admin/tenants/<tenant_id>/api_keys/<key_id>")]
+pub fn revoke_api_key(mut db: PgsqlConn, _super_key: RequireSuper, tenant_id: UuidParam, key_id: UuidParam) -> ApiResult<()>
+{
+    let query = "UPDATE api_keys SET revoked_at = COALESCE(revoked_at, NOW()) WHERE tenant_id = $1 AND id = $2;";
+
+    if db.execute(query, &[&tenant_id, &key_id])? == 0
+    {
+        ApiResult::not_found()
+    }
+    else
+    {
+        ApiResult::Ok(())
+    }
+}
+
+/// Replaces the secret of an API key, the old key stops working right
+/// away. The new key is in the `key` property of the response, the key
+/// keeps its id, scopes, calendars and expiry. Revoked keys can't be
+/// rotated.
+///
+/// Required scope: `SUPER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[post("/admin/tenants/<tenant_id>/api_keys/<key_id>/rotate")]
+pub fn rotate_api_key(mut db: PgsqlConn, _super_key: RequireSuper, tenant_id: UuidParam, key_id: UuidParam) -> ApiResult<TenantApiKey>
+{
+    let secret = match NewSecret::generate()
+    {
+        Ok(secret) => secret,
+        Err(_) => return Problem::internal().into(),
+    };
+
+    let query = "
+        UPDATE api_keys SET salt = $3, secret_hash = $4, legacy = FALSE, legacy_digest = NULL
+        WHERE tenant_id = $1 AND id = $2 AND revoked_at IS NULL
+        RETURNING *;
+    ";
+
+    let rows = db.query(query, &[&tenant_id, &key_id, &secret.salt, &secret.hash])?;
+
+    match rows.get(0)
+    {
+        Some(row) =>
+        {
+            let mut api_key = TenantApiKey::from_row(row)?;
+            api_key.key = Some(format_key(&key_id.into_inner(), &secret.secret));
+
+            ApiResult::Ok(api_key)
+        },
+        None => ApiResult::not_found(),
+    }
+}
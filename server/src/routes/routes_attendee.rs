@@ -0,0 +1,245 @@
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::{ErrorCode, Problem};
+use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
+use rocket_contrib::json::Json;
+use crate::database_error::{DatabaseErrorKind, DatabaseError};
+use crate::calendar::is_subscribed;
+use crate::authentication::auth_guard::{RequireRead, RequireWrite};
+use crate::attendee::{Attendee, AttendeeResponse};
+use crate::routes::routes_event::NaiveDateParam;
+use uuid::Uuid;
+
+/// Checks if the event with id `event_id` exists in the calendar
+/// with id `calendar_id`.
+fn event_exists(db: &mut PgsqlConn, calendar_id: &UuidParam, event_id: &UuidParam) -> Result<bool, DatabaseError>
+{
+    let query = "SELECT 1 FROM events WHERE calendar_id = $1 AND id = $2 AND deleted_at IS NULL";
+
+    Ok(!db.query(query, &[calendar_id, event_id])?.is_empty())
+}
+
+/// Lists the attendees of an event. Attendees of overrides are not
+/// included, get the event's instances if you want them.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/events/<event_id>/attendees")]
+pub fn list_attendees(mut db: PgsqlConn, _principal: RequireRead, calendar_id: UuidParam, event_id: UuidParam) -> ApiResult<Vec<Attendee>>
+{
+    if !event_exists(&mut db, &calendar_id, &event_id)?
+    {
+        return ApiResult::not_found();
+    }
+
+    let query = "SELECT * FROM attendees WHERE event_id = $1 ORDER BY email";
+
+    let rows = db.query(query, &[&event_id])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| Attendee::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Adds an attendee to an event.
+///
+/// Returns 409 if the email is already invited to the event.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 201, 400, 403, 404, 409, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/events/<event_id>/attendees", data = "<attendee>")]
+pub fn insert_attendee(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, event_id: UuidParam, attendee: Json<Attendee>) -> ApiResult<Attendee>
+{
+    if attendee.id.is_some()
+    {
+        return Problem::invalid_field("id", "Attendees get their id from the server.").into();
+    }
+
+    if attendee.email.is_empty()
+    {
+        return Problem::bad_request(ErrorCode::MissingField, "The attendee's email is required but missing.").field("email").into();
+    }
+
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    if !event_exists(&mut db, &calendar_id, &event_id)?
+    {
+        return ApiResult::not_found();
+    }
+
+    let query = "INSERT INTO attendees (event_id, email, display_name, role, partstat, rsvp)
+    VALUES ($1, $2, $3, $4, $5, $6)
+    ON CONFLICT (event_id, lower(email)) DO NOTHING
+    RETURNING *;";
+
+    let rows = db.query(query, &[
+        &event_id,
+        &attendee.email,
+        &attendee.display_name,
+        &attendee.role.to_string(),
+        &attendee.partstat.to_string(),
+        &attendee.rsvp,
+    ])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        ApiResult::Created(
+            Attendee::from_row(row)?,
+            format!("/api/calendars/{}/events/{}/attendees/{}", calendar_id, event_id, row.get_cell::<Uuid>("id")?)
+        )
+    }
+    else
+    {
+        // The attendee was already invited to this event.
+        Problem::conflict("This email is already invited to the event.").field("email").into()
+    }
+}
+
+/// Removes an attendee from an event.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[delete("/calendars/<calendar_id>/events/<event_id>/attendees/<attendee_id>")]
+pub fn delete_attendee(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, event_id: UuidParam, attendee_id: UuidParam) -> ApiResult<()>
+{
+    let query = "
+        DELETE FROM attendees
+        USING events
+        WHERE
+            events.id = attendees.event_id
+            AND events.calendar_id = $1
+            AND attendees.event_id = $2
+            AND attendees.id = $3;
+    ";
+
+    if db.execute(query, &[&calendar_id, &event_id, &attendee_id])? == 0
+    {
+        ApiResult::not_found()
+    }
+    else
+    {
+        ApiResult::Ok(())
+    }
+}
+
+/// Sets the participation status of an attendee.
+///
+/// If `recurrence_id` is set the response only applies to the instance of
+/// the (recurring) event that happens on that date. This is done by adding
+/// the attendee, with the new status, to the override of that instance
+/// (the override is created if it doesn't exist yet).
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/events/<event_id>/attendees/<attendee_id>/response?<recurrence_id>", data = "<response>")]
+pub fn respond(
+    mut db: PgsqlConn,
+    _principal: RequireWrite,
+    calendar_id: UuidParam,
+    event_id: UuidParam,
+    attendee_id: UuidParam,
+    recurrence_id: Option<NaiveDateParam>,
+    response: Json<AttendeeResponse>,
+) -> ApiResult<Attendee>
+{
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    let query = "
+        SELECT attendees.*, events.rrule FROM attendees
+        JOIN events ON events.id = attendees.event_id
+        WHERE events.calendar_id = $1 AND attendees.event_id = $2 AND attendees.id = $3 AND events.deleted_at IS NULL
+    ";
+
+    let rows = db.query(query, &[&calendar_id, &event_id, &attendee_id])?;
+
+    let row = match rows.get(0)
+    {
+        Some(row) => row,
+        None => return ApiResult::not_found(),
+    };
+
+    let attendee = Attendee::from_row(row)?;
+    let partstat = response.partstat.to_string();
+
+    let recurrence_id = match recurrence_id
+    {
+        Some(recurrence_id) => recurrence_id.into_inner(),
+        None =>
+        {
+            let query = "UPDATE attendees SET partstat = $2 WHERE id = $1 RETURNING *;";
+            let rows = db.query(query, &[&attendee_id, &partstat])?;
+
+            return match rows.get(0)
+            {
+                Some(row) => ApiResult::Ok(Attendee::from_row(row)?),
+                None => ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()),
+            };
+        },
+    };
+
+    // Only instances of recurring events can be responded to individually.
+    if row.get_cell::<Option<String>>("rrule")?.is_none()
+    {
+        return Problem::invalid_field("recurrence_id", "Only instances of recurring events can be responded to individually.").into();
+    }
+
+    let mut transaction = db.transaction()?;
+
+    // An override without any fields set changes nothing in the
+    // instance, it's just a place to store the attendee's response.
+    let query = "
+        INSERT INTO events (parent_event_id, recurrence_id, calendar_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (parent_event_id, recurrence_id) WHERE deleted_at IS NULL DO UPDATE SET parent_event_id = EXCLUDED.parent_event_id
+        RETURNING id;
+    ";
+
+    let rows = transaction.query(query, &[&event_id, &recurrence_id, &calendar_id])?;
+    let override_id: Uuid = match rows.get(0)
+    {
+        Some(row) => row.get_cell("id")?,
+        None => return ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()),
+    };
+
+    let query = "
+        INSERT INTO attendees (event_id, email, display_name, role, partstat, rsvp)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (event_id, lower(email)) DO UPDATE SET partstat = EXCLUDED.partstat
+        RETURNING *;
+    ";
+
+    let rows = transaction.query(query, &[
+        &override_id,
+        &attendee.email,
+        &attendee.display_name,
+        &attendee.role.to_string(),
+        &partstat,
+        &attendee.rsvp,
+    ])?;
+
+    let result = match rows.get(0)
+    {
+        Some(row) => Attendee::from_row(row)?,
+        None => return ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()),
+    };
+
+    transaction.commit()?;
+
+    ApiResult::Ok(result)
+}
@@ -0,0 +1,116 @@
+//! Admin route to query the audit log, only API keys
+//! with the `SUPER` scope can use it.
+
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::{ErrorCode, Problem};
+use crate::database_helpers::FromRow;
+use crate::authentication::auth_guard::RequireSuper;
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::routes::routes_event::NaiveDateOrTime;
+use crate::audit::{AuditEntry, AuditAction, ResourceType};
+use crate::acl::PrincipalType;
+use chrono::NaiveDateTime;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Lists the audit log entries of the tenant, newest first. Entries can
+/// be filtered by resource, principal and action, and by when they were
+/// recorded: from `since` (inclusive) to `until` (exclusive), which can
+/// be dates or date-times.
+///
+/// Required scope: `SUPER`
+///
+/// Response codes: 200, 400, 403, 500
+#[openapi]
+#[get("/audit?<resource_type>&<resource_id>&<principal_type>&<principal_id>&<action>&<since>&<until>")]
+pub fn list_audit_entries(
+    mut db: PgsqlConn,
+    _super_key: RequireSuper,
+    resource_type: Option<String>,
+    resource_id: Option<String>,
+    principal_type: Option<String>,
+    principal_id: Option<String>,
+    action: Option<String>,
+    since: Option<NaiveDateOrTime>,
+    until: Option<NaiveDateOrTime>,
+    common_params: CommonQueryParams,
+) -> ApiResult<Vec<AuditEntry>>
+{
+    let resource_type = match resource_type.map(|t| ResourceType::from_str(&t)).transpose()
+    {
+        Ok(resource_type) => resource_type.map(|t| t.to_string()),
+        Err(_) => return Problem::invalid_field("resource_type", "resource_type isn't a known value.").into(),
+    };
+
+    let resource_id = match resource_id.map(|id| Uuid::from_str(&id)).transpose()
+    {
+        Ok(resource_id) => resource_id,
+        Err(_) => return Problem::invalid_field("resource_id", "resource_id must be a UUID.").into(),
+    };
+
+    let principal_type = match principal_type.map(|t| PrincipalType::from_str(&t)).transpose()
+    {
+        Ok(principal_type) => principal_type.map(|t| t.to_string()),
+        Err(_) => return Problem::invalid_field("principal_type", "principal_type isn't a known value.").into(),
+    };
+
+    let action = match action.map(|a| AuditAction::from_str(&a)).transpose()
+    {
+        Ok(action) => action.map(|a| a.to_string()),
+        Err(_) => return Problem::invalid_field("action", "action isn't a known value.").into(),
+    };
+
+    // since and until can only be date or date-times
+    if (since.is_some() && since.as_ref().unwrap().as_naive_time().is_some())
+        || (until.is_some() && until.as_ref().unwrap().as_naive_time().is_some())
+    {
+        return Problem::bad_request(ErrorCode::InvalidField, "since and until must be dates or date-times.").into();
+    }
+
+    let since = since.as_ref().and_then(to_date_time);
+    let until = until.as_ref().and_then(to_date_time);
+
+    let query = "
+        SELECT * FROM audit_log
+        WHERE ($1::TEXT IS NULL OR resource_type = $1::TEXT)
+            AND ($2::UUID IS NULL OR resource_id = $2::UUID)
+            AND ($3::TEXT IS NULL OR principal_type = $3::TEXT)
+            AND ($4::TEXT IS NULL OR principal_id = $4::TEXT)
+            AND ($5::TEXT IS NULL OR action = $5::TEXT)
+            AND ($6::TIMESTAMP IS NULL OR created_at >= $6::TIMESTAMP)
+            AND ($7::TIMESTAMP IS NULL OR created_at < $7::TIMESTAMP)
+        ORDER BY id DESC
+        OFFSET $8
+        LIMIT $9;
+    ";
+
+    let rows = db.query(query, &[
+        &resource_type,
+        &resource_id,
+        &principal_type,
+        &principal_id,
+        &action,
+        &since,
+        &until,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| AuditEntry::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Dates are taken as their midnight, times have no date to go with.
+fn to_date_time(date_or_time: &NaiveDateOrTime) -> Option<NaiveDateTime>
+{
+    match date_or_time
+    {
+        NaiveDateOrTime::Date(d) => Some(d.and_hms(0, 0, 0)),
+        NaiveDateOrTime::DateTime(dt) => Some(*dt),
+        NaiveDateOrTime::Time(_) => None,
+    }
+}
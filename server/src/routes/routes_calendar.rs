@@ -1,50 +1,111 @@
 use crate::connection_pool::PgsqlConn;
 use crate::calendar::{Calendar};
-use rocket_route_result::RouteResult;
-use crate::database_helpers::{FromRow, UuidParam};
+use crate::routes::api_result::ApiResult;
+use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
 use rocket_contrib::json::Json;
 use crate::database_error::{DatabaseError, DatabaseErrorKind};
 use crate::routes::common_query_params::CommonQueryParams;
-use crate::authentication::auth_guard::ApiKey;
+use crate::routes::routes_representation::PlainRepresentation;
+use crate::routes::routes_event::NaiveDateOrTime;
+use crate::problem::{ErrorCode, Problem};
+use crate::routes::conditional::{Conditional, IfMatch, IfNoneMatch, etag};
+use crate::authentication::auth_guard::{RequireFreeBusy, RequireRead, RequireWrite, RequireOwner};
+use crate::acl::{Access, Role, get_access, get_visible_calendar_ids, grantee};
+use crate::configs::Configs;
+use crate::subscription::SyncStatus;
+use rocket::State;
+use postgres::Transaction;
+use uuid::Uuid;
+
+/// Checks a calendar of an insert or update request, see `Calendar::validate`
+/// and `Subscription::validate`.
+fn check_calendar(calendar: &Calendar, configs: &Configs) -> Result<(), Problem>
+{
+    match calendar.invalid_field()
+    {
+        Some("color") => return Err(Problem::invalid_field("color", "The color must be a hex color like #1e90ff.")),
+        Some(_) => return Err(Problem::invalid_field("time_zone", "The time zone must be an IANA time zone like Europe/Paris.")),
+        None => (),
+    }
+
+    if let Some(subscription) = calendar.get_subscription()
+    {
+        match subscription.invalid_field()
+        {
+            Some("url") => return Err(Problem::invalid_field("subscription.url", "The URL must be an http, https, webcal or file URL.")),
+            Some(_) => return Err(Problem::invalid_field("subscription.refresh_interval", "The refresh interval must be positive.")),
+            None => (),
+        }
+
+        if subscription.is_file() && !configs.get_subscription_allow_files()
+        {
+            return Err(Problem::invalid_field("subscription.url", "This server doesn't allow file subscriptions."));
+        }
+    }
+
+    Ok(())
+}
+
+/// Locks the calendar with id `calendar_id` until the end of `transaction`,
+/// so that it can't change between checking its ETag and changing it.
+/// Returns its ETag, `None` if there's no such calendar.
+fn lock_calendar(transaction: &mut Transaction, calendar_id: &Uuid) -> Result<Option<String>, DatabaseError>
+{
+    let query = "SELECT last_modified FROM calendars WHERE id = $1 AND deleted_at IS NULL FOR UPDATE";
+
+    transaction.query(query, &[calendar_id])?
+        .get(0)
+        .map(|row| row.get_cell("last_modified").map(etag))
+        .transpose()
+}
 
 /// Gets a calendar by id from the database.
 ///
-/// Response codes: 200, 404, 500
+/// The response has the calendar's ETag, 304 is
+/// returned if it matches `If-None-Match`.
+///
+/// Required scope: `READ`, required role: `FREE_BUSY`
+///
+/// Response codes: 200, 304, 403, 404, 500
 #[openapi]
 #[get("/calendars/<calendar_id>")]
-pub fn get_calendar(mut db: PgsqlConn, _api_key: ApiKey, calendar_id: UuidParam) -> RouteResult<Calendar>
+pub fn get_calendar(mut db: PgsqlConn, _principal: RequireFreeBusy, _representation: PlainRepresentation, if_none_match: IfNoneMatch, calendar_id: UuidParam) -> Conditional<Calendar>
 {
-    let query = "SELECT * FROM calendars WHERE id = $1;";
+    let query = "SELECT * FROM calendars WHERE id = $1 AND deleted_at IS NULL;";
 
     let query = db.query(query, &[&calendar_id])?;
 
-    if let Some(row) = query.get(0)
-    {
-        RouteResult::Ok(
-            Calendar::from_row(row)?
-        )
-    }
-    else
+    let result = match query.get(0)
     {
-        RouteResult::NotFound
-    }
+        Some(row) => ApiResult::Ok(Calendar::from_row(row)?),
+        None => ApiResult::not_found(),
+    };
+
+    Conditional::get(result, &if_none_match, |calendar| calendar.last_modified.map(etag))
 }
 
-/// Lists all calendars in the database.
+/// Lists the calendars of the caller's tenant it can see, i.e. the
+/// ones it has a grant on (see docs/server/acl.md) and, if it's an
+/// API key restricted to some calendars, that it's restricted to.
 ///
-/// Response codes: 200, 500
+/// Required scope: `READ`
+///
+/// Response codes: 200, 403, 500
 #[openapi]
 #[get("/calendars")]
-pub fn list_calendars(mut db: PgsqlConn, _api_key: ApiKey, shared_params: CommonQueryParams) -> RouteResult<Vec<Calendar>>
+pub fn list_calendars(mut db: PgsqlConn, principal: RequireRead, shared_params: CommonQueryParams) -> ApiResult<Vec<Calendar>>
 {
-    let query = "SELECT * FROM calendars OFFSET $1 LIMIT $2;";
+    let visible = get_visible_calendar_ids(&mut db, &principal)?;
+    let query = "SELECT * FROM calendars WHERE tenant_id = $1 AND ($2::UUID[] IS NULL OR id = ANY($2)) AND deleted_at IS NULL OFFSET $3 LIMIT $4;";
 
     let rows = db.query(query, &[
+        &principal.get_tenant_id(),
+        &visible,
         &shared_params.offset(),
         &shared_params.page_size(),
     ])?;
 
-    RouteResult::Ok(
+    ApiResult::Ok(
         rows.into_iter()
             .map(|row| Calendar::from_row(&row))
             .collect::<Result<Vec<_>, _>>()?
@@ -52,32 +113,287 @@ pub fn list_calendars(mut db: PgsqlConn, _api_key: ApiKey, shared_params: Common
 }
 
 
-/// Inserts a calendar into the database and returns it.
+/// Inserts a calendar into the caller's tenant and returns it. Callers
+/// that only see the calendars they have a grant on are granted `OWNER`
+/// on the new calendar.
+///
+/// If the calendar has a subscription it's synced as soon as possible,
+/// `file` URLs are only allowed if `SUBSCRIPTION_ALLOW_FILES` is set.
+/// API keys restricted to specific calendars can't insert calendars.
 ///
-/// Response codes: 201, 500
+/// Required scope: `WRITE`
+///
+/// Response codes: 201, 400, 403, 500
 #[openapi]
 #[post("/calendars", data = "<calendar>")]
-pub fn insert_calendar(mut db: PgsqlConn, _api_key: ApiKey, calendar: Json<Calendar>) -> RouteResult<Calendar>
+pub fn insert_calendar(mut db: PgsqlConn, principal: RequireWrite, configs: State<Configs>, calendar: Json<Calendar>) -> ApiResult<Calendar>
 {
     let calendar = calendar.into_inner();
 
     if !calendar.get_id().is_nil()
     {
-        RouteResult::BadRequest(None)
+        return Problem::invalid_field("id", "Calendars get their id from the server.").into();
     }
-    else
+
+    if principal.get_calendar_ids().is_some()
     {
-        let query = "INSERT INTO calendars DEFAULT VALUES RETURNING *";
+        return Problem::bad_request(ErrorCode::BadRequest, "API keys limited to some calendars can't create calendars.").into();
+    }
 
-        let rows = db.query(query, &[])?;
+    check_calendar(&calendar, &configs)?;
 
-        if let Some(row) = rows.get(0)
-        {
-            RouteResult::Ok(Calendar::from_row(row)?)
-        }
-        else
-        {
-            RouteResult::InternalError(Box::<DatabaseError>::new(DatabaseErrorKind::ReturningIsEmpty.into()))
-        }
+    let access = get_access(&mut db, &principal)?;
+    let mut transaction = db.transaction()?;
+
+    let query = "
+        INSERT INTO calendars (subscription_url, refresh_interval, sync_status, next_sync_at, name, description, color, time_zone, metadata, tenant_id)
+        VALUES ($1, $2, $3, CASE WHEN $1::TEXT IS NULL THEN NULL ELSE NOW() END, $4, $5, $6, $7, $8, $9)
+        RETURNING *;
+    ";
+
+    let subscription = calendar.get_subscription();
+
+    let rows = transaction.query(query, &[
+        &subscription.map(|s| &s.url),
+        &subscription.map(|s| s.refresh_interval),
+        &subscription.map(|_| SyncStatus::Pending.to_string()),
+        &calendar.name,
+        &calendar.description,
+        &calendar.color,
+        &calendar.time_zone,
+        &calendar.metadata,
+        &principal.get_tenant_id(),
+    ])?;
+
+    let calendar = match rows.get(0)
+    {
+        Some(row) => Calendar::from_row(row)?,
+        None => return ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()),
+    };
+
+    if let Access::Granted(_) = access
+    {
+        let (principal_type, principal_id) = grantee(principal.get_subject());
+
+        transaction.execute(
+            "INSERT INTO calendar_grants (calendar_id, principal_type, principal_id, role) VALUES ($1, $2, $3, $4);",
+            &[&calendar.get_id(), &principal_type.to_string(), &principal_id, &Role::Owner.to_string()]
+        )?;
+    }
+
+    transaction.commit()?;
+
+    ApiResult::Ok(calendar)
+}
+
+/// Updates a calendar's properties and returns it.
+///
+/// A subscription can't be added to or removed from a calendar, but
+/// its URL and refresh interval can be changed. Changing the URL
+/// syncs the calendar as soon as possible.
+///
+/// If `If-Match` is set the calendar is only updated if it has one of its
+/// ETags, otherwise 412 is returned. The response has the calendar's new ETag.
+///
+/// Required scope: `WRITE`, required role: `OWNER`
+///
+/// Response codes: 200, 400, 403, 404, 412, 500
+#[openapi]
+#[put("/calendars/<calendar_id>", data = "<calendar>")]
+pub fn update_calendar(
+    mut db: PgsqlConn,
+    _principal: RequireOwner,
+    configs: State<Configs>,
+    if_match: IfMatch,
+    calendar_id: UuidParam,
+    calendar: Json<Calendar>,
+) -> Conditional<Calendar>
+{
+    let calendar_id = calendar_id.into_inner();
+    let calendar = calendar.into_inner();
+
+    if !(calendar.get_id().is_nil() || calendar.get_id() == calendar_id)
+    {
+        return Problem::invalid_field("id", "The id must be the calendar's.").into();
+    }
+
+    check_calendar(&calendar, &configs)?;
+
+    let mut transaction = db.transaction()?;
+
+    let current_etag = lock_calendar(&mut transaction, &calendar_id)?;
+
+    if current_etag.is_none()
+    {
+        return ApiResult::not_found().into();
     }
-}
\ No newline at end of file
+
+    if !if_match.matches(current_etag.as_deref())
+    {
+        return Conditional::PreconditionFailed;
+    }
+
+    // The right-hand sides of SET see the row as it was
+    // before the update, so `subscription_url` is the old URL.
+    let query = "
+        UPDATE calendars SET
+            name = $2,
+            description = $3,
+            color = $4,
+            time_zone = $5,
+            metadata = $6,
+            subscription_url = $7,
+            refresh_interval = $8,
+            sync_status = CASE WHEN subscription_url IS DISTINCT FROM $7 THEN 'PENDING' ELSE sync_status END,
+            next_sync_at = CASE WHEN subscription_url IS DISTINCT FROM $7 THEN NOW() ELSE next_sync_at END,
+            subscription_etag = CASE WHEN subscription_url IS DISTINCT FROM $7 THEN NULL ELSE subscription_etag END
+        WHERE id = $1 AND (subscription_url IS NULL) = ($7::TEXT IS NULL) AND deleted_at IS NULL
+        RETURNING *;
+    ";
+
+    let subscription = calendar.get_subscription();
+
+    let rows = transaction.query(query, &[
+        &calendar_id,
+        &calendar.name,
+        &calendar.description,
+        &calendar.color,
+        &calendar.time_zone,
+        &calendar.metadata,
+        &subscription.map(|s| &s.url),
+        &subscription.map(|s| s.refresh_interval),
+    ])?;
+
+    // The calendar exists (it's locked), so the update
+    // tried to add or remove its subscription.
+    let calendar = match rows.get(0)
+    {
+        Some(row) => Calendar::from_row(row)?,
+        None => return Problem::invalid_field("subscription", "A subscription can't be added to or removed from a calendar.").into(),
+    };
+
+    transaction.commit()?;
+
+    let new_etag = calendar.last_modified.map(etag);
+
+    Conditional::Result(ApiResult::Ok(calendar), new_etag)
+}
+
+/// Moves a calendar to the trash, from which it can be restored until
+/// it's purged (see `TRASH_RETENTION_DAYS`). Its webhooks, feeds, grants
+/// and change log are deleted when it's purged.
+///
+/// Calendars that still have events are only deleted (along with
+/// their events) if `cascade` is `true`, otherwise 400 is returned.
+///
+/// If `If-Match` is set the calendar is only deleted if it has one
+/// of its ETags, otherwise 412 is returned.
+///
+/// Required scope: `WRITE`, required role: `OWNER`
+///
+/// Response codes: 200, 400, 403, 404, 412, 500
+#[openapi]
+#[delete("/calendars/<calendar_id>?<cascade>")]
+pub fn delete_calendar(mut db: PgsqlConn, _principal: RequireOwner, if_match: IfMatch, calendar_id: UuidParam, cascade: Option<bool>) -> Conditional<()>
+{
+    let calendar_id = calendar_id.into_inner();
+    let mut transaction = db.transaction()?;
+
+    if !if_match.matches(lock_calendar(&mut transaction, &calendar_id)?.as_deref())
+    {
+        return Conditional::PreconditionFailed;
+    }
+
+    let has_events = !transaction.query("SELECT 1 FROM events WHERE calendar_id = $1 AND deleted_at IS NULL LIMIT 1", &[&calendar_id])?.is_empty();
+
+    if has_events && !cascade.unwrap_or(false)
+    {
+        return Problem::bad_request(ErrorCode::BadRequest, "The calendar still has events, set cascade to delete them too.").into();
+    }
+
+    // Events are trashed before the calendar so that their triggers
+    // log the deletions while the calendar is still visible. They get
+    // the same deleted_at as the calendar (NOW() is the transaction's
+    // start), which is how restoring the calendar tells them apart
+    // from events that were already in the trash.
+    transaction.execute("UPDATE events SET deleted_at = NOW() WHERE calendar_id = $1 AND deleted_at IS NULL", &[&calendar_id])?;
+
+    if transaction.execute("UPDATE calendars SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL", &[&calendar_id])? == 0
+    {
+        return ApiResult::not_found().into();
+    }
+
+    transaction.commit()?;
+
+    ApiResult::Ok(()).into()
+}
+
+/// Lists the calendars the caller can see (like `list_calendars`) that
+/// were modified since `since`, which can be a date or a date-time.
+/// Changes to a calendar's events don't count as changes to the calendar.
+///
+/// Required scope: `READ`
+///
+/// Response codes: 200, 400, 403, 500
+#[openapi]
+#[get("/calendars/changes?<since>")]
+pub fn check_for_changes(mut db: PgsqlConn, principal: RequireRead, common_params: CommonQueryParams, since: NaiveDateOrTime) -> ApiResult<Vec<Calendar>>
+{
+    if since.as_naive_time().is_some()
+    {
+        return Problem::invalid_field("since", "since must be a date or a date-time.").into();
+    }
+
+    let visible = get_visible_calendar_ids(&mut db, &principal)?;
+
+    let query = "
+        SELECT * FROM calendars
+        WHERE
+            tenant_id = $1
+            AND ($2::UUID[] IS NULL OR id = ANY($2))
+            AND deleted_at IS NULL
+            AND ($3::TIMESTAMP IS NULL OR last_modified >= $3::TIMESTAMP)
+            AND ($4::DATE IS NULL OR last_modified >= $4::DATE)
+        ORDER BY last_modified
+        OFFSET $5
+        LIMIT $6;
+    ";
+
+    let rows = db.query(query, &[
+        &principal.get_tenant_id(),
+        &visible,
+        &since.as_naive_date_time(),
+        &since.as_naive_date(),
+
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| Calendar::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Syncs a subscribed calendar as soon as possible, instead of
+/// waiting for its refresh interval to pass.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/subscription/refresh")]
+pub fn refresh_subscription(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam) -> ApiResult<()>
+{
+    let query = "UPDATE calendars SET next_sync_at = NOW() WHERE id = $1 AND subscription_url IS NOT NULL AND deleted_at IS NULL;";
+
+    if db.execute(query, &[&calendar_id])? == 0
+    {
+        ApiResult::not_found()
+    }
+    else
+    {
+        ApiResult::Ok(())
+    }
+}
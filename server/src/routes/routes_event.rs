@@ -1,6 +1,6 @@
 use crate::connection_pool::PgsqlConn;
-use rocket_route_result::RouteResult;
-use crate::event::{Event, EventPlain, ToPlain};
+use crate::routes::api_result::ApiResult;
+use crate::event::{Event, EventPlain, EventOverride, ToPlain, Redact, GenerateInstances};
 use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
 use rocket_contrib::json::Json;
 use crate::database_error::{DatabaseErrorKind, DatabaseError};
@@ -14,11 +14,20 @@ use postgres::types::{ToSql};
 use std::fmt::Debug;
 use std::str::FromStr;
 use crate::routes::common_query_params::CommonQueryParams;
+use crate::routes::routes_representation::PlainRepresentation;
 use rocket_okapi::request::OpenApiFromFormValue;
 use rocket_okapi::gen::OpenApiGenerator;
 use okapi::openapi3::{Parameter, ParameterValue};
-use crate::authentication::auth_guard::{ApiKey};
+use crate::calendar::is_subscribed;
+use crate::authentication::auth_guard::{RequireFreeBusy, RequireRead, RequireWrite};
 use uuid::Uuid;
+use crate::attendee::{get_attendees, merge_attendees};
+use crate::sync::{SyncToken, SyncResponse, get_changes};
+use crate::problem::{ErrorCode, Problem};
+use crate::routes::conditional::{Conditional, IfMatch, IfNoneMatch, event_etag};
+use crate::acl::Visibility;
+use postgres::{Row, Transaction};
+use serde_json::Value;
 
 
 /// Store a NaiveDate, NaiveTime or NaiveDateTime without knowing
@@ -171,7 +180,7 @@ impl OpenApiFromFormValue<'_> for NaiveDateParam
 
 fn get_event_by_id(db: &mut PgsqlConn, calendar_id: UuidParam, event_id: UuidParam) -> Result<Option<Event>, DatabaseError>
 {
-    let query = "SELECT * FROM events WHERE calendar_id = $1 AND id = $2";
+    let query = "SELECT * FROM events WHERE calendar_id = $1 AND id = $2 AND deleted_at IS NULL";
 
     let rows = db.query(query, &[&calendar_id, &event_id])?;
 
@@ -187,41 +196,225 @@ fn get_event_by_id(db: &mut PgsqlConn, calendar_id: UuidParam, event_id: UuidPar
     }
 }
 
+/// Gets all overrides of the event with id `event_id`.
+fn get_overrides(db: &mut PgsqlConn, event_id: &UuidParam) -> Result<Vec<EventOverride>, DatabaseError>
+{
+    let query = "SELECT * FROM events WHERE parent_event_id = $1 AND deleted_at IS NULL";
+
+    let rows = db.query(query, &[event_id])?;
+
+    let mut overrides = vec![];
+    for row in rows
+    {
+        if let Event::Override(event_override) = Event::from_row(&row)?
+        {
+            overrides.push(event_override);
+        }
+    }
+
+    Ok(overrides)
+}
 
+/// Gets the events (not overrides) of a calendar that happen between
+/// `since` and `until`, which can only be dates or date-times.
+pub fn find_events(
+    db: &mut PgsqlConn,
+    calendar_id: &Uuid,
+    since: &Option<NaiveDateOrTime>,
+    until: &Option<NaiveDateOrTime>,
+    common_params: &CommonQueryParams,
+) -> Result<Vec<Event>, DatabaseError>
+{
+    // A query parameter can only have one type and we want to be able to
+    // use `since` and `until` as either a date or a date-time, so we have
+    // a pair of parameters for each variable, one for each type. If, for example,
+    // `since` is a date, `$2` will be `NULL`.
+    //
+    // Overrides are not listed here, they're merged into the instances
+    // of their parent event.
+    let query = "
+        SELECT * FROM events
+        WHERE
+            calendar_id = $1
+            AND parent_event_id IS NULL
+            AND deleted_at IS NULL
+            AND ($2::TIMESTAMP IS NULL OR start_date + start_time >= $2::TIMESTAMP)
+            AND ($3::DATE IS NULL OR start_date >= $3::DATE)
+            AND ($4::TIMESTAMP IS NULL OR end_date + end_time <= $4::TIMESTAMP)
+            AND ($5::DATE IS NULL OR end_date <= $5::DATE)
+        OFFSET $6
+        LIMIT $7;
+    ";
+
+    let rows = db.query(query, &[
+        calendar_id,
+
+        &since.as_ref().and_then(|x| x.as_naive_date_time() .map(|dt| dt.clone())),
+        &since.as_ref().and_then(|x| x.as_naive_date()      .map(|d|   d.clone())),
+
+        &until.as_ref().and_then(|x| x.as_naive_date_time() .map(|dt| dt.clone())),
+        &until.as_ref().and_then(|x| x.as_naive_date()      .map(|d|   d.clone())),
+
+        &common_params.offset(),
+        &common_params.page_size(),
+    ]);
+
+    rows?
+        .iter()
+        .map(|r| Event::from_row(r))
+        .collect()
+}
+
+/// Fills the `attendees` field of each event in `events` with the
+/// attendees stored for that event.
+pub fn fill_attendees(db: &mut PgsqlConn, events: &mut Vec<EventPlain>) -> Result<(), DatabaseError>
+{
+    let ids: Vec<Uuid> = events.iter().filter_map(|e| e.id).collect();
+    let mut attendees = get_attendees(db, &ids)?;
+
+    for event in events
+    {
+        event.attendees = Some(
+            event.id
+                .and_then(|id| attendees.remove(&id))
+                .unwrap_or(vec![])
+        );
+    }
+
+    Ok(())
+}
+
+
+
+
+/// What an event's ETag is calculated from (see `event_etag`), to be
+/// selected from `events`. Attendees have no modification time, so
+/// their rows are digested.
+const ETAG_COLUMNS: &str = "
+    last_modified,
+    sequence,
+    (
+        SELECT md5(COALESCE(string_agg(attendees::TEXT, ',' ORDER BY attendees.id), ''))
+        FROM attendees WHERE attendees.event_id = events.id
+    ) AS attendees_digest
+";
+
+fn event_etag_of(row: &Row, visibility: Visibility) -> Result<String, DatabaseError>
+{
+    Ok(event_etag(row.get_cell("last_modified")?, row.get_cell("sequence")?, &row.get_cell::<String>("attendees_digest")?, visibility))
+}
+
+/// Gets the ETag of the event with id `event_id` seen with `visibility`,
+/// `None` if there's no such event.
+fn get_event_etag(db: &mut PgsqlConn, calendar_id: &UuidParam, event_id: &UuidParam, visibility: Visibility) -> Result<Option<String>, DatabaseError>
+{
+    let query = format!("SELECT {} FROM events WHERE calendar_id = $1 AND id = $2 AND deleted_at IS NULL", ETAG_COLUMNS);
+
+    db.query(query.as_str(), &[calendar_id, event_id])?
+        .get(0)
+        .map(|row| event_etag_of(row, visibility))
+        .transpose()
+}
+
+/// Locks the event with id `event_id` until the end of `transaction`, so that
+/// it can't change between checking its ETag and changing it. Returns its
+/// ETag seen with `visibility`, `None` if there's no such event.
+fn lock_event(transaction: &mut Transaction, calendar_id: &UuidParam, event_id: &UuidParam, visibility: Visibility) -> Result<Option<String>, DatabaseError>
+{
+    let query = format!("SELECT {} FROM events WHERE calendar_id = $1 AND id = $2 AND deleted_at IS NULL FOR UPDATE", ETAG_COLUMNS);
 
+    transaction.query(query.as_str(), &[calendar_id, event_id])?
+        .get(0)
+        .map(|row| event_etag_of(row, visibility))
+        .transpose()
+}
 
+/// Gets an event by id, with its attendees. Principals that can't see
+/// the event's class (see `Visibility`) only get when it happens.
+///
+/// The response has the event's ETag, 304 is returned if it
+/// matches `If-None-Match`.
+///
+/// Required scope: `READ`, required role: `FREE_BUSY`
+///
+/// Response codes: 200, 304, 403, 404, 500
 #[openapi]
 #[get("/calendars/<calendar_id>/events/<event_id>")]
-pub fn get_event(mut db: PgsqlConn, _api_key: ApiKey, calendar_id: UuidParam, event_id: UuidParam) -> RouteResult<EventPlain>
+pub fn get_event(
+    mut db: PgsqlConn,
+    principal: RequireFreeBusy,
+    _representation: PlainRepresentation,
+    if_none_match: IfNoneMatch,
+    calendar_id: UuidParam,
+    event_id: UuidParam,
+) -> Conditional<EventPlain>
 {
-    get_event_by_id(&mut db, calendar_id, event_id)
-        .map(|opt|
-            opt.map(|event| event.into_plain())
-        )
-        .into()
+    let visibility = principal.get_visibility();
+
+    // The ETag is read first, so that the event is never older than it says.
+    let etag = get_event_etag(&mut db, &calendar_id, &event_id, visibility)?;
+
+    let event = match get_event_by_id(&mut db, calendar_id, event_id)?
+    {
+        Some(event) => event,
+        None => return ApiResult::not_found().into(),
+    };
+
+    let mut events = vec![event.into_plain()];
+    fill_attendees(&mut db, &mut events)?;
+
+    let event = events.remove(0).redact(visibility);
+
+    Conditional::get(ApiResult::Ok(event), &if_none_match, |_| etag.clone())
 }
 
+/// Inserts an event into a calendar and returns it.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 201, 400, 403, 404, 500
 #[openapi]
 #[post("/calendars/<calendar_id>/events", data = "<event>")]
-pub fn insert_event(mut db: PgsqlConn, _api_key: ApiKey, calendar_id: UuidParam, event: Json<EventPlain>) -> RouteResult<EventPlain>
+pub fn insert_event(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, event: Json<EventPlain>) -> ApiResult<EventPlain>
 {
-    if !event.validate_non_patch() || event.id.is_some()
+    if event.id.is_some()
     {
-        return RouteResult::BadRequest(None);
+        return Problem::invalid_field("id", "Events get their id from the server.").into();
+    }
+
+    event.check_complete()?;
+
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    // Overrides can only override instances of recurring
+    // events in the same calendar.
+    if let Some(parent_id) = event.parent_id
+    {
+        let query = "SELECT 1 FROM events WHERE calendar_id = $1 AND id = $2 AND rrule IS NOT NULL AND deleted_at IS NULL";
+
+        if db.query(query, &[&calendar_id, &parent_id])?.is_empty()
+        {
+            return Problem::bad_request(ErrorCode::InvalidReference, "The parent isn't a recurring event of the calendar.").field("parent_id").into();
+        }
     }
 
     let query = "INSERT INTO events
     (
-        parent_event_id,
+        parent_event_id, recurrence_id,
         start_date, start_time, end_date, end_time, rrule, exdates,
-        rdates, calendar_id
+        rdates, title, description, metadata, organizer_email,
+        organizer_name, class, calendar_id
     )
 
-    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
     RETURNING *;";
 
     let rows = db.query(query, &[
         &event.parent_id,
+        &event.recurrence_id,
         &event.start_date,
         &event.start_time,
         &event.end_date,
@@ -229,29 +422,73 @@ pub fn insert_event(mut db: PgsqlConn, _api_key: ApiKey, calendar_id: UuidParam,
         &event.recurrence.as_ref().map(|r| &r.rrule),
         &event.recurrence.as_ref().map(|r| &r.exdates),
         &event.recurrence.as_ref().map(|r| &r.rdates),
+        &event.title,
+        &event.description,
+        &event.metadata,
+        &event.organizer.as_ref().map(|o| &o.email),
+        &event.organizer.as_ref().and_then(|o| o.display_name.as_ref()),
+        &event.class.unwrap_or_default().to_string(),
         &calendar_id,
     ])?;
 
     if let Some(row) = rows.get(0)
     {
-        RouteResult::Created(
-            Event::from_row(row)?.into_plain(),
+        let mut event = Event::from_row(row)?.into_plain();
+        event.attendees = Some(vec![]);
+
+        ApiResult::Created(
+            event,
             //TODO: prepend host to url.
             format!("/api/calendars/{}/events/{}", calendar_id, row.get_cell::<Uuid>("id")?)
         )
     }
     else
     {
-        RouteResult::InternalError(Box::new(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty)))
+        ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into())
     }
 }
 
+/// Updates an event, only the properties that are set are changed.
+///
+/// If `If-Match` is set the event is only updated if it has one of its
+/// ETags, otherwise 412 is returned. The response has the event's new ETag.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 412, 500
 #[openapi]
 #[put("/calendars/<calendar_id>/events/<event_id>", data = "<event_data>")]
-pub fn update_event(mut db: PgsqlConn, _api_key: ApiKey, calendar_id: UuidParam, event_id: UuidParam, event_data: Json<EventPlain>) -> RouteResult<()>
+pub fn update_event(
+    mut db: PgsqlConn,
+    principal: RequireWrite,
+    if_match: IfMatch,
+    calendar_id: UuidParam,
+    event_id: UuidParam,
+    event_data: Json<EventPlain>,
+) -> Conditional<()>
 {
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    let mut transaction = db.transaction()?;
+
+    let current_etag = lock_event(&mut transaction, &calendar_id, &event_id, principal.get_visibility())?;
+
+    if current_etag.is_none()
+    {
+        return ApiResult::not_found().into();
+    }
+
+    if !if_match.matches(current_etag.as_deref())
+    {
+        return Conditional::PreconditionFailed;
+    }
+
     let mut query = "UPDATE events SET ".to_owned();
 
+    let class = event_data.class.map(|c| c.to_string());
 
     let fields: Vec<(&str, Option<&(dyn ToSql + Sync)>)> = vec![
         ("start_date",  event_data.start_date   .as_ref()                       .map::<&(dyn ToSql + Sync), _>(|x| &*x)),
@@ -261,6 +498,12 @@ pub fn update_event(mut db: PgsqlConn, _api_key: ApiKey, calendar_id: UuidParam,
         ("rrule",       event_data.recurrence   .as_ref().and_then(|x| x.rrule      .as_ref().map::<&(dyn ToSql + Sync), _>(|x| &*x))),
         ("exdates",     event_data.recurrence   .as_ref().and_then(|x| x.exdates    .as_ref().map::<&(dyn ToSql + Sync), _>(|x| &*x))),
         ("rdates",      event_data.recurrence   .as_ref().and_then(|x| x.rdates     .as_ref().map::<&(dyn ToSql + Sync), _>(|x| &*x))),
+        ("title",       event_data.title        .as_ref()                       .map::<&(dyn ToSql + Sync), _>(|x| &*x)),
+        ("description", event_data.description  .as_ref()                       .map::<&(dyn ToSql + Sync), _>(|x| &*x)),
+        ("metadata",    event_data.metadata     .as_ref()                       .map::<&(dyn ToSql + Sync), _>(|x| &*x)),
+        ("organizer_email", event_data.organizer.as_ref()                       .map::<&(dyn ToSql + Sync), _>(|x| &x.email)),
+        ("organizer_name",  event_data.organizer.as_ref().and_then(|x| x.display_name.as_ref().map::<&(dyn ToSql + Sync), _>(|x| &*x))),
+        ("class",       class                   .as_ref()                       .map::<&(dyn ToSql + Sync), _>(|x| &*x)),
     ];
 
     let mut param_counter = 0;
@@ -290,133 +533,354 @@ pub fn update_event(mut db: PgsqlConn, _api_key: ApiKey, calendar_id: UuidParam,
     // the character removed was not a comma.
     assert_eq!(query.remove(query.len() - 1), ',');
 
-    query = query.add(" WHERE calendar_id = $1 AND id = $2 RETURNING *;");
+    query = query.add(" WHERE calendar_id = $1 AND id = $2 AND deleted_at IS NULL;");
 
-    if params.len() > 0
+    let new_etag = if params.len() > 0
     {
         params.insert(0, &calendar_id);
         params.insert(1, &event_id);
 
+        transaction.execute(query.as_str(), &params)?;
 
-        db.execute(query.as_str(), &params)?;
+        lock_event(&mut transaction, &calendar_id, &event_id, principal.get_visibility())?
     }
+    else
+    {
+        current_etag
+    };
 
-    RouteResult::Ok(())
+    transaction.commit()?;
+
+    Conditional::Result(ApiResult::Ok(()), new_etag)
 }
 
+/// Changes an event with a JSON Merge Patch (RFC 7396) and returns it.
+/// Fields missing from the patch are left as they are, fields that are
+/// `null` are cleared and the others are replaced, `recurrence` is merged
+/// the same way. Unlike `update_event` this can clear fields, e.g. turn a
+/// recurring event into a single one (`"recurrence": null`) or make an
+/// event all-day (`"start_time": null, "end_time": null`).
+///
+/// The ids and the fields the server sets can't be patched. The patched
+/// event must be valid as a whole (see `check_complete`), otherwise
+/// 400 is returned and nothing changes. Overrides of instances the
+/// patched event doesn't have anymore are deleted.
+///
+/// If `If-Match` is set the event is only patched if it has one of its
+/// ETags, otherwise 412 is returned. The response has the event's new ETag.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 412, 500
+#[openapi]
+#[patch("/calendars/<calendar_id>/events/<event_id>", data = "<patch>")]
+pub fn patch_event(
+    mut db: PgsqlConn,
+    principal: RequireWrite,
+    if_match: IfMatch,
+    calendar_id: UuidParam,
+    event_id: UuidParam,
+    patch: Json<Value>,
+) -> Conditional<EventPlain>
+{
+    // A patch that isn't an object would replace the whole event.
+    if !patch.is_object()
+    {
+        return Problem::bad_request(ErrorCode::InvalidBody, "The patch must be an object.").into();
+    }
+
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    let mut transaction = db.transaction()?;
+
+    let current_etag = match lock_event(&mut transaction, &calendar_id, &event_id, principal.get_visibility())?
+    {
+        Some(etag) => etag,
+        None => return ApiResult::not_found().into(),
+    };
+
+    if !if_match.matches(Some(&current_etag))
+    {
+        return Conditional::PreconditionFailed;
+    }
+
+    let query = "SELECT * FROM events WHERE calendar_id = $1 AND id = $2";
+
+    let current = match transaction.query(query, &[&calendar_id, &event_id])?.get(0)
+    {
+        Some(row) => Event::from_row(row)?.into_plain(),
+        None => return ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()).into(),
+    };
+
+    let event = match current.merge_patch(&patch)
+    {
+        Ok(event) => event,
+        Err(e) => return Problem::bad_request(ErrorCode::InvalidBody, "The patched event doesn't have the expected shape.")
+            .cause(ErrorCode::InvalidBody, &e.to_string())
+            .into(),
+    };
+
+    event.check_complete()?;
+
+    let query = "
+        UPDATE events SET
+            start_date = $3,
+            start_time = $4,
+            end_date = $5,
+            end_time = $6,
+            rrule = $7,
+            exdates = $8,
+            rdates = $9,
+            title = $10,
+            description = $11,
+            metadata = $12,
+            organizer_email = $13,
+            organizer_name = $14,
+            class = $15
+        WHERE calendar_id = $1 AND id = $2
+        RETURNING *;
+    ";
+
+    let rows = transaction.query(query, &[
+        &calendar_id,
+        &event_id,
+        &event.start_date,
+        &event.start_time,
+        &event.end_date,
+        &event.end_time,
+        &event.recurrence.as_ref().map(|r| &r.rrule),
+        &event.recurrence.as_ref().map(|r| &r.exdates),
+        &event.recurrence.as_ref().map(|r| &r.rdates),
+        &event.title,
+        &event.description,
+        &event.metadata,
+        &event.organizer.as_ref().map(|o| &o.email),
+        &event.organizer.as_ref().and_then(|o| o.display_name.as_ref()),
+        &event.class.unwrap_or_default().to_string(),
+    ])?;
+
+    let event = match rows.get(0)
+    {
+        Some(row) => Event::from_row(row)?,
+        None => return ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()).into(),
+    };
+
+    // Overrides of instances the patched event doesn't have anymore (all
+    // of them if it isn't recurring anymore) would be left dangling, so
+    // they're moved to the trash like deleting them would.
+    let recurring = match &event
+    {
+        Event::Recurring(recurring) => Some(recurring),
+        _ => None,
+    };
+
+    let mut orphaned: Vec<Uuid> = vec![];
+    for row in transaction.query("SELECT * FROM events WHERE parent_event_id = $1 AND deleted_at IS NULL", &[&event_id])?
+    {
+        if let Event::Override(o) = Event::from_row(&row)?
+        {
+            if recurring.map_or(true, |r| !r.has_instance(o.get_recurrence_id()))
+            {
+                orphaned.push(o.get_id());
+            }
+        }
+    }
+
+    if !orphaned.is_empty()
+    {
+        transaction.execute("UPDATE events SET deleted_at = NOW() WHERE parent_event_id = $1 AND id = ANY($2)", &[&event_id, &orphaned])?;
+    }
+
+    let new_etag = lock_event(&mut transaction, &calendar_id, &event_id, principal.get_visibility())?;
+
+    transaction.commit()?;
+
+    let event = event.into_plain();
+
+    let mut events = vec![event];
+    fill_attendees(&mut db, &mut events)?;
+
+    Conditional::Result(ApiResult::Ok(events.remove(0).redact(principal.get_visibility())), new_etag)
+}
+
+/// Moves an event (along with its overrides) to the trash, from which
+/// it can be restored until it's purged (see `TRASH_RETENTION_DAYS`).
+/// Overrides deleted on their own are moved to the trash too, but
+/// can't be restored: their instance is as the recurring event says.
+/// Overrides deleted with their event get its `deleted_at`, so they're
+/// restored with it.
+///
+/// If `If-Match` is set the event is only deleted if it has one
+/// of its ETags, otherwise 412 is returned.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 412, 500
+#[openapi]
+#[delete("/calendars/<calendar_id>/events/<event_id>")]
+pub fn delete_event(mut db: PgsqlConn, principal: RequireWrite, if_match: IfMatch, calendar_id: UuidParam, event_id: UuidParam) -> Conditional<()>
+{
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    let mut transaction = db.transaction()?;
+
+    if !if_match.matches(lock_event(&mut transaction, &calendar_id, &event_id, principal.get_visibility())?.as_deref())
+    {
+        return Conditional::PreconditionFailed;
+    }
+
+    let trashed = transaction.execute(
+        "UPDATE events SET deleted_at = NOW() WHERE calendar_id = $1 AND (id = $2 OR parent_event_id = $2) AND deleted_at IS NULL",
+        &[&calendar_id, &event_id]
+    )?;
+
+    if trashed == 0
+    {
+        return ApiResult::not_found().into();
+    }
+
+    transaction.commit()?;
+
+    ApiResult::Ok(()).into()
+}
+
+/// Gets the instances of a recurring event between `since` and `until`.
+/// Principals that can't see the event's class only get when they happen.
+///
+/// Required scope: `READ`, required role: `FREE_BUSY`
+///
+/// Response codes: 200, 403, 404, 500
 #[openapi]
 #[get("/calendars/<calendar_id>/events/<event_id>/instances?<since>&<until>")]
 pub fn get_instances(
     mut db: PgsqlConn,
-    _api_key: ApiKey,
+    principal: RequireFreeBusy,
     calendar_id: UuidParam,
     event_id: UuidParam,
     since: Option<NaiveDateParam>,
     until: Option<NaiveDateParam>,
     common_params: CommonQueryParams,
-) -> RouteResult<Vec<EventPlain>>
+) -> ApiResult<Vec<EventPlain>>
 {
+    let overrides = get_overrides(&mut db, &event_id)?;
+
     if let Some(event) = get_event_by_id(&mut db, calendar_id, event_id)?
     {
         match event
         {
-            Event::Recurring(event) => RouteResult::Ok(
-
-                event
-                    .generate_instances(
-                        since.map(|x| x.into_inner()),
-                        until.map(|x| x.into_inner()),
-                        common_params.offset() as usize,
-                        common_params.page_size() as usize
-                    )?
-                    .into_iter()
-                    .map(|e| e.into_plain())
-                    .collect()
-
-            ),
-            Event::Single(_) => RouteResult::NotFound,
+            Event::Recurring(event) =>
+            {
+                // Instances have the parent's attendees, but attendees can respond
+                // to a single instance through an override.
+                let ids: Vec<Uuid> = overrides.iter().map(|o| o.get_id()).chain(Some(event.get_id())).collect();
+                let attendees = get_attendees(&mut db, &ids)?;
+                let parent_attendees = attendees.get(&event.get_id()).cloned().unwrap_or(vec![]);
+                let visibility = principal.get_visibility();
+
+                ApiResult::Ok(
+
+                    event
+                        .generate_instances(
+                            since.map(|x| x.into_inner()),
+                            until.map(|x| x.into_inner()),
+                            &overrides,
+                            common_params.offset() as usize,
+                            common_params.page_size() as usize
+                        )
+                        .into_iter()
+                        .map(|instance| {
+                            let override_attendees = instance.get_override_id()
+                                .and_then(|id| attendees.get(&id))
+                                .cloned()
+                                .unwrap_or(vec![]);
+
+                            let mut plain = instance.into_plain();
+                            plain.attendees = Some(merge_attendees(&parent_attendees, &override_attendees));
+
+                            plain.redact(visibility)
+                        })
+                        .collect()
+
+                )
+            },
+            Event::Single(_) | Event::Override(_) => ApiResult::not_found(),
         }
     }
     else
     {
-        RouteResult::NotFound
+        ApiResult::not_found()
     }
 }
 
+/// Lists the events of a calendar between `since` and `until`,
+/// which can be dates or date-times. Principals only get when the
+/// events whose class they can't see happen.
+///
+/// Required scope: `READ`, required role: `FREE_BUSY`
+///
+/// Response codes: 200, 400, 403, 404, 500
 #[openapi]
 #[get("/calendars/<calendar_id>/events?<since>&<until>")]
 pub fn list_events(
     mut db: PgsqlConn,
-    _api_key: ApiKey,
+    principal: RequireFreeBusy,
+    _representation: PlainRepresentation,
     calendar_id: UuidParam,
     since: Option<NaiveDateOrTime>,
     until: Option<NaiveDateOrTime>,
     common_params: CommonQueryParams,
-) -> RouteResult<Vec<EventPlain>>
+) -> ApiResult<Vec<EventPlain>>
 {
     // since and until can only be date or date-times
     if (since.is_some() && since.as_ref().unwrap().as_naive_time().is_some())
         || (until.is_some() && until.as_ref().unwrap().as_naive_time().is_some())
     {
-        return RouteResult::BadRequest(None);
+        return Problem::bad_request(ErrorCode::InvalidField, "since and until must be dates or date-times.").into();
     }
 
-    // A query parameter can only have one type and we want to be able to
-    // use `since` and `until` as either a date or a date-time, so we have
-    // a pair of parameters for each variable, one for each type. If, for example,
-    // `since` is a date, `$2` will be `NULL`.
-    let query = "
-        SELECT * FROM events
-        WHERE
-            calendar_id = $1
-            AND ($2::TIMESTAMP IS NULL OR start_date + start_time >= $2::TIMESTAMP)
-            AND ($3::DATE IS NULL OR start_date >= $3::DATE)
-            AND ($4::TIMESTAMP IS NULL OR end_date + end_time <= $4::TIMESTAMP)
-            AND ($5::DATE IS NULL OR end_date <= $5::DATE)
-        OFFSET $6
-        LIMIT $7;
-    ";
-
-    let rows = db.query(query, &[
-        &calendar_id,
-
-        &since.as_ref().and_then(|x| x.as_naive_date_time() .map(|dt| dt.clone())),
-        &since.as_ref().and_then(|x| x.as_naive_date()      .map(|d|   d.clone())),
+    let mut events = find_events(&mut db, &calendar_id.into_inner(), &since, &until, &common_params)?
+        .into_iter()
+        .map(|e| e.into_plain())
+        .collect::<Vec<EventPlain>>();
 
-        &until.as_ref().and_then(|x| x.as_naive_date_time() .map(|dt| dt.clone())),
-        &until.as_ref().and_then(|x| x.as_naive_date()      .map(|d|   d.clone())),
+    fill_attendees(&mut db, &mut events)?;
 
-        &common_params.offset(),
-        &common_params.page_size(),
-    ]);
-
-    RouteResult::Ok(
-        rows?
-            .into_iter()
-            .map::<Result<EventPlain, _>, _>(|r| Event::from_row(&r).map(|e| e.into_plain()))
-            .collect::<Result<Vec<EventPlain>, _>>()?
-    )
+    ApiResult::Ok(events.redact(principal.get_visibility()))
 }
 
+/// Lists the events of a calendar that were modified since `since`,
+/// which can be a date or a date-time. Principals only get when the
+/// events whose class they can't see happen.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 400, 403, 500
 #[openapi]
 #[get("/calendars/<calendar_id>/events/changes?<since>")]
 pub fn check_for_changes(
     mut db: PgsqlConn,
-    _api_key: ApiKey,
+    principal: RequireRead,
     common_params: CommonQueryParams,
     calendar_id: UuidParam,
     since: NaiveDateOrTime,
-) -> RouteResult<Vec<EventPlain>>
+) -> ApiResult<Vec<EventPlain>>
 {
     if since.as_naive_time().is_some()
     {
-        return RouteResult::BadRequest(None);
+        return Problem::invalid_field("since", "since must be a date or a date-time.").into();
     }
 
     let query = "
         SELECT * FROM events
         WHERE
             calendar_id = $1
+            AND deleted_at IS NULL
             AND ($2::TIMESTAMP IS NULL OR last_modified >= $2::TIMESTAMP)
             AND ($3::DATE IS NULL OR last_modified >= $3::DATE)
         OFFSET $4
@@ -434,10 +898,45 @@ pub fn check_for_changes(
         &common_params.page_size(),
     ]);
 
-    RouteResult::Ok (
-        rows?
-            .into_iter()
-            .map::<Result<EventPlain, _>, _>(|r| Event::from_row(&r).map(|e| e.into_plain()))
-            .collect::<Result<Vec<EventPlain>, _>>()?
-    )
-}
\ No newline at end of file
+    let mut events = rows?
+        .into_iter()
+        .map::<Result<EventPlain, _>, _>(|r| Event::from_row(&r).map(|e| e.into_plain()))
+        .collect::<Result<Vec<EventPlain>, _>>()?;
+
+    fill_attendees(&mut db, &mut events)?;
+
+    ApiResult::Ok(events.redact(principal.get_visibility()))
+}
+
+/// Gets the events that changed since `token`, including tombstones of
+/// deleted events, and a new token to use on the next sync. Without a
+/// token all events of the calendar are returned (tombstones are not).
+///
+/// Each event is returned once with its latest state, no matter how many
+/// times it changed. If there are more than a page of changes `more` is
+/// true and the returned token points to the next page. Principals only
+/// get when the events whose class they can't see happen.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 400, 403, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/events/sync?<token>")]
+pub fn sync_events(
+    mut db: PgsqlConn,
+    principal: RequireRead,
+    common_params: CommonQueryParams,
+    calendar_id: UuidParam,
+    token: Option<String>,
+) -> ApiResult<SyncResponse>
+{
+    let token = match token.map(|t| SyncToken::from_str(&t)).transpose()
+    {
+        Ok(token) => token,
+        Err(_) => return Problem::invalid_field("token", "The sync token is invalid.").into(),
+    };
+
+    let page = get_changes(&mut db, &calendar_id.into_inner(), token, common_params.page_size(), principal.get_visibility())?;
+
+    ApiResult::Ok(page.into())
+}
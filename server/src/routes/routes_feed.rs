@@ -0,0 +1,229 @@
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::Problem;
+use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
+use rocket_contrib::json::Json;
+use crate::authentication::auth_guard::{Principal, RequireRead, RequireWrite};
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::routes::conditional::IfNoneMatch;
+use crate::feed::{Feed, get_etag, write_feed};
+use crate::acl::Visibility;
+use crate::ical::get_calendar_events;
+use crate::sync::get_current_token;
+use rocket::{Request, Response};
+use rocket::request::FromParam;
+use rocket::response::{self, Responder};
+use rocket::http::{ContentType, RawStr, Status};
+use chrono::Utc;
+use std::io::Cursor;
+use uuid::Uuid;
+
+/// A feed token followed by `.ics`.
+pub struct FeedTokenParam(String);
+
+impl FeedTokenParam
+{
+    pub fn into_inner(self) -> String { self.0 }
+}
+
+impl FromParam<'_> for FeedTokenParam
+{
+    type Error = ();
+
+    fn from_param(param: &RawStr) -> Result<Self, Self::Error>
+    {
+        param.as_str()
+            .strip_suffix(".ics")
+            .filter(|token| !token.is_empty() && token.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(|token| FeedTokenParam(token.to_owned()))
+            .ok_or(())
+    }
+}
+
+pub enum FeedResponse
+{
+    NotModified { etag: String },
+    Calendar { etag: String, ics: String },
+}
+
+impl<'r> Responder<'r> for FeedResponse
+{
+    fn respond_to(self, _request: &Request) -> response::Result<'r>
+    {
+        match self
+        {
+            FeedResponse::NotModified { etag } =>
+            {
+                Response::build()
+                    .status(Status::NotModified)
+                    .raw_header("ETag", etag)
+                    .ok()
+            },
+            FeedResponse::Calendar { etag, ics } =>
+            {
+                // Apps should check for changes (cheaply, with the ETag)
+                // every time they refresh the feed.
+                Response::build()
+                    .header(ContentType::new("text", "calendar"))
+                    .raw_header("ETag", etag)
+                    .raw_header("Cache-Control", "no-cache")
+                    .sized_body(Cursor::new(ics))
+                    .ok()
+            },
+        }
+    }
+}
+
+/// Feed tokens let anyone read the calendar, so only principals that
+/// can see every event can list or create them.
+fn check_sees_all(principal: &Principal) -> Result<(), Problem>
+{
+    if principal.get_visibility() == Visibility::All
+    {
+        Ok(())
+    }
+    else
+    {
+        Err(Problem::forbidden("Only principals with the ALL visibility can list or create feeds."))
+    }
+}
+
+/// Lists the feeds of a calendar.
+///
+/// Required scope: `READ`, required role: `READER` with the `ALL` visibility
+///
+/// Response codes: 200, 403, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/feeds")]
+pub fn list_feeds(mut db: PgsqlConn, principal: RequireRead, calendar_id: UuidParam, common_params: CommonQueryParams) -> ApiResult<Vec<Feed>>
+{
+    check_sees_all(&principal)?;
+
+    let query = "SELECT * FROM feeds WHERE calendar_id = $1 ORDER BY created_at OFFSET $2 LIMIT $3;";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| Feed::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Creates a feed with a new secret token.
+///
+/// Required scope: `WRITE`, required role: `WRITER` with the `ALL` visibility
+///
+/// Response codes: 201, 400, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/feeds", data = "<feed>")]
+pub fn insert_feed(mut db: PgsqlConn, principal: RequireWrite, calendar_id: UuidParam, feed: Json<Feed>) -> ApiResult<Feed>
+{
+    check_sees_all(&principal)?;
+
+    if feed.id.is_some()
+    {
+        return Problem::invalid_field("id", "Feeds get their id from the server.").into();
+    }
+
+    if !feed.validate()
+    {
+        return Problem::invalid_field("expansion_horizon", "The expansion horizon is out of range.").into();
+    }
+
+    let query = "
+        INSERT INTO feeds (calendar_id, expansion_horizon, visibility)
+        SELECT id, $2, $3 FROM calendars
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING *;
+    ";
+
+    let rows = db.query(query, &[&calendar_id, &feed.expansion_horizon, &feed.visibility.to_string()])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        ApiResult::Created(
+            Feed::from_row(row)?,
+            format!("/api/calendars/{}/feeds/{}", calendar_id, row.get_cell::<Uuid>("id")?)
+        )
+    }
+    else
+    {
+        ApiResult::not_found()
+    }
+}
+
+/// Deletes a feed, revoking its token.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[delete("/calendars/<calendar_id>/feeds/<feed_id>")]
+pub fn delete_feed(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, feed_id: UuidParam) -> ApiResult<()>
+{
+    let query = "DELETE FROM feeds WHERE calendar_id = $1 AND id = $2;";
+
+    if db.execute(query, &[&calendar_id, &feed_id])? == 0
+    {
+        ApiResult::not_found()
+    }
+    else
+    {
+        ApiResult::Ok(())
+    }
+}
+
+/// Serves a feed as an iCalendar file. This route doesn't require
+/// an API key, the feed's token is the credential.
+///
+/// Response codes: 200, 304, 404, 500
+#[openapi(skip)]
+#[get("/feeds/<token>")]
+pub fn get_feed(mut db: PgsqlConn, if_none_match: IfNoneMatch, token: FeedTokenParam) -> Result<FeedResponse, Problem>
+{
+    // Feeds of calendars in the trash are gone until the calendar is restored.
+    let query = "
+        SELECT feeds.*, calendars.tenant_id FROM feeds
+        JOIN calendars ON calendars.id = feeds.calendar_id
+        WHERE feeds.token = $1 AND calendars.deleted_at IS NULL;
+    ";
+
+    // The request has no principal, the tenant is only known once the
+    // feed is found. Only the lookup sees the rows of every tenant.
+    let rows = db.transaction()
+        .and_then(|mut transaction| {
+            transaction.batch_execute("SET LOCAL caser.unrestricted = 'on';")?;
+            let rows = transaction.query(query, &[&token.into_inner()])?;
+            transaction.commit()?;
+
+            Ok(rows)
+        })?;
+
+    let row = rows.get(0).ok_or_else(Problem::not_found)?;
+
+    let feed = Feed::from_row(row)?;
+    let calendar_id: Uuid = row.get_cell("calendar_id")?;
+    let tenant_id: Uuid = row.get_cell("tenant_id")?;
+
+    db.restrict_to_tenant(&tenant_id)?;
+
+    // The ETag is calculated before reading the events, so that
+    // the events are never older than the ETag says.
+    let now = Utc::now().naive_utc();
+    let sync_token = get_current_token(&mut db, &calendar_id)?;
+    let etag = get_etag(&feed, sync_token, now.date());
+
+    if if_none_match.matches(&etag)
+    {
+        return Ok(FeedResponse::NotModified { etag });
+    }
+
+    let events = get_calendar_events(&mut db, &calendar_id)?;
+
+    Ok(FeedResponse::Calendar { ics: write_feed(&feed, events, now), etag })
+}
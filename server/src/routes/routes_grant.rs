@@ -0,0 +1,133 @@
+//! Routes to manage the grants of a calendar (its access control
+//! list), only the calendar's owners can use them.
+
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::{ErrorCode, Problem};
+use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use rocket_contrib::json::Json;
+use crate::authentication::auth_guard::RequireOwner;
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::acl::{CalendarGrant, PrincipalType, Role, Visibility};
+use uuid::Uuid;
+use std::str::FromStr;
+
+/// Lists the grants of a calendar.
+///
+/// Required scope: `WRITE`, required role: `OWNER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/grants")]
+pub fn list_grants(mut db: PgsqlConn, _principal: RequireOwner, calendar_id: UuidParam, common_params: CommonQueryParams) -> ApiResult<Vec<CalendarGrant>>
+{
+    let query = "SELECT * FROM calendar_grants WHERE calendar_id = $1 ORDER BY created_at OFFSET $2 LIMIT $3;";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| CalendarGrant::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Gives a principal a role and a visibility on a calendar. If the principal
+/// already has a grant on the calendar its role and visibility are replaced.
+/// API keys must belong to the calendar's tenant.
+///
+/// Required scope: `WRITE`, required role: `OWNER`
+///
+/// Response codes: 201, 400, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/grants", data = "<grant>")]
+pub fn insert_grant(mut db: PgsqlConn, principal: RequireOwner, calendar_id: UuidParam, grant: Json<CalendarGrant>) -> ApiResult<CalendarGrant>
+{
+    if grant.id.is_some()
+    {
+        return Problem::invalid_field("id", "Grants get their id from the server.").into();
+    }
+
+    if !grant.validate()
+    {
+        return Problem::bad_request(ErrorCode::InvalidField, "The grant's principal or role is invalid.").into();
+    }
+
+    if grant.principal_type == PrincipalType::ApiKey
+    {
+        let key_id = match Uuid::from_str(&grant.principal_id)
+        {
+            Ok(key_id) => key_id,
+            Err(_) => return Problem::invalid_field("principal_id", "The principal of an API key grant must be the key's id.").into(),
+        };
+
+        let query = "SELECT 1 FROM api_keys WHERE id = $1 AND tenant_id = $2";
+
+        if db.query(query, &[&key_id, &principal.get_tenant_id()])?.is_empty()
+        {
+            return Problem::bad_request(ErrorCode::InvalidReference, "The API key isn't one of the tenant's.").field("principal_id").into();
+        }
+    }
+
+    // Principals that can only see free/busy time see no details.
+    let visibility = match grant.role
+    {
+        Role::FreeBusy => Visibility::FreeBusy,
+        _ => grant.visibility,
+    };
+
+    let query = "
+        INSERT INTO calendar_grants (calendar_id, principal_type, principal_id, role, visibility)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (calendar_id, principal_type, principal_id)
+            DO UPDATE SET role = EXCLUDED.role, visibility = EXCLUDED.visibility
+        RETURNING *;
+    ";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &grant.principal_type.to_string(),
+        &grant.principal_id,
+        &grant.role.to_string(),
+        &visibility.to_string(),
+    ])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        ApiResult::Created(
+            CalendarGrant::from_row(row)?,
+            format!("/api/calendars/{}/grants/{}", calendar_id, row.get_cell::<Uuid>("id")?)
+        )
+    }
+    else
+    {
+        ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into())
+    }
+}
+
+/// Removes a grant, the principal stops seeing the calendar
+/// (unless it has another grant on it through a group).
+///
+/// Required scope: `WRITE`, required role: `OWNER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[delete("/calendars/<calendar_id>/grants/<grant_id>")]
+pub fn delete_grant(mut db: PgsqlConn, _principal: RequireOwner, calendar_id: UuidParam, grant_id: UuidParam) -> ApiResult<()>
+{
+    let query = "DELETE FROM calendar_grants WHERE calendar_id = $1 AND id = $2;";
+
+    if db.execute(query, &[&calendar_id, &grant_id])? == 0
+    {
+        ApiResult::not_found()
+    }
+    else
+    {
+        ApiResult::Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use crate::connection_pool::PgsqlConn;
+use crate::database_helpers::UuidParam;
+use crate::authentication::auth_guard::{RequireRead, RequireWrite};
+use crate::calendar::is_subscribed;
+use crate::ical::{import_components, get_calendar_events, get_event_family};
+use crate::event::Redact;
+use caser_common::ical::writer::write_calendar;
+use caser_common::ical::parser::parse;
+use caser_common::ical::import::ImportReport;
+use crate::routes::api_result::ApiResult;
+use crate::problem::{ErrorCode, Problem};
+use rocket::Data;
+use rocket::request::FromParam;
+use rocket::response::Content;
+use rocket::http::{ContentType, RawStr};
+use chrono::Utc;
+use std::io::Read;
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Maximum size of an imported iCalendar file, in bytes.
+const MAX_IMPORT_SIZE: u64 = 10 * 1024 * 1024;
+
+/// A UUID followed by `.ics`, used as the last segment of
+/// iCalendar export routes.
+pub struct IcsParam(Uuid);
+
+impl IcsParam
+{
+    pub fn into_inner(self) -> Uuid { self.0 }
+}
+
+impl FromParam<'_> for IcsParam
+{
+    type Error = ();
+
+    fn from_param(param: &RawStr) -> Result<Self, Self::Error>
+    {
+        param.as_str()
+            .strip_suffix(".ics")
+            .and_then(|id| Uuid::from_str(id).ok())
+            .map(IcsParam)
+            .ok_or(())
+    }
+}
+
+fn calendar_content(ics: String) -> Content<String>
+{
+    Content(ContentType::new("text", "calendar"), ics)
+}
+
+/// Exports all events of a calendar as an iCalendar file. Events
+/// whose class the principal can't see only tell when they happen.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi(skip)]
+#[get("/calendars/<calendar_id>", rank = 2)]
+pub fn export_calendar(mut db: PgsqlConn, principal: RequireRead, calendar_id: IcsParam) -> Result<Content<String>, Problem>
+{
+    let calendar_id = calendar_id.into_inner();
+
+    if db.query("SELECT 1 FROM calendars WHERE id = $1 AND deleted_at IS NULL", &[&calendar_id])?.is_empty()
+    {
+        return Err(Problem::not_found());
+    }
+
+    let events = get_calendar_events(&mut db, &calendar_id)?.redact(principal.get_visibility());
+
+    Ok(calendar_content(write_calendar(&events, Utc::now().naive_utc())))
+}
+
+/// Exports an event as an iCalendar file. Recurring events are
+/// exported along with their overrides, overrides along with
+/// their recurring event. Events whose class the principal
+/// can't see only tell when they happen.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi(skip)]
+#[get("/calendars/<calendar_id>/events/<event_id>", rank = 2)]
+pub fn export_event(mut db: PgsqlConn, principal: RequireRead, calendar_id: UuidParam, event_id: IcsParam) -> Result<Content<String>, Problem>
+{
+    let events = get_event_family(&mut db, &calendar_id.into_inner(), &event_id.into_inner())?
+        .ok_or_else(Problem::not_found)?
+        .redact(principal.get_visibility());
+
+    Ok(calendar_content(write_calendar(&events, Utc::now().naive_utc())))
+}
+
+/// Imports the events of an iCalendar file into a calendar. Events that
+/// were imported before (identified by their UID) are updated.
+///
+/// Returns a report with the result of each component of the file.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi(skip)]
+#[post("/calendars/<calendar_id>/import", data = "<data>")]
+pub fn import_calendar(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, data: Data) -> ApiResult<ImportReport>
+{
+    let calendar_id = calendar_id.into_inner();
+
+    if db.query("SELECT 1 FROM calendars WHERE id = $1 AND deleted_at IS NULL", &[&calendar_id])?.is_empty()
+    {
+        return ApiResult::not_found();
+    }
+
+    // Subscribed calendars only have the events of their file.
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    let mut input = String::new();
+    if data.open().take(MAX_IMPORT_SIZE + 1).read_to_string(&mut input).is_err()
+        || input.len() as u64 > MAX_IMPORT_SIZE
+    {
+        return Problem::bad_request(ErrorCode::InvalidBody, "The file must be UTF-8 and at most 10 MiB.").into();
+    }
+
+    let calendars = match parse(&input)
+    {
+        Ok(calendars) if calendars.iter().any(|c| c.name == "VCALENDAR") => calendars,
+        _ => return Problem::bad_request(ErrorCode::InvalidBody, "The file isn't an iCalendar file.").into(),
+    };
+
+    let mut transaction = db.transaction()?;
+    let report = import_components(&mut transaction, &calendar_id, &calendars)?;
+    transaction.commit()?;
+
+    ApiResult::Ok(report)
+}
@@ -0,0 +1,275 @@
+//! jCal and JSCalendar versions of the event and calendar routes,
+//! picked by the `Accept` header. Requests that don't ask for
+//! either get the usual `EventPlain`s and `Calendar`s.
+
+use crate::connection_pool::PgsqlConn;
+use crate::database_helpers::{FromRow, UuidParam};
+use crate::authentication::auth_guard::RequireRead;
+use crate::event::{Event, Redact};
+use crate::calendar::Calendar;
+use crate::ical::{get_calendar_events, get_event_family};
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::routes::routes_event::{NaiveDateOrTime, find_events};
+use crate::problem::Problem;
+use caser_common::ical::jcal;
+use caser_common::jscalendar;
+use rocket::Request;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::Content;
+use rocket::http::{Accept, ContentType};
+use rocket_contrib::json::Json;
+use serde_json::Value;
+use chrono::Utc;
+use uuid::Uuid;
+
+/// The representations of events and calendars clients can ask for.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Representation
+{
+    /// `application/json`, `EventPlain`s and `Calendar`s.
+    Plain,
+
+    /// `application/calendar+json` (RFC 7265).
+    JCal,
+
+    /// `application/jscalendar+json` (RFC 8984).
+    JSCalendar,
+}
+
+impl Representation
+{
+    /// Picks the representation with the highest weight in `accept`,
+    /// the first one wins ties. Media types we don't know about are
+    /// ignored, `Plain` if there's none we know.
+    pub fn from_accept(accept: Option<&Accept>) -> Representation
+    {
+        let mut chosen = (Representation::Plain, 0.0);
+
+        for media_type in accept.iter().flat_map(|accept| accept.iter())
+        {
+            let top = media_type.top().as_str().to_ascii_lowercase();
+            let sub = media_type.sub().as_str().to_ascii_lowercase();
+
+            let representation = match (top.as_str(), sub.as_str())
+            {
+                ("application", "json") => Representation::Plain,
+                ("application", "calendar+json") => Representation::JCal,
+                ("application", "jscalendar+json") => Representation::JSCalendar,
+                _ => continue,
+            };
+
+            let weight = media_type.weight_or(1.0);
+            if weight > chosen.1
+            {
+                chosen = (representation, weight);
+            }
+        }
+
+        chosen.0
+    }
+
+    fn content_type(&self) -> ContentType
+    {
+        match self
+        {
+            Representation::Plain => ContentType::JSON,
+            Representation::JCal => ContentType::new("application", "calendar+json"),
+            Representation::JSCalendar => ContentType::new("application", "jscalendar+json"),
+        }
+    }
+}
+
+/// Request guard of the plain routes, forwards the request
+/// if the client asked for jCal or JSCalendar.
+pub struct PlainRepresentation;
+
+impl<'a, 'r> FromRequest<'a, 'r> for PlainRepresentation
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        match Representation::from_accept(request.accept())
+        {
+            Representation::Plain => Outcome::Success(PlainRepresentation),
+            _ => Outcome::Forward(()),
+        }
+    }
+}
+
+/// Request guard of the routes in this module, forwards the
+/// request if the client didn't ask for jCal or JSCalendar.
+pub struct CalendarRepresentation(Representation);
+
+impl<'a, 'r> FromRequest<'a, 'r> for CalendarRepresentation
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        match Representation::from_accept(request.accept())
+        {
+            Representation::Plain => Outcome::Forward(()),
+            representation => Outcome::Success(CalendarRepresentation(representation)),
+        }
+    }
+}
+
+fn document(representation: Representation, value: Value) -> Content<Json<Value>>
+{
+    Content(representation.content_type(), Json(value))
+}
+
+/// Writes `events` as a jCal vcalendar or as a JSCalendar Group.
+fn write_events(representation: Representation, calendar: &Calendar, events: &[Event]) -> Result<Value, Problem>
+{
+    match representation
+    {
+        Representation::JCal => write_jcal(events),
+        _ => Ok(jscalendar::write_group(calendar, events)),
+    }
+}
+
+fn write_jcal(events: &[Event]) -> Result<Value, Problem>
+{
+    jcal::write_calendar(events, Utc::now().naive_utc())
+        .map_err(|_| Problem::internal())
+}
+
+fn find_calendar(db: &mut PgsqlConn, calendar_id: &Uuid) -> Result<Calendar, Problem>
+{
+    let rows = db.query("SELECT * FROM calendars WHERE id = $1 AND deleted_at IS NULL", &[calendar_id])?;
+
+    match rows.get(0)
+    {
+        Some(row) => Ok(Calendar::from_row(row)?),
+        None => Err(Problem::not_found()),
+    }
+}
+
+/// Gets a calendar as a jCal vcalendar or a JSCalendar Group, with all
+/// of its events. Like in the plain routes, events whose class the
+/// principal can't see only tell when they happen.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi(skip)]
+#[get("/calendars/<calendar_id>", rank = 3)]
+pub fn get_calendar(mut db: PgsqlConn, principal: RequireRead, representation: CalendarRepresentation, calendar_id: UuidParam) -> Result<Content<Json<Value>>, Problem>
+{
+    let calendar = find_calendar(&mut db, &calendar_id.into_inner())?;
+
+    let events = get_calendar_events(&mut db, &calendar.id)?.redact(principal.get_visibility());
+
+    Ok(document(representation.0, write_events(representation.0, &calendar, &events)?))
+}
+
+/// Gets an event as a jCal vcalendar or a JSCalendar Event. Like in
+/// iCalendar exports, recurring events come with their overrides and
+/// overrides with their recurring event.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi(skip)]
+#[get("/calendars/<calendar_id>/events/<event_id>", rank = 3)]
+pub fn get_event(mut db: PgsqlConn, principal: RequireRead, representation: CalendarRepresentation, calendar_id: UuidParam, event_id: UuidParam) -> Result<Content<Json<Value>>, Problem>
+{
+    let events = get_event_family(&mut db, &calendar_id.into_inner(), &event_id.into_inner())?
+        .ok_or_else(Problem::not_found)?
+        .redact(principal.get_visibility());
+
+    let value = match representation.0
+    {
+        Representation::JCal => write_jcal(&events)?,
+        _ => jscalendar::write_events(&events).into_iter().next().ok_or_else(Problem::not_found)?,
+    };
+
+    Ok(document(representation.0, value))
+}
+
+/// Lists events like the plain route does, as a jCal vcalendar or
+/// a JSCalendar Group. Overrides of the listed events are included.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi(skip)]
+#[get("/calendars/<calendar_id>/events?<since>&<until>", rank = 3)]
+pub fn list_events(
+    mut db: PgsqlConn,
+    principal: RequireRead,
+    representation: CalendarRepresentation,
+    calendar_id: UuidParam,
+    since: Option<NaiveDateOrTime>,
+    until: Option<NaiveDateOrTime>,
+    common_params: CommonQueryParams,
+) -> Result<Content<Json<Value>>, Problem>
+{
+    // since and until can only be date or date-times
+    if since.as_ref().and_then(|x| x.as_naive_time()).is_some()
+    {
+        return Err(Problem::invalid_field("since", "since must be a date or a date-time."));
+    }
+
+    if until.as_ref().and_then(|x| x.as_naive_time()).is_some()
+    {
+        return Err(Problem::invalid_field("until", "until must be a date or a date-time."));
+    }
+
+    let calendar = find_calendar(&mut db, &calendar_id.into_inner())?;
+
+    let mut events = find_events(&mut db, &calendar.id, &since, &until, &common_params)?;
+
+    let ids: Vec<Uuid> = events
+        .iter()
+        .filter_map(|event| match event
+        {
+            Event::Recurring(event) => Some(event.get_id()),
+            _ => None,
+        })
+        .collect();
+
+    let rows = db.query("SELECT * FROM events WHERE parent_event_id = ANY($1) AND deleted_at IS NULL ORDER BY recurrence_id", &[&ids])?;
+
+    for row in rows
+    {
+        events.push(Event::from_row(&row)?);
+    }
+
+    let events = events.redact(principal.get_visibility());
+
+    Ok(document(representation.0, write_events(representation.0, &calendar, &events)?))
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::str::FromStr;
+
+    fn representation(accept: &str) -> Representation
+    {
+        Representation::from_accept(Some(&Accept::from_str(accept).unwrap()))
+    }
+
+    #[test]
+    fn picks_representation_from_accept()
+    {
+        assert_eq!(Representation::from_accept(None), Representation::Plain);
+        assert_eq!(representation("*/*"), Representation::Plain);
+        assert_eq!(representation("application/json"), Representation::Plain);
+        assert_eq!(representation("application/calendar+json"), Representation::JCal);
+        assert_eq!(representation("text/html, application/jscalendar+json"), Representation::JSCalendar);
+    }
+
+    #[test]
+    fn prefers_highest_weight()
+    {
+        assert_eq!(representation("application/json;q=0.5, application/calendar+json"), Representation::JCal);
+        assert_eq!(representation("application/jscalendar+json;q=0.2, application/json"), Representation::Plain);
+        assert_eq!(representation("application/calendar+json, application/jscalendar+json"), Representation::JCal);
+        assert_eq!(representation("application/calendar+json;q=0"), Representation::Plain);
+    }
+}
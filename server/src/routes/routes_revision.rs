@@ -0,0 +1,147 @@
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::Problem;
+use crate::database_helpers::{FromRow, UuidParam};
+use crate::database_error::DatabaseError;
+use crate::calendar::is_subscribed;
+use crate::authentication::auth_guard::{RequireRead, RequireWrite};
+use crate::event::{Event, EventPlain, ToPlain, Redact};
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::routes::routes_event::fill_attendees;
+
+/// Checks if the event with id `event_id` exists in the calendar
+/// with id `calendar_id`.
+fn event_exists(db: &mut PgsqlConn, calendar_id: &UuidParam, event_id: &UuidParam) -> Result<bool, DatabaseError>
+{
+    let query = "SELECT 1 FROM events WHERE calendar_id = $1 AND id = $2 AND deleted_at IS NULL";
+
+    Ok(!db.query(query, &[calendar_id, event_id])?.is_empty())
+}
+
+/// Lists the revisions of an event, newest first. Each revision is the
+/// event as it was when it got that `sequence`, without attendees.
+/// Principals only get when the revisions whose class they can't see happen.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/events/<event_id>/revisions")]
+pub fn list_revisions(
+    mut db: PgsqlConn,
+    principal: RequireRead,
+    calendar_id: UuidParam,
+    event_id: UuidParam,
+    common_params: CommonQueryParams,
+) -> ApiResult<Vec<EventPlain>>
+{
+    if !event_exists(&mut db, &calendar_id, &event_id)?
+    {
+        return ApiResult::not_found();
+    }
+
+    // Revisions are copies of the event's row, jsonb_populate_record
+    // turns them back into rows that Event::from_row can read.
+    let query = "
+        SELECT event.* FROM event_revisions, jsonb_populate_record(NULL::events, event_revisions.event) AS event
+        WHERE event_revisions.calendar_id = $1 AND event_revisions.event_id = $2
+        ORDER BY event_revisions.sequence DESC
+        OFFSET $3
+        LIMIT $4;
+    ";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &event_id,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    let revisions = rows.into_iter()
+        .map(|row| Event::from_row(&row).map(|e| e.into_plain()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    ApiResult::Ok(revisions.redact(principal.get_visibility()))
+}
+
+/// Gets the revision of an event with sequence number `sequence`.
+/// Principals only get when it happens if they can't see its class.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/events/<event_id>/revisions/<sequence>")]
+pub fn get_revision(mut db: PgsqlConn, principal: RequireRead, calendar_id: UuidParam, event_id: UuidParam, sequence: i32) -> ApiResult<EventPlain>
+{
+    if !event_exists(&mut db, &calendar_id, &event_id)?
+    {
+        return ApiResult::not_found();
+    }
+
+    let query = "
+        SELECT event.* FROM event_revisions, jsonb_populate_record(NULL::events, event_revisions.event) AS event
+        WHERE event_revisions.calendar_id = $1 AND event_revisions.event_id = $2 AND event_revisions.sequence = $3;
+    ";
+
+    let rows = db.query(query, &[&calendar_id, &event_id, &sequence])?;
+
+    match rows.get(0)
+    {
+        Some(row) => ApiResult::Ok(Event::from_row(row)?.into_plain().redact(principal.get_visibility())),
+        None => ApiResult::not_found(),
+    }
+}
+
+/// Restores an event to its revision with sequence number `sequence`
+/// and returns it. Restoring is a change like any other, the event gets
+/// a new sequence number (unless it's already like the revision). The
+/// event's attendees, calendar and recurrence id are left as they are.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/events/<event_id>/revisions/<sequence>/restore")]
+pub fn restore_revision(mut db: PgsqlConn, principal: RequireWrite, calendar_id: UuidParam, event_id: UuidParam, sequence: i32) -> ApiResult<EventPlain>
+{
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    let query = "
+        UPDATE events SET
+            start_date = revision.start_date,
+            start_time = revision.start_time,
+            end_date = revision.end_date,
+            end_time = revision.end_time,
+            rrule = revision.rrule,
+            exdates = revision.exdates,
+            rdates = revision.rdates,
+            title = revision.title,
+            description = revision.description,
+            metadata = revision.metadata,
+            organizer_email = revision.organizer_email,
+            organizer_name = revision.organizer_name,
+            class = revision.class
+        FROM event_revisions, jsonb_populate_record(NULL::events, event_revisions.event) AS revision
+        WHERE events.calendar_id = $1 AND events.id = $2 AND events.deleted_at IS NULL
+            AND event_revisions.event_id = events.id AND event_revisions.sequence = $3
+        RETURNING events.*;
+    ";
+
+    let rows = db.query(query, &[&calendar_id, &event_id, &sequence])?;
+
+    match rows.get(0)
+    {
+        Some(row) =>
+        {
+            let mut events = vec![Event::from_row(row)?.into_plain()];
+            fill_attendees(&mut db, &mut events)?;
+
+            ApiResult::Ok(events.remove(0).redact(principal.get_visibility()))
+        },
+        None => ApiResult::not_found(),
+    }
+}
@@ -0,0 +1,363 @@
+use crate::connection_pool::{PgsqlConn, PgsqlPool};
+use crate::routes::api_result::ApiResult;
+use crate::problem::Problem;
+use crate::database_helpers::UuidParam;
+use crate::database_error::DatabaseError;
+use crate::authentication::auth_guard::RequireRead;
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::change_listener::{ChangeHub, Notice};
+use crate::sync::{SyncToken, SyncResponse, get_changes, get_current_token};
+use crate::acl::Visibility;
+use rocket::{Request, Response, State};
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::http::ContentType;
+use std::io::{self, Read};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use serde_json::json;
+
+/// How long a stream stays open. Each open stream takes one of
+/// the server's workers, so streams are closed once in a while and
+/// clients reconnect (resuming from the last event they got).
+const STREAM_DURATION: Duration = Duration::from_secs(5 * 60);
+
+/// How long a stream can go without sending anything, proxies
+/// tend to close connections that are idle for too long.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default and maximum amount of seconds a long-poll waits for changes.
+const DEFAULT_POLL_TIMEOUT: u64 = 30;
+const MAX_POLL_TIMEOUT: u64 = 60;
+
+/// Maximum amount of changes read from the change log at once.
+const STREAM_PAGE_SIZE: i64 = 100;
+
+/// Rocket fills a whole chunk before writing it to the connection, so
+/// each batch of messages is padded (with blank lines, which SSE clients
+/// ignore) to a multiple of this. This is also large enough for the
+/// connection's write buffer to send chunks right away.
+const CHUNK_SIZE: usize = 8192;
+
+/// Milliseconds clients should wait before reconnecting.
+const RETRY_MS: u64 = 3000;
+
+/// The `Last-Event-ID` header, which SSE clients send
+/// when they reconnect to a stream.
+pub struct LastEventId(Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for LastEventId
+{
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error>
+    {
+        Outcome::Success(LastEventId(
+            request.headers().get_one("Last-Event-ID").map(|id| id.to_owned())
+        ))
+    }
+}
+
+/// Parses a sync token, if there's no token the current
+/// end of the calendar's change log is used.
+fn parse_token(db: &mut PgsqlConn, calendar_id: &Uuid, token: Option<String>) -> Result<Option<SyncToken>, DatabaseError>
+{
+    match token
+    {
+        Some(token) => Ok(SyncToken::from_str(&token).ok()),
+        None => get_current_token(db, calendar_id).map(Some),
+    }
+}
+
+fn calendar_exists(db: &mut PgsqlConn, calendar_id: &Uuid) -> Result<bool, DatabaseError>
+{
+    Ok(!db.query("SELECT 1 FROM calendars WHERE id = $1 AND deleted_at IS NULL", &[calendar_id])?.is_empty())
+}
+
+fn to_io_error<E: std::fmt::Display>(e: E) -> io::Error
+{
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+/// Server-Sent Events body that writes the changes of a calendar
+/// as they happen.
+pub struct ChangeStream
+{
+    pool: PgsqlPool,
+    hub: Arc<ChangeHub>,
+    calendar_id: Uuid,
+
+    /// Tenant of the principal that opened the stream, the stream's
+    /// connections are restricted to it like the request's.
+    tenant_id: Uuid,
+
+    /// What the principal that opened the stream can see of the events.
+    visibility: Visibility,
+
+    /// Sync token of the last change written.
+    token: SyncToken,
+
+    /// Cursor of the last notice received from the hub.
+    cursor: u64,
+
+    buffer: Vec<u8>,
+    position: usize,
+    ends_at: Instant,
+    finished: bool,
+}
+
+impl ChangeStream
+{
+    fn write_message(&mut self, event: &str, id: Option<SyncToken>, data: &str)
+    {
+        if let Some(id) = id
+        {
+            self.buffer.extend(format!("id: {}\n", id).as_bytes());
+        }
+
+        self.buffer.extend(format!("event: {}\ndata: {}\n\n", event, data).as_bytes());
+    }
+
+    /// Writes all changes made since the last change written.
+    fn write_changes(&mut self) -> io::Result<()>
+    {
+        let mut db = self.pool.get_conn().map_err(to_io_error)?;
+        db.restrict_to_tenant(&self.tenant_id).map_err(to_io_error)?;
+
+        loop
+        {
+            let page = get_changes(&mut db, &self.calendar_id, Some(self.token), STREAM_PAGE_SIZE, self.visibility)
+                .map_err(to_io_error)?;
+
+            for (token, change) in &page.changes
+            {
+                let data = serde_json::to_string(change)?;
+                self.write_message("event", Some(*token), &data);
+            }
+
+            self.token = page.sync_token;
+
+            if !page.more
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Waits for the next changes and writes them to the buffer, or
+    /// writes a heartbeat if nothing changes for a while.
+    fn fill(&mut self) -> io::Result<()>
+    {
+        let now = Instant::now();
+        if now >= self.ends_at
+        {
+            self.finished = true;
+            return Ok(());
+        }
+
+        let timeout = HEARTBEAT_INTERVAL.min(self.ends_at - now);
+        let (cursor, notices) = self.hub.wait(self.cursor, self.calendar_id, timeout);
+        self.cursor = cursor;
+
+        if notices.contains(&Notice::Events)
+        {
+            self.write_changes()?;
+        }
+
+        if notices.contains(&Notice::CalendarUpdated)
+        {
+            let data = json!({ "id": self.calendar_id, "deleted": false }).to_string();
+            self.write_message("calendar", None, &data);
+        }
+
+        if notices.contains(&Notice::CalendarDeleted)
+        {
+            let data = json!({ "id": self.calendar_id, "deleted": true }).to_string();
+            self.write_message("calendar", None, &data);
+            self.finished = true;
+        }
+
+        if self.buffer.is_empty()
+        {
+            self.buffer.extend(b": keep-alive\n\n");
+        }
+
+        self.pad();
+
+        Ok(())
+    }
+
+    fn pad(&mut self)
+    {
+        let padding = (CHUNK_SIZE - self.buffer.len() % CHUNK_SIZE) % CHUNK_SIZE;
+        self.buffer.resize(self.buffer.len() + padding, b'\n');
+    }
+}
+
+impl Read for ChangeStream
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>
+    {
+        while self.position >= self.buffer.len()
+        {
+            if self.finished
+            {
+                return Ok(0);
+            }
+
+            self.buffer.clear();
+            self.position = 0;
+            self.fill()?;
+        }
+
+        let n = buf.len().min(self.buffer.len() - self.position);
+        buf[..n].copy_from_slice(&self.buffer[self.position..self.position + n]);
+        self.position += n;
+
+        Ok(n)
+    }
+}
+
+impl<'r> Responder<'r> for ChangeStream
+{
+    fn respond_to(self, _request: &Request) -> response::Result<'r>
+    {
+        Response::build()
+            .header(ContentType::new("text", "event-stream"))
+            .raw_header("Cache-Control", "no-cache")
+            .raw_header("X-Accel-Buffering", "no")
+            .chunked_body(self, CHUNK_SIZE as u64)
+            .ok()
+    }
+}
+
+/// Streams the changes of a calendar as Server-Sent Events.
+///
+/// Each change to an event is sent as an `event` message whose id is a sync
+/// token, so reconnecting with the `Last-Event-ID` header (or the
+/// `last_event_id` parameter) resumes the stream right after the last
+/// change received. Without it only changes made after connecting are sent.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi(skip)]
+#[get("/calendars/<calendar_id>/stream?<last_event_id>")]
+pub fn stream(
+    mut db: PgsqlConn,
+    principal: RequireRead,
+    pool: State<PgsqlPool>,
+    hub: State<Arc<ChangeHub>>,
+    header: LastEventId,
+    calendar_id: UuidParam,
+    last_event_id: Option<String>,
+) -> Result<ChangeStream, Problem>
+{
+    let calendar_id = calendar_id.into_inner();
+
+    if !calendar_exists(&mut db, &calendar_id)?
+    {
+        return Err(Problem::not_found());
+    }
+
+    // Get the hub's cursor before reading the change log, so that
+    // changes made in between are not missed.
+    let cursor = hub.get_cursor();
+
+    let resume = header.0.or(last_event_id);
+    let replay = resume.is_some();
+
+    let token = parse_token(&mut db, &calendar_id, resume)?
+        .ok_or_else(|| Problem::invalid_field("Last-Event-ID", "The Last-Event-ID is invalid."))?;
+
+    let mut stream = ChangeStream {
+        pool: pool.inner().clone(),
+        hub: hub.inner().clone(),
+        calendar_id,
+        tenant_id: principal.get_tenant_id(),
+        visibility: principal.get_visibility(),
+        token,
+        cursor,
+        buffer: vec![],
+        position: 0,
+        ends_at: Instant::now() + STREAM_DURATION,
+        finished: false,
+    };
+
+    stream.buffer.extend(format!("retry: {}\n\n", RETRY_MS).as_bytes());
+    stream.write_message("ready", Some(token), &json!({ "sync_token": token.to_string() }).to_string());
+
+    if replay
+    {
+        stream.write_changes().map_err(|_| Problem::internal())?;
+    }
+
+    stream.pad();
+
+    Ok(stream)
+}
+
+/// Long-poll alternative to the change stream. Waits up to `timeout`
+/// seconds for changes made after `last_event_id` (a sync token) and
+/// returns them as soon as there are any.
+///
+/// Without `last_event_id` it waits for changes made after the request.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/stream/poll?<last_event_id>&<timeout>")]
+pub fn poll(
+    principal: RequireRead,
+    pool: State<PgsqlPool>,
+    hub: State<Arc<ChangeHub>>,
+    common_params: CommonQueryParams,
+    calendar_id: UuidParam,
+    last_event_id: Option<String>,
+    timeout: Option<u64>,
+) -> ApiResult<SyncResponse>
+{
+    let calendar_id = calendar_id.into_inner();
+    let deadline = Instant::now() + Duration::from_secs(timeout.unwrap_or(DEFAULT_POLL_TIMEOUT).min(MAX_POLL_TIMEOUT));
+
+    let mut cursor = hub.get_cursor();
+
+    let mut token = {
+        let mut db = pool.get_conn()?;
+        db.restrict_to_tenant(&principal.get_tenant_id())?;
+
+        if !calendar_exists(&mut db, &calendar_id)?
+        {
+            return ApiResult::not_found();
+        }
+
+        match parse_token(&mut db, &calendar_id, last_event_id)?
+        {
+            Some(token) => token,
+            None => return Problem::invalid_field("Last-Event-ID", "The Last-Event-ID is invalid.").into(),
+        }
+    };
+
+    loop
+    {
+        // The connection goes back to the pool while waiting.
+        let page = {
+            let mut db = pool.get_conn()?;
+            db.restrict_to_tenant(&principal.get_tenant_id())?;
+
+            get_changes(&mut db, &calendar_id, Some(token), common_params.page_size(), principal.get_visibility())?
+        };
+
+        let now = Instant::now();
+        if !page.changes.is_empty() || now >= deadline
+        {
+            return ApiResult::Ok(page.into());
+        }
+
+        token = page.sync_token;
+        cursor = hub.wait(cursor, calendar_id, deadline - now).0;
+    }
+}
@@ -0,0 +1,80 @@
+//! Admin routes, only API keys with the `SUPER` scope can use them.
+
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::Problem;
+use crate::database_helpers::{FromRow, UuidParam};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use rocket_contrib::json::Json;
+use crate::authentication::auth_guard::RequireSuper;
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::tenant::Tenant;
+
+/// Creates a tenant.
+///
+/// Response codes: 201, 400, 403, 500
+#[openapi]
+#[post("/admin/tenants", data = "<tenant>")]
+pub fn insert_tenant(mut db: PgsqlConn, _principal: RequireSuper, tenant: Json<Tenant>) -> ApiResult<Tenant>
+{
+    if !tenant.id.is_nil()
+    {
+        return Problem::invalid_field("id", "Tenants get their id from the server.").into();
+    }
+
+    if !tenant.validate()
+    {
+        return Problem::invalid_field("name", "The name can't be blank.").into();
+    }
+
+    let rows = db.query("INSERT INTO tenants (name) VALUES ($1) RETURNING *;", &[&tenant.name])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        let tenant = Tenant::from_row(row)?;
+        let location = format!("/api/admin/tenants/{}", tenant.id);
+
+        ApiResult::Created(tenant, location)
+    }
+    else
+    {
+        ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into())
+    }
+}
+
+/// Lists all tenants.
+///
+/// Response codes: 200, 403, 500
+#[openapi]
+#[get("/admin/tenants")]
+pub fn list_tenants(mut db: PgsqlConn, _principal: RequireSuper, common_params: CommonQueryParams) -> ApiResult<Vec<Tenant>>
+{
+    let query = "SELECT * FROM tenants ORDER BY created_at OFFSET $1 LIMIT $2;";
+
+    let rows = db.query(query, &[
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| Tenant::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Gets a tenant by id.
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[get("/admin/tenants/<tenant_id>")]
+pub fn get_tenant(mut db: PgsqlConn, _principal: RequireSuper, tenant_id: UuidParam) -> ApiResult<Tenant>
+{
+    let rows = db.query("SELECT * FROM tenants WHERE id = $1;", &[&tenant_id])?;
+
+    match rows.get(0)
+    {
+        Some(row) => ApiResult::Ok(Tenant::from_row(row)?),
+        None => ApiResult::not_found(),
+    }
+}
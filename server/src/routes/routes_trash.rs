@@ -0,0 +1,205 @@
+//! Routes to list and restore the calendars and events in the trash,
+//! where they stay for `TRASH_RETENTION_DAYS` after they're deleted.
+
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::Problem;
+use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+use crate::calendar::{Calendar, is_subscribed};
+use crate::authentication::auth_guard::{RequireRead, RequireWrite};
+use crate::event::{Event, EventPlain, ToPlain, Redact};
+use crate::acl::{Role, get_calendar_ids_with_role};
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::routes::routes_event::fill_attendees;
+use chrono::NaiveDateTime;
+
+/// Lists the events of a calendar that are in the trash, most recently
+/// deleted first. Overrides are in the trash with their recurring event,
+/// so they're not listed. Principals only get when the events whose class
+/// they can't see happen.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/trash")]
+pub fn list_trash(mut db: PgsqlConn, principal: RequireRead, calendar_id: UuidParam, common_params: CommonQueryParams) -> ApiResult<Vec<EventPlain>>
+{
+    let query = "
+        SELECT * FROM events
+        WHERE calendar_id = $1 AND parent_event_id IS NULL AND deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC, id
+        OFFSET $2
+        LIMIT $3;
+    ";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    let mut events = vec![];
+    for row in rows
+    {
+        let mut event = Event::from_row(&row)?.into_plain();
+        event.deleted_at = row.get_cell("deleted_at")?;
+
+        events.push(event);
+    }
+
+    ApiResult::Ok(events.redact(principal.get_visibility()))
+}
+
+/// Takes an event (and its overrides) out of the trash and returns it.
+///
+/// Returns 400 if the calendar is subscribed, and 409 if another event
+/// took the event's UID or CalDAV name while it was in the trash.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 409, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/trash/<event_id>/restore")]
+pub fn restore_event(mut db: PgsqlConn, principal: RequireWrite, calendar_id: UuidParam, event_id: UuidParam) -> ApiResult<EventPlain>
+{
+    if is_subscribed(&mut db, &calendar_id)?
+    {
+        return Problem::subscribed_calendar().into();
+    }
+
+    let mut transaction = db.transaction()?;
+
+    // Locking the trashed event makes concurrent restores of it wait.
+    let query = "
+        SELECT deleted_at FROM events
+        WHERE calendar_id = $1 AND id = $2 AND parent_event_id IS NULL AND deleted_at IS NOT NULL
+        FOR UPDATE;
+    ";
+
+    let deleted_at: NaiveDateTime = match transaction.query(query, &[&calendar_id, &event_id])?.get(0)
+    {
+        Some(row) => row.get_cell("deleted_at")?,
+        None => return ApiResult::not_found(),
+    };
+
+    let query = "
+        SELECT 1 FROM events AS live
+        JOIN events AS trashed ON trashed.id = $2
+        WHERE
+            live.calendar_id = $1
+            AND live.deleted_at IS NULL
+            AND (live.ical_uid = trashed.ical_uid OR live.dav_name = trashed.dav_name);
+    ";
+
+    if !transaction.query(query, &[&calendar_id, &event_id])?.is_empty()
+    {
+        return Problem::conflict("Another event took the event's UID or CalDAV name.").into();
+    }
+
+    // Overrides deleted with the event have its deleted_at, see delete_event.
+    transaction.execute(
+        "UPDATE events SET deleted_at = NULL WHERE calendar_id = $1 AND (id = $2 OR parent_event_id = $2) AND deleted_at = $3",
+        &[&calendar_id, &event_id, &deleted_at]
+    )?;
+
+    transaction.commit()?;
+
+    let rows = db.query("SELECT * FROM events WHERE calendar_id = $1 AND id = $2", &[&calendar_id, &event_id])?;
+
+    match rows.get(0)
+    {
+        Some(row) =>
+        {
+            let mut events = vec![Event::from_row(row)?.into_plain()];
+            fill_attendees(&mut db, &mut events)?;
+
+            ApiResult::Ok(events.remove(0).redact(principal.get_visibility()))
+        },
+        None => ApiResult::not_found(),
+    }
+}
+
+/// Lists the calendars in the trash the caller is an owner of, most
+/// recently deleted first.
+///
+/// Required scope: `READ`
+///
+/// Response codes: 200, 403, 500
+#[openapi]
+#[get("/trash/calendars")]
+pub fn list_trashed_calendars(mut db: PgsqlConn, principal: RequireRead, common_params: CommonQueryParams) -> ApiResult<Vec<Calendar>>
+{
+    let owned = get_calendar_ids_with_role(&mut db, &principal, Role::Owner)?;
+
+    let query = "
+        SELECT * FROM calendars
+        WHERE tenant_id = $1 AND ($2::UUID[] IS NULL OR id = ANY($2)) AND deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC, id
+        OFFSET $3
+        LIMIT $4;
+    ";
+
+    let rows = db.query(query, &[
+        &principal.get_tenant_id(),
+        &owned,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| Calendar::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Takes a calendar out of the trash, along with the events that were
+/// deleted with it, and returns it. Events that were already in the
+/// trash when the calendar was deleted stay there.
+///
+/// Required scope: `WRITE`, required role: `OWNER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[post("/trash/calendars/<trashed_calendar_id>/restore")]
+pub fn restore_calendar(mut db: PgsqlConn, principal: RequireWrite, trashed_calendar_id: UuidParam) -> ApiResult<Calendar>
+{
+    // The parameter isn't named calendar_id, otherwise the guards would
+    // look the calendar up and not find it, so the role is checked here.
+    let calendar_id = trashed_calendar_id.into_inner();
+
+    if get_calendar_ids_with_role(&mut db, &principal, Role::Owner)?.map_or(false, |owned| !owned.contains(&calendar_id))
+    {
+        return ApiResult::not_found();
+    }
+
+    let mut transaction = db.transaction()?;
+
+    let query = "SELECT deleted_at FROM calendars WHERE id = $1 AND tenant_id = $2 AND deleted_at IS NOT NULL FOR UPDATE;";
+
+    let deleted_at: NaiveDateTime = match transaction.query(query, &[&calendar_id, &principal.get_tenant_id()])?.get(0)
+    {
+        Some(row) => row.get_cell("deleted_at")?,
+        None => return ApiResult::not_found(),
+    };
+
+    let rows = transaction.query("UPDATE calendars SET deleted_at = NULL WHERE id = $1 RETURNING *;", &[&calendar_id])?;
+
+    let calendar = match rows.get(0)
+    {
+        Some(row) => Calendar::from_row(row)?,
+        None => return ApiResult::Problem(DatabaseError::from(DatabaseErrorKind::ReturningIsEmpty).into()),
+    };
+
+    // Events deleted with the calendar have its deleted_at, see delete_calendar.
+    transaction.execute(
+        "UPDATE events SET deleted_at = NULL WHERE calendar_id = $1 AND deleted_at = $2",
+        &[&calendar_id, &deleted_at]
+    )?;
+
+    transaction.commit()?;
+
+    ApiResult::Ok(calendar)
+}
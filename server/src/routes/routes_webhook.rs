@@ -0,0 +1,286 @@
+use crate::connection_pool::PgsqlConn;
+use crate::routes::api_result::ApiResult;
+use crate::problem::{ErrorCode, Problem};
+use crate::database_helpers::{FromRow, RowHelpers, UuidParam};
+use rocket_contrib::json::Json;
+use crate::database_error::DatabaseError;
+use crate::authentication::auth_guard::{RequireRead, RequireWrite};
+use crate::routes::common_query_params::CommonQueryParams;
+use crate::webhook::{Webhook, WebhookScope, WebhookDelivery, DeliveryStatus};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// Checks if the event a webhook is subscribed to can be subscribed to
+/// with the webhook's scope, i.e. if it exists in the calendar with id
+/// `calendar_id` and, for `INSTANCES` webhooks, if it is recurring.
+fn can_subscribe(db: &mut PgsqlConn, calendar_id: &UuidParam, webhook: &Webhook) -> Result<bool, DatabaseError>
+{
+    let query = match webhook.scope
+    {
+        WebhookScope::Calendar => return Ok(true),
+        WebhookScope::Event => "SELECT 1 FROM events WHERE calendar_id = $1 AND id = $2 AND deleted_at IS NULL",
+        WebhookScope::Instances => "SELECT 1 FROM events WHERE calendar_id = $1 AND id = $2 AND rrule IS NOT NULL AND deleted_at IS NULL",
+    };
+
+    Ok(!db.query(query, &[calendar_id, &webhook.event_id])?.is_empty())
+}
+
+/// The problem with a webhook that doesn't pass `Webhook::validate`.
+fn invalid_webhook() -> Problem
+{
+    Problem::bad_request(
+        ErrorCode::InvalidField,
+        "The URL must be http or https, the secret can't be empty, and only EVENT and INSTANCES webhooks have an event_id."
+    )
+}
+
+/// Lists the webhooks of a calendar.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/webhooks")]
+pub fn list_webhooks(mut db: PgsqlConn, _principal: RequireRead, calendar_id: UuidParam, common_params: CommonQueryParams) -> ApiResult<Vec<Webhook>>
+{
+    let query = "SELECT * FROM webhooks WHERE calendar_id = $1 ORDER BY created_at OFFSET $2 LIMIT $3;";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| Webhook::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Gets a webhook by id.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/webhooks/<webhook_id>")]
+pub fn get_webhook(mut db: PgsqlConn, _principal: RequireRead, calendar_id: UuidParam, webhook_id: UuidParam) -> ApiResult<Webhook>
+{
+    let query = "SELECT * FROM webhooks WHERE calendar_id = $1 AND id = $2;";
+
+    let rows = db.query(query, &[&calendar_id, &webhook_id])?;
+
+    match rows.get(0)
+    {
+        Some(row) => ApiResult::Ok(Webhook::from_row(row)?),
+        None => ApiResult::not_found(),
+    }
+}
+
+/// Subscribes a webhook to changes of a calendar's events. This is the
+/// only response with the webhook's secret.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 201, 400, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/webhooks", data = "<webhook>")]
+pub fn insert_webhook(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, webhook: Json<Webhook>) -> ApiResult<Webhook>
+{
+    if webhook.id.is_some()
+    {
+        return Problem::invalid_field("id", "Webhooks get their id from the server.").into();
+    }
+
+    if !webhook.validate()
+    {
+        return invalid_webhook().into();
+    }
+
+    if !can_subscribe(&mut db, &calendar_id, &webhook)?
+    {
+        return Problem::bad_request(ErrorCode::InvalidReference, "The event isn't in the calendar, or isn't recurring for an INSTANCES webhook.").field("event_id").into();
+    }
+
+    let query = "
+        INSERT INTO webhooks (calendar_id, url, scope, event_id, secret)
+        SELECT id, $2, $3, $4, COALESCE($5, encode(gen_random_bytes(32), 'hex')) FROM calendars
+        WHERE id = $1 AND deleted_at IS NULL
+        RETURNING *;
+    ";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &webhook.url,
+        &webhook.scope.to_string(),
+        &webhook.event_id,
+        &webhook.secret,
+    ])?;
+
+    if let Some(row) = rows.get(0)
+    {
+        let mut created = Webhook::from_row(row)?;
+        created.secret = row.get_cell("secret")?;
+
+        ApiResult::Created(
+            created,
+            format!("/api/calendars/{}/webhooks/{}", calendar_id, row.get_cell::<Uuid>("id")?)
+        )
+    }
+    else
+    {
+        ApiResult::not_found()
+    }
+}
+
+/// Updates a webhook. The secret is kept if `secret` is not set.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi]
+#[put("/calendars/<calendar_id>/webhooks/<webhook_id>", data = "<webhook>")]
+pub fn update_webhook(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, webhook_id: UuidParam, webhook: Json<Webhook>) -> ApiResult<Webhook>
+{
+    let webhook_id = webhook_id.into_inner();
+
+    if webhook.id.map_or(false, |id| id != webhook_id)
+    {
+        return Problem::invalid_field("id", "The id must be the webhook's.").into();
+    }
+
+    if !webhook.validate()
+    {
+        return invalid_webhook().into();
+    }
+
+    if !can_subscribe(&mut db, &calendar_id, &webhook)?
+    {
+        return Problem::bad_request(ErrorCode::InvalidReference, "The event isn't in the calendar, or isn't recurring for an INSTANCES webhook.").field("event_id").into();
+    }
+
+    let query = "
+        UPDATE webhooks SET url = $3, scope = $4, event_id = $5, secret = COALESCE($6, secret)
+        WHERE calendar_id = $1 AND id = $2
+        RETURNING *;
+    ";
+
+    let rows = db.query(query, &[
+        &calendar_id,
+        &webhook_id,
+        &webhook.url,
+        &webhook.scope.to_string(),
+        &webhook.event_id,
+        &webhook.secret,
+    ])?;
+
+    match rows.get(0)
+    {
+        Some(row) => ApiResult::Ok(Webhook::from_row(row)?),
+        None => ApiResult::not_found(),
+    }
+}
+
+/// Deletes a webhook along with its deliveries.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[delete("/calendars/<calendar_id>/webhooks/<webhook_id>")]
+pub fn delete_webhook(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, webhook_id: UuidParam) -> ApiResult<()>
+{
+    let query = "DELETE FROM webhooks WHERE calendar_id = $1 AND id = $2;";
+
+    if db.execute(query, &[&calendar_id, &webhook_id])? == 0
+    {
+        ApiResult::not_found()
+    }
+    else
+    {
+        ApiResult::Ok(())
+    }
+}
+
+/// Lists the deliveries of a webhook, newest first. If `status` is
+/// set only deliveries with that status are listed.
+///
+/// Required scope: `READ`, required role: `READER`
+///
+/// Response codes: 200, 400, 403, 404, 500
+#[openapi]
+#[get("/calendars/<calendar_id>/webhooks/<webhook_id>/deliveries?<status>")]
+pub fn list_deliveries(
+    mut db: PgsqlConn,
+    _principal: RequireRead,
+    calendar_id: UuidParam,
+    webhook_id: UuidParam,
+    status: Option<String>,
+    common_params: CommonQueryParams,
+) -> ApiResult<Vec<WebhookDelivery>>
+{
+    let status = match status.map(|s| DeliveryStatus::from_str(&s)).transpose()
+    {
+        Ok(status) => status.map(|s| s.to_string()),
+        Err(_) => return Problem::invalid_field("status", "The status must be PENDING, DELIVERED, DEAD or CANCELLED.").into(),
+    };
+
+    let query = "SELECT 1 FROM webhooks WHERE calendar_id = $1 AND id = $2";
+    if db.query(query, &[&calendar_id, &webhook_id])?.is_empty()
+    {
+        return ApiResult::not_found();
+    }
+
+    let query = "
+        SELECT * FROM webhook_deliveries
+        WHERE webhook_id = $1 AND ($2::TEXT IS NULL OR status = $2::TEXT)
+        ORDER BY created_at DESC
+        OFFSET $3
+        LIMIT $4;
+    ";
+
+    let rows = db.query(query, &[
+        &webhook_id,
+        &status,
+        &common_params.offset(),
+        &common_params.page_size(),
+    ])?;
+
+    ApiResult::Ok(
+        rows.into_iter()
+            .map(|row| WebhookDelivery::from_row(&row))
+            .collect::<Result<Vec<_>, _>>()?
+    )
+}
+
+/// Moves a dead delivery back to the queue, it's attempted again
+/// as if it had never been attempted.
+///
+/// Required scope: `WRITE`, required role: `WRITER`
+///
+/// Response codes: 200, 403, 404, 500
+#[openapi]
+#[post("/calendars/<calendar_id>/webhooks/<webhook_id>/deliveries/<delivery_id>/retry")]
+pub fn retry_delivery(mut db: PgsqlConn, _principal: RequireWrite, calendar_id: UuidParam, webhook_id: UuidParam, delivery_id: UuidParam) -> ApiResult<WebhookDelivery>
+{
+    let query = "
+        UPDATE webhook_deliveries SET status = 'PENDING', attempts = 0, next_attempt_at = NOW()
+        FROM webhooks
+        WHERE
+            webhooks.id = webhook_deliveries.webhook_id
+            AND webhooks.calendar_id = $1
+            AND webhook_deliveries.webhook_id = $2
+            AND webhook_deliveries.id = $3
+            AND webhook_deliveries.status = 'DEAD'
+        RETURNING webhook_deliveries.*;
+    ";
+
+    let rows = db.query(query, &[&calendar_id, &webhook_id, &delivery_id])?;
+
+    match rows.get(0)
+    {
+        Some(row) => ApiResult::Ok(WebhookDelivery::from_row(row)?),
+        None => ApiResult::not_found(),
+    }
+}
@@ -0,0 +1,154 @@
+//! Fetching the iCalendar files subscribed calendars mirror, and
+//! removing the events that are not in them anymore.
+
+use std::io::Read;
+use std::fs::File;
+use std::time::Duration;
+use postgres::Transaction;
+use uuid::Uuid;
+use reqwest::blocking::Client;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use caser_common::ical::import::{ImportReport, ImportStatus};
+use crate::database_error::DatabaseError;
+
+pub use caser_common::subscription::*;
+
+/// Maximum size of a subscribed file, in bytes.
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum FetchError
+{
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+
+    #[error("Could not read the file: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("The server responded with status {0}.")]
+    Status(u16),
+
+    #[error("The file is larger than {0} bytes.")]
+    TooLarge(u64),
+
+    #[error("file URLs are not allowed.")]
+    FileNotAllowed,
+}
+
+/// A fetched iCalendar file.
+pub enum Fetched
+{
+    /// The file didn't change since it was fetched with the given ETag.
+    NotModified,
+
+    File
+    {
+        body: String,
+        etag: Option<String>,
+    },
+}
+
+/// Fetches the file at `url`. `webcal` URLs are fetched over HTTPS,
+/// `file` URLs are read from the server's file system if `allow_files`
+/// is true (which is meant for testing).
+///
+/// If `etag` is set (the ETag of the last fetch), HTTP servers can
+/// respond that the file didn't change.
+pub fn fetch(url: &str, etag: Option<&str>, allow_files: bool) -> Result<Fetched, FetchError>
+{
+    if let Some(path) = url.strip_prefix("file://")
+    {
+        if !allow_files
+        {
+            return Err(FetchError::FileNotAllowed);
+        }
+
+        let body = read_limited(File::open(path)?)?;
+        return Ok(Fetched::File { body, etag: None });
+    }
+
+    let url = match url.strip_prefix("webcal://")
+    {
+        Some(rest) => format!("https://{}", rest),
+        None => url.to_owned(),
+    };
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    let mut request = client.get(&url);
+    if let Some(etag) = etag
+    {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+
+    let response = request.send()?;
+
+    if response.status() == StatusCode::NOT_MODIFIED
+    {
+        return Ok(Fetched::NotModified);
+    }
+
+    if !response.status().is_success()
+    {
+        return Err(FetchError::Status(response.status().as_u16()));
+    }
+
+    let etag = response.headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(|etag| etag.to_owned());
+
+    Ok(Fetched::File { body: read_limited(response)?, etag })
+}
+
+fn read_limited<R: Read>(reader: R) -> Result<String, FetchError>
+{
+    let mut body = String::new();
+    reader.take(MAX_FILE_SIZE + 1).read_to_string(&mut body)?;
+
+    if body.len() as u64 > MAX_FILE_SIZE
+    {
+        return Err(FetchError::TooLarge(MAX_FILE_SIZE));
+    }
+
+    Ok(body)
+}
+
+/// Moves the events of a subscribed calendar that were not in the file
+/// `report` is the import report of to the trash. Events whose UID failed
+/// to import are kept, so a broken event in the file doesn't make it disappear.
+///
+/// Returns how many events were deleted.
+pub fn remove_stale_events(transaction: &mut Transaction, calendar_id: &Uuid, report: &ImportReport) -> Result<u64, DatabaseError>
+{
+    let imported: Vec<Uuid> = report.components
+        .iter()
+        .filter(|c| c.status == ImportStatus::Imported)
+        .filter_map(|c| c.event_id)
+        .collect();
+
+    let failed: Vec<&String> = report.components
+        .iter()
+        .filter(|c| c.status == ImportStatus::Failed)
+        .filter_map(|c| c.uid.as_ref())
+        .collect();
+
+    // Overrides are identified by the UID of their recurring event.
+    let query = "
+        UPDATE events SET deleted_at = NOW()
+        WHERE
+            calendar_id = $1
+            AND deleted_at IS NULL
+            AND id <> ALL($2)
+            AND COALESCE(
+                ical_uid,
+                (SELECT parent.ical_uid FROM events AS parent WHERE parent.id = events.parent_event_id),
+                ''
+            ) <> ALL($3);
+    ";
+
+    Ok(transaction.execute(query, &[calendar_id, &imported, &failed])?)
+}
@@ -0,0 +1,139 @@
+//! Background job that syncs subscribed calendars.
+//!
+//! On every tick the fetcher claims the subscribed calendars whose
+//! `next_sync_at` has passed (pushing it forward, so other servers using
+//! the same database don't pick them up), fetches their file and imports
+//! it, removing the events that are not in the file anymore. Each sync
+//! happens in a single transaction, so clients never see half a sync.
+
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::error::Error;
+use uuid::Uuid;
+use caser_common::ical::parser::parse;
+use crate::connection_pool::{PgsqlPool, PgsqlConn};
+use crate::configs::Configs;
+use crate::database_helpers::RowHelpers;
+use crate::database_error::DatabaseError;
+use crate::ical::import_components;
+use crate::subscription::{self, Fetched, SyncStatus};
+
+/// Starts the subscription fetcher in a new thread.
+pub fn spawn(pool: PgsqlPool, configs: Configs) -> JoinHandle<()>
+{
+    thread::spawn(move || loop
+    {
+        if let Err(e) = tick(&pool, &configs)
+        {
+            log::error!("Subscription fetcher failed: {}", e);
+        }
+
+        thread::sleep(Duration::from_secs(configs.get_subscription_poll_interval()));
+    })
+}
+
+fn tick(pool: &PgsqlPool, configs: &Configs) -> Result<(), Box<dyn Error>>
+{
+    let mut db = pool.get_unrestricted_conn()?;
+
+    while let Some((calendar_id, url, etag)) = claim_due(&mut db)?
+    {
+        let result = sync(&mut db, &calendar_id, &url, etag.as_deref(), configs.get_subscription_allow_files());
+
+        if let Err(e) = result
+        {
+            set_status(&mut db, &calendar_id, SyncStatus::Failed, Some(&e.to_string()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Claims a subscribed calendar that is due for a sync, returns its
+/// id, the URL of its file and the ETag of the last fetch.
+fn claim_due(db: &mut PgsqlConn) -> Result<Option<(Uuid, String, Option<String>)>, DatabaseError>
+{
+    // The lease only matters if the server stops in the middle
+    // of a sync, otherwise next_sync_at is overwritten after it.
+    let query = "
+        UPDATE calendars SET next_sync_at = NOW() + INTERVAL '5 minutes'
+        WHERE id = (
+            SELECT id FROM calendars
+            WHERE subscription_url IS NOT NULL AND next_sync_at <= NOW() AND deleted_at IS NULL
+            ORDER BY next_sync_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, subscription_url, subscription_etag;
+    ";
+
+    let rows = db.query(query, &[])?;
+
+    match rows.get(0)
+    {
+        Some(row) => Ok(Some((
+            row.get_cell("id")?,
+            row.get_cell("subscription_url")?,
+            row.get_cell("subscription_etag")?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Fetches the file of a subscribed calendar and imports it.
+fn sync(db: &mut PgsqlConn, calendar_id: &Uuid, url: &str, etag: Option<&str>, allow_files: bool) -> Result<(), Box<dyn Error>>
+{
+    let (body, etag) = match subscription::fetch(url, etag, allow_files)?
+    {
+        Fetched::NotModified => return Ok(set_status(db, calendar_id, SyncStatus::Ok, None)?),
+        Fetched::File { body, etag } => (body, etag),
+    };
+
+    let calendars = parse(&body)?;
+
+    // Otherwise every event of the calendar would be removed.
+    if !calendars.iter().any(|c| c.name == "VCALENDAR")
+    {
+        return Err("The file has no VCALENDAR.".into());
+    }
+
+    let mut transaction = db.transaction()?;
+
+    let report = import_components(&mut transaction, calendar_id, &calendars)?;
+    subscription::remove_stale_events(&mut transaction, calendar_id, &report)?;
+
+    // Events that couldn't be imported don't fail the sync,
+    // but they're reported.
+    let error = match report.failed
+    {
+        0 => None,
+        failed => Some(format!("{} of {} components could not be imported.", failed, report.components.len())),
+    };
+
+    let query = "
+        UPDATE calendars SET
+            sync_status = $2, last_sync_error = $3, last_synced_at = NOW(), subscription_etag = $4,
+            next_sync_at = NOW() + make_interval(mins => refresh_interval)
+        WHERE id = $1;
+    ";
+
+    transaction.execute(query, &[calendar_id, &SyncStatus::Ok.to_string(), &error, &etag])?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+fn set_status(db: &mut PgsqlConn, calendar_id: &Uuid, status: SyncStatus, error: Option<&str>) -> Result<(), DatabaseError>
+{
+    let query = "
+        UPDATE calendars SET
+            sync_status = $2, last_sync_error = $3,
+            last_synced_at = CASE WHEN $2 = 'OK' THEN NOW() ELSE last_synced_at END,
+            next_sync_at = NOW() + make_interval(mins => refresh_interval)
+        WHERE id = $1;
+    ";
+
+    db.execute(query, &[calendar_id, &status.to_string(), &error])?;
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+//! Reads the event change log (the `event_changes` table), which backs
+//! sync tokens and the live change stream.
+
+use uuid::Uuid;
+use crate::connection_pool::PgsqlConn;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::DatabaseError;
+use crate::event::{Event, ToPlain, Redact};
+use crate::acl::Visibility;
+use crate::attendee::get_attendees;
+
+pub use caser_common::sync::*;
+
+/// A page of changes read from the change log.
+pub struct ChangePage
+{
+    /// Changes in the order they happened, each with the token
+    /// that points right after it.
+    pub changes: Vec<(SyncToken, EventChange)>,
+
+    /// Token that points right after the last change of this
+    /// page (or to the end of the log, if there are no more changes).
+    pub sync_token: SyncToken,
+
+    pub more: bool,
+}
+
+impl From<ChangePage> for SyncResponse
+{
+    fn from(page: ChangePage) -> Self
+    {
+        SyncResponse {
+            changes: page.changes.into_iter().map(|(_, change)| change).collect(),
+            sync_token: page.sync_token.to_string(),
+            more: page.more,
+        }
+    }
+}
+
+/// Gets a token that points to the end of the change log of the
+/// calendar with id `calendar_id`, i.e. syncing with it only
+/// returns changes made after this was called.
+pub fn get_current_token(db: &mut PgsqlConn, calendar_id: &Uuid) -> Result<SyncToken, DatabaseError>
+{
+    let query = "SELECT COALESCE(MAX(seq), 0) AS seq FROM event_changes WHERE calendar_id = $1";
+
+    let rows = db.query(query, &[calendar_id])?;
+
+    Ok(SyncToken::new(
+        match rows.get(0)
+        {
+            Some(row) => row.get_cell("seq")?,
+            None => 0,
+        }
+    ))
+}
+
+/// Gets the events of the calendar with id `calendar_id` that changed
+/// since `token`, including tombstones of deleted events (events moved to
+/// the trash too). Without a token all events of the calendar are returned
+/// (tombstones are not).
+///
+/// Each event is returned once with its latest state, no matter how many
+/// times it changed. Events are redacted to what `visibility` shows.
+pub fn get_changes(db: &mut PgsqlConn, calendar_id: &Uuid, token: Option<SyncToken>, page_size: i64, visibility: Visibility) -> Result<ChangePage, DatabaseError>
+{
+    let since = token.map(|t| t.get_seq()).unwrap_or(0);
+
+    // Changes committed after this are left for the next sync, so that
+    // the new token doesn't skip them.
+    let until = get_current_token(db, calendar_id)?.get_seq().max(since);
+
+    let query = "
+        WITH latest AS (
+            SELECT DISTINCT ON (event_id)
+                seq AS change_seq,
+                event_id AS change_event_id,
+                parent_event_id AS change_parent_id,
+                change_type
+            FROM event_changes
+            WHERE calendar_id = $1 AND seq > $2 AND seq <= $3
+            ORDER BY event_id, seq DESC
+        )
+        SELECT latest.*, events.*
+        FROM latest
+        LEFT JOIN events ON events.id = latest.change_event_id AND events.deleted_at IS NULL
+        WHERE $4 OR latest.change_type <> 'DELETED'
+        ORDER BY latest.change_seq
+        LIMIT $5;
+    ";
+
+    // One more than a page, to find out if there are more changes.
+    let rows = db.query(query, &[
+        calendar_id,
+        &since,
+        &until,
+        &token.is_some(),
+        &(page_size + 1),
+    ])?;
+
+    let more = rows.len() as i64 > page_size;
+
+    let mut changes = vec![];
+    for row in rows.iter().take(page_size as usize)
+    {
+        let deleted = row.get_cell::<String>("change_type")? == "DELETED"
+            || row.get_cell::<Option<Uuid>>("id")?.is_none();
+
+        let event = if deleted { None } else { Some(Event::from_row(row)?.into_plain()) };
+
+        changes.push((
+            SyncToken::new(row.get_cell("change_seq")?),
+            EventChange {
+                id: row.get_cell("change_event_id")?,
+                parent_id: row.get_cell("change_parent_id")?,
+                deleted,
+                event,
+            }
+        ));
+    }
+
+    let ids: Vec<Uuid> = changes.iter().filter(|(_, c)| !c.deleted).map(|(_, c)| c.id).collect();
+    let mut attendees = get_attendees(db, &ids)?;
+
+    for (_, change) in &mut changes
+    {
+        if let Some(mut event) = change.event.take()
+        {
+            event.attendees = Some(attendees.remove(&change.id).unwrap_or(vec![]));
+            change.event = Some(event.redact(visibility));
+        }
+    }
+
+    let sync_token = match changes.last()
+    {
+        Some((token, _)) if more => *token,
+        _ => SyncToken::new(until),
+    };
+
+    Ok(ChangePage { changes, sync_token, more })
+}
@@ -0,0 +1,44 @@
+use postgres::Row;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::DatabaseError;
+
+pub use caser_common::tenant::*;
+
+impl FromRow for Tenant
+{
+    type SelfType = Tenant;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        Ok(
+            Tenant {
+                id: row.get_cell("id")?,
+                name: row.get_cell("name")?,
+                created_at: row.get_cell("created_at")?,
+            }
+        )
+    }
+}
+
+impl FromRow for TenantApiKey
+{
+    type SelfType = TenantApiKey;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        Ok(
+            TenantApiKey {
+                id: row.get_cell("id")?,
+                key: None,
+                tenant_id: row.get_cell("tenant_id")?,
+                name: row.get_cell("name")?,
+                scopes: row.get_cell("scopes")?,
+                calendar_ids: row.get_cell("calendar_ids")?,
+                expires_at: row.get_cell("expires_at")?,
+                created_at: row.get_cell("created_at")?,
+                last_used_at: row.get_cell("last_used_at")?,
+                revoked_at: row.get_cell("revoked_at")?,
+            }
+        )
+    }
+}
@@ -0,0 +1,51 @@
+//! Background job that removes calendars and events for good once
+//! they've been in the trash for more than `TRASH_RETENTION_DAYS`.
+
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::error::Error;
+use crate::connection_pool::PgsqlPool;
+
+/// How often (in seconds) old calendars and events are removed.
+const PURGE_INTERVAL: u64 = 60 * 60;
+
+/// Starts the trash purger in a new thread.
+pub fn spawn(pool: PgsqlPool, retention_days: u32) -> JoinHandle<()>
+{
+    thread::spawn(move || loop
+    {
+        if let Err(e) = purge(&pool, retention_days)
+        {
+            log::error!("Trash purger failed: {}", e);
+        }
+
+        thread::sleep(Duration::from_secs(PURGE_INTERVAL));
+    })
+}
+
+fn purge(pool: &PgsqlPool, retention_days: u32) -> Result<(), Box<dyn Error>>
+{
+    let mut db = pool.get_unrestricted_conn()?;
+    let mut transaction = db.transaction()?;
+
+    let retention_days = retention_days as i32;
+
+    // Calendars don't cascade to their events, so the events of the
+    // calendars that are purged are removed first.
+    let query = "
+        DELETE FROM events
+        WHERE
+            deleted_at < NOW() - $1::INTEGER * INTERVAL '1 day'
+            OR calendar_id IN (SELECT id FROM calendars WHERE deleted_at < NOW() - $1::INTEGER * INTERVAL '1 day');
+    ";
+
+    transaction.execute(query, &[&retention_days])?;
+
+    let query = "DELETE FROM calendars WHERE deleted_at < NOW() - $1::INTEGER * INTERVAL '1 day';";
+
+    transaction.execute(query, &[&retention_days])?;
+
+    transaction.commit()?;
+
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Delivery of notifications to webhooks (i.e. POSTing
+//! JSON to a URL).
+
+use serde_json::Value;
+use std::time::Duration;
+use std::str::FromStr;
+use postgres::Row;
+use ring::hmac;
+use uuid::Uuid;
+use reqwest::blocking::Client;
+use crate::database_helpers::{FromRow, RowHelpers};
+use crate::database_error::{DatabaseError, DatabaseErrorKind};
+
+pub use caser_common::webhook::*;
+
+/// Header that stores the signature of a notification's body.
+pub const SIGNATURE_HEADER: &str = "X-Caser-Signature";
+
+/// Header that stores the id of the delivery a notification belongs to.
+pub const DELIVERY_HEADER: &str = "X-Caser-Delivery";
+
+/// Delay before the first retry of a failed delivery, the
+/// delay doubles on each subsequent retry.
+const RETRY_BASE_DELAY: i64 = 30;
+
+/// Maximum delay between retries, in seconds.
+const RETRY_MAX_DELAY: i64 = 6 * 60 * 60;
+
+#[derive(Error, Debug)]
+pub enum WebhookError
+{
+    #[error(transparent)]
+    Request(#[from] reqwest::Error),
+
+    #[error(transparent)]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("Webhook responded with status {0}.")]
+    Status(u16),
+}
+
+impl WebhookError
+{
+    /// The HTTP status the webhook responded with, if it responded.
+    pub fn get_status(&self) -> Option<u16>
+    {
+        match self
+        {
+            WebhookError::Request(e) => e.status().map(|s| s.as_u16()),
+            WebhookError::Serialize(_) => None,
+            WebhookError::Status(status) => Some(*status),
+        }
+    }
+}
+
+impl FromRow for Webhook
+{
+    type SelfType = Webhook;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let scope: String = row.get_cell("scope")?;
+
+        Ok(
+            Webhook {
+                id: row.get_cell("id")?,
+                url: row.get_cell("url")?,
+                scope: WebhookScope::from_str(&scope)
+                    .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?,
+                event_id: row.get_cell("event_id")?,
+                // Only the dispatcher reads it, see `Webhook::secret`.
+                secret: None,
+            }
+        )
+    }
+}
+
+impl FromRow for WebhookDelivery
+{
+    type SelfType = WebhookDelivery;
+
+    fn from_row(row: &Row) -> Result<Self::SelfType, DatabaseError>
+    {
+        let status: String = row.get_cell("status")?;
+        let status = DeliveryStatus::from_str(&status)
+            .map_err(|e| DatabaseErrorKind::Other(Box::new(e)))?;
+
+        Ok(
+            WebhookDelivery {
+                id: row.get_cell("id")?,
+                event_type: row.get_cell("event_type")?,
+                payload: row.get_cell("payload")?,
+                status,
+                attempts: row.get_cell("attempts")?,
+                created_at: row.get_cell("created_at")?,
+                last_attempt_at: row.get_cell("last_attempt_at")?,
+                next_attempt_at: match status
+                {
+                    DeliveryStatus::Pending => Some(row.get_cell("next_attempt_at")?),
+                    DeliveryStatus::Delivered | DeliveryStatus::Dead | DeliveryStatus::Cancelled => None,
+                },
+                delivered_at: row.get_cell("delivered_at")?,
+                response_status: row.get_cell("response_status")?,
+                last_error: row.get_cell("last_error")?,
+            }
+        )
+    }
+}
+
+/// POSTs `payload` to `url` with the id of the delivery and the
+/// signature of the body (see `sign`) in the request's headers.
+///
+/// Returns the response's status, fails if the request fails or if
+/// the response's status is not a 2xx.
+pub fn deliver_signed(url: &str, payload: &Value, delivery_id: Uuid, secret: &str) -> Result<u16, WebhookError>
+{
+    let body = serde_json::to_vec(payload)?;
+
+    let request = build_client()?
+        .post(url)
+        .header(DELIVERY_HEADER, delivery_id.to_string())
+        .header(SIGNATURE_HEADER, format!("sha256={}", sign(secret, &body)));
+
+    post(request, body)
+}
+
+/// Signs `body` with HMAC-SHA256 using `secret` as the key, returns
+/// the signature as a lowercase hex string.
+pub fn sign(secret: &str, body: &[u8]) -> String
+{
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+
+    hmac::sign(&key, body)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// How long to wait before attempting a delivery again after
+/// it failed `attempts` times.
+pub fn retry_delay(attempts: i32) -> chrono::Duration
+{
+    let exponent = (attempts.max(1) - 1).min(30) as u32;
+    let delay = RETRY_BASE_DELAY.saturating_mul(2i64.saturating_pow(exponent));
+
+    chrono::Duration::seconds(delay.min(RETRY_MAX_DELAY))
+}
+
+fn build_client() -> Result<Client, WebhookError>
+{
+    Ok(
+        Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?
+    )
+}
+
+fn post(request: reqwest::blocking::RequestBuilder, body: Vec<u8>) -> Result<u16, WebhookError>
+{
+    let response = request
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()?;
+
+    if response.status().is_success()
+    {
+        Ok(response.status().as_u16())
+    }
+    else
+    {
+        Err(WebhookError::Status(response.status().as_u16()))
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn hmac_sha256_signature()
+    {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn exponential_backoff()
+    {
+        assert_eq!(retry_delay(1), chrono::Duration::seconds(30));
+        assert_eq!(retry_delay(2), chrono::Duration::seconds(60));
+        assert_eq!(retry_delay(4), chrono::Duration::seconds(240));
+        assert_eq!(retry_delay(100), chrono::Duration::seconds(RETRY_MAX_DELAY));
+    }
+}
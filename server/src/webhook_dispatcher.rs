@@ -0,0 +1,134 @@
+//! Background job that POSTs queued notifications to webhooks.
+//!
+//! Notifications are queued in `webhook_deliveries` by a trigger on the
+//! events table (see db_schema/7.sql). On every tick the dispatcher claims
+//! the deliveries that are due by pushing their `next_attempt_at` forward,
+//! so other servers using the same database don't pick them up, and then
+//! tries to deliver them. Failed deliveries are retried with exponential
+//! backoff until they have been attempted `WEBHOOK_MAX_ATTEMPTS` times,
+//! after which they're marked as `DEAD`. Alarm notifications (queued by
+//! the alarm scheduler) of events that were trashed since are marked as
+//! `CANCELLED` instead of being delivered.
+
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::error::Error;
+use serde_json::Value;
+use uuid::Uuid;
+use crate::connection_pool::{PgsqlPool, PgsqlConn};
+use crate::configs::Configs;
+use crate::database_helpers::RowHelpers;
+use crate::database_error::DatabaseError;
+use crate::webhook::{self, DeliveryStatus};
+
+/// Maximum amount of deliveries attempted in a single tick.
+const DELIVERY_BATCH_SIZE: i64 = 100;
+
+/// Starts the webhook dispatcher in a new thread.
+pub fn spawn(pool: PgsqlPool, configs: Configs) -> JoinHandle<()>
+{
+    thread::spawn(move || loop
+    {
+        if let Err(e) = tick(&pool, &configs)
+        {
+            log::error!("Webhook dispatcher failed: {}", e);
+        }
+
+        thread::sleep(Duration::from_secs(configs.get_webhook_poll_interval()));
+    })
+}
+
+fn tick(pool: &PgsqlPool, configs: &Configs) -> Result<(), Box<dyn Error>>
+{
+    let mut db = pool.get_unrestricted_conn()?;
+
+    deliver_due(&mut db, configs.get_webhook_max_attempts())?;
+
+    Ok(())
+}
+
+/// Attempts to deliver all pending deliveries whose
+/// `next_attempt_at` has passed.
+fn deliver_due(db: &mut PgsqlConn, max_attempts: i32) -> Result<(), DatabaseError>
+{
+    let cancel = "
+        UPDATE webhook_deliveries SET status = 'CANCELLED'
+        WHERE
+            status = 'PENDING'
+            AND event_type = 'alarm'
+            AND NOT EXISTS (
+                SELECT 1 FROM events
+                WHERE events.id = (webhook_deliveries.payload->>'event_id')::uuid AND events.deleted_at IS NULL
+            );
+    ";
+
+    db.execute(cancel, &[])?;
+
+    // The lease only matters if the server stops in the middle of a
+    // delivery, otherwise next_attempt_at is overwritten below.
+    let query = "
+        WITH claimed AS (
+            UPDATE webhook_deliveries SET next_attempt_at = NOW() + INTERVAL '5 minutes'
+            WHERE id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'PENDING' AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, webhook_id, payload, attempts
+        )
+        SELECT claimed.*, webhooks.url, webhooks.secret
+        FROM claimed
+        JOIN webhooks ON webhooks.id = claimed.webhook_id;
+    ";
+
+    let rows = db.query(query, &[&DELIVERY_BATCH_SIZE])?;
+
+    for row in rows
+    {
+        let id: Uuid = row.get_cell("id")?;
+        let payload: Value = row.get_cell("payload")?;
+        let url: String = row.get_cell("url")?;
+        let secret: String = row.get_cell("secret")?;
+        let attempts = row.get_cell::<i32>("attempts")? + 1;
+
+        match webhook::deliver_signed(&url, &payload, id, &secret)
+        {
+            Ok(status) =>
+            {
+                let query = "
+                    UPDATE webhook_deliveries SET
+                        status = $2, attempts = $3, response_status = $4,
+                        last_attempt_at = NOW(), delivered_at = NOW(), last_error = NULL
+                    WHERE id = $1;
+                ";
+
+                db.execute(query, &[&id, &DeliveryStatus::Delivered.to_string(), &attempts, &(status as i32)])?;
+            },
+            Err(e) =>
+            {
+                let status = if attempts >= max_attempts { DeliveryStatus::Dead } else { DeliveryStatus::Pending };
+                let delay = webhook::retry_delay(attempts).num_seconds() as f64;
+
+                let query = "
+                    UPDATE webhook_deliveries SET
+                        status = $2, attempts = $3, response_status = $4, last_error = $5,
+                        last_attempt_at = NOW(), next_attempt_at = NOW() + make_interval(secs => $6)
+                    WHERE id = $1;
+                ";
+
+                db.execute(query, &[
+                    &id,
+                    &status.to_string(),
+                    &attempts,
+                    &e.get_status().map(|s| s as i32),
+                    &e.to_string(),
+                    &delay,
+                ])?;
+            },
+        }
+    }
+
+    Ok(())
+}